@@ -4,12 +4,47 @@ use std::thread;
 
 use rodio::{buffer::SamplesBuffer, OutputStream, Sink};
 
-use crate::music::{Instrument, NoteEvent, Score};
+use crate::dynamics;
+use crate::form;
+use crate::harmony;
+use crate::music::{
+    Articulation, DurationValue, Instrument, NoteEvent, Part, Score, ScoreSettings,
+};
+use crate::rebar;
+use crate::tempo::Timeline;
 
 const SAMPLE_RATE: u32 = 44_100;
 
+/// Level of each note of a chord symbol's voicing, under the written parts.
+const CHORD_LEVEL: f32 = 0.12;
+
+/// How a score is played back or rendered to audio.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PlaybackOptions {
+    /// Multiplier on every tempo of the tempo map.
+    pub speed: f32,
+    /// Whether repeat barlines are taken and every ending played.
+    pub repeats: bool,
+    /// Whether the chord symbols are voiced on the piano as well.
+    pub chords: bool,
+}
+
+impl Default for PlaybackOptions {
+    fn default() -> Self {
+        Self {
+            speed: 1.0,
+            repeats: true,
+            chords: false,
+        }
+    }
+}
+
 enum PlaybackCommand {
-    Play { score: Score, bpm: f32 },
+    Play {
+        score: Score,
+        settings: ScoreSettings,
+        options: PlaybackOptions,
+    },
     Pause,
     Resume,
     Stop,
@@ -22,8 +57,13 @@ pub struct PlaybackController {
 }
 
 impl PlaybackController {
-    pub fn play(&self, score: Score, bpm: f32) {
-        let _ = self.tx.send(PlaybackCommand::Play { score, bpm });
+    /// Plays `score` through its repeats and jumps at its tempo map.
+    pub fn play(&self, score: Score, settings: ScoreSettings, options: PlaybackOptions) {
+        let _ = self.tx.send(PlaybackCommand::Play {
+            score,
+            settings,
+            options,
+        });
     }
 
     pub fn pause(&self) {
@@ -55,15 +95,17 @@ fn playback_thread(rx: Receiver<PlaybackCommand>) {
     };
 
     let mut sink: Option<Sink> = None;
-    let mut last_score: Option<Score> = None;
-    let mut last_bpm = 110.0;
+    let mut last_score: Option<(Score, ScoreSettings, PlaybackOptions)> = None;
 
     while let Ok(cmd) = rx.recv() {
         match cmd {
-            PlaybackCommand::Play { score, bpm } => {
-                last_score = Some(score);
-                last_bpm = bpm;
-                sink = create_sink_with_score(&handle, last_score.as_ref(), last_bpm);
+            PlaybackCommand::Play {
+                score,
+                settings,
+                options,
+            } => {
+                last_score = Some((score, settings, options));
+                sink = create_sink_with_score(&handle, last_score.as_ref());
             }
             PlaybackCommand::Pause => {
                 if let Some(current) = &sink {
@@ -84,7 +126,7 @@ fn playback_thread(rx: Receiver<PlaybackCommand>) {
                 if let Some(current) = sink.take() {
                     current.stop();
                 }
-                sink = create_sink_with_score(&handle, last_score.as_ref(), last_bpm);
+                sink = create_sink_with_score(&handle, last_score.as_ref());
             }
         }
     }
@@ -92,68 +134,319 @@ fn playback_thread(rx: Receiver<PlaybackCommand>) {
 
 fn create_sink_with_score(
     handle: &rodio::OutputStreamHandle,
-    score: Option<&Score>,
-    bpm: f32,
+    score: Option<&(Score, ScoreSettings, PlaybackOptions)>,
 ) -> Option<Sink> {
-    let score = score?;
+    let (score, settings, options) = score?;
     let Ok(sink) = Sink::try_new(handle) else {
         return None;
     };
 
+    let buffer = SamplesBuffer::new(1, SAMPLE_RATE, render_score(score, settings, *options));
+    sink.append(buffer);
+
+    Some(sink)
+}
+
+/// The performance as a mono 16-bit WAV file.
+pub fn export_wav(score: &Score, settings: &ScoreSettings, options: PlaybackOptions) -> Vec<u8> {
+    let samples = render_score(score, settings, options);
+    let data_len = (samples.len() * 2) as u32;
+
+    let mut out = Vec::with_capacity(44 + samples.len() * 2);
+    out.extend_from_slice(b"RIFF");
+    out.extend_from_slice(&(36 + data_len).to_le_bytes());
+    out.extend_from_slice(b"WAVEfmt ");
+    out.extend_from_slice(&16_u32.to_le_bytes());
+    // PCM, one channel.
+    out.extend_from_slice(&1_u16.to_le_bytes());
+    out.extend_from_slice(&1_u16.to_le_bytes());
+    out.extend_from_slice(&SAMPLE_RATE.to_le_bytes());
+    out.extend_from_slice(&(SAMPLE_RATE * 2).to_le_bytes());
+    out.extend_from_slice(&2_u16.to_le_bytes());
+    out.extend_from_slice(&16_u16.to_le_bytes());
+    out.extend_from_slice(b"data");
+    out.extend_from_slice(&data_len.to_le_bytes());
+    for sample in samples {
+        let value = (sample.clamp(-1.0, 1.0) * i16::MAX as f32).round() as i16;
+        out.extend_from_slice(&value.to_le_bytes());
+    }
+    out
+}
+
+/// Renders every voice of every part on its own and mixes them, so voices
+/// sound together instead of one after the other. The score is unrolled
+/// first, so repeats and jumps are heard as written.
+fn render_score(score: &Score, settings: &ScoreSettings, options: PlaybackOptions) -> Vec<f32> {
+    let (settings, score) = form::unroll(settings, score, options.repeats);
+    let (settings, score) = (&settings, &score);
+    let mut streams: Vec<(Part, u8)> = Vec::new();
     for note in &score.notes {
-        let beat_duration_s = 60.0 / bpm.max(20.0);
-        let duration_s = note.duration.beats() * beat_duration_s;
-        let samples = synthesize_note(note, duration_s);
-        let buffer = SamplesBuffer::new(1, SAMPLE_RATE, samples);
-        sink.append(buffer);
+        if !streams.contains(&(note.part(), note.voice)) {
+            streams.push((note.part(), note.voice));
+        }
     }
+    let streams: Vec<Vec<NoteEvent>> = streams
+        .into_iter()
+        .map(|(part, voice)| rebar::voice_stream(&score.notes, part, voice))
+        .collect();
+    let timeline = Timeline::new(settings, &streams, options.speed);
 
-    Some(sink)
+    let mut rendered: Vec<Vec<f32>> = streams
+        .iter()
+        .map(|stream| render_stream(stream, &timeline))
+        .collect();
+    if options.chords {
+        rendered.push(render_chords(&streams, &timeline));
+    }
+
+    let mut mix: Vec<f32> = Vec::new();
+    for samples in rendered {
+        for (index, sample) in samples.into_iter().enumerate() {
+            match mix.get_mut(index) {
+                Some(mixed) => *mixed += sample,
+                None => mix.push(sample),
+            }
+        }
+    }
+
+    // Scale down only when the voices together would clip.
+    let peak = mix
+        .iter()
+        .fold(0.0_f32, |peak, sample| peak.max(sample.abs()));
+    if peak > 1.0 {
+        for sample in &mut mix {
+            *sample /= peak;
+        }
+    }
+    if mix.is_empty() {
+        mix.push(0.0);
+    }
+    mix
+}
+
+/// Sample at which `beat` is heard.
+fn sample_at(timeline: &Timeline, beat: f32) -> usize {
+    (timeline.seconds(beat) * SAMPLE_RATE as f32).round() as usize
+}
+
+fn render_stream(stream: &[NoteEvent], timeline: &Timeline) -> Vec<f32> {
+    let levels = dynamics::levels(stream);
+    let mut position = 0.0;
+    // Start, starting level and first note of the tie chain being read.
+    let mut chain: Option<(f32, f32, &NoteEvent)> = None;
+    let mut out = Vec::new();
+
+    for (note, (from, to)) in stream.iter().zip(levels) {
+        let start = position;
+        position += note.beats();
+        // A tied note is sounded once for the length of the whole chain, the
+        // way its first note is marked.
+        if note.tie && !note.rest {
+            chain.get_or_insert((start, from, note));
+            continue;
+        }
+
+        let (start, from, first) = chain.take().unwrap_or((start, from, note));
+        out.resize(sample_at(timeline, start), 0.0);
+        let samples = sample_at(timeline, position).saturating_sub(out.len());
+        if note.rest {
+            out.resize(out.len() + samples, 0.0);
+        } else {
+            out.extend(synthesize_note(first, samples, from, to));
+        }
+    }
+
+    out
+}
+
+/// The chord symbols of every stream voiced on the piano, each held until
+/// the next. A chord written on several staves at once is played once.
+fn render_chords(streams: &[Vec<NoteEvent>], timeline: &Timeline) -> Vec<f32> {
+    let mut spans = Vec::new();
+    for stream in streams {
+        for span in harmony::chord_spans(stream) {
+            if !spans.contains(&span) {
+                spans.push(span);
+            }
+        }
+    }
+
+    let mut out: Vec<f32> = Vec::new();
+    for (chord, from, to) in spans {
+        let start = sample_at(timeline, from);
+        let samples = sample_at(timeline, to).saturating_sub(start);
+        for pitch in chord.voicing() {
+            let note = NoteEvent::new(pitch, DurationValue::Whole, Instrument::PIANO);
+            let sound = synthesize_note(&note, samples, CHORD_LEVEL, CHORD_LEVEL);
+            if out.len() < start + samples {
+                out.resize(start + samples, 0.0);
+            }
+            for (mixed, sample) in out[start..].iter_mut().zip(sound) {
+                *mixed += sample;
+            }
+        }
+    }
+    out
+}
+
+/// How a note's articulations shape its sound.
+pub struct Touch {
+    /// Share of the note's time that sounds.
+    pub gate: f32,
+    /// Seconds the sound takes to die away at the end.
+    pub release: f32,
+    /// Extra amplitude at the attack, as a share of the level.
+    pub accent: f32,
+}
+
+impl Touch {
+    pub fn of(note: &NoteEvent) -> Self {
+        let mut touch = Self {
+            gate: 1.0,
+            release: 0.08,
+            accent: 0.0,
+        };
+        for articulation in &note.articulations {
+            match articulation {
+                Articulation::Staccato => touch.gate = touch.gate.min(0.5),
+                Articulation::Staccatissimo => touch.gate = touch.gate.min(0.25),
+                Articulation::Tenuto => touch.release = 0.02,
+                Articulation::Accent => touch.accent = touch.accent.max(0.6),
+                Articulation::Marcato => {
+                    touch.accent = touch.accent.max(1.0);
+                    touch.gate = touch.gate.min(0.75);
+                }
+                // Held through the timeline instead.
+                Articulation::Fermata => {}
+            }
+        }
+        touch
+    }
 }
 
-fn synthesize_note(note: &NoteEvent, duration_s: f32) -> Vec<f32> {
+/// Sounds `note` over `sample_count` samples, its level moving from `from`
+/// to `to`.
+fn synthesize_note(note: &NoteEvent, sample_count: usize, from: f32, to: f32) -> Vec<f32> {
     let frequency = note.pitch.frequency_hz();
-    let sample_count = (duration_s * SAMPLE_RATE as f32).max(1.0) as usize;
+    let duration_s = sample_count as f32 / SAMPLE_RATE as f32;
+    let touch = Touch::of(note);
+    let sounding_s = duration_s * touch.gate;
+    let synth = note.instrument.synth();
     let mut out = Vec::with_capacity(sample_count);
 
     for index in 0..sample_count {
         let t = index as f32 / SAMPLE_RATE as f32;
         let phase = 2.0 * PI * frequency * t;
-        let harmonic = harmonic_mix(phase, note.instrument);
-        let env = envelope(t, duration_s);
-        out.push(harmonic * env * 0.35);
+        let harmonic = synth.harmonic_mix(phase);
+        let env = envelope(t, sounding_s, touch.release);
+        let gain = from + (to - from) * (t / duration_s).min(1.0);
+        let attack = 1.0 + touch.accent * (-t / 0.08).exp();
+        out.push(harmonic * env * gain * attack);
     }
 
     out
 }
 
-fn harmonic_mix(phase: f32, instrument: Instrument) -> f32 {
-    match instrument {
-        Instrument::Violin | Instrument::Viola => {
-            (phase.sin() + 0.35 * (2.0 * phase).sin() + 0.2 * (3.0 * phase).sin()) / 1.55
-        }
-        Instrument::Cello | Instrument::Horn => {
-            (phase.sin() + 0.45 * (0.5 * phase).sin() + 0.2 * (2.0 * phase).sin()) / 1.65
-        }
-        Instrument::Flute => (phase.sin() + 0.1 * (2.0 * phase).sin()) / 1.1,
-        Instrument::Clarinet => {
-            (phase.sin() + 0.55 * (3.0 * phase).sin() + 0.3 * (5.0 * phase).sin()) / 1.85
-        }
-        Instrument::Trumpet => {
-            (phase.sin() + 0.4 * (2.0 * phase).sin() + 0.25 * (4.0 * phase).sin()) / 1.65
-        }
-        Instrument::Timpani => ((phase.sin() * 0.8) + (0.27 * (1.6 * phase).sin())) / 1.07,
-        Instrument::Piano => {
-            (phase.sin() + 0.5 * (2.0 * phase).sin() + 0.3 * (4.0 * phase).sin()) / 1.8
+/// Playback presets, named in the instrument catalogue: a mix of
+/// harmonics for each kind of sound.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Synth {
+    /// Violin and viola.
+    Bowed,
+    /// Cello, double bass, horn and tuba.
+    Warm,
+    Flute,
+    /// Single reeds: clarinets and saxophones.
+    Reed,
+    /// Oboes and bassoons.
+    DoubleReed,
+    Brass,
+    Timpani,
+    Drum,
+    /// Bars of glockenspiel, xylophone and the like.
+    Mallet,
+    Plucked,
+    Piano,
+    Organ,
+    Voice,
+}
+
+impl Synth {
+    pub const ALL: [Self; 13] = [
+        Self::Bowed,
+        Self::Warm,
+        Self::Flute,
+        Self::Reed,
+        Self::DoubleReed,
+        Self::Brass,
+        Self::Timpani,
+        Self::Drum,
+        Self::Mallet,
+        Self::Plucked,
+        Self::Piano,
+        Self::Organ,
+        Self::Voice,
+    ];
+
+    fn harmonic_mix(self, phase: f32) -> f32 {
+        match self {
+            Self::Bowed => {
+                (phase.sin() + 0.35 * (2.0 * phase).sin() + 0.2 * (3.0 * phase).sin()) / 1.55
+            }
+            Self::Warm => {
+                (phase.sin() + 0.45 * (0.5 * phase).sin() + 0.2 * (2.0 * phase).sin()) / 1.65
+            }
+            Self::Flute => (phase.sin() + 0.1 * (2.0 * phase).sin()) / 1.1,
+            Self::Reed => {
+                (phase.sin() + 0.55 * (3.0 * phase).sin() + 0.3 * (5.0 * phase).sin()) / 1.85
+            }
+            Self::DoubleReed => {
+                (phase.sin()
+                    + 0.6 * (2.0 * phase).sin()
+                    + 0.5 * (3.0 * phase).sin()
+                    + 0.3 * (4.0 * phase).sin())
+                    / 2.4
+            }
+            Self::Brass => {
+                (phase.sin() + 0.4 * (2.0 * phase).sin() + 0.25 * (4.0 * phase).sin()) / 1.65
+            }
+            Self::Timpani => ((phase.sin() * 0.8) + (0.27 * (1.6 * phase).sin())) / 1.07,
+            // Partials out of tune with each other, for a pitch barely heard.
+            Self::Drum => {
+                (0.6 * phase.sin() + 0.4 * (2.3 * phase).sin() + 0.3 * (3.7 * phase).sin()) / 1.3
+            }
+            Self::Mallet => {
+                (phase.sin() + 0.25 * (4.0 * phase).sin() + 0.1 * (10.0 * phase).sin()) / 1.35
+            }
+            Self::Plucked => {
+                (phase.sin() + 0.3 * (2.0 * phase).sin() + 0.1 * (3.0 * phase).sin()) / 1.4
+            }
+            Self::Organ => {
+                (phase.sin()
+                    + 0.5 * (2.0 * phase).sin()
+                    + 0.35 * (4.0 * phase).sin()
+                    + 0.25 * (8.0 * phase).sin())
+                    / 2.1
+            }
+            Self::Piano => {
+                (phase.sin() + 0.5 * (2.0 * phase).sin() + 0.3 * (4.0 * phase).sin()) / 1.8
+            }
+            Self::Voice => {
+                (phase.sin() + 0.3 * (2.0 * phase).sin() + 0.15 * (3.0 * phase).sin()) / 1.45
+            }
         }
     }
 }
 
-fn envelope(t: f32, total: f32) -> f32 {
+/// Rises over the attack, holds, and dies away over `release` seconds
+/// before `total`; silent after it.
+fn envelope(t: f32, total: f32, release: f32) -> f32 {
     let attack = 0.02_f32.min(total * 0.25);
-    let release_start = (total - 0.08).max(total * 0.7);
+    let release_start = (total - release).max(total * 0.7);
 
-    if t < attack {
+    if t >= total {
+        0.0
+    } else if t < attack {
         (t / attack).clamp(0.0, 1.0)
     } else if t > release_start {
         ((total - t) / (total - release_start)).clamp(0.0, 1.0)
@@ -161,3 +454,123 @@ fn envelope(t: f32, total: f32) -> f32 {
         0.9
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::music::{Navigation, NavigationMark, Pitch, PitchClass};
+
+    fn quarter(instrument: Instrument) -> NoteEvent {
+        NoteEvent::new(
+            Pitch::new(PitchClass::A, 4),
+            DurationValue::Quarter,
+            instrument,
+        )
+    }
+
+    #[test]
+    fn fermata_holds_every_voice_sounding_with_it() {
+        let mut held = quarter(Instrument::FLUTE);
+        held.articulations.push(Articulation::Fermata);
+        let streams = vec![
+            vec![held, quarter(Instrument::FLUTE)],
+            vec![NoteEvent {
+                duration: DurationValue::Half,
+                ..quarter(Instrument::CELLO)
+            }],
+        ];
+        let settings = ScoreSettings {
+            bpm: 60.0,
+            ..ScoreSettings::default()
+        };
+        let timeline = Timeline::new(&settings, &streams, 1.0);
+
+        let second = SAMPLE_RATE as usize;
+        assert_eq!(sample_at(&timeline, 1.0), 2 * second);
+        assert_eq!(sample_at(&timeline, 2.0), 3 * second);
+        assert_eq!(render_stream(&streams[1], &timeline).len(), 3 * second);
+    }
+
+    #[test]
+    fn staccato_falls_silent_halfway_and_accent_starts_louder() {
+        let plain = quarter(Instrument::FLUTE);
+        let mut short = plain.clone();
+        short.articulations.push(Articulation::Staccato);
+        let mut accented = plain.clone();
+        accented.articulations.push(Articulation::Accent);
+
+        let count = SAMPLE_RATE as usize / 2;
+        let peak = |samples: &[f32]| samples.iter().fold(0.0_f32, |peak, s| peak.max(s.abs()));
+        let short_samples = synthesize_note(&short, count, 0.35, 0.35);
+        assert!(peak(&short_samples[count / 2..]) == 0.0);
+        assert!(peak(&synthesize_note(&plain, count, 0.35, 0.35)[count / 2..]) > 0.1);
+
+        let start = |note: &NoteEvent| peak(&synthesize_note(note, count, 0.35, 0.35)[..2000]);
+        assert!(start(&accented) > start(&plain) * 1.3);
+    }
+
+    #[test]
+    fn wav_export_plays_the_repeat() {
+        let settings = ScoreSettings {
+            bpm: 60.0,
+            navigation: vec![NavigationMark {
+                measure: 0,
+                navigation: Navigation::RepeatEnd { times: 2 },
+            }],
+            ..ScoreSettings::default()
+        };
+        let score = Score {
+            notes: vec![NoteEvent {
+                duration: DurationValue::Whole,
+                ..quarter(Instrument::FLUTE)
+            }],
+        };
+        let bytes = |repeats| {
+            export_wav(
+                &score,
+                &settings,
+                PlaybackOptions {
+                    repeats,
+                    ..PlaybackOptions::default()
+                },
+            )
+        };
+
+        let played = bytes(true);
+        assert_eq!(&played[..4], b"RIFF");
+        assert_eq!(&played[8..16], b"WAVEfmt ");
+        // Eight seconds of 16-bit samples after the 44-byte header.
+        assert_eq!(played.len(), 44 + 8 * SAMPLE_RATE as usize * 2);
+        assert_eq!(bytes(false).len(), 44 + 4 * SAMPLE_RATE as usize * 2);
+    }
+
+    #[test]
+    fn chord_symbols_sound_only_when_asked_for() {
+        let settings = ScoreSettings {
+            bpm: 60.0,
+            ..ScoreSettings::default()
+        };
+        let score = Score {
+            notes: vec![NoteEvent {
+                chord: Some("Cmaj7".parse().unwrap()),
+                ..NoteEvent::rest(DurationValue::Whole, Instrument::FLUTE)
+            }],
+        };
+        let render = |chords| {
+            render_score(
+                &score,
+                &settings,
+                PlaybackOptions {
+                    chords,
+                    ..PlaybackOptions::default()
+                },
+            )
+        };
+        let peak = |samples: &[f32]| samples.iter().fold(0.0_f32, |peak, s| peak.max(s.abs()));
+
+        assert_eq!(peak(&render(false)), 0.0);
+        let voiced = render(true);
+        assert_eq!(voiced.len(), 4 * SAMPLE_RATE as usize);
+        assert!(peak(&voiced) > 0.1);
+    }
+}
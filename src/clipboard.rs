@@ -0,0 +1,452 @@
+//! Copying and pasting passages of music.
+//!
+//! On the system clipboard a passage travels as an `NTR2` document (see
+//! [`crate::ntr`]) whose staves are the copied ones, so another Notarium
+//! window reads it back with the regular file parser.
+
+use std::collections::BTreeSet;
+
+use crate::layout;
+use crate::measures::MeasureMap;
+use crate::music::{NoteEvent, Part, Score, ScoreSettings, Staff, TimeSignature};
+use crate::ntr::{self, NtrError};
+use crate::rebar;
+use crate::selection::Selection;
+
+/// Marks clipboard text written by [`Passage::to_text`]; readers ignore it as a comment.
+const PASSAGE_MARKER: &str = "# notarium-passage";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PassageStaff {
+    pub part: Part,
+    pub events: Vec<NoteEvent>,
+}
+
+/// Copied music: one event list per staff, top staff first.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Passage {
+    pub staves: Vec<PassageStaff>,
+}
+
+/// Result of [`paste`], ready to be applied as a single edit.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PasteOutcome {
+    pub notes: Vec<NoteEvent>,
+    /// Staves actually written; the rest of the passage fell below the last staff.
+    pub staves: usize,
+    /// Where the longest pasted staff ends, in beats from the start of the score.
+    pub end_beat: f32,
+}
+
+impl Passage {
+    /// Copies the selected events. A measure range keeps its empty staves so
+    /// the passage pastes back with the same vertical spacing. A selection
+    /// of single notes is copied from the first selected beat on: events
+    /// left out between the selected ones become rests, so the passage
+    /// keeps its rhythm.
+    pub fn copy(
+        score: &Score,
+        parts: &[Part],
+        measures: &MeasureMap,
+        selection: &Selection,
+    ) -> Option<Self> {
+        let selected: BTreeSet<usize> = selection
+            .event_indices(score, parts, measures)
+            .into_iter()
+            .collect();
+        let locations = layout::locate_events(score, parts, measures);
+        let start_of = |index: usize| {
+            locations[index].map(|location| measures.start(location.measure) + location.beat)
+        };
+
+        let (staves, from_beat): (BTreeSet<usize>, f32) = match selection.range() {
+            Some(range) => (
+                (range.first_staff..=range.last_staff.min(parts.len().checked_sub(1)?)).collect(),
+                measures.start(range.first_measure),
+            ),
+            None => (
+                selected
+                    .iter()
+                    .filter_map(|index| locations[*index].map(|location| location.staff))
+                    .collect(),
+                selected
+                    .iter()
+                    .filter_map(|index| start_of(*index))
+                    .reduce(f32::min)?,
+            ),
+        };
+        let first = *staves.first()?;
+        let last = *staves.last()?;
+
+        let staves = (first..=last)
+            .map(|staff| PassageStaff {
+                part: parts[staff],
+                events: (0..NoteEvent::VOICES)
+                    .flat_map(|voice| {
+                        let indices: Vec<usize> = (0..score.notes.len())
+                            .filter(|index| {
+                                score.notes[*index].voice == voice
+                                    && locations[*index].is_some_and(|l| l.staff == staff)
+                            })
+                            .collect();
+                        let span = indices
+                            .iter()
+                            .position(|index| selected.contains(index))
+                            .zip(indices.iter().rposition(|index| selected.contains(index)));
+                        let Some((first, last)) = span else {
+                            return Vec::new();
+                        };
+
+                        // Voices that start later still line up with the others.
+                        let gap = start_of(indices[first]).unwrap_or(from_beat) - from_beat;
+                        let mut events =
+                            rebar::rests(parts[staff], voice, from_beat, gap, measures);
+                        events.extend(indices[first..=last].iter().map(|index| {
+                            let note = &score.notes[*index];
+                            if selected.contains(index) {
+                                note.clone()
+                            } else {
+                                rest_for(note)
+                            }
+                        }));
+                        rebar::clear_dangling_ties(&mut events);
+                        events
+                    })
+                    .collect(),
+            })
+            .collect();
+
+        Some(Self { staves })
+    }
+
+    pub fn to_text(&self, time_signature: TimeSignature) -> String {
+        let settings = ScoreSettings {
+            time_signature,
+            staves: self
+                .staves
+                .iter()
+                .map(|staff| Staff::new(staff.part))
+                .collect(),
+            ..ScoreSettings::default()
+        };
+        let score = Score {
+            notes: self
+                .staves
+                .iter()
+                .flat_map(|staff| staff.events.iter().cloned())
+                .collect(),
+        };
+        let document = ntr::serialize_ntr(&settings, &score);
+        match document.split_once('\n') {
+            Some((header, rest)) => format!("{header}\n{PASSAGE_MARKER}\n{rest}"),
+            None => document,
+        }
+    }
+
+    pub fn from_text(text: &str) -> Result<Self, NtrError> {
+        let document = ntr::deserialize_ntr(text)?;
+        Ok(Self {
+            staves: document
+                .settings
+                .parts()
+                .into_iter()
+                .map(|part| PassageStaff {
+                    part,
+                    events: rebar::staff_stream(&document.score.notes, part),
+                })
+                .collect(),
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.staves.iter().all(|staff| staff.events.is_empty())
+    }
+}
+
+/// Overwrites the passage into the score starting at `start_beat` of
+/// `target_staff`, one passage staff per score staff downwards. Each staff is
+/// re-barred, and music moved to a different instrument is shifted by octaves
+/// to sit on that instrument's clef.
+pub fn paste(
+    score: &Score,
+    parts: &[Part],
+    measures: &MeasureMap,
+    passage: &Passage,
+    target_staff: usize,
+    start_beat: f32,
+) -> PasteOutcome {
+    let mut notes = score.notes.clone();
+    let mut staves = 0;
+    let mut end_beat = start_beat;
+
+    for (offset, staff) in passage.staves.iter().enumerate() {
+        let Some(&part) = parts.get(target_staff + offset) else {
+            break;
+        };
+        staves += 1;
+
+        let octaves =
+            if staff.part.instrument == part.instrument && staff.part.clef() == part.clef() {
+                0
+            } else {
+                octave_shift(&staff.events, part)
+            };
+        let insert: Vec<NoteEvent> = staff
+            .events
+            .iter()
+            .map(|event| {
+                let mut event = event.clone();
+                event.set_part(part);
+                if !event.rest {
+                    event.pitch.octave += octaves;
+                }
+                event
+            })
+            .collect();
+
+        // Each voice is pasted over the same voice, all from `start_beat`.
+        for voice in 0..NoteEvent::VOICES {
+            let mut insert: Vec<NoteEvent> = insert
+                .iter()
+                .filter(|event| event.voice == voice)
+                .cloned()
+                .collect();
+            // A tie copied out of the middle of a chain would lead nowhere.
+            let Some(last) = insert.last_mut() else {
+                continue;
+            };
+            last.tie = false;
+
+            let length: f32 = insert.iter().map(NoteEvent::beats).sum();
+            end_beat = end_beat.max(start_beat + length);
+
+            let stream = rebar::voice_stream(&notes, part, voice);
+            let stream = rebar::overwrite(&stream, part, start_beat, &insert, measures);
+            notes = rebar::replace_stream(&notes, part, voice, stream);
+        }
+    }
+
+    PasteOutcome {
+        notes,
+        staves,
+        end_beat,
+    }
+}
+
+/// Turns the given events into rests of the same length, as cutting does.
+/// Event order is kept so indices into the score stay valid.
+pub fn silence(score: &Score, indices: &[usize]) -> Vec<NoteEvent> {
+    let mut notes = score.notes.clone();
+    for index in indices {
+        if let Some(note) = notes.get_mut(*index) {
+            *note = rest_for(note);
+        }
+    }
+
+    rebar::clear_broken_ties(&mut notes);
+    notes
+}
+
+/// A rest as long as `note`, in its part and voice.
+fn rest_for(note: &NoteEvent) -> NoteEvent {
+    NoteEvent {
+        dots: note.dots,
+        voice: note.voice,
+        ..NoteEvent::rest(note.duration, note.part())
+    }
+}
+
+/// Whole octaves that bring the passage's average pitch, as `part`'s
+/// instrument reads it, closest to the middle line of the clef of `part`.
+fn octave_shift(events: &[NoteEvent], part: Part) -> i8 {
+    let steps: Vec<i32> = events
+        .iter()
+        .filter(|event| !event.rest)
+        .map(|event| part.instrument.written_pitch(event.pitch).diatonic_number())
+        .collect();
+    if steps.is_empty() {
+        return 0;
+    }
+
+    let average = steps.iter().sum::<i32>() as f32 / steps.len() as f32;
+    let middle_line = part.clef().bottom_line().diatonic_number() + 4;
+    ((middle_line as f32 - average) / 7.0).round() as i8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::music::{DurationValue, Instrument, Pitch, PitchClass};
+
+    fn quarter(class: PitchClass, octave: i8, instrument: Instrument) -> NoteEvent {
+        NoteEvent::new(
+            Pitch::new(class, octave),
+            DurationValue::Quarter,
+            instrument,
+        )
+    }
+
+    fn four_four() -> MeasureMap {
+        MeasureMap::new(TimeSignature::FourFour, &[])
+    }
+
+    fn two_staff_score() -> Score {
+        let mut notes: Vec<NoteEvent> = [PitchClass::C, PitchClass::D, PitchClass::E]
+            .into_iter()
+            .flat_map(|class| (0..4).map(move |_| quarter(class, 5, Instrument::VIOLIN)))
+            .collect();
+        notes.extend((0..8).map(|_| quarter(PitchClass::C, 3, Instrument::CELLO)));
+        Score { notes }
+    }
+
+    #[test]
+    fn copies_a_measure_range_across_staves() {
+        let score = two_staff_score();
+        let parts = [Instrument::VIOLIN, Instrument::CELLO].map(Part::from);
+        let mut selection = Selection::default();
+        selection.select_measure(0, 1);
+        selection.extend_to(1, 1);
+
+        let passage = Passage::copy(&score, &parts, &four_four(), &selection)
+            .expect("selection is not empty");
+
+        assert_eq!(passage.staves.len(), 2);
+        assert_eq!(passage.staves[0].events.len(), 4);
+        assert!(passage.staves[0]
+            .events
+            .iter()
+            .all(|event| event.pitch.class == PitchClass::D));
+        assert_eq!(passage.staves[1].events.len(), 4);
+    }
+
+    #[test]
+    fn copied_notes_keep_the_rests_between_them() {
+        let score = two_staff_score();
+        let parts = [Instrument::VIOLIN, Instrument::CELLO].map(Part::from);
+        let mut selection = Selection::default();
+        selection.select_notes([0, 2], false);
+
+        let passage = Passage::copy(&score, &parts, &four_four(), &selection)
+            .expect("selection is not empty");
+        assert_eq!(passage.staves.len(), 1);
+        let outcome = paste(&score, &parts, &four_four(), &passage, 0, 8.0);
+
+        let violin = rebar::staff_stream(&outcome.notes, Instrument::VIOLIN.into());
+        let third_measure: Vec<(bool, PitchClass)> = violin[8..]
+            .iter()
+            .map(|event| (event.rest, event.pitch.class))
+            .collect();
+        assert_eq!(
+            third_measure,
+            [
+                (false, PitchClass::C),
+                (true, PitchClass::B),
+                (false, PitchClass::C),
+                (false, PitchClass::E),
+            ]
+        );
+    }
+
+    #[test]
+    fn passage_text_round_trips() {
+        let passage = Passage {
+            staves: vec![
+                PassageStaff {
+                    part: Instrument::FLUTE.into(),
+                    events: vec![
+                        quarter(PitchClass::G, 5, Instrument::FLUTE),
+                        NoteEvent::rest(DurationValue::Half, Instrument::FLUTE),
+                    ],
+                },
+                PassageStaff {
+                    part: Part {
+                        player: 1,
+                        ..Instrument::FLUTE.into()
+                    },
+                    events: Vec::new(),
+                },
+            ],
+        };
+
+        let text = passage.to_text(TimeSignature::ThreeFour);
+
+        assert!(text.starts_with("NTR2\n# notarium-passage\n"));
+        assert_eq!(Passage::from_text(&text), Ok(passage));
+        assert!(Passage::from_text("texto qualquer").is_err());
+    }
+
+    #[test]
+    fn paste_rebars_and_moves_octaves_for_other_instruments() {
+        let score = two_staff_score();
+        let parts = [Instrument::VIOLIN, Instrument::CELLO].map(Part::from);
+        let passage = Passage {
+            staves: vec![PassageStaff {
+                part: Instrument::VIOLIN.into(),
+                events: vec![NoteEvent::new(
+                    Pitch::new(PitchClass::A, 4),
+                    DurationValue::Half,
+                    Instrument::VIOLIN,
+                )],
+            }],
+        };
+
+        let outcome = paste(&score, &parts, &four_four(), &passage, 1, 7.0);
+
+        assert_eq!(outcome.staves, 1);
+        assert!((outcome.end_beat - 9.0).abs() < 1e-4);
+        let cello = rebar::staff_stream(&outcome.notes, Instrument::CELLO.into());
+        assert_eq!(cello.len(), 8);
+        assert_eq!(cello[7].pitch, Pitch::new(PitchClass::A, 2));
+        assert_eq!(cello[7].duration, DurationValue::Half);
+        assert_eq!(
+            rebar::staff_stream(&outcome.notes, Instrument::VIOLIN.into()),
+            rebar::staff_stream(&score.notes, Instrument::VIOLIN.into())
+        );
+    }
+
+    #[test]
+    fn octave_transposing_instruments_paste_by_their_written_pitch() {
+        let piccolo = Instrument::from_id("Piccolo").unwrap();
+        let score = Score {
+            notes: vec![NoteEvent::rest(DurationValue::Whole, piccolo)],
+        };
+        let passage = Passage {
+            staves: vec![PassageStaff {
+                part: Instrument::FLUTE.into(),
+                events: vec![quarter(PitchClass::C, 5, Instrument::FLUTE)],
+            }],
+        };
+
+        let outcome = paste(&score, &[piccolo.into()], &four_four(), &passage, 0, 0.0);
+
+        // Written C5 on the piccolo staff, as the flute read it.
+        assert_eq!(outcome.notes[0].pitch, Pitch::new(PitchClass::C, 6));
+        assert_eq!(
+            piccolo.written_pitch(outcome.notes[0].pitch),
+            Pitch::new(PitchClass::C, 5)
+        );
+    }
+
+    #[test]
+    fn cutting_leaves_rests_of_the_same_length() {
+        let score = Score {
+            notes: vec![
+                NoteEvent {
+                    tie: true,
+                    ..quarter(PitchClass::C, 5, Instrument::VIOLIN)
+                },
+                NoteEvent {
+                    dots: 1,
+                    ..quarter(PitchClass::C, 5, Instrument::VIOLIN)
+                },
+            ],
+        };
+
+        let notes = silence(&score, &[1]);
+
+        assert!(!notes[0].tie);
+        assert!(notes[1].rest);
+        assert_eq!(notes[1].dots, 1);
+        assert!((Score { notes }.total_beats() - score.total_beats()).abs() < 1e-4);
+    }
+}
@@ -0,0 +1,188 @@
+//! Dynamic markings and hairpins: putting them on a selection and turning
+//! them into playback levels.
+
+use std::ops::Range;
+
+use crate::music::{Dynamic, Hairpin, NoteEvent, Part};
+
+/// Writes `dynamic` under the first of the given events in each voice, or
+/// clears the markings of all of them when `dynamic` is `None`.
+pub fn set_dynamic(
+    notes: &[NoteEvent],
+    indices: &[usize],
+    dynamic: Option<Dynamic>,
+) -> Vec<NoteEvent> {
+    let mut notes = notes.to_vec();
+    let mut marked: Vec<(Part, u8)> = Vec::new();
+    for index in indices {
+        let Some(note) = notes.get_mut(*index) else {
+            continue;
+        };
+        if dynamic.is_none() {
+            note.dynamic = None;
+        } else if !marked.contains(&(note.part(), note.voice)) {
+            marked.push((note.part(), note.voice));
+            note.dynamic = dynamic;
+        }
+    }
+    notes
+}
+
+/// Puts the given events under `hairpin`, or takes them out of their wedge.
+pub fn set_hairpin(
+    notes: &[NoteEvent],
+    indices: &[usize],
+    hairpin: Option<Hairpin>,
+) -> Vec<NoteEvent> {
+    let mut notes = notes.to_vec();
+    for index in indices {
+        if let Some(note) = notes.get_mut(*index) {
+            note.hairpin = hairpin;
+        }
+    }
+    notes
+}
+
+/// Where each wedge of `stream` starts and ends, as index ranges. A marking
+/// inside a run of events under the same hairpin starts a new wedge there.
+pub fn wedges(stream: &[NoteEvent]) -> Vec<(Hairpin, Range<usize>)> {
+    let mut wedges: Vec<(Hairpin, Range<usize>)> = Vec::new();
+    for (index, event) in stream.iter().enumerate() {
+        let Some(hairpin) = event.hairpin else {
+            continue;
+        };
+        match wedges.last_mut() {
+            Some((open, run))
+                if *open == hairpin && run.end == index && event.dynamic.is_none() =>
+            {
+                run.end = index + 1;
+            }
+            _ => wedges.push((hairpin, index..index + 1)),
+        }
+    }
+    wedges
+}
+
+/// Amplitude at the start and at the end of each event of one voice's
+/// stream. Markings set the level from their event on; a wedge ramps to the
+/// marking right after it, or one step past its starting level without one.
+pub fn levels(stream: &[NoteEvent]) -> Vec<(f32, f32)> {
+    let mut out = Vec::with_capacity(stream.len());
+    let mut level = Dynamic::Mf.gain();
+    let mut wedges = wedges(stream).into_iter().peekable();
+
+    let mut index = 0;
+    while index < stream.len() {
+        let event = &stream[index];
+        let accent = event.dynamic.filter(|dynamic| dynamic.is_accent());
+        if let Some(dynamic) = event.dynamic.filter(|dynamic| !dynamic.is_accent()) {
+            level = dynamic.gain();
+        }
+
+        let Some((hairpin, run)) = wedges.next_if(|(_, run)| run.start == index) else {
+            out.push((accent.map_or(level, Dynamic::gain), level));
+            index += 1;
+            continue;
+        };
+
+        let target = stream
+            .get(run.end)
+            .and_then(|next| next.dynamic)
+            .filter(|dynamic| !dynamic.is_accent())
+            .map_or_else(|| step(level, hairpin), Dynamic::gain);
+        let total: f32 = stream[run.clone()].iter().map(NoteEvent::beats).sum();
+        let mut elapsed = 0.0;
+        for event in &stream[run.clone()] {
+            let from = level + (target - level) * elapsed / total;
+            elapsed += event.beats();
+            out.push((from, level + (target - level) * elapsed / total));
+        }
+        level = target;
+        index = run.end;
+    }
+
+    out
+}
+
+/// The next marked level louder or softer than `level`.
+fn step(level: f32, hairpin: Hairpin) -> f32 {
+    let gains = Dynamic::ALL
+        .into_iter()
+        .filter(|dynamic| !dynamic.is_accent())
+        .map(Dynamic::gain);
+    match hairpin {
+        Hairpin::Crescendo => gains
+            .filter(|gain| *gain > level + 1e-3)
+            .reduce(f32::min)
+            .unwrap_or(level),
+        Hairpin::Diminuendo => gains
+            .filter(|gain| *gain < level - 1e-3)
+            .reduce(f32::max)
+            .unwrap_or(level),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::music::{DurationValue, Instrument, Pitch, PitchClass};
+
+    fn quarter() -> NoteEvent {
+        NoteEvent::new(
+            Pitch::new(PitchClass::C, 4),
+            DurationValue::Quarter,
+            Instrument::VIOLIN,
+        )
+    }
+
+    fn close(a: f32, b: f32) -> bool {
+        (a - b).abs() < 1e-4
+    }
+
+    #[test]
+    fn hairpin_ramps_to_the_marking_after_it() {
+        let mut stream = vec![quarter(); 4];
+        stream[0].dynamic = Some(Dynamic::P);
+        stream[0].hairpin = Some(Hairpin::Crescendo);
+        stream[1].hairpin = Some(Hairpin::Crescendo);
+        stream[2].dynamic = Some(Dynamic::F);
+
+        let levels = levels(&stream);
+        let (p, f) = (Dynamic::P.gain(), Dynamic::F.gain());
+        assert!(close(levels[0].0, p));
+        assert!(close(levels[0].1, (p + f) / 2.0));
+        assert!(close(levels[1].1, f));
+        assert!(close(levels[2].0, f) && close(levels[3].1, f));
+    }
+
+    #[test]
+    fn unfinished_hairpin_goes_one_step_and_sforzando_accents_one_note() {
+        let mut stream = vec![quarter(); 3];
+        stream[0].hairpin = Some(Hairpin::Diminuendo);
+        stream[2].dynamic = Some(Dynamic::Sfz);
+
+        let levels = levels(&stream);
+        assert!(close(levels[0].0, Dynamic::Mf.gain()));
+        assert!(close(levels[0].1, Dynamic::Mp.gain()));
+        assert!(close(levels[1].0, Dynamic::Mp.gain()));
+        assert!(close(levels[2].0, Dynamic::Sfz.gain()));
+        assert!(close(levels[2].1, Dynamic::Mp.gain()));
+    }
+
+    #[test]
+    fn dynamic_marks_the_first_selected_event_of_each_voice() {
+        let lower = NoteEvent {
+            voice: 1,
+            ..quarter()
+        };
+        let notes = vec![quarter(), lower.clone(), quarter(), lower];
+
+        let marked = set_dynamic(&notes, &[0, 1, 2, 3], Some(Dynamic::Ff));
+        let dynamics: Vec<_> = marked.iter().map(|note| note.dynamic).collect();
+        assert_eq!(dynamics, [Some(Dynamic::Ff), Some(Dynamic::Ff), None, None]);
+
+        let cleared = set_dynamic(&marked, &[1], None);
+        assert_eq!(cleared[0].dynamic, Some(Dynamic::Ff));
+        assert_eq!(cleared[1].dynamic, None);
+    }
+}
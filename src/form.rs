@@ -0,0 +1,310 @@
+//! The form of a score: the order its measures are played in once repeats,
+//! endings and D.C./D.S. jumps are followed, and the score written out in
+//! that order for playback and export.
+
+use crate::measures::MeasureMap;
+use crate::music::{
+    DurationValue, Meter, MeterChange, Navigation, NavigationMark, NoteEvent, Part, Score,
+    ScoreSettings, Until, BEAT_EPSILON,
+};
+use crate::rebar;
+
+/// Longest performance the form will unroll, in measures, so marks that
+/// send the music round in circles still end.
+const MAX_MEASURES: usize = 10_000;
+
+/// Measures of a score `measure_count` long in the order they are played.
+/// Without `repeats`, repeat barlines are not taken and only the last
+/// ending of each set is played. Repeats are never taken after a D.C. or
+/// D.S., and a jump is followed once.
+pub fn play_order(marks: &[NavigationMark], measure_count: usize, repeats: bool) -> Vec<usize> {
+    let on = |measure: usize| {
+        marks
+            .iter()
+            .filter(move |mark| mark.measure == measure)
+            .map(|mark| mark.navigation)
+    };
+    let ending = |measure: usize| {
+        on(measure).find_map(|navigation| match navigation {
+            Navigation::Ending { passes } => Some(passes),
+            _ => None,
+        })
+    };
+    // The last ending of a set is the one no other ending follows on from.
+    let last_ending = |measure: usize, passes: u8| {
+        (measure + 1..measure_count)
+            .map_while(ending)
+            .all(|next| next == passes)
+    };
+
+    let mut order = Vec::new();
+    let mut section_start = 0;
+    let mut pass: u8 = 1;
+    let mut returning = false;
+    let mut jump: Option<Until> = None;
+    let mut measure = 0;
+
+    while measure < measure_count && order.len() < MAX_MEASURES {
+        let repeating = repeats && jump.is_none();
+        match ending(measure) {
+            Some(passes) => {
+                let taken = if repeating {
+                    passes & (1 << (pass - 1).min(7)) != 0
+                } else {
+                    last_ending(measure, passes)
+                };
+                if !taken {
+                    measure += 1;
+                    continue;
+                }
+            }
+            // The music after a set of endings starts a new section.
+            None if !returning && measure > 0 && ending(measure - 1).is_some() => {
+                section_start = measure;
+                pass = 1;
+            }
+            None => {}
+        }
+        if !returning && on(measure).any(|navigation| navigation == Navigation::RepeatStart) {
+            section_start = measure;
+            pass = 1;
+        }
+        returning = false;
+        order.push(measure);
+
+        if matches!(jump, Some(Until::Fine | Until::End))
+            && on(measure).any(|navigation| navigation == Navigation::Fine)
+        {
+            break;
+        }
+        if jump == Some(Until::Coda)
+            && on(measure).any(|navigation| navigation == Navigation::ToCoda)
+        {
+            match marks
+                .iter()
+                .find(|mark| mark.measure > measure && mark.navigation == Navigation::Coda)
+            {
+                Some(coda) => measure = coda.measure,
+                None => break,
+            }
+            continue;
+        }
+        if let Some(times) = on(measure).find_map(|navigation| match navigation {
+            Navigation::RepeatEnd { times } => Some(times),
+            _ => None,
+        }) {
+            if repeating && pass < times {
+                pass += 1;
+                measure = section_start;
+                returning = true;
+                continue;
+            }
+            section_start = measure + 1;
+            pass = 1;
+        }
+        if jump.is_none() {
+            let target = on(measure).find_map(|navigation| match navigation {
+                Navigation::DaCapo(until) => Some((until, 0)),
+                Navigation::DalSegno(until) => {
+                    let segno = marks
+                        .iter()
+                        .find(|mark| mark.navigation == Navigation::Segno)
+                        .map_or(0, |mark| mark.measure);
+                    Some((until, segno))
+                }
+                _ => None,
+            });
+            if let Some((until, target)) = target {
+                jump = Some(until);
+                measure = target;
+                continue;
+            }
+        }
+        measure += 1;
+    }
+
+    order
+}
+
+/// The score written out as it is played, with the meters and tempo marks
+/// of each measure carried along and no repeats or jumps left in it.
+pub fn unroll(settings: &ScoreSettings, score: &Score, repeats: bool) -> (ScoreSettings, Score) {
+    let measures = settings.measures();
+    let mut voices: Vec<(Part, u8)> = Vec::new();
+    for note in &score.notes {
+        if !voices.contains(&(note.part(), note.voice)) {
+            voices.push((note.part(), note.voice));
+        }
+    }
+    let streams: Vec<Vec<NoteEvent>> = voices
+        .into_iter()
+        .map(|(part, voice)| rebar::voice_stream(&score.notes, part, voice))
+        .collect();
+    let length = streams
+        .iter()
+        .map(|stream| stream.iter().map(NoteEvent::beats).sum::<f32>())
+        .fold(0.0, f32::max);
+    let count = measures.count(length);
+
+    let order = play_order(&settings.navigation, count, repeats);
+    if order.iter().copied().eq(0..count) {
+        return (
+            ScoreSettings {
+                navigation: Vec::new(),
+                ..settings.clone()
+            },
+            score.clone(),
+        );
+    }
+
+    let mut unrolled = ScoreSettings {
+        meter_changes: Vec::new(),
+        tempo_marks: Vec::new(),
+        navigation: Vec::new(),
+        ..settings.clone()
+    };
+    let mut meter = Meter::from(settings.time_signature);
+    for (index, measure) in order.iter().enumerate() {
+        if measures.meter(*measure) != meter {
+            meter = measures.meter(*measure);
+            unrolled.meter_changes.push(MeterChange {
+                measure: index,
+                meter,
+            });
+        }
+    }
+    let map = unrolled.measures();
+    for (index, measure) in order.iter().enumerate() {
+        let shift = map.start(index) - measures.start(*measure);
+        for mark in &settings.tempo_marks {
+            if measures.locate(mark.beat).0 == *measure {
+                let mut mark = mark.clone();
+                mark.beat += shift;
+                unrolled.tempo_marks.push(mark);
+            }
+        }
+    }
+
+    let notes = streams
+        .iter()
+        .flat_map(|stream| unroll_stream(stream, &measures, &order))
+        .collect();
+    (unrolled, Score { notes })
+}
+
+/// One voice played through `order`. Measures the voice never reaches are
+/// filled with rests, and ties into a measure that is not played next are
+/// let go.
+fn unroll_stream(stream: &[NoteEvent], measures: &MeasureMap, order: &[usize]) -> Vec<NoteEvent> {
+    let Some(first) = stream.first() else {
+        return Vec::new();
+    };
+    let mut bars: Vec<Vec<NoteEvent>> = Vec::new();
+    let mut position = 0.0;
+    for event in rebar::reflow(stream, measures, 0.0, f32::INFINITY) {
+        let (measure, _) = measures.locate(position);
+        position += event.beats();
+        if bars.len() <= measure {
+            bars.resize(measure + 1, Vec::new());
+        }
+        bars[measure].push(event);
+    }
+
+    let rest = NoteEvent {
+        voice: first.voice,
+        ..NoteEvent::rest(DurationValue::Quarter, first.part())
+    };
+    let mut out = Vec::new();
+    for (index, measure) in order.iter().enumerate() {
+        let mut bar = bars.get(*measure).cloned().unwrap_or_default();
+        let missing = measures.length(*measure) - bar.iter().map(NoteEvent::beats).sum::<f32>();
+        if missing > BEAT_EPSILON {
+            bar.extend(rebar::events_for(&rest, missing));
+        }
+        if order.get(index + 1) != Some(&(measure + 1)) {
+            if let Some(last) = bar.last_mut() {
+                last.tie = false;
+            }
+        }
+        out.extend(bar);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::music::{Instrument, Pitch, PitchClass, TempoMark};
+
+    fn mark(measure: usize, navigation: Navigation) -> NavigationMark {
+        NavigationMark {
+            measure,
+            navigation,
+        }
+    }
+
+    #[test]
+    fn repeats_take_each_ending_on_its_pass() {
+        let marks = [
+            mark(0, Navigation::RepeatStart),
+            mark(1, Navigation::Ending { passes: 0b01 }),
+            mark(1, Navigation::RepeatEnd { times: 2 }),
+            mark(2, Navigation::Ending { passes: 0b10 }),
+            mark(3, Navigation::RepeatEnd { times: 3 }),
+        ];
+        assert_eq!(play_order(&marks, 4, true), [0, 1, 0, 2, 3, 3, 3]);
+        assert_eq!(play_order(&marks, 4, false), [0, 2, 3]);
+    }
+
+    #[test]
+    fn da_capo_al_fine_skips_repeats_on_the_way_back() {
+        let marks = [
+            mark(0, Navigation::RepeatEnd { times: 2 }),
+            mark(1, Navigation::Fine),
+            mark(3, Navigation::DaCapo(Until::Fine)),
+        ];
+        assert_eq!(play_order(&marks, 4, true), [0, 0, 1, 2, 3, 0, 1]);
+    }
+
+    #[test]
+    fn dal_segno_al_coda_jumps_to_the_coda() {
+        let marks = [
+            mark(1, Navigation::Segno),
+            mark(2, Navigation::ToCoda),
+            mark(3, Navigation::DalSegno(Until::Coda)),
+            mark(4, Navigation::Coda),
+        ];
+        assert_eq!(play_order(&marks, 5, true), [0, 1, 2, 3, 1, 2, 4]);
+    }
+
+    #[test]
+    fn unrolled_score_repeats_the_music_and_its_tempo_marks() {
+        let settings = ScoreSettings {
+            navigation: vec![mark(0, Navigation::RepeatEnd { times: 2 })],
+            tempo_marks: vec![TempoMark {
+                beat: 0.0,
+                text: "Allegro".to_owned(),
+                bpm: Some(140.0),
+                change: None,
+            }],
+            ..ScoreSettings::default()
+        };
+        let mut note = NoteEvent::new(
+            Pitch::new(PitchClass::G, 4),
+            DurationValue::Whole,
+            Instrument::FLUTE,
+        );
+        note.tie = true;
+        let score = Score {
+            notes: vec![note.clone(), NoteEvent { tie: false, ..note }],
+        };
+
+        let (settings, unrolled) = unroll(&settings, &score, true);
+        assert!(settings.navigation.is_empty());
+        let beats: Vec<f32> = settings.tempo_marks.iter().map(|mark| mark.beat).collect();
+        assert_eq!(beats, [0.0, 4.0]);
+        // Held into the measure after it only the second time through.
+        let ties: Vec<bool> = unrolled.notes.iter().map(|note| note.tie).collect();
+        assert_eq!(ties, [false, true, false]);
+    }
+}
@@ -0,0 +1,714 @@
+//! Chord symbols for lead sheets: reading them from text such as `Cmaj7`,
+//! `F#m7b5/C` or `Bb13sus`, writing them back with superscripts, and the
+//! notes they are voiced with in playback.
+
+use std::fmt;
+use std::str::FromStr;
+
+use crate::music::{NoteEvent, Part, Pitch, PitchClass};
+
+/// A note name without an octave, such as the F♯ of F♯m7.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Tone {
+    pub class: PitchClass,
+    /// Chromatic alteration in semitones, as in [`Pitch`].
+    pub alter: i8,
+}
+
+impl Tone {
+    pub fn new(class: PitchClass, alter: i8) -> Self {
+        Self { class, alter }
+    }
+
+    pub fn of(pitch: Pitch) -> Self {
+        Self::new(pitch.class, pitch.alter)
+    }
+
+    pub fn pitch(self, octave: i8) -> Pitch {
+        Pitch {
+            class: self.class,
+            alter: self.alter,
+            octave,
+        }
+    }
+
+    /// Semitones above C, from 0 to 11.
+    pub fn semitone(self) -> i32 {
+        (self.class.semitone_offset() + i32::from(self.alter)).rem_euclid(12)
+    }
+
+    /// The letter with sharps and flats as they are engraved.
+    pub fn label(self) -> String {
+        let accidental = if self.alter < 0 { "♭" } else { "♯" };
+        format!(
+            "{}{}",
+            self.class.label(),
+            accidental.repeat(self.alter.unsigned_abs().into())
+        )
+    }
+
+    /// The letter with `#` and `b`, as typed.
+    fn text(self) -> String {
+        let accidental = if self.alter < 0 { "b" } else { "#" };
+        format!(
+            "{}{}",
+            self.class.label(),
+            accidental.repeat(self.alter.unsigned_abs().into())
+        )
+    }
+}
+
+/// The triad a chord is built on.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ChordQuality {
+    #[default]
+    Major,
+    Minor,
+    Diminished,
+    Augmented,
+    /// Root and fifth only, as in C5.
+    Power,
+}
+
+impl ChordQuality {
+    fn symbol(self) -> &'static str {
+        match self {
+            Self::Major | Self::Power => "",
+            Self::Minor => "m",
+            Self::Diminished => "°",
+            Self::Augmented => "+",
+        }
+    }
+
+    fn text(self) -> &'static str {
+        match self {
+            Self::Major | Self::Power => "",
+            Self::Minor => "m",
+            Self::Diminished => "dim",
+            Self::Augmented => "aug",
+        }
+    }
+}
+
+/// The highest note stacked on the triad. Sevenths and up imply the
+/// sevenths and extensions under them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Extension {
+    Sixth,
+    /// A sixth with an added ninth, written 6/9.
+    SixNine,
+    Seventh,
+    Ninth,
+    Eleventh,
+    Thirteenth,
+}
+
+impl Extension {
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Sixth => "6",
+            Self::SixNine => "6/9",
+            Self::Seventh => "7",
+            Self::Ninth => "9",
+            Self::Eleventh => "11",
+            Self::Thirteenth => "13",
+        }
+    }
+
+    fn has_seventh(self) -> bool {
+        !matches!(self, Self::Sixth | Self::SixNine)
+    }
+}
+
+/// A second or fourth in place of the third.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Suspension {
+    Second,
+    Fourth,
+}
+
+impl Suspension {
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Second => "sus2",
+            Self::Fourth => "sus4",
+        }
+    }
+}
+
+/// A chord tone raised or lowered, or a note added without the ones under it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Alteration {
+    FlatFifth,
+    SharpFifth,
+    FlatNinth,
+    SharpNinth,
+    SharpEleventh,
+    FlatThirteenth,
+    AddNinth,
+    AddEleventh,
+}
+
+impl Alteration {
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::FlatFifth => "♭5",
+            Self::SharpFifth => "♯5",
+            Self::FlatNinth => "♭9",
+            Self::SharpNinth => "♯9",
+            Self::SharpEleventh => "♯11",
+            Self::FlatThirteenth => "♭13",
+            Self::AddNinth => "add9",
+            Self::AddEleventh => "add11",
+        }
+    }
+
+    fn text(self) -> &'static str {
+        match self {
+            Self::FlatFifth => "b5",
+            Self::SharpFifth => "#5",
+            Self::FlatNinth => "b9",
+            Self::SharpNinth => "#9",
+            Self::SharpEleventh => "#11",
+            Self::FlatThirteenth => "b13",
+            Self::AddNinth => "add9",
+            Self::AddEleventh => "add11",
+        }
+    }
+
+    /// The chord degree it changes and the semitones above the root it
+    /// puts there.
+    pub fn degree(self) -> (u8, i32) {
+        match self {
+            Self::FlatFifth => (5, 6),
+            Self::SharpFifth => (5, 8),
+            Self::FlatNinth => (9, 13),
+            Self::SharpNinth => (9, 15),
+            Self::SharpEleventh => (11, 18),
+            Self::FlatThirteenth => (13, 20),
+            Self::AddNinth => (9, 14),
+            Self::AddEleventh => (11, 17),
+        }
+    }
+}
+
+/// A chord symbol written above the staff, such as F♯m7♭5/C.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChordSymbol {
+    pub root: Tone,
+    pub quality: ChordQuality,
+    pub extension: Option<Extension>,
+    /// The seventh is major, as in Cmaj7 and Cm(maj7).
+    pub major_seventh: bool,
+    pub suspension: Option<Suspension>,
+    /// In the order written.
+    pub alterations: Vec<Alteration>,
+    /// Note in the bass when it is not the root: the C of F♯m7♭5/C.
+    pub bass: Option<Tone>,
+}
+
+/// Why text is not a chord symbol.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChordError {
+    /// The text does not start with a note name from A to G.
+    MissingRoot,
+    /// Something after the root was not understood; holds the rest of the text.
+    Unknown(String),
+}
+
+impl fmt::Display for ChordError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MissingRoot => write!(f, "a cifra deve começar com uma nota de A a G"),
+            Self::Unknown(rest) => write!(f, "\"{rest}\" não é reconhecido na cifra"),
+        }
+    }
+}
+
+impl std::error::Error for ChordError {}
+
+/// Reads a note name and its accidentals from the start of `text`.
+fn parse_tone(text: &str) -> Option<(Tone, &str)> {
+    let mut chars = text.chars();
+    let class = match chars.next()? {
+        'C' => PitchClass::C,
+        'D' => PitchClass::D,
+        'E' => PitchClass::E,
+        'F' => PitchClass::F,
+        'G' => PitchClass::G,
+        'A' => PitchClass::A,
+        'B' => PitchClass::B,
+        _ => return None,
+    };
+    let mut rest = chars.as_str();
+    let mut alter = 0_i8;
+    while alter.abs() < 2 {
+        let Some(ch) = rest.chars().next() else {
+            break;
+        };
+        let step = match ch {
+            '#' | '♯' => 1,
+            'b' | '♭' => -1,
+            _ => break,
+        };
+        if alter != 0 && alter.signum() != step {
+            break;
+        }
+        alter += step;
+        rest = &rest[ch.len_utf8()..];
+    }
+    Some((Tone::new(class, alter), rest))
+}
+
+/// Takes the first of `prefixes` that `text` starts with.
+fn strip_any<'a>(text: &'a str, prefixes: &[&str]) -> Option<&'a str> {
+    prefixes.iter().find_map(|prefix| text.strip_prefix(prefix))
+}
+
+/// `text` after a major-seventh sign; "madd9" is minor with an added ninth,
+/// not "ma" and "dd9".
+fn strip_major_seventh(text: &str) -> Option<&str> {
+    strip_any(text, &["maj", "Maj", "ma", "M", "Δ"]).filter(|after| !after.starts_with("dd"))
+}
+
+impl FromStr for ChordSymbol {
+    type Err = ChordError;
+
+    /// Reads the usual spellings: `maj`, `M` or `Δ` for a major seventh;
+    /// `m`, `min` or `-` for minor; `dim` or `°`, `aug` or `+`, and `ø` for
+    /// half-diminished; `sus` (a fourth) and `add`. Parentheses, commas and
+    /// spaces around the alterations are ignored.
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let (root, rest) = parse_tone(text.trim()).ok_or(ChordError::MissingRoot)?;
+        let mut chord = Self::triad(root, ChordQuality::Major);
+
+        // Only a note name after the last slash is a bass; 6/9 is not.
+        let mut suffix = rest;
+        if let Some((head, tail)) = rest.rsplit_once('/') {
+            if let Some((bass, "")) = parse_tone(tail.trim()) {
+                chord.bass = Some(bass);
+                suffix = head;
+            }
+        }
+        let suffix: String = suffix
+            .chars()
+            .filter(|ch| !matches!(ch, '(' | ')' | ',' | ' '))
+            .collect();
+        let unknown = |rest: &str| ChordError::Unknown(rest.to_owned());
+        let mut rest = suffix.as_str();
+
+        if let Some(after) = strip_major_seventh(rest) {
+            chord.major_seventh = true;
+            rest = after;
+        } else if let Some(after) = strip_any(rest, &["min", "mi", "m", "-"]) {
+            chord.quality = ChordQuality::Minor;
+            rest = after;
+            if let Some(after) = strip_major_seventh(rest) {
+                chord.major_seventh = true;
+                rest = after;
+            }
+        } else if let Some(after) = strip_any(rest, &["dim", "°", "o"]) {
+            chord.quality = ChordQuality::Diminished;
+            rest = after;
+        } else if let Some(after) = strip_any(rest, &["aug", "+"]) {
+            chord.quality = ChordQuality::Augmented;
+            rest = after;
+        } else if let Some(after) = strip_any(rest, &["ø", "Ø"]) {
+            chord.quality = ChordQuality::Minor;
+            chord.extension = Some(Extension::Seventh);
+            chord.alterations.push(Alteration::FlatFifth);
+            rest = after.strip_prefix('7').unwrap_or(after);
+        }
+
+        let extensions = [
+            ("6/9", Extension::SixNine),
+            ("69", Extension::SixNine),
+            ("13", Extension::Thirteenth),
+            ("11", Extension::Eleventh),
+            ("9", Extension::Ninth),
+            ("7", Extension::Seventh),
+            ("6", Extension::Sixth),
+        ];
+        if let Some((after, extension)) = extensions
+            .iter()
+            .find_map(|(text, extension)| Some((rest.strip_prefix(text)?, *extension)))
+        {
+            chord.extension = Some(extension);
+            rest = after;
+        } else if chord.quality == ChordQuality::Major && !chord.major_seventh {
+            if let Some(after) = rest.strip_prefix('5') {
+                chord.quality = ChordQuality::Power;
+                rest = after;
+            }
+        }
+        if chord.major_seventh && chord.extension.is_none_or(|ext| !ext.has_seventh()) {
+            if chord.extension.is_some() {
+                return Err(unknown(&suffix));
+            }
+            // A bare "maj" or "Δ" stands for the major seventh.
+            chord.extension = Some(Extension::Seventh);
+        }
+
+        while !rest.is_empty() {
+            if let Some(after) = rest.strip_prefix("sus") {
+                let (suspension, after) = match after.chars().next() {
+                    Some('2') => (Suspension::Second, &after[1..]),
+                    Some('4') => (Suspension::Fourth, &after[1..]),
+                    _ => (Suspension::Fourth, after),
+                };
+                chord.suspension = Some(suspension);
+                rest = after;
+                continue;
+            }
+            let alterations = [
+                ("add9", Alteration::AddNinth),
+                ("add2", Alteration::AddNinth),
+                ("add11", Alteration::AddEleventh),
+                ("add4", Alteration::AddEleventh),
+                ("b5", Alteration::FlatFifth),
+                ("♭5", Alteration::FlatFifth),
+                ("-5", Alteration::FlatFifth),
+                ("#5", Alteration::SharpFifth),
+                ("♯5", Alteration::SharpFifth),
+                ("+5", Alteration::SharpFifth),
+                ("b9", Alteration::FlatNinth),
+                ("♭9", Alteration::FlatNinth),
+                ("#9", Alteration::SharpNinth),
+                ("♯9", Alteration::SharpNinth),
+                ("#11", Alteration::SharpEleventh),
+                ("♯11", Alteration::SharpEleventh),
+                ("b13", Alteration::FlatThirteenth),
+                ("♭13", Alteration::FlatThirteenth),
+            ];
+            let Some((after, alteration)) = alterations
+                .iter()
+                .find_map(|(text, alteration)| Some((rest.strip_prefix(text)?, *alteration)))
+            else {
+                return Err(unknown(rest));
+            };
+            if !chord.alterations.contains(&alteration) {
+                chord.alterations.push(alteration);
+            }
+            rest = after;
+        }
+        Ok(chord)
+    }
+}
+
+impl fmt::Display for ChordSymbol {
+    /// The symbol as it is typed, with `#` and `b`; it reads back the same.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.root.text())?;
+        match self.quality {
+            ChordQuality::Power => write!(f, "5")?,
+            quality => write!(f, "{}", quality.text())?,
+        }
+        match (self.major_seventh, self.quality) {
+            (true, ChordQuality::Minor) => write!(f, "(maj")?,
+            (true, _) => write!(f, "maj")?,
+            (false, _) => {}
+        }
+        if let Some(extension) = self.extension {
+            write!(f, "{}", extension.label())?;
+        }
+        if self.major_seventh && self.quality == ChordQuality::Minor {
+            write!(f, ")")?;
+        }
+        if let Some(suspension) = self.suspension {
+            write!(f, "{}", suspension.label())?;
+        }
+        for alteration in &self.alterations {
+            write!(f, "{}", alteration.text())?;
+        }
+        if let Some(bass) = self.bass {
+            write!(f, "/{}", bass.text())?;
+        }
+        Ok(())
+    }
+}
+
+/// A chord symbol split the way it is engraved: the root and triad on the
+/// line, the extensions and alterations raised and smaller, then the bass.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChordLabel {
+    pub base: String,
+    pub superscript: String,
+    pub bass: String,
+}
+
+impl ChordSymbol {
+    pub fn triad(root: Tone, quality: ChordQuality) -> Self {
+        Self {
+            root,
+            quality,
+            extension: None,
+            major_seventh: false,
+            suspension: None,
+            alterations: Vec::new(),
+            bass: None,
+        }
+    }
+
+    pub fn label(&self) -> ChordLabel {
+        let mut superscript = String::new();
+        if self.quality == ChordQuality::Power {
+            superscript.push('5');
+        }
+        if self.major_seventh {
+            superscript.push_str("maj");
+        }
+        if let Some(extension) = self.extension {
+            superscript.push_str(extension.label());
+        }
+        if let Some(suspension) = self.suspension {
+            superscript.push_str(suspension.label());
+        }
+        for alteration in &self.alterations {
+            superscript.push_str(alteration.label());
+        }
+        ChordLabel {
+            base: format!("{}{}", self.root.label(), self.quality.symbol()),
+            superscript,
+            bass: self
+                .bass
+                .map(|bass| format!("/{}", bass.label()))
+                .unwrap_or_default(),
+        }
+    }
+
+    /// The symbol with its root and bass moved by `transpose`, which is
+    /// given them as pitches in octave 4.
+    pub fn transposed(&self, transpose: impl Fn(Pitch) -> Pitch) -> Self {
+        let tone = |tone: Tone| Tone::of(transpose(tone.pitch(4)));
+        Self {
+            root: tone(self.root),
+            bass: self.bass.map(tone),
+            ..self.clone()
+        }
+    }
+
+    /// The chord tones as semitones above the root, lowest first.
+    pub fn intervals(&self) -> Vec<i32> {
+        let mut tones = vec![0];
+        match (self.suspension, self.quality) {
+            (_, ChordQuality::Power) => {}
+            (Some(Suspension::Second), _) => tones.push(2),
+            (Some(Suspension::Fourth), _) => tones.push(5),
+            (None, ChordQuality::Minor | ChordQuality::Diminished) => tones.push(3),
+            (None, _) => tones.push(4),
+        }
+        tones.push(match self.quality {
+            ChordQuality::Diminished => 6,
+            ChordQuality::Augmented => 8,
+            _ => 7,
+        });
+        let extension = self.extension;
+        let reaches = |degree: Extension| {
+            extension.is_some_and(|extension| extension.has_seventh() && extension >= degree)
+        };
+        if matches!(extension, Some(Extension::Sixth | Extension::SixNine)) {
+            tones.push(9);
+        }
+        if extension == Some(Extension::SixNine) {
+            tones.push(14);
+        }
+        if reaches(Extension::Seventh) {
+            tones.push(match (self.major_seventh, self.quality, extension) {
+                (true, _, _) => 11,
+                (false, ChordQuality::Diminished, Some(Extension::Seventh)) => 9,
+                _ => 10,
+            });
+        }
+        for (degree, semitones) in [
+            (Extension::Ninth, 14),
+            (Extension::Eleventh, 17),
+            (Extension::Thirteenth, 21),
+        ] {
+            if reaches(degree) {
+                tones.push(semitones);
+            }
+        }
+        // An alteration takes the place of the degree it changes.
+        for alteration in &self.alterations {
+            let (degree, semitones) = alteration.degree();
+            let natural = match degree {
+                5 => 7,
+                9 => 14,
+                11 => 17,
+                _ => 21,
+            };
+            let replaces = !matches!(alteration, Alteration::AddNinth | Alteration::AddEleventh);
+            if replaces {
+                tones.retain(|tone| *tone != natural);
+            }
+            if !tones.contains(&semitones) {
+                tones.push(semitones);
+            }
+        }
+        tones.sort_unstable();
+        tones
+    }
+
+    /// Notes the chord is played with: the bass in the second octave and
+    /// the chord tones from the root in the third.
+    pub fn voicing(&self) -> Vec<Pitch> {
+        let bass = self.bass.unwrap_or(self.root);
+        let root = 48 + self.root.semitone();
+        std::iter::once(36 + bass.semitone())
+            .chain(self.intervals().into_iter().map(|tone| root + tone))
+            .map(|midi| Pitch::from_midi(midi, false))
+            .collect()
+    }
+}
+
+/// Writes `chord` above the first of the given events in each voice, or
+/// clears the symbols of all of them when `chord` is `None`.
+pub fn set_chord(
+    notes: &[NoteEvent],
+    indices: &[usize],
+    chord: Option<&ChordSymbol>,
+) -> Vec<NoteEvent> {
+    let mut notes = notes.to_vec();
+    let mut marked: Vec<(Part, u8)> = Vec::new();
+    for index in indices {
+        let Some(note) = notes.get_mut(*index) else {
+            continue;
+        };
+        if chord.is_none() {
+            note.chord = None;
+        } else if !marked.contains(&(note.part(), note.voice)) {
+            marked.push((note.part(), note.voice));
+            note.chord = chord.cloned();
+        }
+    }
+    notes
+}
+
+/// The chords of `stream` with the beats each one lasts: from its event to
+/// the next chord of the stream, or to the end of the stream.
+pub fn chord_spans(stream: &[NoteEvent]) -> Vec<(&ChordSymbol, f32, f32)> {
+    let end: f32 = stream.iter().map(NoteEvent::beats).sum();
+    let mut spans: Vec<(&ChordSymbol, f32, f32)> = Vec::new();
+    let mut position = 0.0;
+    for event in stream {
+        if let Some(chord) = &event.chord {
+            if let Some(last) = spans.last_mut() {
+                last.2 = position;
+            }
+            spans.push((chord, position, end));
+        }
+        position += event.beats();
+    }
+    spans
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::music::{DurationValue, Instrument};
+    use crate::transpose::Interval;
+
+    fn chord(text: &str) -> ChordSymbol {
+        text.parse().unwrap()
+    }
+
+    #[test]
+    fn symbols_parse_into_their_parts() {
+        let cmaj7 = chord("Cmaj7");
+        assert_eq!(cmaj7.root, Tone::new(PitchClass::C, 0));
+        assert_eq!(cmaj7.extension, Some(Extension::Seventh));
+        assert!(cmaj7.major_seventh);
+        assert_eq!(chord("CΔ"), cmaj7);
+
+        let half_diminished = chord("F#m7b5/C");
+        assert_eq!(half_diminished.root, Tone::new(PitchClass::F, 1));
+        assert_eq!(half_diminished.quality, ChordQuality::Minor);
+        assert_eq!(half_diminished.alterations, [Alteration::FlatFifth]);
+        assert_eq!(half_diminished.bass, Some(Tone::new(PitchClass::C, 0)));
+        assert_eq!(chord("F#ø/C"), half_diminished);
+
+        let sus = chord("Bb13sus");
+        assert_eq!(sus.root, Tone::new(PitchClass::B, -1));
+        assert_eq!(sus.extension, Some(Extension::Thirteenth));
+        assert_eq!(sus.suspension, Some(Suspension::Fourth));
+
+        assert_eq!(chord("C6/9").extension, Some(Extension::SixNine));
+        assert_eq!(chord("C6/9").bass, None);
+        assert_eq!(chord("G7(b9, #11)").alterations.len(), 2);
+        assert_eq!(chord("E5").quality, ChordQuality::Power);
+        assert!(chord("Cm(maj7)").major_seventh);
+        let minor_add_ninth = chord("Amadd9");
+        assert_eq!(minor_add_ninth.quality, ChordQuality::Minor);
+        assert!(!minor_add_ninth.major_seventh);
+        assert_eq!(minor_add_ninth.alterations, [Alteration::AddNinth]);
+
+        assert_eq!("H7".parse::<ChordSymbol>(), Err(ChordError::MissingRoot));
+        assert_eq!(
+            "C7x".parse::<ChordSymbol>(),
+            Err(ChordError::Unknown("x".to_owned()))
+        );
+    }
+
+    #[test]
+    fn symbols_read_back_and_engrave_with_superscripts() {
+        for text in [
+            "Cmaj7",
+            "F#m7b5/C",
+            "Bb13sus4",
+            "Ebm(maj9)",
+            "Adim7",
+            "C6/9",
+            "G7#9b13",
+        ] {
+            assert_eq!(chord(text).to_string(), text);
+        }
+        assert_eq!(
+            chord("F#m7b5/C").label(),
+            ChordLabel {
+                base: "F♯m".to_owned(),
+                superscript: "7♭5".to_owned(),
+                bass: "/C".to_owned(),
+            }
+        );
+        assert_eq!(chord("Bbdim7").label().base, "B♭°");
+    }
+
+    #[test]
+    fn chords_transpose_and_voice_their_tones() {
+        let up = chord("F#m7b5/C").transposed(|pitch| Interval::new(1, 1).apply(pitch));
+        assert_eq!(up.to_string(), "Gm7b5/Db");
+
+        assert_eq!(chord("C7").intervals(), [0, 4, 7, 10]);
+        assert_eq!(chord("Cdim7").intervals(), [0, 3, 6, 9]);
+        assert_eq!(chord("C13sus").intervals(), [0, 5, 7, 10, 14, 17, 21]);
+        assert_eq!(chord("C7b9").intervals(), [0, 4, 7, 10, 13]);
+        let midi: Vec<i32> = chord("D/F#")
+            .voicing()
+            .into_iter()
+            .map(Pitch::midi_number)
+            .collect();
+        assert_eq!(midi, [42, 50, 54, 57]);
+    }
+
+    #[test]
+    fn each_chord_lasts_until_the_next() {
+        let quarter = |chord: Option<&str>| NoteEvent {
+            chord: chord.map(|text| text.parse().unwrap()),
+            ..NoteEvent::rest(DurationValue::Quarter, Instrument::PIANO)
+        };
+        let stream = [
+            quarter(None),
+            quarter(Some("C")),
+            quarter(None),
+            quarter(Some("G7")),
+        ];
+        let spans: Vec<(String, f32, f32)> = chord_spans(&stream)
+            .into_iter()
+            .map(|(chord, from, to)| (chord.to_string(), from, to))
+            .collect();
+        assert_eq!(
+            spans,
+            [("C".to_owned(), 1.0, 3.0), ("G7".to_owned(), 3.0, 4.0)]
+        );
+    }
+}
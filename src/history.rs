@@ -0,0 +1,522 @@
+use std::collections::VecDeque;
+use std::mem::size_of;
+
+use crate::harmony::Alteration;
+use crate::music::{
+    Articulation, Lyric, MeterChange, NavigationMark, NoteEvent, Score, ScoreSettings, Staff,
+    TempoMark,
+};
+
+/// Default ceiling for the memory kept by undo/redo entries.
+pub const DEFAULT_MEMORY_BUDGET: usize = 8 * 1024 * 1024;
+
+/// Hard cap on undo steps, whatever their size.
+pub const MAX_UNDO_STEPS: usize = 500;
+
+/// A reversible change to the score or its settings.
+#[derive(Debug, Clone, PartialEq)]
+pub enum EditCommand {
+    InsertNote {
+        index: usize,
+        note: NoteEvent,
+    },
+    RemoveNote {
+        index: usize,
+        note: NoteEvent,
+    },
+    ReplaceNote {
+        index: usize,
+        before: NoteEvent,
+        after: NoteEvent,
+    },
+    /// Puts `after` in place of the events `start..start + before.len()`,
+    /// for edits that rewrite the event list (clearing, re-barring). Build it
+    /// with [`EditCommand::replace_notes`], which keeps only the changed run.
+    ReplaceNotes {
+        start: usize,
+        before: Vec<NoteEvent>,
+        after: Vec<NoteEvent>,
+    },
+    /// Boxed, as settings are much larger than the other commands.
+    ChangeSettings {
+        before: Box<ScoreSettings>,
+        after: Box<ScoreSettings>,
+    },
+}
+
+impl EditCommand {
+    /// Turns the event list `before` into `after`. Only the events between
+    /// the unchanged opening and ending are stored, so an edit of a few
+    /// notes costs a few notes of memory whatever the size of the score.
+    pub fn replace_notes(before: &[NoteEvent], mut after: Vec<NoteEvent>) -> Self {
+        let start = before
+            .iter()
+            .zip(&after)
+            .take_while(|(old, new)| old == new)
+            .count();
+        let end = before[start..]
+            .iter()
+            .rev()
+            .zip(after[start..].iter().rev())
+            .take_while(|(old, new)| old == new)
+            .count();
+        after.truncate(after.len() - end);
+        after.drain(..start);
+        after.shrink_to_fit();
+        Self::ReplaceNotes {
+            start,
+            before: before[start..before.len() - end].to_vec(),
+            after,
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::InsertNote { .. } => "Inserir nota",
+            Self::RemoveNote { .. } => "Apagar nota",
+            Self::ReplaceNote { .. } => "Alterar nota",
+            Self::ReplaceNotes { .. } => "Alterar notas",
+            Self::ChangeSettings { .. } => "Alterar propriedades",
+        }
+    }
+
+    fn apply(&self, score: &mut Score, settings: &mut ScoreSettings) {
+        match self {
+            Self::InsertNote { index, note } => score.notes.insert(*index, note.clone()),
+            Self::RemoveNote { index, .. } => {
+                score.notes.remove(*index);
+            }
+            Self::ReplaceNote { index, after, .. } => score.notes[*index] = after.clone(),
+            Self::ReplaceNotes {
+                start,
+                before,
+                after,
+            } => {
+                score
+                    .notes
+                    .splice(*start..*start + before.len(), after.iter().cloned());
+            }
+            Self::ChangeSettings { after, .. } => *settings = (**after).clone(),
+        }
+    }
+
+    fn revert(&self, score: &mut Score, settings: &mut ScoreSettings) {
+        match self {
+            Self::InsertNote { index, .. } => {
+                score.notes.remove(*index);
+            }
+            Self::RemoveNote { index, note } => score.notes.insert(*index, note.clone()),
+            Self::ReplaceNote { index, before, .. } => score.notes[*index] = before.clone(),
+            Self::ReplaceNotes {
+                start,
+                before,
+                after,
+            } => {
+                score
+                    .notes
+                    .splice(*start..*start + after.len(), before.iter().cloned());
+            }
+            Self::ChangeSettings { before, .. } => *settings = (**before).clone(),
+        }
+    }
+
+    /// Rough heap plus inline size, used to keep the history within budget.
+    fn approximate_bytes(&self) -> usize {
+        let settings_bytes = |settings: &ScoreSettings| {
+            let names: usize = settings
+                .staves
+                .iter()
+                .map(|staff| staff.name.capacity() + staff.short_name.capacity())
+                .chain(settings.tempo_marks.iter().map(|mark| mark.text.capacity()))
+                .sum();
+            size_of::<ScoreSettings>()
+                + settings.title.capacity()
+                + settings.composer.capacity()
+                + settings.staves.capacity() * size_of::<Staff>()
+                + settings.meter_changes.capacity() * size_of::<MeterChange>()
+                + settings.tempo_marks.capacity() * size_of::<TempoMark>()
+                + settings.navigation.capacity() * size_of::<NavigationMark>()
+                + names
+        };
+        let notes_bytes = |notes: &Vec<NoteEvent>| {
+            notes.capacity() * size_of::<NoteEvent>() + notes.iter().map(owned_bytes).sum::<usize>()
+        };
+        let heap = match self {
+            Self::InsertNote { note, .. } | Self::RemoveNote { note, .. } => owned_bytes(note),
+            Self::ReplaceNote { before, after, .. } => owned_bytes(before) + owned_bytes(after),
+            Self::ReplaceNotes { before, after, .. } => notes_bytes(before) + notes_bytes(after),
+            Self::ChangeSettings { before, after } => {
+                settings_bytes(before) + settings_bytes(after)
+            }
+        };
+        size_of::<Self>() + heap
+    }
+}
+
+/// Heap owned by an event: its articulations, lyrics and chord symbol.
+fn owned_bytes(note: &NoteEvent) -> usize {
+    let lyrics: usize = note.lyrics.iter().map(|lyric| lyric.text.capacity()).sum();
+    let chord = note.chord.as_ref().map_or(0, |chord| {
+        chord.alterations.capacity() * size_of::<Alteration>()
+    });
+    note.articulations.capacity() * size_of::<Articulation>()
+        + note.lyrics.capacity() * size_of::<Lyric>()
+        + lyrics
+        + chord
+}
+
+/// One undo step: a single command or a group of commands undone together.
+#[derive(Debug, Clone)]
+struct HistoryEntry {
+    label: String,
+    commands: Vec<EditCommand>,
+    bytes: usize,
+}
+
+impl HistoryEntry {
+    fn new(label: &str) -> Self {
+        Self {
+            label: label.to_owned(),
+            commands: Vec::new(),
+            bytes: size_of::<Self>() + label.len(),
+        }
+    }
+
+    fn push(&mut self, command: EditCommand) {
+        self.bytes += command.approximate_bytes();
+        self.commands.push(command);
+    }
+}
+
+/// Undo/redo stacks for every edit made through [`EditHistory::apply`].
+#[derive(Debug)]
+pub struct EditHistory {
+    undo: VecDeque<HistoryEntry>,
+    redo: Vec<HistoryEntry>,
+    open_group: Option<HistoryEntry>,
+    group_depth: usize,
+    memory_budget: usize,
+}
+
+impl Default for EditHistory {
+    fn default() -> Self {
+        Self::with_memory_budget(DEFAULT_MEMORY_BUDGET)
+    }
+}
+
+impl EditHistory {
+    pub fn with_memory_budget(memory_budget: usize) -> Self {
+        Self {
+            undo: VecDeque::new(),
+            redo: Vec::new(),
+            open_group: None,
+            group_depth: 0,
+            memory_budget,
+        }
+    }
+
+    /// Runs `command` and records it, dropping anything that could be redone.
+    pub fn apply(&mut self, score: &mut Score, settings: &mut ScoreSettings, command: EditCommand) {
+        command.apply(score, settings);
+        self.redo.clear();
+
+        match &mut self.open_group {
+            Some(group) => group.push(command),
+            None => {
+                let mut entry = HistoryEntry::new(command.label());
+                entry.push(command);
+                self.push_undo(entry);
+            }
+        }
+    }
+
+    /// Starts collecting commands into one undo step. Groups may nest; only
+    /// the outermost label is kept.
+    pub fn begin_group(&mut self, label: &str) {
+        if self.group_depth == 0 {
+            self.open_group = Some(HistoryEntry::new(label));
+        }
+        self.group_depth += 1;
+    }
+
+    pub fn end_group(&mut self) {
+        self.group_depth = self.group_depth.saturating_sub(1);
+        if self.group_depth > 0 {
+            return;
+        }
+        if let Some(group) = self.open_group.take() {
+            if !group.commands.is_empty() {
+                self.push_undo(group);
+            }
+        }
+    }
+
+    /// Reverts the last step and returns its label.
+    pub fn undo(&mut self, score: &mut Score, settings: &mut ScoreSettings) -> Option<String> {
+        let entry = self.undo.pop_back()?;
+        for command in entry.commands.iter().rev() {
+            command.revert(score, settings);
+        }
+        let label = entry.label.clone();
+        self.redo.push(entry);
+        Some(label)
+    }
+
+    /// Re-applies the last undone step and returns its label.
+    pub fn redo(&mut self, score: &mut Score, settings: &mut ScoreSettings) -> Option<String> {
+        let entry = self.redo.pop()?;
+        for command in &entry.commands {
+            command.apply(score, settings);
+        }
+        let label = entry.label.clone();
+        self.undo.push_back(entry);
+        Some(label)
+    }
+
+    pub fn undo_label(&self) -> Option<&str> {
+        self.undo.back().map(|entry| entry.label.as_str())
+    }
+
+    pub fn redo_label(&self) -> Option<&str> {
+        self.redo.last().map(|entry| entry.label.as_str())
+    }
+
+    /// Forgets every step, e.g. after loading another file.
+    pub fn clear(&mut self) {
+        self.undo.clear();
+        self.redo.clear();
+        self.open_group = None;
+        self.group_depth = 0;
+    }
+
+    fn push_undo(&mut self, entry: HistoryEntry) {
+        self.undo.push_back(entry);
+
+        let mut used: usize = self.undo.iter().map(|entry| entry.bytes).sum();
+        while self.undo.len() > 1 && (used > self.memory_budget || self.undo.len() > MAX_UNDO_STEPS)
+        {
+            if let Some(oldest) = self.undo.pop_front() {
+                used -= oldest.bytes;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::music::{DurationValue, Instrument, KeySignature, Pitch, PitchClass, Syllabic};
+
+    fn note(class: PitchClass) -> NoteEvent {
+        NoteEvent::new(
+            Pitch::new(class, 4),
+            DurationValue::Quarter,
+            Instrument::FLUTE,
+        )
+    }
+
+    fn insert(
+        history: &mut EditHistory,
+        score: &mut Score,
+        settings: &mut ScoreSettings,
+        class: PitchClass,
+    ) {
+        let index = score.notes.len();
+        history.apply(
+            score,
+            settings,
+            EditCommand::InsertNote {
+                index,
+                note: note(class),
+            },
+        );
+    }
+
+    #[test]
+    fn undo_and_redo_restore_each_step() {
+        let mut history = EditHistory::default();
+        let mut score = Score::default();
+        let mut settings = ScoreSettings::default();
+
+        insert(&mut history, &mut score, &mut settings, PitchClass::C);
+        history.apply(
+            &mut score,
+            &mut settings,
+            EditCommand::ReplaceNote {
+                index: 0,
+                before: note(PitchClass::C),
+                after: note(PitchClass::D),
+            },
+        );
+        assert_eq!(score.notes, vec![note(PitchClass::D)]);
+
+        assert_eq!(
+            history.undo(&mut score, &mut settings).as_deref(),
+            Some("Alterar nota")
+        );
+        assert_eq!(score.notes, vec![note(PitchClass::C)]);
+        history.undo(&mut score, &mut settings);
+        assert!(score.notes.is_empty());
+        assert!(history.undo(&mut score, &mut settings).is_none());
+
+        history.redo(&mut score, &mut settings);
+        history.redo(&mut score, &mut settings);
+        assert_eq!(score.notes, vec![note(PitchClass::D)]);
+    }
+
+    #[test]
+    fn new_edit_discards_redo_stack() {
+        let mut history = EditHistory::default();
+        let mut score = Score::default();
+        let mut settings = ScoreSettings::default();
+
+        insert(&mut history, &mut score, &mut settings, PitchClass::C);
+        history.undo(&mut score, &mut settings);
+        assert_eq!(history.redo_label(), Some("Inserir nota"));
+
+        insert(&mut history, &mut score, &mut settings, PitchClass::E);
+
+        assert!(history.redo_label().is_none());
+        assert_eq!(score.notes, vec![note(PitchClass::E)]);
+    }
+
+    #[test]
+    fn grouped_commands_undo_together() {
+        let mut history = EditHistory::default();
+        let mut score = Score::default();
+        let mut settings = ScoreSettings::default();
+
+        insert(&mut history, &mut score, &mut settings, PitchClass::C);
+        history.begin_group("Colar");
+        insert(&mut history, &mut score, &mut settings, PitchClass::D);
+        history.begin_group("interno");
+        insert(&mut history, &mut score, &mut settings, PitchClass::E);
+        history.end_group();
+        history.apply(
+            &mut score,
+            &mut settings,
+            EditCommand::RemoveNote {
+                index: 0,
+                note: note(PitchClass::C),
+            },
+        );
+        history.end_group();
+        assert_eq!(score.notes, vec![note(PitchClass::D), note(PitchClass::E)]);
+
+        assert_eq!(
+            history.undo(&mut score, &mut settings).as_deref(),
+            Some("Colar")
+        );
+        assert_eq!(score.notes, vec![note(PitchClass::C)]);
+        history.redo(&mut score, &mut settings);
+        assert_eq!(score.notes, vec![note(PitchClass::D), note(PitchClass::E)]);
+    }
+
+    #[test]
+    fn settings_and_bulk_changes_are_reversible() {
+        let mut history = EditHistory::default();
+        let mut score = Score {
+            notes: vec![note(PitchClass::G)],
+        };
+        let mut settings = ScoreSettings::default();
+        let changed = ScoreSettings {
+            key_signature: KeySignature::D,
+            ..settings.clone()
+        };
+
+        let before = settings.clone();
+        history.apply(
+            &mut score,
+            &mut settings,
+            EditCommand::ChangeSettings {
+                before: Box::new(before),
+                after: Box::new(changed.clone()),
+            },
+        );
+        history.apply(
+            &mut score,
+            &mut settings,
+            EditCommand::replace_notes(&[note(PitchClass::G)], Vec::new()),
+        );
+        assert_eq!(settings, changed);
+        assert!(score.notes.is_empty());
+
+        history.undo(&mut score, &mut settings);
+        history.undo(&mut score, &mut settings);
+        assert_eq!(settings, ScoreSettings::default());
+        assert_eq!(score.notes, vec![note(PitchClass::G)]);
+    }
+
+    #[test]
+    fn small_edits_of_a_large_score_stay_undoable() {
+        let mut history = EditHistory::default();
+        let original = Score {
+            notes: vec![note(PitchClass::C); 20_000],
+        };
+        let mut score = original.clone();
+        let mut settings = ScoreSettings::default();
+
+        for step in 0..400 {
+            let mut after = score.notes.clone();
+            after[step * 50].pitch = Pitch::new(PitchClass::D, 5);
+            after.insert(step * 50 + 1, note(PitchClass::E));
+            let command = EditCommand::replace_notes(&score.notes, after);
+            history.apply(&mut score, &mut settings, command);
+        }
+        assert_eq!(score.notes.len(), 20_400);
+
+        let mut undone = 0;
+        while history.undo(&mut score, &mut settings).is_some() {
+            undone += 1;
+        }
+        assert_eq!(undone, 400);
+        assert_eq!(score, original);
+    }
+
+    #[test]
+    fn lyrics_count_towards_the_budget() {
+        let mut sung = note(PitchClass::C);
+        sung.set_lyric(Lyric {
+            verse: 0,
+            text: "a".repeat(4096),
+            syllabic: Syllabic::Single,
+            extend: false,
+        });
+        let plain = EditCommand::InsertNote {
+            index: 0,
+            note: note(PitchClass::C),
+        };
+        let with_lyric = EditCommand::InsertNote {
+            index: 0,
+            note: sung,
+        };
+
+        assert!(with_lyric.approximate_bytes() >= plain.approximate_bytes() + 4096);
+    }
+
+    #[test]
+    fn oldest_steps_are_dropped_over_budget() {
+        let entry_bytes = {
+            let mut entry = HistoryEntry::new("Inserir nota");
+            entry.push(EditCommand::InsertNote {
+                index: 0,
+                note: note(PitchClass::C),
+            });
+            entry.bytes
+        };
+        let mut history = EditHistory::with_memory_budget(entry_bytes * 3);
+        let mut score = Score::default();
+        let mut settings = ScoreSettings::default();
+
+        for _ in 0..10 {
+            insert(&mut history, &mut score, &mut settings, PitchClass::C);
+        }
+
+        let mut undone = 0;
+        while history.undo(&mut score, &mut settings).is_some() {
+            undone += 1;
+        }
+        assert_eq!(undone, 3);
+        assert_eq!(score.notes.len(), 7);
+    }
+}
@@ -0,0 +1,300 @@
+//! The instrument catalogue: every instrument Notarium knows, with its
+//! family, clef, transposition, ranges, MIDI program, playback preset and
+//! names, read from `assets/instruments.txt`. The file is bundled into the
+//! executable, so a new instrument takes a line there and no code.
+//!
+//! The file uses the record syntax of `.ntr` files; its header comment
+//! describes the fields of an `instrument` record.
+
+use std::sync::OnceLock;
+
+use crate::audio::Synth;
+use crate::music::{Clef, Family, Instrument, RangeLevel};
+use crate::ntr::{NtrError, NtrErrorKind, Record};
+use crate::transpose::Interval;
+
+const BUNDLED: &str = include_str!("../assets/instruments.txt");
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Language {
+    Portuguese,
+    English,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Names {
+    pub long: String,
+    /// Printed before the staves of later systems.
+    pub short: String,
+}
+
+/// One entry of the catalogue.
+#[derive(Debug, Clone, PartialEq)]
+pub struct InstrumentInfo {
+    /// Name saved in `.ntr` files.
+    pub id: String,
+    pub family: Family,
+    /// Clef of the top staff; lower staves read the bass clef.
+    pub clef: Clef,
+    pub staves: u8,
+    /// General MIDI program number, 1-based.
+    pub program: u8,
+    pub synth: Synth,
+    /// Lowest and highest sounding MIDI numbers a professional reaches.
+    pub professional: (i32, i32),
+    pub amateur: (i32, i32),
+    /// From the written to the sounding pitch.
+    pub transposition: Interval,
+    pub portuguese: Names,
+    pub english: Names,
+    /// Lowercase words that name the instrument in imported parts, its
+    /// names included.
+    pub keywords: Vec<String>,
+}
+
+impl InstrumentInfo {
+    pub fn names(&self, language: Language) -> &Names {
+        match language {
+            Language::Portuguese => &self.portuguese,
+            Language::English => &self.english,
+        }
+    }
+
+    pub fn range(&self, level: RangeLevel) -> (i32, i32) {
+        match level {
+            RangeLevel::Amateur => self.amateur,
+            RangeLevel::Professional => self.professional,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Catalogue {
+    /// In score order, as listed in the file.
+    instruments: Vec<InstrumentInfo>,
+}
+
+impl Catalogue {
+    pub fn parse(text: &str) -> Result<Self, NtrError> {
+        let mut instruments: Vec<InstrumentInfo> = Vec::new();
+        for (index, line) in text.lines().enumerate() {
+            let trimmed = line.trim_start();
+            if trimmed.is_empty() || trimmed.starts_with('#') {
+                continue;
+            }
+            let mut record = Record::parse(line, index + 1)?;
+            if record.keyword != "instrument" {
+                return Err(record.error(NtrErrorKind::UnknownRecord(record.keyword.clone())));
+            }
+            let info = read_instrument(&mut record, &instruments)?;
+            record.finish()?;
+            instruments.push(info);
+        }
+        Ok(Self { instruments })
+    }
+
+    pub fn get(&self, id: &str) -> Option<&InstrumentInfo> {
+        self.instruments.iter().find(|info| info.id == id)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &InstrumentInfo> {
+        self.instruments.iter()
+    }
+}
+
+/// The bundled catalogue, read on first use.
+pub fn catalogue() -> &'static Catalogue {
+    static CATALOGUE: OnceLock<Catalogue> = OnceLock::new();
+    CATALOGUE.get_or_init(|| {
+        Catalogue::parse(BUNDLED)
+            .unwrap_or_else(|error| panic!("catálogo de instrumentos inválido: {error}"))
+    })
+}
+
+/// The instrument a part name from another program refers to: the one with
+/// the longest of its names or keywords in `name`, so "English Horn" is not
+/// taken for a horn.
+pub fn find_by_name(name: &str) -> Option<Instrument> {
+    let name = name.to_lowercase();
+    catalogue()
+        .iter()
+        .flat_map(|info| {
+            info.keywords
+                .iter()
+                .filter(|keyword| names_word(&name, keyword))
+                .map(move |keyword| (keyword.chars().count(), info))
+        })
+        .max_by_key(|(length, _)| *length)
+        .and_then(|(_, info)| Instrument::from_id(&info.id))
+}
+
+/// Whether `keyword` stands as whole words in `name`, or in the plural, so
+/// "Violins" names a violin but "Basso" no bass voice.
+fn names_word(name: &str, keyword: &str) -> bool {
+    name.match_indices(keyword).any(|(start, _)| {
+        let before = name[..start].chars().next_back();
+        let after = name[start + keyword.len()..].trim_start_matches('s');
+        !before.is_some_and(char::is_alphanumeric)
+            && !after.chars().next().is_some_and(char::is_alphanumeric)
+    })
+}
+
+fn read_instrument(
+    record: &mut Record,
+    known: &[InstrumentInfo],
+) -> Result<InstrumentInfo, NtrError> {
+    let id = record.required("id")?;
+    if id.value.is_empty() || known.iter().any(|info| info.id == id.value) {
+        return Err(id.invalid("um id ainda não usado"));
+    }
+    let family = record.required("family")?.parse_enum(&Family::ALL)?;
+    let clef = record.required("clef")?.parse_enum(&Clef::ALL)?;
+    let staves = match record.take("staves") {
+        Some(field) => match field.parse_number::<u8>("1 a 3")? {
+            staves @ 1..=3 => staves,
+            _ => return Err(field.invalid("1 a 3")),
+        },
+        None => 1,
+    };
+    let program = record.required("program")?;
+    let program = match program.parse_number::<u8>("1 a 128")? {
+        number @ 1..=128 => number,
+        _ => return Err(program.invalid("1 a 128")),
+    };
+    let synth = record.required("synth")?.parse_enum(&Synth::ALL)?;
+
+    let professional = midi_range(record, ("low", "high"), None)?;
+    let amateur = midi_range(record, ("amateur_low", "amateur_high"), Some(professional))?;
+
+    let steps = record.optional_number("steps", 0, "número inteiro")?;
+    let semitones = record.optional_number("semitones", 0, "número inteiro")?;
+    let mut names = |name: &'static str, short: &'static str| -> Result<Names, NtrError> {
+        Ok(Names {
+            long: record.required(name)?.value,
+            short: record.required(short)?.value,
+        })
+    };
+    let portuguese = names("name", "short")?;
+    let english = names("name_en", "short_en")?;
+    let extra = record
+        .take("match")
+        .map(|field| field.value)
+        .unwrap_or_default();
+    let keywords = [portuguese.long.as_str(), english.long.as_str()]
+        .into_iter()
+        .chain(extra.split(','))
+        .map(|keyword| keyword.trim().to_lowercase())
+        .filter(|keyword| !keyword.is_empty())
+        .collect();
+
+    Ok(InstrumentInfo {
+        id: id.value,
+        family,
+        clef,
+        staves,
+        program,
+        synth,
+        professional,
+        amateur,
+        transposition: Interval::new(steps, semitones),
+        portuguese,
+        english,
+        keywords,
+    })
+}
+
+/// Lowest and highest sounding MIDI numbers from a pair of fields, each
+/// falling back to `default` when left out.
+fn midi_range(
+    record: &mut Record,
+    (low, high): (&'static str, &'static str),
+    default: Option<(i32, i32)>,
+) -> Result<(i32, i32), NtrError> {
+    const EXPECTED: &str = "nota MIDI de 0 a 127";
+    let mut read = |name: &'static str, default: Option<i32>| {
+        let field = match (record.take(name), default) {
+            (Some(field), _) => field,
+            (None, Some(default)) => return Ok((default, None)),
+            (None, None) => record.required(name)?,
+        };
+        match field.parse_number::<i32>(EXPECTED)? {
+            number @ 0..=127 => Ok((number, Some(field))),
+            _ => Err(field.invalid(EXPECTED)),
+        }
+    };
+    let (low, _) = read(low, default.map(|(low, _)| low))?;
+    let (high, field) = read(high, default.map(|(_, high)| high))?;
+    match field {
+        Some(field) if high < low => Err(field.invalid("nota não abaixo da mais grave")),
+        _ => Ok((low, high)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_bundled_catalogue_reads_and_has_every_named_instrument() {
+        let catalogue = catalogue();
+        assert!(catalogue.iter().count() >= 35);
+        for instrument in Instrument::NAMED {
+            assert!(
+                catalogue.get(instrument.id()).is_some(),
+                "{instrument:?} não está no catálogo"
+            );
+        }
+        for info in catalogue.iter() {
+            let (low, high) = info.professional;
+            assert!(
+                low <= info.amateur.0 && info.amateur.1 <= high,
+                "{}: a extensão amadora passa da profissional",
+                info.id
+            );
+        }
+
+        let oboe = Instrument::from_id("Oboe").unwrap();
+        assert_eq!(oboe.family(), Family::Woodwinds);
+        assert_eq!(oboe.names(Language::English).long, "Oboe");
+        assert_eq!(oboe.label(), "Oboé");
+        let horn = Instrument::from_id("EnglishHorn").unwrap();
+        assert_eq!(horn.transposition(), Interval::new(4, 7).down());
+        assert_eq!(Instrument::from_id("Kazoo"), None);
+    }
+
+    #[test]
+    fn a_new_instrument_is_only_a_line_and_bad_lines_point_at_the_field() {
+        let text = "# teste\n\
+                    instrument id=Ukulele family=Plucked clef=Treble program=25 synth=Plucked \
+                    low=60 high=81 name=\"Cavaquinho\" short=\"Cvq.\" name_en=\"Ukulele\" \
+                    short_en=\"Uk.\" match=\"uke\"\n";
+        let catalogue = Catalogue::parse(text).unwrap();
+        let ukulele = catalogue.get("Ukulele").unwrap();
+        assert_eq!(ukulele.staves, 1);
+        assert_eq!(ukulele.amateur, ukulele.professional);
+        assert_eq!(ukulele.keywords, ["cavaquinho", "ukulele", "uke"]);
+
+        let error = Catalogue::parse(&text.replace("program=25", "program=300")).unwrap_err();
+        assert_eq!(error.line, 2);
+        assert!(matches!(
+            error.kind,
+            NtrErrorKind::InvalidValue { ref field, .. } if field == "program"
+        ));
+        let twice = format!("{text}{}", &text[8..]);
+        assert!(Catalogue::parse(&twice).is_err());
+    }
+
+    #[test]
+    fn part_names_pick_the_longest_match_in_either_language() {
+        let id = |name: &str| find_by_name(name).map(Instrument::id);
+        assert_eq!(id("English Horn"), Some("EnglishHorn"));
+        assert_eq!(id("Horn in F"), Some("Horn"));
+        assert_eq!(id("Contrabaixo"), Some("Contrabass"));
+        assert_eq!(id("Violoncello"), Some("Cello"));
+        assert_eq!(id("Violins I"), Some("Violin"));
+        assert_eq!(id("Basso"), None);
+        assert_eq!(id("Contralto"), Some("Alto"));
+        assert_eq!(id("Harpsichord"), Some("Harpsichord"));
+        assert_eq!(id("Theremin"), None);
+    }
+}
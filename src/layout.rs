@@ -0,0 +1,1649 @@
+use egui::{Pos2, Rect, Vec2};
+
+use crate::measures::MeasureMap;
+use std::collections::BTreeMap;
+use std::ops::Range;
+
+use crate::dynamics;
+use crate::music::{
+    Articulation, DurationValue, Dynamic, Hairpin, Navigation, NavigationMark, NoteEvent, Part,
+    Pitch, Score, TempoMark,
+};
+
+/// Measures shown side by side on one page; each page holds a single system.
+pub const MEASURES_PER_PAGE: usize = 6;
+
+/// Size of lyric text at 100% zoom.
+pub const LYRIC_FONT_SIZE: f32 = 13.0;
+/// Distance between the lines of two verses at 100% zoom.
+const VERSE_SPACING: f32 = 16.0;
+/// Size of a chord symbol's root at 100% zoom.
+pub const CHORD_FONT_SIZE: f32 = 16.0;
+/// Room a staff with chord symbols gets above it at 100% zoom.
+const CHORD_SPACING: f32 = 20.0;
+
+/// Where an event of `Score::notes` falls once its voice's stream is barred.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EventLocation {
+    pub staff: usize,
+    pub measure: usize,
+    /// Offset from the measure's downbeat, in quarter-note beats.
+    pub beat: f32,
+}
+
+/// Barlines of the page starting at `first_measure`, from the left edge of
+/// staves `width` wide. Measures share the width evenly unless a syllable
+/// needs more room than its note's share of an even measure; those measures
+/// widen and the page is scaled back to fit.
+fn barlines(
+    score: &Score,
+    locations: &[Option<EventLocation>],
+    measures: &MeasureMap,
+    first_measure: usize,
+    width: f32,
+    padding: f32,
+    zoom: f32,
+) -> Vec<f32> {
+    let even = width / MEASURES_PER_PAGE as f32;
+    let mut widths = [even; MEASURES_PER_PAGE];
+    for (note, location) in score.notes.iter().zip(locations) {
+        let Some(location) = location else {
+            continue;
+        };
+        let Some(column) = location
+            .measure
+            .checked_sub(first_measure)
+            .filter(|column| *column < MEASURES_PER_PAGE)
+        else {
+            continue;
+        };
+        let widest = note
+            .lyrics
+            .iter()
+            .map(|lyric| lyric_width(&lyric.text, zoom))
+            .fold(0.0, f32::max);
+        if widest > 0.0 {
+            let share = note.beats() / measures.length(location.measure);
+            let needed = (widest + 6.0 * zoom) / share + 2.0 * padding;
+            widths[column] = widths[column].max(needed);
+        }
+    }
+
+    let scale = width / widths.iter().sum::<f32>();
+    let mut edges = vec![0.0];
+    for measure_width in widths {
+        edges.push(edges[edges.len() - 1] + measure_width * scale);
+    }
+    edges
+}
+
+/// Locates every event on its staff. Events whose part has no staff get `None`.
+pub fn locate_events(
+    score: &Score,
+    parts: &[Part],
+    measures: &MeasureMap,
+) -> Vec<Option<EventLocation>> {
+    let mut positions = vec![[0.0_f32; NoteEvent::VOICES as usize]; parts.len()];
+
+    score
+        .notes
+        .iter()
+        .map(|note| {
+            let staff = parts.iter().position(|part| *part == note.part())?;
+            let position = positions[staff].get_mut(usize::from(note.voice))?;
+            let start = *position;
+            *position += note.beats();
+
+            let (measure, beat) = measures.locate(start);
+            Some(EventLocation {
+                staff,
+                measure,
+                beat,
+            })
+        })
+        .collect()
+}
+
+/// Number of measures needed by the longest voice, never less than one.
+pub fn measure_count(score: &Score, parts: &[Part], measures: &MeasureMap) -> usize {
+    let mut totals = vec![[0.0_f32; NoteEvent::VOICES as usize]; parts.len()];
+    for note in &score.notes {
+        if let Some(staff) = parts.iter().position(|part| *part == note.part()) {
+            if let Some(total) = totals[staff].get_mut(usize::from(note.voice)) {
+                *total += note.beats();
+            }
+        }
+    }
+
+    totals
+        .into_iter()
+        .flatten()
+        .map(|beats| measures.count(beats))
+        .max()
+        .unwrap_or(0)
+        .max(1)
+}
+
+/// Index ranges of the runs of neighbouring staves that share `key`.
+fn runs<K: PartialEq>(
+    staves: &[StaffLayout],
+    key: impl Fn(&StaffLayout) -> K,
+) -> Vec<Range<usize>> {
+    let mut runs: Vec<Range<usize>> = Vec::new();
+    for (index, staff) in staves.iter().enumerate() {
+        match runs.last_mut() {
+            Some(run) if key(&staves[run.start]) == key(staff) => run.end = index + 1,
+            _ => runs.push(index..index + 1),
+        }
+    }
+    runs
+}
+
+/// A measure and a beat within it.
+type BeatSlot = (usize, u32);
+
+/// Runs of eighths and sixteenths in one voice that fall in the same beat of
+/// a measure, as indices into `score.notes`. Rests and longer notes break a
+/// run; a run of a single note keeps its flag and is left out.
+fn beam_groups(
+    score: &Score,
+    locations: &[Option<EventLocation>],
+    measures: &MeasureMap,
+) -> Vec<Vec<usize>> {
+    let beamed = |note: &NoteEvent| {
+        !note.rest
+            && matches!(
+                note.duration,
+                DurationValue::Eighth | DurationValue::Sixteenth
+            )
+    };
+    let mut groups = Vec::new();
+    // The open run of each staff and voice, with the measure and beat it is in.
+    let mut open: BTreeMap<(usize, u8), (BeatSlot, Vec<usize>)> = BTreeMap::new();
+
+    for (index, (note, location)) in score.notes.iter().zip(locations).enumerate() {
+        let Some(location) = location else {
+            continue;
+        };
+        let stream = (location.staff, note.voice);
+        let beat = (location.beat / measures.meter(location.measure).beam_beats() + 1e-3) as u32;
+        let slot = (location.measure, beat);
+
+        if beamed(note) {
+            if let Some((open_slot, run)) = open.get_mut(&stream) {
+                if *open_slot == slot {
+                    run.push(index);
+                    continue;
+                }
+            }
+        }
+        if let Some((_, run)) = open.remove(&stream) {
+            groups.push(run);
+        }
+        if beamed(note) {
+            open.insert(stream, (slot, vec![index]));
+        }
+    }
+    groups.extend(open.into_values().map(|(_, run)| run));
+    groups.retain(|run| run.len() > 1);
+    groups
+}
+
+pub fn page_count(measure_count: usize) -> usize {
+    measure_count.div_ceil(MEASURES_PER_PAGE).max(1)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StaffLayout {
+    pub staff: usize,
+    pub part: Part,
+    /// The five lines, top to bottom.
+    pub rect: Rect,
+    /// Area left of the staff holding the instrument name.
+    pub name_rect: Rect,
+}
+
+impl StaffLayout {
+    /// Distance between two adjacent staff lines.
+    pub fn line_spacing(&self) -> f32 {
+        self.rect.height() / 4.0
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MeasureLayout {
+    pub staff: usize,
+    pub measure: usize,
+    pub rect: Rect,
+}
+
+/// Which way a note's stem points.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stem {
+    /// Away from the middle line, as in a measure with a single voice.
+    Auto,
+    Up,
+    Down,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NoteLayout {
+    pub index: usize,
+    pub staff: usize,
+    pub measure: usize,
+    /// Staff the note is drawn on; another staff of the same grand staff when
+    /// the note crosses over.
+    pub shown_staff: usize,
+    pub center: Pos2,
+    /// Steps (lines and spaces) above the bottom staff line of `shown_staff`.
+    pub staff_step: i32,
+    pub stem: Stem,
+    /// Where the stem meets its beam, for beamed notes.
+    pub stem_end: Option<Pos2>,
+    pub hit_rect: Rect,
+}
+
+impl NoteLayout {
+    /// Whether the stem hangs below the notehead. Beamed notes point at their
+    /// beam; lone notes on or above the middle line are stemmed down.
+    pub fn stem_down(&self) -> bool {
+        match (self.stem_end, self.stem) {
+            (Some(tip), _) => tip.y > self.center.y,
+            (None, Stem::Auto) => self.staff_step >= 4,
+            (None, Stem::Up) => false,
+            (None, Stem::Down) => true,
+        }
+    }
+}
+
+/// An articulation mark by its note: on the side away from the stem, and a
+/// fermata always above the staff.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ArticulationLayout {
+    /// Staff the note is drawn on.
+    pub staff: usize,
+    pub articulation: Articulation,
+    pub center: Pos2,
+    pub above: bool,
+}
+
+/// A dynamic marking under the staff, centred on its event.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DynamicLayout {
+    pub staff: usize,
+    pub dynamic: Dynamic,
+    pub center: Pos2,
+}
+
+/// A crescendo or diminuendo wedge under the staff, between the middles of
+/// its two ends.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HairpinLayout {
+    pub staff: usize,
+    pub hairpin: Hairpin,
+    pub from: Pos2,
+    pub to: Pos2,
+}
+
+/// A tempo mark above the system, written from `anchor` (the left end of
+/// its baseline). A gradual change draws a dashed line on to `extend_to`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TempoLayout {
+    /// Index into the score's tempo marks.
+    pub mark: usize,
+    /// `None` for a change running on from an earlier page.
+    pub anchor: Option<Pos2>,
+    pub extend_to: Option<Pos2>,
+}
+
+/// A chord symbol above the staff, written from `anchor`, the left end of
+/// its baseline.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ChordLayout {
+    pub staff: usize,
+    /// Index into `Score::notes`.
+    pub index: usize,
+    pub anchor: Pos2,
+}
+
+/// A syllable under the staff, centred on `center`. A hyphen is drawn
+/// midway between the syllable and `hyphen_to`, and an extender line runs
+/// along its baseline to `extend_to`; both end at the next syllable, or at
+/// the page edge when that is on a later page.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LyricLayout {
+    pub staff: usize,
+    /// Index into `Score::notes`.
+    pub index: usize,
+    pub verse: u8,
+    pub center: Pos2,
+    /// Estimated from the text; see [`lyric_width`].
+    pub width: f32,
+    pub hyphen_to: Option<f32>,
+    pub extend_to: Option<f32>,
+}
+
+/// Room a syllable takes at `zoom`, from the average width of a character
+/// of the lyric font.
+pub fn lyric_width(text: &str, zoom: f32) -> f32 {
+    text.chars().count() as f32 * LYRIC_FONT_SIZE * 0.55 * zoom
+}
+
+/// A repeat, ending or navigation sign on the page. A repeat barline runs
+/// down the system at `rect.left()`; an ending's bracket spans the top of
+/// `rect`, hooked down at the left and, when `closed`, at the right; signs
+/// and words sit on the bottom of `rect`, signs from its left and words up
+/// to its right.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NavigationLayout {
+    /// Index into the score's navigation marks.
+    pub mark: usize,
+    pub rect: Rect,
+    pub closed: bool,
+}
+
+/// Where a click on a staff would put a new note.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct InsertPoint {
+    pub staff: usize,
+    /// Beats from the start of the score.
+    pub beat: f32,
+    /// Natural pitch of the line or space under the pointer.
+    pub pitch: Pitch,
+    pub center: Pos2,
+    pub staff_step: i32,
+}
+
+/// Something the pointer can land on in a rendered page.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HitTarget {
+    Note {
+        index: usize,
+        staff: usize,
+        measure: usize,
+    },
+    Measure {
+        staff: usize,
+        measure: usize,
+    },
+    Staff(usize),
+}
+
+/// Geometry of one rendered page, shared by drawing and hit-testing.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PageLayout {
+    pub first_measure: usize,
+    pub staves: Vec<StaffLayout>,
+    /// From the top line of the first staff to the bottom line of the last,
+    /// where the system starts.
+    pub system: Option<Rect>,
+    /// The staves of each grand staff on the page, spanned the same way.
+    pub braces: Vec<Rect>,
+    /// The staves of each bracketed family, such as the strings.
+    pub brackets: Vec<Rect>,
+    /// Players of one instrument inside a bracket, such as Violin I and II.
+    pub sub_brackets: Vec<Rect>,
+    pub measures: Vec<MeasureLayout>,
+    pub notes: Vec<NoteLayout>,
+    /// Beam lines, secondary beams included.
+    pub beams: Vec<[Pos2; 2]>,
+    pub articulations: Vec<ArticulationLayout>,
+    pub dynamics: Vec<DynamicLayout>,
+    /// Wedges cut at the page edges when they run on from or to another page.
+    pub hairpins: Vec<HairpinLayout>,
+    /// Filled by [`Self::place_tempo_marks`].
+    pub tempos: Vec<TempoLayout>,
+    pub lyrics: Vec<LyricLayout>,
+    pub chords: Vec<ChordLayout>,
+    /// Filled by [`Self::place_navigation`].
+    pub navigation: Vec<NavigationLayout>,
+    measure_map: MeasureMap,
+    /// Barlines of the page from the left edge of the staves, the system's
+    /// end included.
+    barlines: Vec<f32>,
+    padding: f32,
+    zoom: f32,
+}
+
+impl PageLayout {
+    pub fn new(
+        rect: Rect,
+        zoom: f32,
+        page: usize,
+        score: &Score,
+        parts: &[Part],
+        measure_map: &MeasureMap,
+    ) -> Self {
+        let first_measure = page * MEASURES_PER_PAGE;
+        let staff_left = rect.left() + 95.0 * zoom;
+        let staff_right = rect.right() - 24.0 * zoom;
+        let padding = 14.0 * zoom;
+        let locations = locate_events(score, parts, measure_map);
+        let barlines = barlines(
+            score,
+            &locations,
+            measure_map,
+            first_measure,
+            staff_right - staff_left,
+            padding,
+            zoom,
+        );
+
+        // Staves with lyrics make room under them for their verses, and
+        // staves with chord symbols above them.
+        let mut verses = vec![0_u8; parts.len()];
+        let mut chords = vec![false; parts.len()];
+        for (note, location) in score.notes.iter().zip(&locations) {
+            let Some(location) = location.filter(|location| {
+                (first_measure..first_measure + MEASURES_PER_PAGE).contains(&location.measure)
+            }) else {
+                continue;
+            };
+            if let Some(lyric) = note.lyrics.last() {
+                verses[location.staff] = verses[location.staff].max(lyric.verse + 1);
+            }
+            chords[location.staff] |= note.chord.is_some();
+        }
+
+        let mut staves = Vec::new();
+        let mut y = rect.top() + 120.0 * zoom;
+        for (staff, part) in parts.iter().enumerate() {
+            if chords[staff] {
+                y += CHORD_SPACING * zoom;
+            }
+            if y > rect.bottom() - 90.0 * zoom {
+                break;
+            }
+            staves.push(StaffLayout {
+                staff,
+                part: *part,
+                rect: Rect::from_min_max(
+                    Pos2::new(staff_left, y),
+                    Pos2::new(staff_right, y + 54.0 * zoom),
+                ),
+                name_rect: Rect::from_min_max(
+                    Pos2::new(rect.left(), y - 12.0 * zoom),
+                    Pos2::new(staff_left, y + 66.0 * zoom),
+                ),
+            });
+            y += (78.0 + VERSE_SPACING * f32::from(verses[staff])) * zoom;
+        }
+
+        let span = |run: &Range<usize>| staves[run.start].rect.union(staves[run.end - 1].rect);
+        let system = (!staves.is_empty()).then(|| span(&(0..staves.len())));
+        let braces = runs(&staves, |staff| staff.part.on_staff(0))
+            .into_iter()
+            .filter(|run| run.len() > 1)
+            .map(|run| span(&run))
+            .collect();
+        let bracketed = |run: &Range<usize>| staves[run.start].part.instrument.family().bracketed();
+        let brackets = runs(&staves, |staff| staff.part.instrument.family())
+            .into_iter()
+            .filter(bracketed)
+            .map(|run| span(&run))
+            .collect();
+        let sub_brackets = runs(&staves, |staff| staff.part.instrument)
+            .into_iter()
+            .filter(|run| run.len() > 1 && bracketed(run))
+            .map(|run| span(&run))
+            .collect();
+
+        let measures = staves
+            .iter()
+            .flat_map(|staff| {
+                barlines.windows(2).enumerate().map(move |(column, edges)| {
+                    let left = staff.rect.left() + edges[0];
+                    let right = staff.rect.left() + edges[1];
+                    MeasureLayout {
+                        staff: staff.staff,
+                        measure: first_measure + column,
+                        // Measures also take clicks on ledger-line space around the staff.
+                        rect: Rect::from_min_max(
+                            Pos2::new(left, staff.rect.top() - 12.0 * zoom),
+                            Pos2::new(right, staff.rect.bottom() + 12.0 * zoom),
+                        ),
+                    }
+                })
+            })
+            .collect();
+
+        let mut layout = Self {
+            first_measure,
+            staves,
+            system,
+            braces,
+            brackets,
+            sub_brackets,
+            measures,
+            notes: Vec::new(),
+            beams: Vec::new(),
+            articulations: Vec::new(),
+            dynamics: Vec::new(),
+            hairpins: Vec::new(),
+            tempos: Vec::new(),
+            lyrics: Vec::new(),
+            chords: Vec::new(),
+            navigation: Vec::new(),
+            measure_map: measure_map.clone(),
+            barlines,
+            padding,
+            zoom,
+        };
+
+        let beam_groups = beam_groups(score, &locations, measure_map);
+        // Voices with events in each measure of each staff, as a bit set.
+        let mut voices: BTreeMap<(usize, usize), u8> = BTreeMap::new();
+        for (note, location) in score.notes.iter().zip(&locations) {
+            if let Some(location) = location {
+                *voices
+                    .entry((location.staff, location.measure))
+                    .or_default() |= 1 << note.voice;
+            }
+        }
+
+        layout.notes = locations
+            .iter()
+            .enumerate()
+            .filter_map(|(index, location)| {
+                let location = (*location)?;
+                let staff = layout
+                    .staves
+                    .iter()
+                    .find(|staff| staff.staff == location.staff)?;
+                let x = layout.beat_x(staff, location.measure, location.beat)?;
+                let note = &score.notes[index];
+                // A note crossing to a staff that is not there stays on its own.
+                let shown = layout
+                    .staves
+                    .iter()
+                    .find(|staff| staff.part == note.shown_part())
+                    .unwrap_or(staff);
+
+                // With several voices in the measure, odd voices stem down and
+                // each voice's rests move off the middle line.
+                let shared = voices[&(location.staff, location.measure)].count_ones() > 1;
+                let stem = match note.voice % 2 {
+                    _ if !shared => Stem::Auto,
+                    0 => Stem::Up,
+                    _ => Stem::Down,
+                };
+                let staff_step = if note.rest {
+                    match (shared, note.voice) {
+                        (false, _) => 4,
+                        (true, 0) => 8,
+                        (true, 1) => 0,
+                        (true, 2) => 10,
+                        (true, _) => -2,
+                    }
+                } else {
+                    note.pitch.diatonic_number() - shown.part.clef().bottom_line().diatonic_number()
+                };
+                let spacing = staff.line_spacing();
+                let center = Pos2::new(x, shown.rect.bottom() - staff_step as f32 * spacing / 2.0);
+
+                Some(NoteLayout {
+                    index,
+                    staff: location.staff,
+                    measure: location.measure,
+                    shown_staff: shown.staff,
+                    center,
+                    staff_step,
+                    stem,
+                    stem_end: None,
+                    hit_rect: Rect::from_center_size(center, Vec2::new(spacing * 1.2, spacing)),
+                })
+            })
+            .collect();
+        layout.separate_noteheads(score);
+        for group in beam_groups {
+            layout.beam(score, &group);
+        }
+        layout.place_articulations(score);
+        layout.place_dynamics(score, &locations, 14.0 * zoom);
+        layout.place_lyrics(score, &locations);
+        layout.place_chords(score);
+
+        layout
+    }
+
+    /// Barlines of the page from the left edge of the staves, the system's
+    /// end included.
+    pub fn barlines(&self) -> &[f32] {
+        &self.barlines
+    }
+
+    /// Middle of the line of `verse` under `staff`.
+    fn lyric_y(&self, staff: &StaffLayout, verse: u8) -> f32 {
+        staff.rect.bottom() + (30.0 + VERSE_SPACING * f32::from(verse)) * self.zoom
+    }
+
+    /// Where the syllable of `verse` under the event at `index` is written,
+    /// when the event is on this page.
+    pub fn lyric_position(&self, index: usize, verse: u8) -> Option<Pos2> {
+        let note = self.notes.iter().find(|note| note.index == index)?;
+        let staff = self.staves.iter().find(|staff| staff.staff == note.staff)?;
+        Some(Pos2::new(note.center.x, self.lyric_y(staff, verse)))
+    }
+
+    /// Lays out each verse on its own line under the staff. Hyphens and
+    /// extenders reach on to the next syllable of the same voice and verse.
+    fn place_lyrics(&mut self, score: &Score, locations: &[Option<EventLocation>]) {
+        let zoom = self.zoom;
+        // Each voice's sung notes, as indices into `score.notes`.
+        let mut streams: BTreeMap<(usize, u8), Vec<usize>> = BTreeMap::new();
+        for (index, (note, location)) in score.notes.iter().zip(locations).enumerate() {
+            if let (Some(location), false) = (location, note.rest) {
+                streams
+                    .entry((location.staff, note.voice))
+                    .or_default()
+                    .push(index);
+            }
+        }
+
+        let mut lyrics = Vec::new();
+        for ((staff, _), stream) in streams {
+            let Some(layout) = self.staves.iter().find(|layout| layout.staff == staff) else {
+                continue;
+            };
+            let x = |index: usize| {
+                let location = locations[index]?;
+                self.beat_x(layout, location.measure, location.beat)
+            };
+            for (position, index) in stream.iter().enumerate() {
+                let Some(center_x) = x(*index) else {
+                    continue;
+                };
+                for lyric in &score.notes[*index].lyrics {
+                    let width = lyric_width(&lyric.text, zoom);
+                    let following = &stream[position + 1..];
+                    let next = following
+                        .iter()
+                        .position(|next| score.notes[*next].lyric(lyric.verse).is_some());
+                    // Where the line after the syllable stops: short of the
+                    // next syllable, or at the end of the last note it holds.
+                    let next_left = next.map_or(Some(layout.rect.right()), |next| {
+                        let next = following[next];
+                        let text = &score.notes[next].lyric(lyric.verse)?.text;
+                        Some(
+                            x(next)
+                                .map_or(layout.rect.right(), |x| x - lyric_width(text, zoom) / 2.0),
+                        )
+                    });
+                    let held = match next {
+                        Some(0) => None,
+                        Some(next) => Some(following[next - 1]),
+                        None => following.last().copied(),
+                    };
+                    let extend_to = held
+                        .filter(|_| lyric.extend)
+                        .map(|held| x(held).map_or(layout.rect.right(), |x| x + zoom * 5.0));
+                    lyrics.push(LyricLayout {
+                        staff,
+                        index: *index,
+                        verse: lyric.verse,
+                        center: Pos2::new(center_x, self.lyric_y(layout, lyric.verse)),
+                        width,
+                        hyphen_to: next_left.filter(|_| lyric.syllabic.hyphenated()),
+                        extend_to,
+                    });
+                }
+            }
+        }
+        self.lyrics = lyrics;
+    }
+
+    /// Writes each chord symbol over the left edge of its event's notehead,
+    /// clear of the top line.
+    fn place_chords(&mut self, score: &Score) {
+        let mut chords = Vec::new();
+        for note in &self.notes {
+            if score.notes[note.index].chord.is_none() {
+                continue;
+            }
+            let Some(staff) = self.staves.iter().find(|staff| staff.staff == note.staff) else {
+                continue;
+            };
+            let spacing = staff.line_spacing();
+            chords.push(ChordLayout {
+                staff: note.staff,
+                index: note.index,
+                anchor: Pos2::new(
+                    note.center.x - spacing * 0.6,
+                    staff.rect.top() - spacing * 1.2,
+                ),
+            });
+        }
+        self.chords = chords;
+    }
+
+    /// Stacks each note's articulations outwards from the notehead, in the
+    /// order they were added.
+    fn place_articulations(&mut self, score: &Score) {
+        for note in &self.notes {
+            let Some(staff) = self.staves.iter().find(|s| s.staff == note.shown_staff) else {
+                continue;
+            };
+            let spacing = staff.line_spacing();
+            let above = note.stem_down();
+            let mut offset = spacing * 1.2;
+            for articulation in &score.notes[note.index].articulations {
+                let center = if *articulation == Articulation::Fermata {
+                    let top = note.center.y.min(staff.rect.top());
+                    Pos2::new(note.center.x, top - spacing * 1.5)
+                } else {
+                    let y = if above { -offset } else { offset };
+                    offset += spacing;
+                    note.center + Vec2::new(0.0, y)
+                };
+                self.articulations.push(ArticulationLayout {
+                    staff: staff.staff,
+                    articulation: *articulation,
+                    center,
+                    above: above || *articulation == Articulation::Fermata,
+                });
+            }
+        }
+    }
+
+    /// Lays out markings and wedges `below` the bottom line of their staff.
+    fn place_dynamics(&mut self, score: &Score, locations: &[Option<EventLocation>], below: f32) {
+        for note in &self.notes {
+            let event = &score.notes[note.index];
+            let (Some(dynamic), Some(staff)) = (
+                event.dynamic,
+                self.staves.iter().find(|staff| staff.staff == note.staff),
+            ) else {
+                continue;
+            };
+            self.dynamics.push(DynamicLayout {
+                staff: note.staff,
+                dynamic,
+                center: Pos2::new(note.center.x, staff.rect.bottom() + below),
+            });
+        }
+
+        // Each voice's events, as indices into `score.notes`.
+        let mut streams: BTreeMap<(usize, u8), Vec<usize>> = BTreeMap::new();
+        for (index, (note, location)) in score.notes.iter().zip(locations).enumerate() {
+            if let Some(location) = location {
+                streams
+                    .entry((location.staff, note.voice))
+                    .or_default()
+                    .push(index);
+            }
+        }
+        let start = |index: usize| {
+            locations[index].map_or(0.0, |location| {
+                self.measure_map.start(location.measure) + location.beat
+            })
+        };
+
+        let page_start = self.measure_map.start(self.first_measure);
+        let page_end = self
+            .measure_map
+            .start(self.first_measure + MEASURES_PER_PAGE);
+        let mut hairpins = Vec::new();
+        for ((staff, _), stream) in streams {
+            let Some(layout) = self.staves.iter().find(|layout| layout.staff == staff) else {
+                continue;
+            };
+            let events: Vec<NoteEvent> = stream.iter().map(|i| score.notes[*i].clone()).collect();
+            for (hairpin, run) in dynamics::wedges(&events) {
+                let last = stream[run.end - 1];
+                let (start, end) = (
+                    start(stream[run.start]),
+                    start(last) + score.notes[last].beats(),
+                );
+                if end <= page_start + 1e-3 || start >= page_end - 1e-3 {
+                    continue;
+                }
+                let x = |beat: f32| {
+                    let (measure, offset) = self.measure_map.locate(beat);
+                    self.beat_x(layout, measure, offset)
+                };
+                // Wedges keep clear of the markings at either end.
+                let gap = layout.line_spacing() * 1.5;
+                let marked = events[run.start].dynamic.is_some();
+                let from =
+                    x(start).map_or(layout.rect.left(), |x| x + if marked { gap } else { 0.0 });
+                let to = x(end)
+                    .map_or(layout.rect.right(), |x| x - gap)
+                    .max(from + gap);
+                let y = layout.rect.bottom() + below;
+                hairpins.push(HairpinLayout {
+                    staff,
+                    hairpin,
+                    from: Pos2::new(from, y),
+                    to: Pos2::new(to, y),
+                });
+            }
+        }
+        self.hairpins = hairpins;
+    }
+
+    /// Lays out the tempo marks over the page's top staff. A gradual change
+    /// runs to the next mark, or to `end` beats after the last one.
+    pub fn place_tempo_marks(&mut self, marks: &[TempoMark], end: f32) {
+        let Some(top) = self.staves.first() else {
+            return;
+        };
+        let y = top.rect.top() - top.line_spacing() * 3.0;
+        let page_start = self.measure_map.start(self.first_measure);
+        let page_end = self
+            .measure_map
+            .start(self.first_measure + MEASURES_PER_PAGE);
+        let x = |beat: f32| {
+            let (measure, offset) = self.measure_map.locate(beat);
+            self.beat_x(top, measure, offset)
+        };
+
+        let mut tempos = Vec::new();
+        for (index, mark) in marks.iter().enumerate() {
+            let on_page = mark.beat >= page_start - 1e-3 && mark.beat < page_end - 1e-3;
+            let anchor = on_page
+                .then(|| x(mark.beat))
+                .flatten()
+                .map(|x| Pos2::new(x - self.padding * 0.5, y));
+            let until = marks.get(index + 1).map_or(end, |next| next.beat);
+            let extend_to = mark
+                .change
+                .filter(|_| mark.beat < page_end && until > page_start)
+                .map(|_| Pos2::new(x(until).unwrap_or(top.rect.right()), y));
+            if anchor.is_some() || extend_to.is_some() {
+                tempos.push(TempoLayout {
+                    mark: index,
+                    anchor,
+                    extend_to,
+                });
+            }
+        }
+        self.tempos = tempos;
+    }
+
+    /// Lays out the repeats, endings and navigation signs of the page's
+    /// measures. An ending over several measures is one bracket, cut at
+    /// the page edge.
+    pub fn place_navigation(&mut self, marks: &[NavigationMark]) {
+        let (Some(top), Some(system)) = (self.staves.first(), self.system) else {
+            return;
+        };
+        let spacing = top.line_spacing();
+        let left = |measure: usize| top.rect.left() + self.barlines[measure - self.first_measure];
+        let right =
+            |measure: usize| top.rect.left() + self.barlines[measure - self.first_measure + 1];
+        let ending = |measure: usize| {
+            marks.iter().find_map(|mark| match mark.navigation {
+                Navigation::Ending { passes } if mark.measure == measure => Some(passes),
+                _ => None,
+            })
+        };
+        let page = self.first_measure..self.first_measure + MEASURES_PER_PAGE;
+
+        let mut navigation = Vec::new();
+        for (index, mark) in marks.iter().enumerate() {
+            if !page.contains(&mark.measure) {
+                continue;
+            }
+            let x = left(mark.measure);
+            let rect = match mark.navigation {
+                Navigation::RepeatStart => Rect::from_x_y_ranges(x..=x, system.y_range()),
+                Navigation::RepeatEnd { .. } => {
+                    let x = right(mark.measure);
+                    Rect::from_x_y_ranges(x..=x, system.y_range())
+                }
+                Navigation::Ending { passes } => {
+                    // Measures of the same ending after the first extend its bracket.
+                    if mark.measure > page.start && ending(mark.measure - 1) == Some(passes) {
+                        continue;
+                    }
+                    let last = (mark.measure + 1..page.end)
+                        .take_while(|measure| ending(*measure) == Some(passes))
+                        .last()
+                        .unwrap_or(mark.measure);
+                    let y = top.rect.top() - spacing * 2.0;
+                    navigation.push(NavigationLayout {
+                        mark: index,
+                        rect: Rect::from_min_max(
+                            Pos2::new(x + spacing * 0.5, y),
+                            Pos2::new(right(last) - spacing * 0.5, y + spacing),
+                        ),
+                        closed: ending(last + 1).is_some_and(|next| next != passes),
+                    });
+                    continue;
+                }
+                // Signs and words stand clear of the tempo marks.
+                _ => {
+                    let y = top.rect.top() - spacing * 5.5;
+                    Rect::from_min_max(
+                        Pos2::new(x + self.padding * 0.5, y - spacing * 2.0),
+                        Pos2::new(right(mark.measure) - self.padding * 0.5, y),
+                    )
+                }
+            };
+            navigation.push(NavigationLayout {
+                mark: index,
+                rect,
+                closed: false,
+            });
+        }
+        self.navigation = navigation;
+    }
+
+    /// Joins the stems of the notes of `group` (indices into the score) with
+    /// beams. A group spread over two staves is beamed between them, stems
+    /// pointing at the beam from both sides.
+    fn beam(&mut self, score: &Score, group: &[usize]) {
+        let members: Vec<usize> = group
+            .iter()
+            .filter_map(|index| self.notes.iter().position(|note| note.index == *index))
+            .collect();
+        // Groups cut off by the edge of the page keep their flags.
+        if members.len() < 2 {
+            return;
+        }
+        let shown = |note: &NoteLayout| {
+            self.staves
+                .iter()
+                .find(|staff| staff.staff == note.shown_staff)
+                .copied()
+        };
+        let staves: Vec<StaffLayout> = members
+            .iter()
+            .filter_map(|member| shown(&self.notes[*member]))
+            .collect();
+        if staves.len() < members.len() {
+            return;
+        }
+        let spacing = staves[0].line_spacing();
+
+        let upper = staves
+            .iter()
+            .min_by(|a, b| a.rect.top().total_cmp(&b.rect.top()));
+        let lower = staves
+            .iter()
+            .max_by(|a, b| a.rect.top().total_cmp(&b.rect.top()));
+        let (directions, beam_y): (Vec<Stem>, f32) = match (upper, lower) {
+            (Some(upper), Some(lower)) if upper.staff != lower.staff => (
+                staves
+                    .iter()
+                    .map(|staff| {
+                        if staff.staff == upper.staff {
+                            Stem::Down
+                        } else {
+                            Stem::Up
+                        }
+                    })
+                    .collect(),
+                (upper.rect.bottom() + lower.rect.top()) / 2.0,
+            ),
+            _ => {
+                let notes = members.iter().map(|member| &self.notes[*member]);
+                let down = match self.notes[members[0]].stem {
+                    Stem::Auto => {
+                        let steps: i32 = notes.clone().map(|note| note.staff_step).sum();
+                        steps as f32 / members.len() as f32 >= 4.0
+                    }
+                    stem => stem == Stem::Down,
+                };
+                let beam_y = if down {
+                    notes.map(|note| note.center.y).fold(f32::MIN, f32::max) + spacing * 3.5
+                } else {
+                    notes.map(|note| note.center.y).fold(f32::MAX, f32::min) - spacing * 3.5
+                };
+                let stem = if down { Stem::Down } else { Stem::Up };
+                (vec![stem; members.len()], beam_y)
+            }
+        };
+
+        let mut ends = Vec::with_capacity(members.len());
+        for (member, stem) in members.iter().zip(&directions) {
+            let note = &mut self.notes[*member];
+            let x = match stem {
+                Stem::Down => note.center.x - spacing * 0.4,
+                _ => note.center.x + spacing * 0.4,
+            };
+            note.stem = *stem;
+            note.stem_end = Some(Pos2::new(x, beam_y));
+            ends.push(Pos2::new(x, beam_y));
+        }
+        self.beams.push([ends[0], ends[ends.len() - 1]]);
+
+        // Sixteenths get a second beam just inside the first; a lone one gets
+        // a stub pointing at its neighbour.
+        let inward = Vec2::new(
+            0.0,
+            match directions[0] {
+                Stem::Down => -spacing * 0.6,
+                _ => spacing * 0.6,
+            },
+        );
+        let sixteenth: Vec<bool> = members
+            .iter()
+            .map(|member| {
+                score.notes[self.notes[*member].index].duration == DurationValue::Sixteenth
+            })
+            .collect();
+        for (position, end) in ends.iter().enumerate() {
+            if !sixteenth[position] {
+                continue;
+            }
+            let start = *end + inward;
+            if sixteenth.get(position + 1) == Some(&true) {
+                self.beams.push([start, ends[position + 1] + inward]);
+            } else if position == 0 || !sixteenth[position - 1] {
+                let stub = if position + 1 < ends.len() {
+                    spacing
+                } else {
+                    -spacing
+                };
+                self.beams.push([start, start + Vec2::new(stub, 0.0)]);
+            }
+        }
+    }
+
+    /// Moves a down-stem notehead right of an up-stem one on the same beat
+    /// when they are a second apart, or a unison the two voices cannot share:
+    /// a unison of the same pitch, fill and dots is drawn as one notehead.
+    fn separate_noteheads(&mut self, score: &Score) {
+        let hollow = |duration| matches!(duration, DurationValue::Whole | DurationValue::Half);
+        let colliding: Vec<usize> = (0..self.notes.len())
+            .filter(|&lower| {
+                let layout = &self.notes[lower];
+                let note = &score.notes[layout.index];
+                layout.stem == Stem::Down
+                    && !note.rest
+                    && self.notes.iter().any(|upper| {
+                        let other = &score.notes[upper.index];
+                        upper.stem == Stem::Up
+                            && !other.rest
+                            && upper.shown_staff == layout.shown_staff
+                            && (upper.center.x - layout.center.x).abs() < 0.5
+                            && (upper.staff_step - layout.staff_step).abs() <= 1
+                            && !(other.pitch == note.pitch
+                                && other.dots == note.dots
+                                && hollow(other.duration) == hollow(note.duration))
+                    })
+            })
+            .collect();
+
+        for index in colliding {
+            let note = &mut self.notes[index];
+            let Some(staff) = self
+                .staves
+                .iter()
+                .find(|staff| staff.staff == note.shown_staff)
+            else {
+                continue;
+            };
+            let shift = Vec2::new(staff.line_spacing() * 1.1, 0.0);
+            note.center += shift;
+            note.hit_rect = note.hit_rect.translate(shift);
+        }
+    }
+
+    /// Horizontal position of `beat` inside `measure`, when that measure is on this page.
+    pub fn beat_x(&self, staff: &StaffLayout, measure: usize, beat: f32) -> Option<f32> {
+        let column = measure.checked_sub(self.first_measure)?;
+        if column >= MEASURES_PER_PAGE {
+            return None;
+        }
+        let left = staff.rect.left() + self.barlines[column] + self.padding;
+        let usable = self.barlines[column + 1] - self.barlines[column] - 2.0 * self.padding;
+        Some(left + beat / self.measure_map.length(measure) * usable)
+    }
+
+    /// The line or space under `pos` and the beat it falls on, snapped down to
+    /// multiples of `grid` beats. Positions are limited to four ledger lines.
+    pub fn insert_point(&self, pos: Pos2, grid: f32) -> Option<InsertPoint> {
+        let measure = self.measures.iter().find(|m| m.rect.contains(pos))?;
+        let staff = self.staves.iter().find(|s| s.staff == measure.staff)?;
+        let spacing = staff.line_spacing();
+
+        let staff_step =
+            (((staff.rect.bottom() - pos.y) / (spacing / 2.0)).round() as i32).clamp(-8, 16);
+        let pitch = Pitch::from_diatonic_number(
+            staff.part.clef().bottom_line().diatonic_number() + staff_step,
+        );
+
+        let column_left = measure.rect.left() + self.padding;
+        let usable = measure.rect.width() - 2.0 * self.padding;
+        let measure_beats = self.measure_map.length(measure.measure);
+        let raw = (pos.x - column_left) / usable * measure_beats;
+        let last_slot = ((measure_beats - grid) / grid).floor().max(0.0);
+        let beat = ((raw / grid).floor().clamp(0.0, last_slot)) * grid;
+
+        let x = self.beat_x(staff, measure.measure, beat)?;
+        Some(InsertPoint {
+            staff: staff.staff,
+            beat: self.measure_map.start(measure.measure) + beat,
+            pitch,
+            center: Pos2::new(x, staff.rect.bottom() - staff_step as f32 * spacing / 2.0),
+            staff_step,
+        })
+    }
+
+    /// Staff-high line at `beat`, counted from the start of the score.
+    pub fn caret_line(&self, staff: usize, beat: f32) -> Option<[Pos2; 2]> {
+        let staff = self.staves.iter().find(|layout| layout.staff == staff)?;
+        let (measure, offset) = self.measure_map.locate(beat);
+        let x = self.beat_x(staff, measure, offset)?;
+        let overhang = staff.line_spacing();
+        Some([
+            Pos2::new(x, staff.rect.top() - overhang),
+            Pos2::new(x, staff.rect.bottom() + overhang),
+        ])
+    }
+
+    /// Topmost target under `pos`: notes win over measures, measures over staff names.
+    pub fn hit_test(&self, pos: Pos2) -> Option<HitTarget> {
+        if let Some(note) = self
+            .notes
+            .iter()
+            .filter(|note| note.hit_rect.contains(pos))
+            .min_by(|a, b| {
+                a.center
+                    .distance_sq(pos)
+                    .total_cmp(&b.center.distance_sq(pos))
+            })
+        {
+            return Some(HitTarget::Note {
+                index: note.index,
+                staff: note.staff,
+                measure: note.measure,
+            });
+        }
+
+        if let Some(measure) = self.measures.iter().find(|m| m.rect.contains(pos)) {
+            return Some(HitTarget::Measure {
+                staff: measure.staff,
+                measure: measure.measure,
+            });
+        }
+
+        self.staves
+            .iter()
+            .find(|staff| staff.name_rect.contains(pos))
+            .map(|staff| HitTarget::Staff(staff.staff))
+    }
+
+    /// Notes whose glyph touches `area`, for rubber-band selection.
+    pub fn notes_in(&self, area: Rect) -> impl Iterator<Item = &NoteLayout> {
+        self.notes
+            .iter()
+            .filter(move |note| note.hit_rect.intersects(area))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::music::{DurationValue, Instrument, NoteEvent, Pitch, PitchClass, TimeSignature};
+
+    fn four_four() -> MeasureMap {
+        MeasureMap::new(TimeSignature::FourFour, &[])
+    }
+
+    fn quarter(class: PitchClass, octave: i8, instrument: Instrument) -> NoteEvent {
+        NoteEvent::new(
+            Pitch::new(class, octave),
+            DurationValue::Quarter,
+            instrument,
+        )
+    }
+
+    fn page_rect() -> Rect {
+        Rect::from_min_size(Pos2::ZERO, Vec2::new(860.0, 1180.0))
+    }
+
+    #[test]
+    fn events_are_barred_per_instrument() {
+        let score = Score {
+            notes: vec![
+                NoteEvent::new(
+                    Pitch::new(PitchClass::C, 5),
+                    DurationValue::Whole,
+                    Instrument::VIOLIN,
+                ),
+                quarter(PitchClass::C, 3, Instrument::CELLO),
+                quarter(PitchClass::D, 5, Instrument::VIOLIN),
+                quarter(PitchClass::E, 4, Instrument::PIANO),
+            ],
+        };
+        let parts = [Instrument::VIOLIN, Instrument::CELLO].map(Part::from);
+
+        let locations = locate_events(&score, &parts, &four_four());
+
+        assert_eq!(
+            locations,
+            vec![
+                Some(EventLocation {
+                    staff: 0,
+                    measure: 0,
+                    beat: 0.0
+                }),
+                Some(EventLocation {
+                    staff: 1,
+                    measure: 0,
+                    beat: 0.0
+                }),
+                Some(EventLocation {
+                    staff: 0,
+                    measure: 1,
+                    beat: 0.0
+                }),
+                None,
+            ]
+        );
+        assert_eq!(measure_count(&score, &parts, &four_four()), 2);
+        assert_eq!(measure_count(&Score::default(), &parts, &four_four()), 1);
+    }
+
+    #[test]
+    fn pitches_sit_on_their_clef_lines() {
+        let score = Score {
+            notes: vec![
+                quarter(PitchClass::E, 4, Instrument::VIOLIN),
+                quarter(PitchClass::F, 3, Instrument::VIOLA),
+                quarter(PitchClass::A, 3, Instrument::CELLO),
+            ],
+        };
+        let parts = [Instrument::VIOLIN, Instrument::VIOLA, Instrument::CELLO].map(Part::from);
+        let layout = PageLayout::new(page_rect(), 1.0, 0, &score, &parts, &four_four());
+
+        let steps: Vec<i32> = layout.notes.iter().map(|note| note.staff_step).collect();
+        assert_eq!(steps, vec![0, 0, 8]);
+        let violin = &layout.staves[0];
+        assert_eq!(layout.notes[0].center.y, violin.rect.bottom());
+        let cello = &layout.staves[2];
+        assert_eq!(layout.notes[2].center.y, cello.rect.top());
+    }
+
+    #[test]
+    fn voices_sharing_a_measure_split_stems_and_rests() {
+        let second = |note: NoteEvent| NoteEvent { voice: 1, ..note };
+        let score = Score {
+            notes: vec![
+                quarter(PitchClass::A, 4, Instrument::VIOLIN),
+                second(quarter(PitchClass::G, 4, Instrument::VIOLIN)),
+                second(NoteEvent::rest(DurationValue::Quarter, Instrument::VIOLIN)),
+                NoteEvent::new(
+                    Pitch::new(PitchClass::C, 5),
+                    DurationValue::Whole,
+                    Instrument::FLUTE,
+                ),
+            ],
+        };
+        let parts = [Instrument::VIOLIN, Instrument::FLUTE].map(Part::from);
+        let layout = PageLayout::new(page_rect(), 1.0, 0, &score, &parts, &four_four());
+
+        let stems: Vec<Stem> = layout.notes.iter().map(|note| note.stem).collect();
+        assert_eq!(stems, vec![Stem::Up, Stem::Down, Stem::Down, Stem::Auto]);
+        assert_eq!(layout.notes[2].staff_step, 0);
+        // A second apart on the same beat: the lower voice moves aside.
+        assert!(layout.notes[1].center.x > layout.notes[0].center.x);
+    }
+
+    #[test]
+    fn articulations_sit_opposite_the_stem_and_fermatas_above_the_staff() {
+        let mut low = quarter(PitchClass::G, 4, Instrument::VIOLIN);
+        low.articulations = vec![Articulation::Staccato, Articulation::Fermata];
+        let mut high = quarter(PitchClass::E, 5, Instrument::VIOLIN);
+        high.articulations = vec![Articulation::Accent, Articulation::Tenuto];
+        let score = Score {
+            notes: vec![low, high],
+        };
+        let parts = [Part::from(Instrument::VIOLIN)];
+        let layout = PageLayout::new(page_rect(), 1.0, 0, &score, &parts, &four_four());
+
+        let marks = &layout.articulations;
+        let (low, high) = (layout.notes[0].center, layout.notes[1].center);
+        // Stem up under the low note, so its staccato hangs below it.
+        assert!(!marks[0].above && marks[0].center.y > low.y);
+        assert!(marks[1].above && marks[1].center.y < layout.staves[0].rect.top());
+        // Stem down: the accent sits above, the tenuto further out.
+        assert!(marks[2].above && marks[2].center.y < high.y);
+        assert!(marks[3].center.y < marks[2].center.y);
+    }
+
+    #[test]
+    fn dynamics_and_wedges_sit_under_their_staff() {
+        let mut notes = vec![quarter(PitchClass::A, 4, Instrument::VIOLIN); 3];
+        notes[0].dynamic = Some(Dynamic::P);
+        notes[0].hairpin = Some(Hairpin::Crescendo);
+        notes[1].hairpin = Some(Hairpin::Crescendo);
+        notes[2].dynamic = Some(Dynamic::F);
+        let score = Score { notes };
+        let parts = [Part::from(Instrument::VIOLIN)];
+        let layout = PageLayout::new(page_rect(), 1.0, 0, &score, &parts, &four_four());
+
+        let staff = layout.staves[0].rect;
+        let marked: Vec<Dynamic> = layout.dynamics.iter().map(|d| d.dynamic).collect();
+        assert_eq!(marked, vec![Dynamic::P, Dynamic::F]);
+        assert!(layout.dynamics.iter().all(|d| d.center.y > staff.bottom()));
+
+        let [wedge] = layout.hairpins[..] else {
+            panic!("expected one wedge, got {:?}", layout.hairpins);
+        };
+        assert_eq!(wedge.hairpin, Hairpin::Crescendo);
+        assert!(wedge.from.x > layout.notes[0].center.x);
+        assert!(wedge.to.x < layout.notes[2].center.x);
+        assert!(wedge.from.x < wedge.to.x);
+    }
+
+    #[test]
+    fn tempo_marks_sit_above_the_system_and_changes_run_on_across_pages() {
+        use crate::music::TempoChange;
+
+        let mark = |beat, change| TempoMark {
+            beat,
+            text: String::new(),
+            bpm: None,
+            change,
+        };
+        let marks = [
+            mark(4.0, None),
+            mark(8.0, Some(TempoChange::Ritardando)),
+            mark(32.0, None),
+        ];
+        let parts = [Part::from(Instrument::VIOLIN)];
+        let mut first =
+            PageLayout::new(page_rect(), 1.0, 0, &Score::default(), &parts, &four_four());
+        first.place_tempo_marks(&marks, 40.0);
+
+        let staff = first.staves[0].rect;
+        let [plain, rit] = first.tempos[..] else {
+            panic!("expected two marks, got {:?}", first.tempos);
+        };
+        assert!(plain.anchor.unwrap().y < staff.top());
+        assert_eq!(plain.extend_to, None);
+        assert!(rit.anchor.unwrap().x > plain.anchor.unwrap().x);
+        assert_eq!(rit.extend_to.unwrap().x, staff.right());
+
+        let mut second =
+            PageLayout::new(page_rect(), 1.0, 1, &Score::default(), &parts, &four_four());
+        second.place_tempo_marks(&marks, 40.0);
+        let [running, last] = second.tempos[..] else {
+            panic!("expected two marks, got {:?}", second.tempos);
+        };
+        assert_eq!((running.mark, running.anchor), (1, None));
+        assert!(running.extend_to.unwrap().x < last.anchor.unwrap().x + first.padding);
+    }
+
+    #[test]
+    fn chord_symbols_sit_above_their_staff_and_make_room_for_it() {
+        let mut chord = NoteEvent::new(
+            Pitch::new(PitchClass::C, 4),
+            DurationValue::Whole,
+            Instrument::CELLO,
+        );
+        chord.chord = Some("Cmaj7".parse().unwrap());
+        let score = Score { notes: vec![chord] };
+        let parts = [
+            Part::from(Instrument::VIOLIN),
+            Part::from(Instrument::CELLO),
+        ];
+        let plain = PageLayout::new(page_rect(), 1.0, 0, &Score::default(), &parts, &four_four());
+        let layout = PageLayout::new(page_rect(), 1.0, 0, &score, &parts, &four_four());
+
+        assert_eq!(layout.staves[0].rect, plain.staves[0].rect);
+        let cello = layout.staves[1].rect;
+        assert!(cello.top() > plain.staves[1].rect.top());
+        let [symbol] = layout.chords[..] else {
+            panic!("expected one chord symbol, got {:?}", layout.chords);
+        };
+        assert_eq!((symbol.staff, symbol.index), (1, 0));
+        assert!(symbol.anchor.y < cello.top());
+        assert!(symbol.anchor.y > layout.staves[0].rect.bottom());
+    }
+
+    #[test]
+    fn lyrics_widen_their_measure_and_hyphens_reach_the_next_syllable() {
+        use crate::music::{Lyric, Syllabic};
+
+        let sixteenth = |class| {
+            NoteEvent::new(
+                Pitch::new(class, 5),
+                DurationValue::Sixteenth,
+                Instrument::VIOLIN,
+            )
+        };
+        let mut notes: Vec<NoteEvent> =
+            [PitchClass::C, PitchClass::D, PitchClass::E, PitchClass::F]
+                .into_iter()
+                .map(sixteenth)
+                .collect();
+        let lyric = |text: &str, syllabic, extend| Lyric {
+            verse: 0,
+            text: text.to_owned(),
+            syllabic,
+            extend,
+        };
+        notes[0].lyrics = vec![lyric("Hal", Syllabic::Begin, false)];
+        notes[1].lyrics = vec![lyric("le", Syllabic::Middle, false)];
+        notes[2].lyrics = vec![lyric("lujah", Syllabic::End, true)];
+        let score = Score { notes };
+        let parts = [
+            Part::from(Instrument::VIOLIN),
+            Part::from(Instrument::CELLO),
+        ];
+        let plain = PageLayout::new(page_rect(), 1.0, 0, &Score::default(), &parts, &four_four());
+        let layout = PageLayout::new(page_rect(), 1.0, 0, &score, &parts, &four_four());
+
+        let width = |layout: &PageLayout, column: usize| {
+            layout.barlines()[column + 1] - layout.barlines()[column]
+        };
+        assert!(width(&layout, 0) > width(&plain, 0));
+        assert!(width(&layout, 1) < width(&plain, 1));
+        assert!(
+            (layout.barlines()[MEASURES_PER_PAGE] - plain.barlines()[MEASURES_PER_PAGE]).abs()
+                < 1e-3
+        );
+        // The staff under the lyrics moves down to make room for them.
+        assert!(layout.staves[1].rect.top() > plain.staves[1].rect.top());
+
+        let [hal, le, lujah] = layout.lyrics[..] else {
+            panic!("expected three syllables, got {:?}", layout.lyrics);
+        };
+        assert!(hal.center.y > layout.staves[0].rect.bottom());
+        assert!(hal.hyphen_to.unwrap() <= le.center.x - le.width / 2.0);
+        assert_eq!(le.extend_to, None);
+        assert_eq!(lujah.hyphen_to, None);
+        // The melisma runs on under the note after the syllable.
+        let held = layout.notes.iter().find(|note| note.index == 3).unwrap();
+        assert!(lujah.extend_to.unwrap() > held.center.x);
+    }
+
+    #[test]
+    fn endings_are_bracketed_over_their_measures_and_repeats_cross_the_system() {
+        let mark = |measure, navigation| NavigationMark {
+            measure,
+            navigation,
+        };
+        let marks = [
+            mark(1, Navigation::Ending { passes: 0b01 }),
+            mark(2, Navigation::Ending { passes: 0b01 }),
+            mark(2, Navigation::RepeatEnd { times: 2 }),
+            mark(3, Navigation::Ending { passes: 0b10 }),
+            mark(6, Navigation::Segno),
+        ];
+        let parts = [
+            Part::from(Instrument::VIOLIN),
+            Part::from(Instrument::CELLO),
+        ];
+        let mut layout =
+            PageLayout::new(page_rect(), 1.0, 0, &Score::default(), &parts, &four_four());
+        layout.place_navigation(&marks);
+
+        let [first, repeat, second] = layout.navigation[..] else {
+            panic!("expected three marks, got {:?}", layout.navigation);
+        };
+        let staff = layout.staves[0].rect;
+        assert_eq!(first.mark, 0);
+        assert!(first.closed && !second.closed);
+        assert!(first.rect.bottom() < staff.top());
+        assert!(first.rect.right() < repeat.rect.left());
+        assert!(second.rect.left() > repeat.rect.left());
+        assert_eq!(repeat.rect.y_range(), layout.system.unwrap().y_range());
+    }
+
+    #[test]
+    fn grand_staff_is_braced_and_beams_cross_between_its_staves() {
+        let piano = Part::from(Instrument::PIANO);
+        let eighth =
+            |class, octave| NoteEvent::new(Pitch::new(class, octave), DurationValue::Eighth, piano);
+        let score = Score {
+            notes: vec![
+                eighth(PitchClass::E, 5),
+                NoteEvent {
+                    cross_staff: 1,
+                    ..eighth(PitchClass::C, 3)
+                },
+                eighth(PitchClass::G, 4),
+                NoteEvent::rest(DurationValue::Eighth, piano),
+            ],
+        };
+        let parts = [piano, piano.on_staff(1), Instrument::CELLO.into()];
+        let layout = PageLayout::new(page_rect(), 1.0, 0, &score, &parts, &four_four());
+
+        let (upper, lower) = (layout.staves[0].rect, layout.staves[1].rect);
+        assert_eq!(layout.braces, vec![upper.union(lower)]);
+        assert_eq!(layout.notes[1].shown_staff, 1);
+        assert_eq!(layout.notes[1].staff_step, 3);
+
+        // The first beat is beamed between the staves; the third eighth is alone.
+        assert_eq!(layout.beams.len(), 1);
+        assert_eq!(layout.notes[0].stem, Stem::Down);
+        assert_eq!(layout.notes[1].stem, Stem::Up);
+        let beam_y = layout.notes[0].stem_end.map(|end| end.y);
+        assert_eq!(beam_y, Some((upper.bottom() + lower.top()) / 2.0));
+        assert_eq!(layout.notes[2].stem_end, None);
+    }
+
+    #[test]
+    fn families_are_bracketed_and_divisi_sub_bracketed() {
+        let violin = Part::from(Instrument::VIOLIN);
+        let piano = Part::from(Instrument::PIANO);
+        let parts = [
+            Instrument::FLUTE.into(),
+            Instrument::CLARINET.into(),
+            Instrument::HORN.into(),
+            violin,
+            Part {
+                player: 1,
+                ..violin
+            },
+            piano,
+            piano.on_staff(1),
+            Instrument::CELLO.into(),
+        ];
+        let layout = PageLayout::new(page_rect(), 1.0, 0, &Score::default(), &parts, &four_four());
+        let span =
+            |first: usize, last: usize| layout.staves[first].rect.union(layout.staves[last].rect);
+
+        assert_eq!(layout.system, Some(span(0, 7)));
+        assert_eq!(
+            layout.brackets,
+            vec![span(0, 1), span(2, 2), span(3, 4), span(7, 7)]
+        );
+        assert_eq!(layout.sub_brackets, vec![span(3, 4)]);
+        assert_eq!(layout.braces, vec![span(5, 6)]);
+    }
+
+    #[test]
+    fn hit_test_prefers_notes_then_measures_then_names() {
+        let score = Score {
+            notes: vec![
+                quarter(PitchClass::G, 4, Instrument::FLUTE),
+                quarter(PitchClass::A, 4, Instrument::FLUTE),
+            ],
+        };
+        let parts = [Instrument::FLUTE, Instrument::CLARINET].map(Part::from);
+        let layout = PageLayout::new(page_rect(), 1.0, 0, &score, &parts, &four_four());
+
+        let second = layout.notes[1];
+        assert_eq!(
+            layout.hit_test(second.center),
+            Some(HitTarget::Note {
+                index: 1,
+                staff: 0,
+                measure: 0
+            })
+        );
+
+        let clarinet = layout.staves[1];
+        let third_bar = layout
+            .measures
+            .iter()
+            .find(|m| m.staff == 1 && m.measure == 2)
+            .expect("measure laid out");
+        assert_eq!(
+            layout.hit_test(third_bar.rect.center()),
+            Some(HitTarget::Measure {
+                staff: 1,
+                measure: 2
+            })
+        );
+        assert_eq!(
+            layout.hit_test(clarinet.name_rect.center()),
+            Some(HitTarget::Staff(1))
+        );
+        assert_eq!(layout.hit_test(Pos2::new(430.0, 20.0)), None);
+    }
+
+    #[test]
+    fn insert_point_snaps_to_the_grid_and_reads_the_clef() {
+        let parts = [Instrument::VIOLIN, Instrument::CELLO].map(Part::from);
+        let layout = PageLayout::new(page_rect(), 1.0, 0, &Score::default(), &parts, &four_four());
+        let cello = layout.staves[1];
+        let second_bar = layout
+            .measures
+            .iter()
+            .find(|m| m.staff == 1 && m.measure == 1)
+            .expect("measure laid out")
+            .rect;
+
+        let point = layout
+            .insert_point(
+                Pos2::new(second_bar.center().x + 3.0, cello.rect.top() + 1.0),
+                1.0,
+            )
+            .expect("pointer is on the staff");
+
+        // Top line of the bass clef is A3; the middle of the bar is beat 2 of 4.
+        assert_eq!(point.pitch, Pitch::new(PitchClass::A, 3));
+        assert_eq!(point.staff, 1);
+        assert!((point.beat - 6.0).abs() < 1e-4);
+        assert_eq!(point.center.y, cello.rect.top());
+
+        let whole = layout
+            .insert_point(second_bar.right_center() - Vec2::new(2.0, 0.0), 4.0)
+            .expect("pointer is on the staff");
+        assert!((whole.beat - 4.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn later_pages_show_later_measures() {
+        let score = Score {
+            notes: (0..30)
+                .map(|_| quarter(PitchClass::C, 5, Instrument::TRUMPET))
+                .collect(),
+        };
+        let parts = [Instrument::TRUMPET].map(Part::from);
+        let layout = PageLayout::new(page_rect(), 1.0, 1, &score, &parts, &four_four());
+
+        assert_eq!(layout.first_measure, 6);
+        assert_eq!(
+            layout
+                .notes
+                .iter()
+                .map(|note| note.index)
+                .collect::<Vec<_>>(),
+            vec![24, 25, 26, 27, 28, 29]
+        );
+        assert_eq!(page_count(8), 2);
+
+        let area = Rect::from_two_pos(layout.notes[0].center, layout.notes[2].center);
+        let picked: Vec<usize> = layout.notes_in(area).map(|note| note.index).collect();
+        assert_eq!(picked, vec![24, 25, 26]);
+    }
+}
@@ -0,0 +1,299 @@
+//! Lyric entry: syllables typed under one note after another of a voice.
+//!
+//! | Key           | Action                                                  |
+//! |---------------|---------------------------------------------------------|
+//! | text          | typed into the syllable                                 |
+//! | `Space`       | ends the word and moves to the next note                |
+//! | `-`           | hyphen to the next syllable, moves to the next note     |
+//! | `_`           | extender under the next note as well (melisma)          |
+//! | `Backspace`   | deletes the last character                              |
+//! | `←` / `→`     | previous / next note, keeping the syllable as it is     |
+//!
+//! Notes tied from the note before hold its syllable and are skipped.
+
+use crate::music::{Lyric, Score, Syllabic};
+use crate::note_input::InputEdit;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LyricKey {
+    Char(char),
+    Space,
+    Hyphen,
+    Extend,
+    Backspace,
+    Left,
+    Right,
+}
+
+impl LyricKey {
+    /// The keys in text typed by the user.
+    pub fn from_text(text: &str) -> impl Iterator<Item = Self> + '_ {
+        text.chars().map(|ch| match ch {
+            ' ' => Self::Space,
+            '-' => Self::Hyphen,
+            '_' => Self::Extend,
+            ch => Self::Char(ch),
+        })
+    }
+
+    pub fn from_key(key: egui::Key) -> Option<Self> {
+        match key {
+            egui::Key::Backspace => Some(Self::Backspace),
+            egui::Key::ArrowLeft => Some(Self::Left),
+            egui::Key::ArrowRight => Some(Self::Right),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LyricInput {
+    /// Note being written under, as an index into `Score::notes`.
+    pub note: Option<usize>,
+    /// Counted from 0.
+    pub verse: u8,
+    /// The syllable as typed so far.
+    pub text: String,
+}
+
+impl LyricInput {
+    pub fn is_active(&self) -> bool {
+        self.note.is_some()
+    }
+
+    /// Starts at the event at `index`, or at the first note after it when it
+    /// is a rest or held by a tie.
+    pub fn start(&mut self, score: &Score, index: usize) -> bool {
+        let stream = sung_notes(score, index);
+        match stream.into_iter().find(|note| *note >= index) {
+            Some(note) => {
+                self.move_to(score, note);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn stop(&mut self) {
+        self.note = None;
+        self.text.clear();
+    }
+
+    /// Applies `key`; a finished syllable comes back as the edit of its note.
+    pub fn apply(&mut self, key: LyricKey, score: &Score) -> Option<InputEdit> {
+        let note = self.note?;
+        let stream = sung_notes(score, note);
+        let position = stream.iter().position(|index| *index == note)?;
+        let next = stream.get(position + 1).copied();
+        // A hyphen before the syllable makes it the middle or end of a word.
+        let continues = stream[..position]
+            .iter()
+            .rev()
+            .find_map(|index| score.notes[*index].lyric(self.verse))
+            .is_some_and(|lyric| lyric.syllabic.hyphenated());
+
+        let edit = match key {
+            LyricKey::Char(ch) => {
+                self.text.push(ch);
+                return None;
+            }
+            LyricKey::Backspace => {
+                self.text.pop();
+                return None;
+            }
+            LyricKey::Left | LyricKey::Right => {
+                let current = score.notes[note].lyric(self.verse);
+                let edit = self.write(
+                    score,
+                    note,
+                    current.map_or(Syllabic::Single, |lyric| lyric.syllabic),
+                    current.is_some_and(|lyric| lyric.extend),
+                );
+                let target = if key == LyricKey::Left {
+                    position.checked_sub(1).map(|position| stream[position])
+                } else {
+                    next
+                };
+                if let Some(target) = target {
+                    self.move_to(score, target);
+                }
+                return edit;
+            }
+            LyricKey::Space => {
+                let syllabic = if continues {
+                    Syllabic::End
+                } else {
+                    Syllabic::Single
+                };
+                self.write(score, note, syllabic, false)
+            }
+            LyricKey::Hyphen => {
+                let syllabic = if continues {
+                    Syllabic::Middle
+                } else {
+                    Syllabic::Begin
+                };
+                self.write(score, note, syllabic, false)
+            }
+            // With nothing typed, the syllable before is held on under this note.
+            LyricKey::Extend if self.text.is_empty() => {
+                stream[..position].iter().rev().find_map(|index| {
+                    let lyric = score.notes[*index].lyric(self.verse)?;
+                    let mut after = score.notes[*index].clone();
+                    after.set_lyric(Lyric {
+                        extend: true,
+                        ..lyric.clone()
+                    });
+                    Some(InputEdit::Replace {
+                        index: *index,
+                        after,
+                    })
+                })
+            }
+            LyricKey::Extend => {
+                let syllabic = if continues {
+                    Syllabic::End
+                } else {
+                    Syllabic::Single
+                };
+                self.write(score, note, syllabic, true)
+            }
+        };
+
+        match next {
+            Some(next) => self.move_to(score, next),
+            None => self.stop(),
+        }
+        edit
+    }
+
+    /// The note at `index` with the typed syllable, when that changes it.
+    fn write(
+        &self,
+        score: &Score,
+        index: usize,
+        syllabic: Syllabic,
+        extend: bool,
+    ) -> Option<InputEdit> {
+        let before = &score.notes[index];
+        let mut after = before.clone();
+        after.set_lyric(Lyric {
+            verse: self.verse,
+            text: self.text.clone(),
+            syllabic,
+            extend,
+        });
+        (after != *before).then_some(InputEdit::Replace { index, after })
+    }
+
+    fn move_to(&mut self, score: &Score, index: usize) {
+        self.note = Some(index);
+        self.text = score.notes[index]
+            .lyric(self.verse)
+            .map(|lyric| lyric.text.clone())
+            .unwrap_or_default();
+    }
+}
+
+/// Notes of the voice of the event at `index` that take a syllable, in
+/// order: every note but those tied from the note before.
+fn sung_notes(score: &Score, index: usize) -> Vec<usize> {
+    let Some(event) = score.notes.get(index) else {
+        return Vec::new();
+    };
+    let mut tied = false;
+    let mut notes = Vec::new();
+    for (index, note) in score.notes.iter().enumerate() {
+        if note.part() != event.part() || note.voice != event.voice {
+            continue;
+        }
+        if !note.rest && !tied {
+            notes.push(index);
+        }
+        tied = note.tie && !note.rest;
+    }
+    notes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::music::{DurationValue, Instrument, NoteEvent, Pitch, PitchClass};
+
+    fn soprano(class: PitchClass) -> NoteEvent {
+        NoteEvent::new(
+            Pitch::new(class, 5),
+            DurationValue::Quarter,
+            Instrument::VIOLIN,
+        )
+    }
+
+    fn type_text(input: &mut LyricInput, score: &mut Score, text: &str) {
+        for key in LyricKey::from_text(text) {
+            if let Some(InputEdit::Replace { index, after }) = input.apply(key, score) {
+                score.notes[index] = after;
+            }
+        }
+    }
+
+    #[test]
+    fn syllables_advance_note_by_note_with_hyphens_and_melismas() {
+        let mut tied = soprano(PitchClass::E);
+        tied.tie = true;
+        let mut score = Score {
+            notes: vec![
+                soprano(PitchClass::C),
+                soprano(PitchClass::D),
+                NoteEvent::rest(DurationValue::Quarter, Instrument::VIOLIN),
+                tied,
+                soprano(PitchClass::E),
+                soprano(PitchClass::F),
+                soprano(PitchClass::G),
+            ],
+        };
+        let mut input = LyricInput::default();
+        assert!(input.start(&score, 0));
+
+        type_text(&mut input, &mut score, "Glo-ri-a_in ");
+        let syllable = |index: usize| score.notes[index].lyric(0).cloned();
+        let glo = syllable(0).unwrap();
+        assert_eq!((glo.text.as_str(), glo.syllabic), ("Glo", Syllabic::Begin));
+        assert_eq!(syllable(1).unwrap().syllabic, Syllabic::Middle);
+        // The rest and the note held by the tie take no syllable.
+        assert_eq!(syllable(2), None);
+        let a = syllable(3).unwrap();
+        assert_eq!((a.syllabic, a.extend), (Syllabic::End, true));
+        assert_eq!(syllable(4), None);
+        assert_eq!(syllable(5).unwrap().syllabic, Syllabic::Single);
+        assert_eq!(input.note, Some(6));
+    }
+
+    #[test]
+    fn an_empty_syllable_removes_the_old_one_and_arrows_keep_it() {
+        let mut score = Score {
+            notes: vec![soprano(PitchClass::C), soprano(PitchClass::D)],
+        };
+        score.notes[0].set_lyric(Lyric {
+            verse: 1,
+            text: "la".to_owned(),
+            syllabic: Syllabic::Single,
+            extend: false,
+        });
+        let mut input = LyricInput {
+            verse: 1,
+            ..LyricInput::default()
+        };
+        input.start(&score, 0);
+        assert_eq!(input.text, "la");
+
+        assert_eq!(input.apply(LyricKey::Right, &score), None);
+        assert_eq!(input.apply(LyricKey::Left, &score), None);
+        input.apply(LyricKey::Backspace, &score);
+        input.apply(LyricKey::Backspace, &score);
+        let Some(InputEdit::Replace { index, after }) = input.apply(LyricKey::Space, &score) else {
+            panic!("expected the syllable to be removed");
+        };
+        assert_eq!(index, 0);
+        assert!(after.lyrics.is_empty());
+    }
+}
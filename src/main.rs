@@ -7,20 +7,52 @@ compile_error!("Notarium atualmente é suportado apenas no Windows.");
 compile_error!("Notarium suporta apenas arquiteturas x64 (64-bit).");
 
 mod audio;
+mod clipboard;
+mod dynamics;
+mod form;
+mod harmony;
+mod history;
+mod instruments;
+mod layout;
+mod lyrics;
+mod measures;
+mod midi;
 mod music;
+mod musicxml;
 mod notation;
+mod note_edit;
+mod note_input;
+mod ntr;
+mod ranges;
+mod rebar;
+mod selection;
+mod staves;
+mod tempo;
+mod transpose;
 
 use egui::{self, ViewportId};
 use egui_glium::EguiGlium;
 use glium::backend::glutin::SimpleWindowBuilder;
 use glium::winit;
 use glium::Surface;
+use std::ops::Range;
 use std::path::PathBuf;
 
+use harmony::ChordSymbol;
+use history::{EditCommand, EditHistory};
+use layout::HitTarget;
+use lyrics::{LyricInput, LyricKey};
 use music::{
-    DurationValue, Instrument, KeySignature, NoteEvent, PaperSize, Pitch, PitchClass, Score,
-    ScoreSettings, TimeSignature,
+    Articulation, DurationValue, Dynamic, Family, Hairpin, Instrument, KeySignature, Lyric,
+    Navigation, NavigationMark, NoteEvent, PaperSize, Part, Pitch, PitchClass, RangeLevel, Score,
+    ScoreSettings, Staff, TempoChange, TempoMark, TimeSignature, Until,
 };
+use notation::PageInteraction;
+use note_edit::{DeleteMode, NoteChange};
+use note_input::{InputEdit, InputKey, NoteInput};
+use selection::Selection;
+use staves::ScoreOrder;
+use transpose::{Interval, TransposeBy, TransposeMode, TransposeOptions, TransposeScope};
 
 fn main() {
     install_panic_hook();
@@ -166,6 +198,14 @@ enum UiTab {
     View,
 }
 
+/// Where the bars dialog inserts new measures.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BarPlacement {
+    Before,
+    After,
+    End,
+}
+
 struct NotariumApp {
     score: Score,
     settings: ScoreSettings,
@@ -176,18 +216,58 @@ struct NotariumApp {
     start_paper_size: PaperSize,
     selected_pitch: PitchClass,
     selected_octave: i8,
-    selected_duration: DurationValue,
-    selected_instrument: Instrument,
-    bpm: f32,
+    /// Staff that "Adicionar Nota" writes to.
+    selected_staff: usize,
+    /// Opening tempo of the score the start screen creates.
+    start_bpm: f32,
+    /// Playback speed as a percentage of the score's tempo.
+    playback_speed: f32,
+    /// Whether playback and export take repeat barlines and every ending.
+    play_repeats: bool,
+    /// Whether playback and WAV export voice the chord symbols on the piano.
+    play_chords: bool,
     screen: AppScreen,
     active_tab: UiTab,
     playback: audio::PlaybackController,
     is_paused: bool,
-    orchestral_order: Vec<Instrument>,
     zoom_percent: f32,
+    /// Shows sounding pitches; otherwise transposing instruments are written
+    /// as their players read them.
+    concert_pitch: bool,
     file_path_input: String,
     start_message: String,
     recent_scores: Vec<PathBuf>,
+    import_log: musicxml::ImportLog,
+    show_import_log: bool,
+    history: EditHistory,
+    selection: Selection,
+    clipboard: Option<clipboard::Passage>,
+    note_input: NoteInput,
+    delete_mode: DeleteMode,
+    show_instruments: bool,
+    new_staff_instrument: Instrument,
+    /// Staff names being typed in the instruments dialog.
+    staff_drafts: Vec<Staff>,
+    show_transpose: bool,
+    transpose: TransposeOptions,
+    show_bars: bool,
+    /// Measures the bars dialog inserts at a time.
+    bar_count: u32,
+    /// Length in beats of the pickup the bars dialog adds.
+    pickup_beats: f32,
+    show_tempo: bool,
+    /// Mark the tempo dialog adds; its beat comes from the selection.
+    tempo_draft: TempoMark,
+    show_navigation: bool,
+    /// Mark the repeats dialog adds to the selected measures.
+    navigation_draft: Navigation,
+    lyric_input: LyricInput,
+    /// Chord symbol typed in the ribbon, as in `F#m7b5/C`.
+    chord_text: String,
+    /// Refuses notes outside the score's instrument ranges instead of
+    /// entering them with a warning.
+    block_out_of_range: bool,
+    show_range_report: bool,
 }
 
 impl Default for NotariumApp {
@@ -201,31 +281,51 @@ impl Default for NotariumApp {
             start_key_signature: settings.key_signature,
             start_time_signature: settings.time_signature,
             start_paper_size: settings.paper_size,
+            start_bpm: settings.bpm,
             settings,
             selected_pitch: PitchClass::C,
             selected_octave: 4,
-            selected_duration: DurationValue::Quarter,
-            selected_instrument: Instrument::Violin,
-            bpm: 110.0,
+            selected_staff: 0,
+            playback_speed: 100.0,
+            play_repeats: true,
+            play_chords: false,
             screen: AppScreen::Start,
             active_tab: UiTab::Home,
             playback: audio::create_playback_controller(),
             is_paused: false,
-            orchestral_order: vec![
-                Instrument::Flute,
-                Instrument::Clarinet,
-                Instrument::Horn,
-                Instrument::Trumpet,
-                Instrument::Violin,
-                Instrument::Viola,
-                Instrument::Cello,
-                Instrument::Timpani,
-                Instrument::Piano,
-            ],
             zoom_percent: 62.5,
+            concert_pitch: false,
             file_path_input: "notarium_score.ntr".to_owned(),
             start_message: "Pronto para criar ou abrir partitura.".to_owned(),
             recent_scores: find_recent_ntr_files(),
+            import_log: musicxml::ImportLog::default(),
+            show_import_log: false,
+            history: EditHistory::default(),
+            selection: Selection::default(),
+            clipboard: None,
+            note_input: NoteInput::default(),
+            delete_mode: DeleteMode::default(),
+            show_instruments: false,
+            new_staff_instrument: Instrument::VIOLIN,
+            staff_drafts: Vec::new(),
+            show_transpose: false,
+            transpose: TransposeOptions::default(),
+            show_bars: false,
+            bar_count: 1,
+            pickup_beats: 1.0,
+            show_tempo: false,
+            tempo_draft: TempoMark {
+                beat: 0.0,
+                text: "Allegro".to_owned(),
+                bpm: Some(120.0),
+                change: None,
+            },
+            show_navigation: false,
+            navigation_draft: Navigation::RepeatEnd { times: 2 },
+            lyric_input: LyricInput::default(),
+            chord_text: String::new(),
+            block_out_of_range: false,
+            show_range_report: false,
         }
     }
 }
@@ -244,23 +344,1027 @@ impl NotariumApp {
             composer: self.start_composer.trim().to_owned(),
             key_signature: self.start_key_signature,
             time_signature: self.start_time_signature,
+            meter_changes: Vec::new(),
             paper_size: self.start_paper_size,
+            staves: ScoreSettings::default().staves,
+            bpm: self.start_bpm,
+            tempo_marks: Vec::new(),
+            navigation: Vec::new(),
+            range_level: RangeLevel::default(),
         };
         self.score.notes.clear();
+        self.history.clear();
+        self.note_input.stop();
+        self.selection.clear();
         self.screen = AppScreen::Editor;
         self.start_message = "Nova partitura criada.".to_owned();
     }
 
-    fn save_ntr(&mut self) {
-        let path = sanitized_ntr_path(&self.file_path_input);
-        let payload = serialize_ntr(
-            &self.settings,
+    fn apply_edit(&mut self, command: EditCommand) {
+        self.history
+            .apply(&mut self.score, &mut self.settings, command);
+        self.selection.retain_valid(self.score.notes.len());
+    }
+
+    fn change_settings(&mut self, after: ScoreSettings) {
+        if after != self.settings {
+            self.apply_edit(EditCommand::ChangeSettings {
+                before: Box::new(self.settings.clone()),
+                after: Box::new(after),
+            });
+        }
+    }
+
+    fn replace_last_note(&mut self) {
+        let Some(index) = self.score.notes.len().checked_sub(1) else {
+            return;
+        };
+        let before = self.score.notes[index].clone();
+        let after = NoteEvent {
+            pitch: Pitch::new(self.selected_pitch, self.selected_octave),
+            duration: self.note_input.duration,
+            rest: false,
+            ..before.clone()
+        };
+        let mut notes = self.score.notes.clone();
+        notes[index] = after.clone();
+        if after != before && self.range_allows(&notes) {
+            self.apply_edit(EditCommand::ReplaceNote {
+                index,
+                before,
+                after,
+            });
+        }
+    }
+
+    /// Removing a note also drops a tie that would now lead nowhere.
+    fn remove_last_note(&mut self) {
+        let Some(index) = self.score.notes.len().checked_sub(1) else {
+            return;
+        };
+        let note = self.score.notes[index].clone();
+
+        self.history.begin_group("Apagar nota");
+        if let Some(previous) = index.checked_sub(1) {
+            let before = self.score.notes[previous].clone();
+            if before.tie && before.instrument == note.instrument {
+                self.apply_edit(EditCommand::ReplaceNote {
+                    index: previous,
+                    after: NoteEvent {
+                        tie: false,
+                        ..before.clone()
+                    },
+                    before,
+                });
+            }
+        }
+        self.apply_edit(EditCommand::RemoveNote { index, note });
+        self.history.end_group();
+    }
+
+    fn change_selected(&mut self, change: NoteChange) {
+        let indices = self.selected_events();
+        if indices.is_empty() {
+            self.start_message = "Selecione as notas a alterar.".to_owned();
+            return;
+        }
+        let after = note_edit::change(&self.score, &indices, change);
+        if after != self.score.notes && self.range_allows(&after) {
+            self.apply_edit(EditCommand::replace_notes(&self.score.notes, after));
+        }
+    }
+
+    fn delete_selected(&mut self) {
+        let indices = self.selected_events();
+        if indices.is_empty() {
+            return;
+        }
+        let after = note_edit::delete(&self.score, &indices, self.delete_mode);
+        self.history.begin_group("Apagar notas");
+        self.apply_edit(EditCommand::replace_notes(&self.score.notes, after));
+        self.history.end_group();
+        if self.delete_mode == DeleteMode::Shift {
+            // Indices past the first deleted event now point at other notes.
+            self.selection.clear();
+        }
+        self.start_message = match indices.len() {
+            1 => "1 evento apagado.".to_owned(),
+            count => format!("{count} eventos apagados."),
+        };
+    }
+
+    /// Inserts the note set up in the side panel just before the first
+    /// selected event, on that event's staff.
+    fn insert_before_selection(&mut self) {
+        let Some(&index) = self.selected_events().first() else {
+            self.start_message = "Selecione a nota antes da qual inserir.".to_owned();
+            return;
+        };
+        let selected = &self.score.notes[index];
+        let note = NoteEvent {
+            voice: selected.voice,
+            ..NoteEvent::new(
+                Pitch::new(self.selected_pitch, self.selected_octave),
+                self.note_input.duration,
+                selected.part(),
+            )
+        };
+        let mut notes = self.score.notes.clone();
+        notes.insert(index, note.clone());
+        if self.range_allows(&notes) {
+            self.apply_edit(EditCommand::InsertNote { index, note });
+            self.selection.clear();
+        }
+    }
+
+    /// Whether the score may become `after`: notes it newly puts outside
+    /// the ranges of the score's level are refused when blocking is on, and
+    /// otherwise go in with a warning.
+    fn range_allows(&mut self, after: &[NoteEvent]) -> bool {
+        let level = self.settings.range_level;
+        let Some(note) = ranges::newly_out_of_range(&self.score.notes, after, level) else {
+            return true;
+        };
+        let (low, high) = note.instrument.range(level);
+        let problem = format!(
+            "{} está fora da extensão {} de {} ({}–{})",
+            ranges::pitch_label(note.pitch),
+            level.label().to_lowercase(),
+            note.instrument.label(),
+            ranges::pitch_label(low),
+            ranges::pitch_label(high)
+        );
+        self.start_message = if self.block_out_of_range {
+            format!("{problem}; alteração recusada.")
+        } else {
+            format!("Atenção: {problem}.")
+        };
+        !self.block_out_of_range
+    }
+
+    fn selected_part(&self) -> Option<Part> {
+        self.settings
+            .staves
+            .get(self.selected_staff)
+            .map(|staff| staff.part)
+    }
+
+    /// Replaces the staff list as one undo step. Selections and the caret
+    /// refer to staff positions, so they are dropped when the order changes.
+    fn change_staves(&mut self, label: &str, staves: Vec<Staff>) {
+        if staves == self.settings.staves {
+            return;
+        }
+        let parts_before = self.settings.parts();
+
+        self.history.begin_group(label);
+        self.change_settings(ScoreSettings {
+            staves,
+            ..self.settings.clone()
+        });
+        self.history.end_group();
+
+        if self.settings.parts() != parts_before {
+            self.selection.clear();
+            self.note_input.stop();
+        }
+        self.selected_staff = self
+            .selected_staff
+            .min(self.settings.staves.len().saturating_sub(1));
+    }
+
+    /// Removes staves together with their music.
+    fn remove_staves(&mut self, range: Range<usize>) {
+        let Some(removed) = self.settings.staves.get(range.clone()) else {
+            return;
+        };
+        let notes = removed
+            .iter()
+            .fold(self.score.notes.clone(), |notes, staff| {
+                staves::notes_without(&notes, staff.part)
+            });
+        let mut staves = self.settings.staves.clone();
+        staves.drain(range);
+
+        self.history.begin_group("Remover pauta");
+        if notes.len() != self.score.notes.len() {
+            self.apply_edit(EditCommand::replace_notes(&self.score.notes, notes));
+        }
+        self.change_staves("Remover pauta", staves);
+        self.history.end_group();
+    }
+
+    /// Moves the selected notes of a grand staff to the staff above or below.
+    fn cross_staff(&mut self, step: i8) {
+        let notes = staves::cross_staff(
+            &self.score.notes,
+            &self.selected_events(),
+            &self.settings.parts(),
+            step,
+        );
+        self.replace_staff_assignment(notes);
+    }
+
+    /// Spreads the selected notes of a grand staff over its staves by pitch.
+    fn assign_staves(&mut self) {
+        let notes = staves::assign_staves(
+            &self.score.notes,
+            &self.selected_events(),
+            &self.settings.parts(),
+        );
+        self.replace_staff_assignment(notes);
+    }
+
+    fn replace_staff_assignment(&mut self, notes: Vec<NoteEvent>) {
+        if notes == self.score.notes {
+            self.start_message = "Nenhuma nota selecionada pode mudar de pauta.".to_owned();
+            return;
+        }
+        self.apply_edit(EditCommand::replace_notes(&self.score.notes, notes));
+    }
+
+    /// Writes `dynamic` under the start of the selection in each voice, or
+    /// clears the markings of the selected notes.
+    fn set_dynamic(&mut self, dynamic: Option<Dynamic>) {
+        let notes = dynamics::set_dynamic(&self.score.notes, &self.selected_events(), dynamic);
+        self.replace_dynamics(notes);
+    }
+
+    /// Puts the selected notes under a crescendo or diminuendo, or takes them out of it.
+    fn set_hairpin(&mut self, hairpin: Option<Hairpin>) {
+        let notes = dynamics::set_hairpin(&self.score.notes, &self.selected_events(), hairpin);
+        self.replace_dynamics(notes);
+    }
+
+    /// Adds `articulation` to the selected notes, or takes it off when they all have it.
+    fn toggle_articulation(&mut self, articulation: Articulation) {
+        let notes =
+            note_edit::toggle_articulation(&self.score, &self.selected_events(), articulation);
+        if notes == self.score.notes {
+            self.start_message = "Nenhuma nota selecionada aceita essa articulação.".to_owned();
+            return;
+        }
+        self.history.begin_group("Articulação");
+        self.apply_edit(EditCommand::replace_notes(&self.score.notes, notes));
+        self.history.end_group();
+    }
+
+    /// Writes the typed chord symbol over the start of the selection in each
+    /// voice, or clears the symbols of the selected events.
+    fn set_chord(&mut self, clear: bool) {
+        let chord = if clear {
+            None
+        } else {
+            match self.chord_text.parse::<ChordSymbol>() {
+                Ok(chord) => Some(chord),
+                Err(err) => {
+                    self.start_message = format!("Cifra inválida: {err}.");
+                    return;
+                }
+            }
+        };
+        let notes = harmony::set_chord(&self.score.notes, &self.selected_events(), chord.as_ref());
+        if notes == self.score.notes {
+            self.start_message = "Nada a mudar nas cifras da seleção.".to_owned();
+            return;
+        }
+        self.history.begin_group("Cifra");
+        self.apply_edit(EditCommand::replace_notes(&self.score.notes, notes));
+        self.history.end_group();
+    }
+
+    fn clear_dynamics(&mut self) {
+        let indices = self.selected_events();
+        let notes = dynamics::set_dynamic(&self.score.notes, &indices, None);
+        let notes = dynamics::set_hairpin(&notes, &indices, None);
+        self.replace_dynamics(notes);
+    }
+
+    fn replace_dynamics(&mut self, notes: Vec<NoteEvent>) {
+        if notes == self.score.notes {
+            self.start_message = "Nada a mudar nas dinâmicas da seleção.".to_owned();
+            return;
+        }
+        self.history.begin_group("Dinâmica");
+        self.apply_edit(EditCommand::replace_notes(&self.score.notes, notes));
+        self.history.end_group();
+    }
+
+    /// Transposes the notes chosen in the transpose dialog. Only a key
+    /// transposition of the whole score changes the key signature.
+    fn transpose(&mut self) {
+        let options = self.transpose;
+        let indices: Vec<usize> = match options.scope {
+            TransposeScope::Selection => self.selected_events(),
+            TransposeScope::Staff => {
+                let part = self.selected_part();
+                (0..self.score.notes.len())
+                    .filter(|index| Some(self.score.notes[*index].part()) == part)
+                    .collect()
+            }
+            TransposeScope::Score => (0..self.score.notes.len()).collect(),
+        };
+        let key = self.settings.key_signature;
+        let new_key = options.by == TransposeBy::Key && options.scope == TransposeScope::Score;
+        if indices.is_empty() && !new_key {
+            self.start_message = "Nada a transpor.".to_owned();
+            return;
+        }
+
+        let result = transpose::transpose(
+            &self.score,
+            &indices,
+            options.interval(key),
+            options.mode(),
+            key,
+            self.settings.range_level,
+        );
+        if !self.range_allows(&result.notes) {
+            return;
+        }
+        self.history.begin_group("Transpor");
+        if result.notes != self.score.notes {
+            self.apply_edit(EditCommand::replace_notes(&self.score.notes, result.notes));
+        }
+        if new_key {
+            self.change_settings(ScoreSettings {
+                key_signature: options.key,
+                ..self.settings.clone()
+            });
+        }
+        self.history.end_group();
+
+        self.start_message = if result.out_of_range.is_empty() {
+            "Transposição concluída.".to_owned()
+        } else {
+            let count = result.out_of_range.len();
+            self.selection.select_notes(result.out_of_range, false);
+            format!("Transposição concluída; {count} nota(s) fora da extensão do instrumento (selecionadas).")
+        };
+    }
+
+    /// The measures selection-based bar commands act on: the selected
+    /// range, or the measure of the first selected note or of the caret.
+    fn selected_measures(&self) -> Option<(usize, usize)> {
+        if let Some(range) = self.selection.range() {
+            return Some((range.first_measure, range.last_measure));
+        }
+        let beat = self
+            .selection_start()
+            .map(|(_, beat)| beat)
+            .or_else(|| self.note_input.caret.map(|caret| caret.beat))?;
+        let measure = self.settings.measures().locate(beat).0;
+        Some((measure, measure))
+    }
+
+    /// Splits the measure under the note-input caret, or else before the
+    /// first selected note, into two measures.
+    fn split_measure(&mut self) {
+        let beat = self
+            .note_input
+            .caret
+            .map(|caret| caret.beat)
+            .or_else(|| self.selection_start().map(|(_, beat)| beat));
+        let Some((measure, offset)) = beat.map(|beat| self.settings.measures().locate(beat)) else {
+            self.start_message =
+                "Posicione o cursor ou selecione uma nota onde dividir.".to_owned();
+            return;
+        };
+        let Some(edit) = measures::split(&self.score.notes, &self.settings, measure, offset) else {
+            self.start_message =
+                "Posicione o cursor dentro do compasso, depois do primeiro tempo.".to_owned();
+            return;
+        };
+
+        self.apply_measure_edit(edit, "Dividir compasso");
+        self.selection.clear();
+        self.start_message = format!("Compasso {} dividido.", measure + 1);
+    }
+
+    /// Puts the drafted tempo mark where the selection starts, replacing a
+    /// mark already on that beat.
+    fn add_tempo_mark(&mut self) {
+        let Some((_, beat)) = self.selection_start() else {
+            self.start_message = "Selecione uma nota ou compasso para o andamento.".to_owned();
+            return;
+        };
+        let mut marks = self.settings.tempo_marks.clone();
+        marks.retain(|mark| (mark.beat - beat).abs() > 1e-3);
+        let at = marks.partition_point(|mark| mark.beat < beat);
+        marks.insert(
+            at,
+            TempoMark {
+                beat,
+                ..self.tempo_draft.clone()
+            },
+        );
+        self.change_tempo("Andamento", self.settings.bpm, marks);
+    }
+
+    /// Puts the drafted sign on the selected measures: an ending over all
+    /// of them, signs at a barline on the first or last, replacing a mark of
+    /// the same kind there.
+    fn add_navigation(&mut self) {
+        let Some((first, last)) = self.selected_measures() else {
+            self.start_message = "Selecione os compassos da repetição.".to_owned();
+            return;
+        };
+        let draft = self.navigation_draft;
+        let measures = match draft {
+            Navigation::Ending { .. } => first..=last,
+            _ if draft.at_end() => last..=last,
+            _ => first..=first,
+        };
+        let mut navigation = self.settings.navigation.clone();
+        navigation
+            .retain(|mark| !(measures.contains(&mark.measure) && mark.navigation.same_kind(draft)));
+        navigation.extend(measures.map(|measure| NavigationMark {
+            measure,
+            navigation: draft,
+        }));
+        navigation.sort_by_key(|mark| mark.measure);
+        self.change_navigation(draft.name(), navigation);
+    }
+
+    fn change_navigation(&mut self, label: &str, navigation: Vec<NavigationMark>) {
+        self.history.begin_group(label);
+        self.change_settings(ScoreSettings {
+            navigation,
+            ..self.settings.clone()
+        });
+        self.history.end_group();
+    }
+
+    fn change_tempo(&mut self, label: &str, bpm: f32, tempo_marks: Vec<TempoMark>) {
+        self.history.begin_group(label);
+        self.change_settings(ScoreSettings {
+            bpm,
+            tempo_marks,
+            ..self.settings.clone()
+        });
+        self.history.end_group();
+    }
+
+    /// Joins the selected measures into one, or the selected measure with
+    /// the next.
+    fn join_measures(&mut self) {
+        let Some((first, last)) = self.selected_measures() else {
+            self.start_message = "Selecione os compassos a unir.".to_owned();
+            return;
+        };
+        let last = last.max(first + 1);
+        if last >= self.measure_count() {
+            self.start_message = "Não há compasso seguinte para unir.".to_owned();
+            return;
+        }
+        let Some(edit) = measures::join(&self.score.notes, &self.settings, first, last) else {
+            self.start_message =
+                "Os compassos selecionados não formam uma fórmula simples.".to_owned();
+            return;
+        };
+
+        self.apply_measure_edit(edit, "Unir compassos");
+        self.select_measures(first, first);
+        self.start_message = format!("Compassos {} a {} unidos.", first + 1, last + 1);
+    }
+
+    /// Inserts empty measures before or after the selection, or appends them
+    /// to the score when nothing is selected.
+    fn insert_measures(&mut self, placement: BarPlacement) {
+        let count = self.bar_count as usize;
+        let end = self.measure_count();
+        let at = match (placement, self.selected_measures()) {
+            (BarPlacement::Before, Some((first, _))) => first,
+            (BarPlacement::After, Some((_, last))) => last + 1,
+            (BarPlacement::End, _) => end,
+            (_, None) => {
+                self.start_message = "Selecione um compasso de referência.".to_owned();
+                return;
+            }
+        };
+
+        let edit = measures::insert(&self.score.notes, &self.settings, at.min(end), count);
+        self.apply_measure_edit(edit, "Inserir compassos");
+        self.select_measures(at.min(end), at.min(end) + count - 1);
+        self.start_message = format!("{count} compasso(s) inserido(s).");
+    }
+
+    fn delete_measures(&mut self) {
+        let Some((first, last)) = self.selected_measures() else {
+            self.start_message = "Selecione os compassos a apagar.".to_owned();
+            return;
+        };
+        let last = last.min(self.measure_count().saturating_sub(1));
+        if first > last {
+            return;
+        }
+
+        let edit = measures::delete(&self.score.notes, &self.settings, first, last);
+        self.apply_measure_edit(edit, "Apagar compassos");
+        self.selection.clear();
+        self.start_message = format!("Compassos {} a {} apagados.", first + 1, last + 1);
+    }
+
+    fn add_pickup(&mut self) {
+        let beats = self.pickup_beats;
+        let Some(edit) = measures::add_pickup(&self.score.notes, &self.settings, beats) else {
+            self.start_message = if self.settings.measures().has_pickup() {
+                "A partitura já começa com uma anacruse.".to_owned()
+            } else {
+                "A anacruse precisa ser mais curta que o primeiro compasso.".to_owned()
+            };
+            return;
+        };
+
+        self.apply_measure_edit(edit, "Criar anacruse");
+        self.select_measures(0, 0);
+        self.start_message = "Anacruse criada.".to_owned();
+    }
+
+    /// Selects `first..=last` on the staves already selected, or on all of them.
+    fn select_measures(&mut self, first_measure: usize, last_measure: usize) {
+        let (first_staff, last_staff) = self
+            .selection
+            .range()
+            .map_or((0, self.settings.staves.len().saturating_sub(1)), |range| {
+                (range.first_staff, range.last_staff)
+            });
+        self.selection.clear();
+        self.selection.select_range(selection::MeasureRange {
+            first_staff,
+            last_staff,
+            first_measure,
+            last_measure,
+        });
+    }
+
+    /// Records the new meter changes and re-barred notes as one undo step.
+    fn apply_measure_edit(&mut self, edit: measures::MeasureEdit, label: &str) {
+        self.history.begin_group(label);
+        self.change_settings(ScoreSettings {
+            meter_changes: edit.meter_changes,
+            tempo_marks: edit.tempo_marks,
+            navigation: edit.navigation,
+            ..self.settings.clone()
+        });
+        if edit.notes != self.score.notes {
+            self.apply_edit(EditCommand::replace_notes(&self.score.notes, edit.notes));
+        }
+        self.history.end_group();
+    }
+
+    fn undo(&mut self) {
+        if let Some(label) = self.history.undo(&mut self.score, &mut self.settings) {
+            self.start_message = format!("Desfeito: {label}");
+            self.selection.retain_valid(self.score.notes.len());
+            self.sync_settings_drafts();
+        }
+    }
+
+    fn redo(&mut self) {
+        if let Some(label) = self.history.redo(&mut self.score, &mut self.settings) {
+            self.start_message = format!("Refeito: {label}");
+            self.selection.retain_valid(self.score.notes.len());
+            self.sync_settings_drafts();
+        }
+    }
+
+    fn measure_count(&self) -> usize {
+        layout::measure_count(
+            &self.score,
+            &self.settings.parts(),
+            &self.settings.measures(),
+        )
+    }
+
+    /// Indices into `score.notes` that selection-based commands act on.
+    fn selected_events(&self) -> Vec<usize> {
+        self.selection.event_indices(
+            &self.score,
+            &self.settings.parts(),
+            &self.settings.measures(),
+        )
+    }
+
+    fn handle_page_interaction(&mut self, interaction: PageInteraction) {
+        match interaction {
+            PageInteraction::Click { target, modifiers } => match target {
+                Some(HitTarget::Note {
+                    index,
+                    staff,
+                    measure,
+                }) => {
+                    if modifiers.shift {
+                        self.selection.extend_to(staff, measure);
+                    } else if modifiers.command {
+                        self.selection.toggle_note(index, staff, measure);
+                    } else {
+                        self.selection.select_note(index, staff, measure);
+                    }
+                }
+                Some(HitTarget::Measure { staff, measure }) => {
+                    if modifiers.shift {
+                        self.selection.extend_to(staff, measure);
+                    } else {
+                        self.selection.select_measure(staff, measure);
+                    }
+                }
+                Some(HitTarget::Staff(staff)) => {
+                    let measures = self.measure_count();
+                    self.selection.select_staff(staff, measures);
+                }
+                None => self.selection.clear(),
+            },
+            PageInteraction::Insert(point) => {
+                let before = self.note_input.clone();
+                let edit = self.note_input.insert_at(
+                    point.staff,
+                    point.beat,
+                    point.pitch,
+                    &self.score,
+                    &self.settings.parts(),
+                    &self.settings,
+                );
+                if !self.apply_input_edit(edit) {
+                    self.note_input = before;
+                }
+                return;
+            }
+            PageInteraction::Marquee { notes, modifiers } => {
+                let indices = notes.into_iter().filter_map(|target| match target {
+                    HitTarget::Note { index, .. } => Some(index),
+                    _ => None,
+                });
+                self.selection
+                    .select_notes(indices, modifiers.shift || modifiers.command);
+            }
+        }
+
+        if self.note_input.is_active() {
+            if let Some((staff, beat)) = self.selection_start() {
+                self.note_input.start(staff, beat);
+            }
+        }
+    }
+
+    fn toggle_note_input(&mut self) {
+        if self.note_input.is_active() {
+            self.note_input.stop();
+            return;
+        }
+
+        let (staff, beat) = self.selection_start().unwrap_or_else(|| {
+            let part = self.settings.parts().first().copied();
+            let beats = self
+                .score
+                .notes
+                .iter()
+                .filter(|note| Some(note.part()) == part)
+                .map(NoteEvent::beats)
+                .sum();
+            (0, beats)
+        });
+        self.note_input.start(staff, beat);
+        self.start_message =
+            "Entrada pelo teclado: A–G notas, 0 pausa, 3–7 durações, Esc para sair.".to_owned();
+    }
+
+    /// Starts lyric entry at the first selected note, or stops it.
+    fn toggle_lyric_input(&mut self) {
+        if self.lyric_input.is_active() {
+            self.lyric_input.stop();
+            return;
+        }
+        let started = self
+            .selected_events()
+            .first()
+            .is_some_and(|index| self.lyric_input.start(&self.score, *index));
+        if started {
+            self.note_input.stop();
+            self.start_message =
+                "Letra: espaço avança, - separa sílabas, _ estende, Enter ou Esc para sair."
+                    .to_owned();
+        } else {
+            self.start_message = "Selecione uma nota para começar a letra.".to_owned();
+        }
+    }
+
+    fn handle_lyric_keys(&mut self, ctx: &egui::Context) {
+        let keys: Vec<LyricKey> = ctx.input(|input| {
+            input
+                .events
+                .iter()
+                .flat_map(|event| match event {
+                    egui::Event::Text(text) => LyricKey::from_text(text).collect(),
+                    egui::Event::Key {
+                        key, pressed: true, ..
+                    } => LyricKey::from_key(*key).into_iter().collect(),
+                    _ => Vec::new(),
+                })
+                .collect()
+        });
+
+        for key in keys {
+            let edit = self.lyric_input.apply(key, &self.score);
+            self.apply_input_edit(edit);
+        }
+    }
+
+    fn handle_note_input_keys(&mut self, ctx: &egui::Context) {
+        let keys: Vec<InputKey> = ctx.input(|input| {
+            input
+                .events
+                .iter()
+                .filter_map(|event| match event {
+                    egui::Event::Key {
+                        key,
+                        pressed: true,
+                        modifiers,
+                        ..
+                    } => InputKey::from_key(*key, *modifiers),
+                    _ => None,
+                })
+                .collect()
+        });
+
+        for key in keys {
+            let parts = self.settings.parts();
+            // Letters name written notes on a transposing staff.
+            let key = match (key, self.note_input.caret) {
+                (InputKey::Pitch(class), Some(caret)) if !self.concert_pitch => {
+                    let instrument = parts.get(caret.staff).map(|part| part.instrument);
+                    InputKey::Pitch(instrument.map_or(class, |instrument| {
+                        instrument.sounding_pitch(Pitch::new(class, 4)).class
+                    }))
+                }
+                _ => key,
+            };
+            // A refused note leaves the caret where it was.
+            let before = self.note_input.clone();
+            let edit = self
+                .note_input
+                .apply(key, &self.score, &parts, &self.settings);
+            if !self.apply_input_edit(edit) {
+                self.note_input = before;
+            }
+        }
+    }
+
+    /// Records the edit unless it is refused for leaving an instrument's
+    /// range; returns whether it went in.
+    fn apply_input_edit(&mut self, edit: Option<InputEdit>) -> bool {
+        match edit {
+            Some(InputEdit::Replace { index, after }) => {
+                let mut notes = self.score.notes.clone();
+                notes[index] = after.clone();
+                if !self.range_allows(&notes) {
+                    return false;
+                }
+                self.apply_edit(EditCommand::ReplaceNote {
+                    index,
+                    before: self.score.notes[index].clone(),
+                    after,
+                });
+            }
+            Some(InputEdit::Rewrite(notes)) => {
+                if !self.range_allows(&notes) {
+                    return false;
+                }
+                self.history.begin_group("Inserir nota");
+                self.apply_edit(EditCommand::replace_notes(&self.score.notes, notes));
+                self.history.end_group();
+            }
+            None => {}
+        }
+        true
+    }
+
+    fn copy_selection(&mut self, ctx: &egui::Context) -> bool {
+        let passage = clipboard::Passage::copy(
+            &self.score,
+            &self.settings.parts(),
+            &self.settings.measures(),
+            &self.selection,
+        )
+        .filter(|passage| !passage.is_empty());
+        let Some(passage) = passage else {
+            self.start_message = "Nada selecionado para copiar.".to_owned();
+            return false;
+        };
+
+        ctx.copy_text(passage.to_text(self.settings.time_signature));
+        self.start_message = "Trecho copiado.".to_owned();
+        self.clipboard = Some(passage);
+        true
+    }
+
+    fn cut_selection(&mut self, ctx: &egui::Context) {
+        if !self.copy_selection(ctx) {
+            return;
+        }
+        let indices = self.selected_events();
+        let after = clipboard::silence(&self.score, &indices);
+        self.history.begin_group("Recortar");
+        self.apply_edit(EditCommand::replace_notes(&self.score.notes, after));
+        self.history.end_group();
+        self.start_message = "Trecho recortado.".to_owned();
+    }
+
+    /// Staff and beat where the selection begins: its first note, or the
+    /// start of the selected measures. Pasting and note input start here.
+    fn selection_start(&self) -> Option<(usize, f32)> {
+        let measures = self.settings.measures();
+        if let Some(range) = self.selection.range() {
+            return Some((range.first_staff, measures.start(range.first_measure)));
+        }
+
+        let first = *self.selected_events().first()?;
+        let location =
+            layout::locate_events(&self.score, &self.settings.parts(), &measures)[first]?;
+        Some((
+            location.staff,
+            measures.start(location.measure) + location.beat,
+        ))
+    }
+
+    fn paste_passage(&mut self, passage: &clipboard::Passage) {
+        let Some((staff, start_beat)) = self.selection_start() else {
+            self.start_message = "Selecione uma nota ou compasso de destino para colar.".to_owned();
+            return;
+        };
+
+        let outcome = clipboard::paste(
             &self.score,
-            self.bpm,
-            self.start_key_signature,
-            self.start_time_signature,
-            self.start_paper_size,
+            &self.settings.parts(),
+            &self.settings.measures(),
+            passage,
+            staff,
+            start_beat,
         );
+        self.start_message.clear();
+        if !self.range_allows(&outcome.notes) {
+            return;
+        }
+        let range_warning = std::mem::take(&mut self.start_message);
+        self.history.begin_group("Colar");
+        self.apply_edit(EditCommand::replace_notes(&self.score.notes, outcome.notes));
+        self.history.end_group();
+
+        let measures = self.settings.measures();
+        let first_measure = measures.locate(start_beat).0;
+        let last_measure = (measures.count(outcome.end_beat).max(1) - 1).max(first_measure);
+        if outcome.staves > 0 {
+            self.selection.select_range(selection::MeasureRange {
+                first_staff: staff,
+                last_staff: staff + outcome.staves - 1,
+                first_measure,
+                last_measure,
+            });
+        }
+
+        let skipped = passage.staves.len() - outcome.staves;
+        self.start_message = if skipped > 0 {
+            format!("Trecho colado; {skipped} pautas não couberam na partitura.")
+        } else {
+            "Trecho colado.".to_owned()
+        };
+        if !range_warning.is_empty() {
+            self.start_message = format!("{} {range_warning}", self.start_message);
+        }
+    }
+
+    fn paste_from_text(&mut self, text: &str) {
+        match clipboard::Passage::from_text(text) {
+            Ok(passage) => {
+                self.paste_passage(&passage);
+                self.clipboard = Some(passage);
+            }
+            Err(err) => {
+                self.start_message =
+                    format!("A área de transferência não contém um trecho do Notarium ({err}).");
+            }
+        }
+    }
+
+    fn paste_from_app_clipboard(&mut self) {
+        match self.clipboard.take() {
+            Some(passage) => {
+                self.paste_passage(&passage);
+                self.clipboard = Some(passage);
+            }
+            None => self.start_message = "Nada copiado ainda.".to_owned(),
+        }
+    }
+
+    fn sync_settings_drafts(&mut self) {
+        self.start_title = self.settings.title.clone();
+        self.start_composer = self.settings.composer.clone();
+        self.start_key_signature = self.settings.key_signature;
+        self.start_time_signature = self.settings.time_signature;
+        self.start_paper_size = self.settings.paper_size;
+        self.start_bpm = self.settings.bpm;
+    }
+
+    fn handle_edit_shortcuts(&mut self, ctx: &egui::Context) {
+        // Text fields keep their own undo while they have focus.
+        if ctx.wants_keyboard_input() {
+            return;
+        }
+
+        let (undo, redo) = ctx.input_mut(|input| {
+            let redo = input.consume_shortcut(&egui::KeyboardShortcut::new(
+                egui::Modifiers::COMMAND | egui::Modifiers::SHIFT,
+                egui::Key::Z,
+            )) || input.consume_shortcut(&egui::KeyboardShortcut::new(
+                egui::Modifiers::COMMAND,
+                egui::Key::Y,
+            ));
+            let undo = input.consume_shortcut(&egui::KeyboardShortcut::new(
+                egui::Modifiers::COMMAND,
+                egui::Key::Z,
+            ));
+            (undo, redo)
+        });
+
+        if undo {
+            self.undo();
+        }
+        if redo {
+            self.redo();
+        }
+
+        let toggle_lyrics = ctx.input_mut(|input| {
+            input.consume_shortcut(&egui::KeyboardShortcut::new(
+                egui::Modifiers::COMMAND,
+                egui::Key::L,
+            ))
+        });
+        if toggle_lyrics {
+            self.toggle_lyric_input();
+        }
+        // Typed syllables take every key until lyric entry is left.
+        if self.lyric_input.is_active() {
+            let leave = ctx.input_mut(|input| {
+                input.consume_key(egui::Modifiers::NONE, egui::Key::Escape)
+                    | input.consume_key(egui::Modifiers::NONE, egui::Key::Enter)
+            });
+            if leave {
+                self.lyric_input.stop();
+            } else {
+                self.handle_lyric_keys(ctx);
+            }
+            return;
+        }
+
+        let (select_all, escape, toggle_input, delete) = ctx.input_mut(|input| {
+            (
+                input.consume_shortcut(&egui::KeyboardShortcut::new(
+                    egui::Modifiers::COMMAND,
+                    egui::Key::A,
+                )),
+                input.consume_key(egui::Modifiers::NONE, egui::Key::Escape),
+                input.consume_key(egui::Modifiers::NONE, egui::Key::N),
+                input.consume_key(egui::Modifiers::NONE, egui::Key::Delete),
+            )
+        });
+        if delete {
+            self.delete_selected();
+        }
+        if select_all {
+            let measures = self.measure_count();
+            self.selection
+                .select_all(self.settings.staves.len(), measures);
+        }
+        if escape && self.note_input.is_active() {
+            self.note_input.stop();
+        } else if escape {
+            self.selection.clear();
+        }
+        if toggle_input {
+            self.toggle_note_input();
+        }
+        if self.note_input.is_active() {
+            self.handle_note_input_keys(ctx);
+        }
+
+        // The platform integration turns Ctrl+C/X/V into these events and
+        // fills `Paste` from the system clipboard.
+        let events = ctx.input(|input| input.events.clone());
+        for event in events {
+            match event {
+                egui::Event::Copy => {
+                    self.copy_selection(ctx);
+                }
+                egui::Event::Cut => self.cut_selection(ctx),
+                egui::Event::Paste(text) => self.paste_from_text(&text),
+                _ => {}
+            }
+        }
+    }
+
+    fn save_ntr(&mut self) {
+        let path = sanitized_ntr_path(&self.file_path_input);
+        let payload = ntr::serialize_ntr(&self.settings, &self.score);
 
         match std::fs::write(&path, payload) {
             Ok(()) => {
@@ -273,13 +1377,86 @@ impl NotariumApp {
         }
     }
 
+    /// Plays the score from the start at the chosen speed.
+    fn play(&mut self) {
+        self.playback.play(
+            self.score.clone(),
+            self.settings.clone(),
+            audio::PlaybackOptions {
+                speed: self.playback_speed / 100.0,
+                repeats: self.play_repeats,
+                chords: self.play_chords,
+            },
+        );
+    }
+
+    fn export_midi(&mut self) {
+        let path = export_path(&self.file_path_input, "mid");
+        match std::fs::write(
+            &path,
+            midi::export_midi(&self.settings, &self.score, self.play_repeats),
+        ) {
+            Ok(()) => {
+                self.start_message = format!("MIDI exportado para {}", path.display());
+            }
+            Err(err) => {
+                self.start_message = format!("Falha ao exportar MIDI: {err}");
+            }
+        }
+    }
+
+    /// Renders the performance at the score's own tempo.
+    fn export_wav(&mut self) {
+        let path = export_path(&self.file_path_input, "wav");
+        let options = audio::PlaybackOptions {
+            speed: 1.0,
+            repeats: self.play_repeats,
+            chords: self.play_chords,
+        };
+        match std::fs::write(
+            &path,
+            audio::export_wav(&self.score, &self.settings, options),
+        ) {
+            Ok(()) => {
+                self.start_message = format!("WAV exportado para {}", path.display());
+            }
+            Err(err) => {
+                self.start_message = format!("Falha ao exportar WAV: {err}");
+            }
+        }
+    }
+
+    fn export_musicxml(&mut self, compressed: bool) {
+        let extension = if compressed { "mxl" } else { "musicxml" };
+        let path = export_path(&self.file_path_input, extension);
+        let payload = if compressed {
+            musicxml::export_mxl(&self.settings, &self.score)
+        } else {
+            Ok(musicxml::export_musicxml(&self.settings, &self.score).into_bytes())
+        };
+
+        match payload.and_then(|bytes| std::fs::write(&path, bytes).map_err(|err| err.to_string()))
+        {
+            Ok(()) => {
+                self.start_message = format!("MusicXML exportado para {}", path.display());
+            }
+            Err(err) => {
+                self.start_message = format!("Falha ao exportar MusicXML: {err}");
+            }
+        }
+    }
+
     fn open_ntr_from_path(&mut self, path: PathBuf) {
         match std::fs::read_to_string(&path) {
-            Ok(contents) => match deserialize_ntr(&contents) {
-                Ok((settings, score, bpm)) => {
+            Ok(contents) => match ntr::deserialize_ntr(&contents) {
+                Ok(document) => {
+                    let settings = document.settings;
                     self.settings = settings.clone();
-                    self.score = score;
-                    self.bpm = bpm;
+                    self.score = document.score;
+                    self.history.clear();
+                    self.note_input.stop();
+                    self.selection.clear();
+                    self.start_bpm = settings.bpm;
                     self.start_title = settings.title;
                     self.start_composer = settings.composer;
                     self.start_key_signature = settings.key_signature;
@@ -287,7 +1464,15 @@ impl NotariumApp {
                     self.start_paper_size = settings.paper_size;
                     self.file_path_input = path.to_string_lossy().to_string();
                     self.screen = AppScreen::Editor;
-                    self.start_message = format!("Arquivo carregado: {}", path.display());
+                    self.start_message = if document.version < 2 {
+                        format!(
+                            "Arquivo NTR{} convertido: {} (será salvo como NTR2)",
+                            document.version,
+                            path.display()
+                        )
+                    } else {
+                        format!("Arquivo carregado: {}", path.display())
+                    };
                     self.recent_scores = find_recent_ntr_files();
                 }
                 Err(err) => {
@@ -305,6 +1490,121 @@ impl NotariumApp {
         self.open_ntr_from_path(path);
     }
 
+    fn import_musicxml_from_input(&mut self) {
+        let path = PathBuf::from(self.file_path_input.trim());
+        let compressed = path.extension().and_then(|e| e.to_str()) == Some("mxl");
+
+        let imported = std::fs::read(&path)
+            .map_err(|err| format!("Não foi possível abrir arquivo: {err}"))
+            .and_then(|bytes| {
+                if compressed {
+                    musicxml::import_mxl(&bytes)
+                } else {
+                    musicxml::decode_document(&bytes)
+                        .and_then(|text| musicxml::import_musicxml(&text))
+                }
+            });
+
+        match imported {
+            Ok(imported) => {
+                self.settings = imported.settings;
+                self.sync_settings_drafts();
+                self.score = imported.score;
+                self.history.clear();
+                self.note_input.stop();
+                self.selection.clear();
+                self.show_import_log = !imported.log.is_empty();
+                self.start_message = format!(
+                    "MusicXML importado: {} ({} aviso(s))",
+                    path.display(),
+                    imported.log.issues.len()
+                );
+                self.import_log = imported.log;
+                self.file_path_input = export_path(&self.file_path_input, "ntr")
+                    .to_string_lossy()
+                    .to_string();
+                self.screen = AppScreen::Editor;
+            }
+            Err(err) => {
+                self.start_message = format!("Falha ao importar MusicXML: {err}");
+            }
+        }
+    }
+
+    /// Edits go through the history; text fields commit when they lose focus.
+    fn render_settings_editor(&mut self, ui: &mut egui::Ui) {
+        ui.label("Título");
+        let title = ui.text_edit_singleline(&mut self.start_title);
+        ui.label("Compositor");
+        let composer = ui.text_edit_singleline(&mut self.start_composer);
+        if title.lost_focus() || composer.lost_focus() {
+            self.change_settings(ScoreSettings {
+                title: self.start_title.trim().to_owned(),
+                composer: self.start_composer.trim().to_owned(),
+                ..self.settings.clone()
+            });
+        }
+
+        let mut key_signature = self.settings.key_signature;
+        egui::ComboBox::from_id_salt("editor_key")
+            .selected_text(key_signature.label())
+            .show_ui(ui, |ui| {
+                for key in KeySignature::ALL {
+                    ui.selectable_value(&mut key_signature, key, key.label());
+                }
+            });
+
+        let mut time_signature = self.settings.time_signature;
+        egui::ComboBox::from_id_salt("editor_time")
+            .selected_text(time_signature.label())
+            .show_ui(ui, |ui| {
+                for time in TimeSignature::ALL {
+                    ui.selectable_value(&mut time_signature, time, time.label());
+                }
+            });
+
+        let mut paper_size = self.settings.paper_size;
+        egui::ComboBox::from_id_salt("editor_paper")
+            .selected_text(paper_size.label())
+            .show_ui(ui, |ui| {
+                for size in PaperSize::ALL {
+                    ui.selectable_value(&mut paper_size, size, size.label());
+                }
+            });
+
+        self.change_settings(ScoreSettings {
+            key_signature,
+            time_signature,
+            paper_size,
+            ..self.settings.clone()
+        });
+        if !title.has_focus() && !composer.has_focus() {
+            self.sync_settings_drafts();
+        }
+    }
+
+    fn render_import_log(&mut self, ctx: &egui::Context) {
+        let mut open = self.show_import_log;
+        egui::Window::new("Relatório de importação MusicXML")
+            .open(&mut open)
+            .default_width(460.0)
+            .show(ctx, |ui| {
+                ui.label(format!(
+                    "{} elemento(s) não foram importados exatamente:",
+                    self.import_log.issues.len()
+                ));
+                ui.separator();
+                egui::ScrollArea::vertical()
+                    .max_height(320.0)
+                    .show(ui, |ui| {
+                        for issue in &self.import_log.issues {
+                            ui.label(issue.to_string());
+                        }
+                    });
+            });
+        self.show_import_log = open;
+    }
+
     fn render_start_screen(&mut self, ctx: &egui::Context) {
         egui::CentralPanel::default().show(ctx, |ui| {
             ui.visuals_mut().panel_fill = egui::Color32::from_rgb(23, 25, 30);
@@ -366,7 +1666,9 @@ impl NotariumApp {
                             }
                         });
 
-                    ui.add(egui::Slider::new(&mut self.bpm, 40.0..=220.0).text("BPM inicial"));
+                    ui.add(
+                        egui::Slider::new(&mut self.start_bpm, 40.0..=220.0).text("BPM inicial"),
+                    );
 
                     if ui.button("✨ Criar e Abrir Editor").clicked() {
                         self.create_new_score_from_start();
@@ -376,7 +1678,7 @@ impl NotariumApp {
                 columns[1].group(|ui| {
                     ui.heading("Partituras .ntr");
                     ui.separator();
-                    ui.label("Caminho do arquivo (.ntr)");
+                    ui.label("Caminho do arquivo (.ntr, .musicxml, .mxl)");
                     ui.text_edit_singleline(&mut self.file_path_input);
 
                     ui.horizontal(|ui| {
@@ -386,6 +1688,9 @@ impl NotariumApp {
                         if ui.button("💾 Salvar .ntr").clicked() {
                             self.save_ntr();
                         }
+                        if ui.button("📥 Importar MusicXML").clicked() {
+                            self.import_musicxml_from_input();
+                        }
                     });
 
                     if ui.button("🔄 Atualizar lista").clicked() {
@@ -417,6 +1722,8 @@ impl NotariumApp {
     }
 
     fn render_editor(&mut self, ctx: &egui::Context) {
+        self.handle_edit_shortcuts(ctx);
+
         egui::TopBottomPanel::top("top_bar").show(ctx, |ui| {
             ui.horizontal_wrapped(|ui| {
                 for (tab, label) in [
@@ -437,32 +1744,304 @@ impl NotariumApp {
 
             ui.separator();
             ui.horizontal_wrapped(|ui| {
+                ui.group(|ui| {
+                    ui.label("Edit");
+                    ui.horizontal(|ui| {
+                        let undo_label = self.history.undo_label().map(str::to_owned);
+                        let undo = ui
+                            .add_enabled(undo_label.is_some(), egui::Button::new("↶ Desfazer"))
+                            .on_hover_text(format!("Ctrl+Z: {}", undo_label.unwrap_or_default()));
+                        if undo.clicked() {
+                            self.undo();
+                        }
+
+                        let redo_label = self.history.redo_label().map(str::to_owned);
+                        let redo = ui
+                            .add_enabled(redo_label.is_some(), egui::Button::new("↷ Refazer"))
+                            .on_hover_text(format!("Ctrl+Y: {}", redo_label.unwrap_or_default()));
+                        if redo.clicked() {
+                            self.redo();
+                        }
+                    });
+                });
+
                 ui.group(|ui| {
                     ui.label("Clipboard");
                     ui.horizontal(|ui| {
-                        let _ = ui.button("Paste");
-                        let _ = ui.button("Copy");
-                        let _ = ui.button("Cut");
+                        let has_selection = !self.selection.is_empty();
+                        if ui
+                            .add_enabled(self.clipboard.is_some(), egui::Button::new("Paste"))
+                            .on_hover_text("Ctrl+V cola também trechos de outra janela do Notarium")
+                            .clicked()
+                        {
+                            self.paste_from_app_clipboard();
+                        }
+                        if ui
+                            .add_enabled(has_selection, egui::Button::new("Copy"))
+                            .clicked()
+                        {
+                            self.copy_selection(ui.ctx());
+                        }
+                        if ui
+                            .add_enabled(has_selection, egui::Button::new("Cut"))
+                            .clicked()
+                        {
+                            self.cut_selection(ui.ctx());
+                        }
                     });
                 });
 
                 ui.group(|ui| {
                     ui.label("Instruments");
                     ui.horizontal(|ui| {
-                        let _ = ui.button("Add");
-                        let _ = ui.button("Remove");
-                        let _ = ui.button("Transpose");
+                        if ui
+                            .button("Add")
+                            .on_hover_text("Adicionar, remover, renomear e reordenar pautas")
+                            .clicked()
+                        {
+                            self.show_instruments = true;
+                        }
+                        let selected_staff = self.selection_start().map(|(staff, _)| staff);
+                        if ui
+                            .add_enabled(selected_staff.is_some(), egui::Button::new("Remove"))
+                            .on_hover_text("Remove a pauta selecionada, ou todo o piano, e suas notas")
+                            .clicked()
+                        {
+                            if let Some(staff) = selected_staff {
+                                let group = staves::grand_staff(&self.settings.staves, staff);
+                                self.remove_staves(group);
+                            }
+                        }
+                        let has_selection = !self.selection.is_empty();
+                        if ui
+                            .add_enabled(has_selection, egui::Button::new("Cross ⬆"))
+                            .on_hover_text("Mostra as notas selecionadas na pauta de cima do piano")
+                            .clicked()
+                        {
+                            self.cross_staff(-1);
+                        }
+                        if ui
+                            .add_enabled(has_selection, egui::Button::new("Cross ⬇"))
+                            .on_hover_text("Mostra as notas selecionadas na pauta de baixo do piano")
+                            .clicked()
+                        {
+                            self.cross_staff(1);
+                        }
+                        if ui
+                            .add_enabled(has_selection, egui::Button::new("Auto Staff"))
+                            .on_hover_text(
+                                "Distribui as notas selecionadas entre as mãos: do Dó central \
+                                 para cima na pauta de cima, abaixo dele na de baixo",
+                            )
+                            .clicked()
+                        {
+                            self.assign_staves();
+                        }
+                        if ui
+                            .button("Transpose")
+                            .on_hover_text("Transpor por intervalo, tonalidade ou oitava")
+                            .clicked()
+                        {
+                            self.show_transpose = true;
+                        }
+                    });
+                });
+
+                ui.group(|ui| {
+                    ui.label("Articulations");
+                    ui.horizontal(|ui| {
+                        let has_selection = !self.selection.is_empty();
+                        for articulation in Articulation::ALL {
+                            let name = format!("{articulation:?}");
+                            if ui
+                                .add_enabled(has_selection, egui::Button::new(name))
+                                .on_hover_text(articulation.label())
+                                .clicked()
+                            {
+                                self.toggle_articulation(articulation);
+                            }
+                        }
+                    });
+                });
+
+                ui.group(|ui| {
+                    ui.label("Lyrics");
+                    ui.horizontal(|ui| {
+                        let has_selection = !self.selection.is_empty();
+                        let typing = self.lyric_input.is_active();
+                        if ui
+                            .add_enabled(
+                                has_selection || typing,
+                                egui::Button::selectable(typing, "Lyrics"),
+                            )
+                            .on_hover_text(
+                                "Ctrl+L: escreve a letra a partir da nota selecionada; espaço \
+                                 avança, - separa sílabas, _ estende a sílaba (melisma)",
+                            )
+                            .clicked()
+                        {
+                            self.toggle_lyric_input();
+                        }
+                        let mut verse = self.lyric_input.verse + 1;
+                        ui.add(
+                            egui::DragValue::new(&mut verse)
+                                .range(1..=Lyric::VERSES)
+                                .prefix("Estrofe "),
+                        );
+                        if verse != self.lyric_input.verse + 1 {
+                            self.lyric_input.verse = verse - 1;
+                            if let Some(note) = self.lyric_input.note {
+                                self.lyric_input.start(&self.score, note);
+                            }
+                        }
+                    });
+                });
+
+                ui.group(|ui| {
+                    ui.label("Chords");
+                    ui.horizontal(|ui| {
+                        let has_selection = !self.selection.is_empty();
+                        let field = ui.add(
+                            egui::TextEdit::singleline(&mut self.chord_text)
+                                .hint_text("Cmaj7, F#m7b5/C")
+                                .desired_width(90.0),
+                        );
+                        let entered =
+                            field.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+                        ui.add_enabled_ui(has_selection, |ui| {
+                            if ui
+                                .button("Apply")
+                                .on_hover_text("Escreve a cifra sobre o início da seleção")
+                                .clicked()
+                                || (entered && has_selection)
+                            {
+                                self.set_chord(false);
+                            }
+                            if ui
+                                .button("Clear")
+                                .on_hover_text("Remove as cifras das notas selecionadas")
+                                .clicked()
+                            {
+                                self.set_chord(true);
+                            }
+                        });
+                    });
+                });
+
+                ui.group(|ui| {
+                    ui.label("Dynamics");
+                    ui.horizontal(|ui| {
+                        let has_selection = !self.selection.is_empty();
+                        ui.add_enabled_ui(has_selection, |ui| {
+                            for dynamic in Dynamic::ALL {
+                                if ui
+                                    .button(egui::RichText::new(dynamic.label()).italics())
+                                    .clicked()
+                                {
+                                    self.set_dynamic(Some(dynamic));
+                                }
+                            }
+                            if ui
+                                .button("Cresc")
+                                .on_hover_text("Crescendo sob as notas selecionadas")
+                                .clicked()
+                            {
+                                self.set_hairpin(Some(Hairpin::Crescendo));
+                            }
+                            if ui
+                                .button("Dim")
+                                .on_hover_text("Diminuendo sob as notas selecionadas")
+                                .clicked()
+                            {
+                                self.set_hairpin(Some(Hairpin::Diminuendo));
+                            }
+                            if ui
+                                .button("Clear")
+                                .on_hover_text("Remove dinâmicas e reguladores das notas selecionadas")
+                                .clicked()
+                            {
+                                self.clear_dynamics();
+                            }
+                        });
+                    });
+                });
+
+                ui.group(|ui| {
+                    ui.label("Bars / View");
+                    ui.horizontal(|ui| {
+                        if ui
+                            .button("Bars")
+                            .on_hover_text("Adicionar, inserir e apagar compassos ou criar anacruse")
+                            .clicked()
+                        {
+                            self.show_bars = true;
+                        }
+                        if ui
+                            .button("Tempo")
+                            .on_hover_text("Andamento inicial, indicações de andamento, rit. e accel.")
+                            .clicked()
+                        {
+                            self.show_tempo = true;
+                        }
+                        if ui
+                            .button("Repeats")
+                            .on_hover_text("Ritornelos, casas, D.C., D.S., Segno, Coda e Fine")
+                            .clicked()
+                        {
+                            self.show_navigation = true;
+                        }
+                        if ui
+                            .button("Split")
+                            .on_hover_text("Dividir o compasso na posição do cursor")
+                            .clicked()
+                        {
+                            self.split_measure();
+                        }
+                        if ui
+                            .button("Join")
+                            .on_hover_text("Unir os compassos selecionados")
+                            .clicked()
+                        {
+                            self.join_measures();
+                        }
+                        ui.add(
+                            egui::Slider::new(&mut self.zoom_percent, 40.0..=140.0).text("Zoom"),
+                        );
+                        ui.toggle_value(&mut self.concert_pitch, "Concert Pitch")
+                            .on_hover_text(
+                                "Mostrar a altura real em vez da escrita dos instrumentos transpositores",
+                            );
                     });
                 });
 
                 ui.group(|ui| {
-                    ui.label("Bars / View");
+                    ui.label("Review");
                     ui.horizontal(|ui| {
-                        let _ = ui.button("Split");
-                        let _ = ui.button("Join");
-                        ui.add(
-                            egui::Slider::new(&mut self.zoom_percent, 40.0..=140.0).text("Zoom"),
-                        );
+                        let mut range_level = self.settings.range_level;
+                        egui::ComboBox::from_id_salt("range_level")
+                            .selected_text(range_level.label())
+                            .show_ui(ui, |ui| {
+                                for level in RangeLevel::ALL {
+                                    ui.selectable_value(&mut range_level, level, level.label());
+                                }
+                            })
+                            .response
+                            .on_hover_text("Extensões dos instrumentos conferidas na partitura");
+                        if range_level != self.settings.range_level {
+                            self.change_settings(ScoreSettings {
+                                range_level,
+                                ..self.settings.clone()
+                            });
+                        }
+                        ui.checkbox(&mut self.block_out_of_range, "Block")
+                            .on_hover_text("Recusa notas fora da extensão em vez de só avisar");
+                        if ui
+                            .button("Ranges")
+                            .on_hover_text("Relatório das notas fora da extensão dos instrumentos")
+                            .clicked()
+                        {
+                            self.show_range_report = true;
+                        }
                     });
                 });
 
@@ -475,7 +2054,7 @@ impl NotariumApp {
                         }
 
                         if ui.button("▶ Play").clicked() {
-                            self.playback.play(self.score.clone(), self.bpm);
+                            self.play();
                             self.is_paused = false;
                         }
 
@@ -512,16 +2091,25 @@ impl NotariumApp {
                     self.settings.key_signature.label()
                 ));
                 ui.separator();
-                ui.label(format!(
-                    "Compasso: {}",
-                    self.settings.time_signature.label()
-                ));
+                // The meter in force where the selection starts.
+                let measures = self.settings.measures();
+                let measure = self
+                    .selection_start()
+                    .map_or(0, |(_, beat)| measures.locate(beat).0);
+                ui.label(format!("Compasso: {}", measures.meter(measure).label()));
+                ui.separator();
+                let beat = self.selection_start().map_or(0.0, |(_, beat)| beat);
+                let tempo = tempo::TempoMap::new(
+                    &self.settings,
+                    measures.start(self.measure_count()),
+                );
+                ui.label(format!("Andamento: ♩ = {}", tempo.bpm_at(beat).round()));
                 ui.separator();
                 ui.label(format!("Papel: {}", self.settings.paper_size.label()));
                 ui.separator();
                 ui.label(format!(
                     "Compassos: {:.1}",
-                    self.score.total_measures(self.settings.time_signature)
+                    self.score.total_measures(&self.settings.measures())
                 ));
                 ui.separator();
                 if ui.button("💾 Salvar .ntr").clicked() {
@@ -530,6 +2118,21 @@ impl NotariumApp {
                 if ui.button("📂 Abrir .ntr").clicked() {
                     self.open_ntr_from_input();
                 }
+                if ui.button("📥 Importar MusicXML").clicked() {
+                    self.import_musicxml_from_input();
+                }
+                if ui.button("📤 Exportar MusicXML").clicked() {
+                    self.export_musicxml(false);
+                }
+                if ui.button("📦 Exportar .mxl").clicked() {
+                    self.export_musicxml(true);
+                }
+                if ui.button("🎹 Exportar MIDI").clicked() {
+                    self.export_midi();
+                }
+                if ui.button("🔊 Exportar WAV").clicked() {
+                    self.export_wav();
+                }
                 if ui.button("← Voltar para Início").clicked() {
                     self.screen = AppScreen::Start;
                 }
@@ -543,18 +2146,41 @@ impl NotariumApp {
                 ui.heading("Entrada de Notas");
                 ui.separator();
 
-                egui::ComboBox::from_label("Instrumento")
-                    .selected_text(self.selected_instrument.label())
+                let typing = self.note_input.is_active();
+                if ui
+                    .selectable_label(typing, "⌨ Entrada pelo teclado (N)")
+                    .on_hover_text(
+                        "A–G notas, 0 pausa, 3–7 durações, ponto pontua, setas movem o cursor \
+                         e alteram a última nota, Ctrl+setas mudam a oitava",
+                    )
+                    .clicked()
+                {
+                    self.toggle_note_input();
+                }
+                ui.separator();
+
+                let staves = &self.settings.staves;
+                let staff_label = |index: usize| {
+                    staves
+                        .get(index)
+                        .map(|staff| staff.labels(staves).0)
+                        .unwrap_or_default()
+                };
+                egui::ComboBox::from_label("Pauta")
+                    .selected_text(staff_label(self.selected_staff))
                     .show_ui(ui, |ui| {
-                        for instrument in Instrument::ALL {
+                        for index in 0..staves.len() {
                             ui.selectable_value(
-                                &mut self.selected_instrument,
-                                instrument,
-                                instrument.label(),
+                                &mut self.selected_staff,
+                                index,
+                                staff_label(index),
                             );
                         }
                     });
 
+                let selected_instrument = self
+                    .selected_part()
+                    .map_or(Instrument::PIANO, |part| part.instrument);
                 egui::ComboBox::from_label("Altura")
                     .selected_text(self.selected_pitch.label())
                     .show_ui(ui, |ui| {
@@ -563,86 +2189,830 @@ impl NotariumApp {
                         }
                     });
 
-                ui.add(egui::Slider::new(&mut self.selected_octave, 1..=7).text("Oitava"));
+                // Octaves the instrument of the staff reaches at the score's level.
+                let (low, high) = selected_instrument.range(self.settings.range_level);
+                self.selected_octave = self.selected_octave.clamp(low.octave, high.octave);
+                ui.add(
+                    egui::Slider::new(&mut self.selected_octave, low.octave..=high.octave)
+                        .text("Oitava"),
+                );
 
                 egui::ComboBox::from_label("Duração")
-                    .selected_text(self.selected_duration.label())
+                    .selected_text(self.note_input.duration.label())
                     .show_ui(ui, |ui| {
                         for duration in DurationValue::ALL {
                             ui.selectable_value(
-                                &mut self.selected_duration,
+                                &mut self.note_input.duration,
                                 duration,
                                 duration.label(),
                             );
                         }
                     });
 
-                ui.add(egui::Slider::new(&mut self.bpm, 40.0..=220.0).text("BPM"));
+                ui.horizontal(|ui| {
+                    for voice in 0..NoteEvent::VOICES {
+                        ui.selectable_value(
+                            &mut self.note_input.voice,
+                            voice,
+                            (voice + 1).to_string(),
+                        )
+                        .on_hover_text(format!(
+                            "Escrever na voz {} (Alt+{})",
+                            voice + 1,
+                            voice + 1
+                        ));
+                    }
+                    ui.label("Voz");
+                });
 
-                if ui.button("Adicionar Nota").clicked() {
-                    self.score.notes.push(NoteEvent {
-                        pitch: Pitch {
-                            class: self.selected_pitch,
-                            octave: self.selected_octave,
-                        },
-                        duration: self.selected_duration,
-                        instrument: self.selected_instrument,
-                    });
+                ui.add(
+                    egui::Slider::new(&mut self.playback_speed, 25.0..=200.0)
+                        .suffix("%")
+                        .text("Velocidade"),
+                )
+                .on_hover_text("Multiplica os andamentos da partitura no playback");
+                ui.checkbox(&mut self.play_repeats, "Tocar repetições")
+                    .on_hover_text("Sem repetições, toca só a última casa de cada ritornelo");
+                ui.checkbox(&mut self.play_chords, "Tocar cifras")
+                    .on_hover_text("Acompanha a partitura com as cifras tocadas ao piano");
+
+                let selected_part = self.selected_part();
+                if ui
+                    .add_enabled(selected_part.is_some(), egui::Button::new("Adicionar Nota"))
+                    .clicked()
+                {
+                    if let Some(part) = selected_part {
+                        let note = NoteEvent::new(
+                            Pitch::new(self.selected_pitch, self.selected_octave),
+                            self.note_input.duration,
+                            part,
+                        );
+                        let mut notes = self.score.notes.clone();
+                        notes.push(note.clone());
+                        if self.range_allows(&notes) {
+                            self.apply_edit(EditCommand::InsertNote {
+                                index: self.score.notes.len(),
+                                note,
+                            });
+                        }
+                    }
                 }
 
-                if ui.button("Limpar Partitura").clicked() {
-                    self.score.notes.clear();
+                ui.horizontal(|ui| {
+                    let has_notes = !self.score.notes.is_empty();
+                    if ui
+                        .add_enabled(has_notes, egui::Button::new("Alterar última"))
+                        .on_hover_text("Aplica altura e duração selecionadas à última nota")
+                        .clicked()
+                    {
+                        self.replace_last_note();
+                    }
+                    if ui
+                        .add_enabled(has_notes, egui::Button::new("Apagar última"))
+                        .clicked()
+                    {
+                        self.remove_last_note();
+                    }
+                });
+
+                ui.separator();
+                ui.label("Seleção");
+                let has_selection = !self.selection.is_empty();
+                ui.horizontal_wrapped(|ui| {
+                    let changes = [
+                        (
+                            "Altura",
+                            NoteChange {
+                                pitch: Some(self.selected_pitch),
+                                ..NoteChange::default()
+                            },
+                        ),
+                        (
+                            "Oitava",
+                            NoteChange {
+                                octave: Some(self.selected_octave),
+                                ..NoteChange::default()
+                            },
+                        ),
+                        (
+                            "Duração",
+                            NoteChange {
+                                duration: Some(self.note_input.duration),
+                                ..NoteChange::default()
+                            },
+                        ),
+                        (
+                            "Pauta",
+                            NoteChange {
+                                part: selected_part,
+                                ..NoteChange::default()
+                            },
+                        ),
+                    ];
+                    for (label, change) in changes {
+                        if ui
+                            .add_enabled(has_selection, egui::Button::new(label))
+                            .on_hover_text("Aplica o valor escolhido acima às notas selecionadas")
+                            .clicked()
+                        {
+                            self.change_selected(change);
+                        }
+                    }
+                });
+                ui.horizontal(|ui| {
+                    if ui
+                        .add_enabled(has_selection, egui::Button::new("Inserir antes"))
+                        .on_hover_text("Insere a nota escolhida antes da primeira selecionada")
+                        .clicked()
+                    {
+                        self.insert_before_selection();
+                    }
+                    if ui
+                        .add_enabled(has_selection, egui::Button::new("Apagar (Del)"))
+                        .clicked()
+                    {
+                        self.delete_selected();
+                    }
+                });
+                egui::ComboBox::from_label("Ao apagar")
+                    .selected_text(self.delete_mode.label())
+                    .show_ui(ui, |ui| {
+                        for mode in DeleteMode::ALL {
+                            ui.selectable_value(&mut self.delete_mode, mode, mode.label());
+                        }
+                    });
+                ui.separator();
+
+                if ui.button("Limpar Partitura").clicked() && !self.score.notes.is_empty() {
+                    self.apply_edit(EditCommand::replace_notes(&self.score.notes, Vec::new()));
                 }
 
                 if ui.button("Play (síntese)").clicked() {
-                    self.playback.play(self.score.clone(), self.bpm);
+                    self.play();
                     self.is_paused = false;
                 }
 
                 ui.separator();
                 ui.label(format!("Notas inseridas: {}", self.score.notes.len()));
+
+                ui.separator();
+                egui::CollapsingHeader::new("Propriedades da partitura").show(ui, |ui| {
+                    self.render_settings_editor(ui);
+                });
             });
 
         egui::CentralPanel::default().show(ctx, |ui| {
             ui.heading("Visualização Orquestral");
             ui.separator();
 
+            let pages = layout::page_count(self.measure_count());
+            let mut interaction = None;
+            let measures = self.settings.measures();
             egui::ScrollArea::both().show(ui, |ui| {
                 ui.horizontal_top(|ui| {
-                    notation::draw_orchestral_page(
-                        ui,
-                        &self.score,
-                        &self.orchestral_order,
-                        "Movement II (excerpt) - Page 1",
-                        self.zoom_percent,
-                    );
-
-                    ui.add_space(24.0);
-
-                    notation::draw_orchestral_page(
-                        ui,
-                        &self.score,
-                        &self.orchestral_order,
-                        "Movement II (excerpt) - Page 2",
-                        self.zoom_percent,
-                    );
+                    for page in 0..pages {
+                        if page > 0 {
+                            ui.add_space(24.0);
+                        }
+                        let page_label = format!("{} - Página {}", self.settings.title, page + 1);
+                        let response = notation::draw_orchestral_page(
+                            ui,
+                            notation::PageView {
+                                score: &self.score,
+                                staves: &self.settings.staves,
+                                measures: &measures,
+                                tempo_marks: &self.settings.tempo_marks,
+                                navigation: &self.settings.navigation,
+                                selection: &self.selection,
+                                caret: self.note_input.caret.map(|caret| (caret.staff, caret.beat)),
+                                lyric_caret: self.lyric_input.note.map(|note| {
+                                    (note, self.lyric_input.verse, self.lyric_input.text.as_str())
+                                }),
+                                insert_duration: self
+                                    .note_input
+                                    .is_active()
+                                    .then_some(self.note_input.duration),
+                                concert_pitch: self.concert_pitch,
+                                range_level: self.settings.range_level,
+                                page,
+                                page_label: &page_label,
+                                zoom_percent: self.zoom_percent,
+                            },
+                        );
+                        if response.is_some() {
+                            interaction = response;
+                        }
+                    }
                 });
             });
+            if let Some(interaction) = interaction {
+                self.handle_page_interaction(interaction);
+            }
         });
 
         egui::TopBottomPanel::bottom("status_bar").show(ctx, |ui| {
             ui.horizontal_wrapped(|ui| {
-                ui.label("Page 1 of 2");
+                let measures = self.measure_count();
+                ui.label(format!("Pages: {}", layout::page_count(measures)));
                 ui.separator();
-                ui.label(format!("Bars: {}", self.score.notes.len().max(1)));
+                ui.label(format!("Bars: {measures}"));
                 ui.separator();
-                ui.label("No Selection");
+                if self.note_input.is_active() {
+                    ui.label(format!("Entrada: {}", self.note_input.describe()));
+                    ui.separator();
+                }
+                ui.label(self.selection.describe());
+                if !self.selection.is_empty() {
+                    ui.label(format!("({} eventos)", self.selected_events().len()));
+                }
                 ui.separator();
-                ui.label("Transposing Score");
+                ui.label(if self.concert_pitch {
+                    "Concert Pitch Score"
+                } else {
+                    "Transposing Score"
+                });
                 ui.separator();
                 ui.label(format!("Zoom: {:.1}%", self.zoom_percent));
+                ui.separator();
+                ui.label(&self.start_message);
+                if !self.import_log.is_empty() && ui.button("Relatório de importação").clicked()
+                {
+                    self.show_import_log = true;
+                }
             });
         });
+
+        if self.show_import_log {
+            self.render_import_log(ctx);
+        }
+        if self.show_instruments {
+            self.render_instruments_dialog(ctx);
+        }
+        if self.show_transpose {
+            self.render_transpose_dialog(ctx);
+        }
+        if self.show_bars {
+            self.render_bars_dialog(ctx);
+        }
+        if self.show_tempo {
+            self.render_tempo_dialog(ctx);
+        }
+        if self.show_navigation {
+            self.render_navigation_dialog(ctx);
+        }
+        if self.show_range_report {
+            self.render_range_report(ctx);
+        }
+    }
+
+    fn render_range_report(&mut self, ctx: &egui::Context) {
+        let issues = ranges::check(&self.score, &self.settings);
+        let mut open = self.show_range_report;
+        let mut select = None;
+        egui::Window::new("Extensões dos instrumentos")
+            .open(&mut open)
+            .default_width(460.0)
+            .show(ctx, |ui| {
+                let level = self.settings.range_level.label().to_lowercase();
+                if issues.is_empty() {
+                    ui.label(format!("Todas as notas estão na extensão {level}."));
+                    return;
+                }
+                ui.horizontal(|ui| {
+                    ui.label(format!(
+                        "{} nota(s) fora da extensão {level}.",
+                        issues.len()
+                    ));
+                    if ui.button("Selecionar todas").clicked() {
+                        select = Some(issues.iter().map(|issue| issue.index).collect());
+                    }
+                });
+                ui.separator();
+                egui::ScrollArea::vertical()
+                    .max_height(320.0)
+                    .show(ui, |ui| {
+                        for issue in &issues {
+                            let color = match issue.beyond {
+                                RangeLevel::Professional => egui::Color32::from_rgb(200, 30, 30),
+                                RangeLevel::Amateur => egui::Color32::from_rgb(215, 120, 0),
+                            };
+                            let text = egui::RichText::new(issue.to_string()).color(color);
+                            if ui.link(text).on_hover_text("Selecionar a nota").clicked() {
+                                select = Some(vec![issue.index]);
+                            }
+                        }
+                    });
+            });
+        if let Some(indices) = select {
+            self.selection.select_notes(indices, false);
+        }
+        self.show_range_report = open;
+    }
+
+    fn render_transpose_dialog(&mut self, ctx: &egui::Context) {
+        let mut open = self.show_transpose;
+        let mut apply = false;
+        egui::Window::new("Transpor")
+            .open(&mut open)
+            .resizable(false)
+            .show(ctx, |ui| {
+                let options = &mut self.transpose;
+                ui.horizontal(|ui| {
+                    for by in TransposeBy::ALL {
+                        ui.radio_value(&mut options.by, by, by.label());
+                    }
+                });
+                ui.separator();
+
+                match options.by {
+                    TransposeBy::Interval => {
+                        egui::ComboBox::from_label("Intervalo")
+                            .selected_text(Interval::NAMED[options.interval].0)
+                            .show_ui(ui, |ui| {
+                                for (index, (name, _)) in Interval::NAMED.iter().enumerate() {
+                                    ui.selectable_value(&mut options.interval, index, *name);
+                                }
+                            });
+                        ui.horizontal(|ui| {
+                            for mode in TransposeMode::ALL {
+                                ui.radio_value(&mut options.mode, mode, mode.label());
+                            }
+                        })
+                        .response
+                        .on_hover_text("Diatônica mantém as notas dentro da armadura de clave");
+                    }
+                    TransposeBy::Key => {
+                        ui.label(format!("De: {}", self.settings.key_signature.label()));
+                        egui::ComboBox::from_label("Para")
+                            .selected_text(options.key.label())
+                            .show_ui(ui, |ui| {
+                                for key in KeySignature::ALL {
+                                    ui.selectable_value(&mut options.key, key, key.label());
+                                }
+                            });
+                    }
+                    TransposeBy::Octave => {
+                        ui.add(
+                            egui::DragValue::new(&mut options.octaves)
+                                .range(1..=3)
+                                .suffix(" oitava(s)"),
+                        );
+                    }
+                }
+                ui.horizontal(|ui| {
+                    ui.radio_value(&mut options.upward, true, "Acima");
+                    ui.radio_value(&mut options.upward, false, "Abaixo");
+                });
+                ui.separator();
+
+                ui.horizontal(|ui| {
+                    ui.label("Aplicar a:");
+                    for scope in TransposeScope::ALL {
+                        ui.radio_value(&mut options.scope, scope, scope.label());
+                    }
+                });
+                if options.scope == TransposeScope::Staff {
+                    let selected = self
+                        .settings
+                        .staves
+                        .get(self.selected_staff)
+                        .map(|staff| staff.labels(&self.settings.staves).0)
+                        .unwrap_or_default();
+                    egui::ComboBox::from_id_salt("transpose_staff")
+                        .selected_text(selected)
+                        .show_ui(ui, |ui| {
+                            for (index, staff) in self.settings.staves.iter().enumerate() {
+                                let label = staff.labels(&self.settings.staves).0;
+                                ui.selectable_value(&mut self.selected_staff, index, label);
+                            }
+                        });
+                }
+                if options.by == TransposeBy::Key && options.scope != TransposeScope::Score {
+                    ui.label("A armadura de clave só muda ao transpor a partitura inteira.");
+                }
+                ui.separator();
+                apply = ui.button("Transpor").clicked();
+            });
+        if apply {
+            self.transpose();
+        }
+        self.show_transpose = open;
+    }
+
+    fn render_bars_dialog(&mut self, ctx: &egui::Context) {
+        let mut open = self.show_bars;
+        let mut insert = None;
+        let mut delete = false;
+        let mut pickup = false;
+        let has_selection = self.selected_measures().is_some();
+        egui::Window::new("Compassos")
+            .open(&mut open)
+            .resizable(false)
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    ui.label("Quantidade:");
+                    ui.add(
+                        egui::DragValue::new(&mut self.bar_count)
+                            .range(1..=64)
+                            .suffix(" compasso(s)"),
+                    );
+                });
+                ui.horizontal(|ui| {
+                    if ui.button("Adicionar ao final").clicked() {
+                        insert = Some(BarPlacement::End);
+                    }
+                    if ui
+                        .add_enabled(has_selection, egui::Button::new("Inserir antes"))
+                        .clicked()
+                    {
+                        insert = Some(BarPlacement::Before);
+                    }
+                    if ui
+                        .add_enabled(has_selection, egui::Button::new("Inserir depois"))
+                        .clicked()
+                    {
+                        insert = Some(BarPlacement::After);
+                    }
+                });
+                ui.separator();
+
+                delete = ui
+                    .add_enabled(
+                        has_selection,
+                        egui::Button::new("Apagar compassos selecionados"),
+                    )
+                    .on_hover_text("Remove os compassos de todas as pautas")
+                    .clicked();
+                ui.separator();
+
+                ui.horizontal(|ui| {
+                    ui.label("Anacruse:");
+                    ui.add(
+                        egui::DragValue::new(&mut self.pickup_beats)
+                            .range(0.5..=3.5)
+                            .speed(0.5)
+                            .suffix(" tempo(s)"),
+                    );
+                    pickup = ui.button("Criar anacruse").clicked();
+                });
+            });
+        if let Some(placement) = insert {
+            self.insert_measures(placement);
+        }
+        if delete {
+            self.delete_measures();
+        }
+        if pickup {
+            self.add_pickup();
+        }
+        self.show_bars = open;
+    }
+
+    fn render_tempo_dialog(&mut self, ctx: &egui::Context) {
+        let mut open = self.show_tempo;
+        let mut set_opening = false;
+        let mut add = false;
+        let mut remove = None;
+        let has_selection = self.selection_start().is_some();
+        let measures = self.settings.measures();
+        egui::Window::new("Andamento")
+            .open(&mut open)
+            .resizable(false)
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    ui.add(
+                        egui::DragValue::new(&mut self.start_bpm)
+                            .range(20.0..=300.0)
+                            .prefix("♩ = "),
+                    );
+                    set_opening = ui.button("Definir andamento inicial").clicked();
+                });
+                ui.separator();
+
+                let draft = &mut self.tempo_draft;
+                ui.horizontal(|ui| {
+                    ui.label("Texto:");
+                    ui.text_edit_singleline(&mut draft.text);
+                });
+                ui.horizontal(|ui| {
+                    let mut metronome = draft.bpm.is_some();
+                    ui.checkbox(&mut metronome, "Metrônomo");
+                    let mut bpm = draft.bpm.unwrap_or(self.settings.bpm);
+                    ui.add_enabled(
+                        metronome,
+                        egui::DragValue::new(&mut bpm)
+                            .range(20.0..=300.0)
+                            .prefix("♩ = "),
+                    );
+                    draft.bpm = metronome.then_some(bpm);
+                });
+                egui::ComboBox::from_label("Mudança gradual")
+                    .selected_text(draft.change.map_or("Nenhuma", TempoChange::label))
+                    .show_ui(ui, |ui| {
+                        ui.selectable_value(&mut draft.change, None, "Nenhuma");
+                        for change in TempoChange::ALL {
+                            ui.selectable_value(&mut draft.change, Some(change), change.label());
+                        }
+                    })
+                    .response
+                    .on_hover_text("Vai até a próxima indicação, ou até o fim da partitura");
+                add = ui
+                    .add_enabled(has_selection, egui::Button::new("Adicionar na seleção"))
+                    .clicked();
+                ui.separator();
+
+                if self.settings.tempo_marks.is_empty() {
+                    ui.label("Nenhuma indicação de andamento.");
+                }
+                for (index, mark) in self.settings.tempo_marks.iter().enumerate() {
+                    let (measure, offset) = measures.locate(mark.beat);
+                    ui.horizontal(|ui| {
+                        if ui.small_button("✕").clicked() {
+                            remove = Some(index);
+                        }
+                        ui.label(format!(
+                            "Compasso {}, tempo {}: {}",
+                            measure + 1,
+                            offset + 1.0,
+                            mark.label()
+                        ));
+                    });
+                }
+            });
+        if set_opening {
+            let marks = self.settings.tempo_marks.clone();
+            self.change_tempo("Andamento", self.start_bpm, marks);
+        }
+        if add {
+            self.add_tempo_mark();
+        }
+        if let Some(index) = remove {
+            let mut marks = self.settings.tempo_marks.clone();
+            marks.remove(index);
+            self.change_tempo("Remover andamento", self.settings.bpm, marks);
+        }
+        self.show_tempo = open;
+    }
+
+    fn render_navigation_dialog(&mut self, ctx: &egui::Context) {
+        let mut open = self.show_navigation;
+        let mut add = false;
+        let mut remove = None;
+        let has_selection = self.selected_measures().is_some();
+        egui::Window::new("Repetições e saltos")
+            .open(&mut open)
+            .resizable(false)
+            .show(ctx, |ui| {
+                let draft = &mut self.navigation_draft;
+                egui::ComboBox::from_label("Sinal")
+                    .selected_text(draft.name())
+                    .show_ui(ui, |ui| {
+                        for navigation in Navigation::ALL {
+                            if ui
+                                .selectable_label(draft.same_kind(navigation), navigation.name())
+                                .clicked()
+                                && !draft.same_kind(navigation)
+                            {
+                                *draft = navigation;
+                            }
+                        }
+                    });
+                match draft {
+                    Navigation::RepeatEnd { times } => {
+                        ui.add(
+                            egui::DragValue::new(times)
+                                .range(2..=9)
+                                .prefix("Tocar ")
+                                .suffix(" vezes"),
+                        );
+                    }
+                    Navigation::Ending { passes } => {
+                        ui.horizontal(|ui| {
+                            ui.label("Passagens:");
+                            for pass in 0..4 {
+                                let mut taken = *passes & (1 << pass) != 0;
+                                if ui.checkbox(&mut taken, format!("{}.", pass + 1)).changed() {
+                                    *passes ^= 1 << pass;
+                                }
+                            }
+                        });
+                    }
+                    Navigation::DaCapo(until) | Navigation::DalSegno(until) => {
+                        egui::ComboBox::from_label("Depois do salto")
+                            .selected_text(until.label())
+                            .show_ui(ui, |ui| {
+                                for option in Until::ALL {
+                                    ui.selectable_value(until, option, option.label());
+                                }
+                            });
+                    }
+                    _ => {}
+                }
+                let valid = !matches!(draft, Navigation::Ending { passes: 0 });
+                add = ui
+                    .add_enabled(
+                        has_selection && valid,
+                        egui::Button::new("Adicionar nos compassos selecionados"),
+                    )
+                    .on_hover_text("Casas cobrem todos os compassos selecionados")
+                    .clicked();
+                ui.separator();
+
+                if self.settings.navigation.is_empty() {
+                    ui.label("Nenhuma repetição ou salto.");
+                }
+                for (index, mark) in self.settings.navigation.iter().enumerate() {
+                    ui.horizontal(|ui| {
+                        if ui.small_button("✕").clicked() {
+                            remove = Some(index);
+                        }
+                        ui.label(format!(
+                            "Compasso {}: {}",
+                            mark.measure + 1,
+                            mark.navigation.label()
+                        ));
+                    });
+                }
+            });
+        if add {
+            self.add_navigation();
+        }
+        if let Some(index) = remove {
+            let mut navigation = self.settings.navigation.clone();
+            navigation.remove(index);
+            self.change_navigation("Remover repetição", navigation);
+        }
+        self.show_navigation = open;
+    }
+
+    fn render_instruments_dialog(&mut self, ctx: &egui::Context) {
+        let mut open = self.show_instruments;
+        egui::Window::new("Instrumentos")
+            .open(&mut open)
+            .default_width(520.0)
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    egui::ComboBox::from_id_salt("new_staff_instrument")
+                        .selected_text(self.new_staff_instrument.label())
+                        .show_ui(ui, |ui| {
+                            for family in Family::ALL {
+                                let mut instruments = Instrument::all()
+                                    .filter(|instrument| instrument.family() == family)
+                                    .peekable();
+                                if instruments.peek().is_none() {
+                                    continue;
+                                }
+                                ui.label(egui::RichText::new(family.label()).weak());
+                                for instrument in instruments {
+                                    ui.selectable_value(
+                                        &mut self.new_staff_instrument,
+                                        instrument,
+                                        instrument.label(),
+                                    )
+                                    .on_hover_text(
+                                        &instrument.names(instruments::Language::English).long,
+                                    );
+                                }
+                            }
+                        });
+                    if ui
+                        .button("Adicionar pauta")
+                        .on_hover_text("Um segundo violino vira Violino II")
+                        .clicked()
+                    {
+                        let mut staves = self.settings.staves.clone();
+                        let index = staves::add_staff(&mut staves, self.new_staff_instrument);
+                        self.change_staves("Adicionar pauta", staves);
+                        self.selected_staff = index;
+                    }
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Ordenar como:");
+                    for order in ScoreOrder::ALL {
+                        if ui.button(order.label()).clicked() {
+                            let mut staves = self.settings.staves.clone();
+                            order.sort(&mut staves);
+                            self.change_staves("Ordenar pautas", staves);
+                        }
+                    }
+                });
+                ui.separator();
+
+                if self.staff_drafts.len() != self.settings.staves.len() {
+                    self.staff_drafts = self.settings.staves.clone();
+                }
+                let mut editing = false;
+                let mut commit = false;
+                let mut moved = None;
+                let mut remove = None;
+                let mut lower = None;
+                let count = self.staff_drafts.len();
+
+                egui::Grid::new("staff_list").striped(true).show(ui, |ui| {
+                    ui.strong("Instrumento");
+                    ui.strong("Nome");
+                    ui.strong("Abreviação");
+                    ui.end_row();
+
+                    for index in 0..count {
+                        let part = self.settings.staves[index].part;
+                        let group = staves::grand_staff(&self.settings.staves, index);
+                        if part.sub_staff > 0 {
+                            // Lower staves of a grand staff print the top one's name.
+                            ui.label(format!("   ↳ pauta {}", part.sub_staff + 1));
+                            ui.label("");
+                            ui.label("");
+                            if ui
+                                .add_enabled(index + 1 == group.end, egui::Button::new("✕"))
+                                .on_hover_text("Remove esta pauta e suas notas")
+                                .clicked()
+                            {
+                                remove = Some(index..index + 1);
+                            }
+                            ui.end_row();
+                            continue;
+                        }
+
+                        let (name, short_name) = Staff::new(part).labels(&self.settings.staves);
+                        ui.label(part.instrument.label());
+
+                        let draft = &mut self.staff_drafts[index];
+                        let name_edit = ui.add(
+                            egui::TextEdit::singleline(&mut draft.name)
+                                .hint_text(name)
+                                .desired_width(140.0),
+                        );
+                        let short_edit = ui.add(
+                            egui::TextEdit::singleline(&mut draft.short_name)
+                                .hint_text(short_name)
+                                .desired_width(80.0),
+                        );
+                        editing |= name_edit.has_focus() || short_edit.has_focus();
+                        commit |= name_edit.lost_focus() || short_edit.lost_focus();
+
+                        ui.horizontal(|ui| {
+                            if ui.add_enabled(index > 0, egui::Button::new("⬆")).clicked() {
+                                moved = Some((index, false));
+                            }
+                            if ui
+                                .add_enabled(group.end < count, egui::Button::new("⬇"))
+                                .clicked()
+                            {
+                                moved = Some((index, true));
+                            }
+                            if ui
+                                .button("✕")
+                                .on_hover_text("Remove a pauta e suas notas")
+                                .clicked()
+                            {
+                                remove = Some(group.clone());
+                            }
+                            if group.len() < usize::from(part.instrument.staff_count())
+                                && ui
+                                    .button("+")
+                                    .on_hover_text(
+                                        "Adiciona a pauta de baixo, como o pedal do órgão",
+                                    )
+                                    .clicked()
+                            {
+                                lower = Some(index);
+                            }
+                        });
+                        ui.end_row();
+                    }
+                });
+
+                if commit {
+                    let staves = self
+                        .staff_drafts
+                        .iter()
+                        .map(|draft| Staff {
+                            name: draft.name.trim().to_owned(),
+                            short_name: draft.short_name.trim().to_owned(),
+                            ..draft.clone()
+                        })
+                        .collect();
+                    self.change_staves("Renomear pauta", staves);
+                }
+                if let Some((index, down)) = moved {
+                    let mut staves = self.settings.staves.clone();
+                    staves::move_grand_staff(&mut staves, index, down);
+                    self.change_staves("Mover pauta", staves);
+                }
+                if let Some(range) = remove {
+                    self.remove_staves(range);
+                }
+                if let Some(index) = lower {
+                    let mut staves = self.settings.staves.clone();
+                    if staves::add_lower_staff(&mut staves, index).is_some() {
+                        self.change_staves("Adicionar pauta", staves);
+                    }
+                }
+                if !editing {
+                    self.staff_drafts = self.settings.staves.clone();
+                }
+            });
+        self.show_instruments = open;
     }
 }
 
@@ -675,181 +3045,13 @@ fn sanitized_ntr_path(input: &str) -> PathBuf {
     }
 }
 
-fn serialize_ntr(
-    settings: &ScoreSettings,
-    score: &Score,
-    bpm: f32,
-    key_signature: KeySignature,
-    time_signature: TimeSignature,
-    paper_size: PaperSize,
-) -> String {
-    let mut out = String::new();
-    out.push_str("NTR1\n");
-    out.push_str(&format!("title={}\n", settings.title.replace('\n', " ")));
-    out.push_str(&format!(
-        "composer={}\n",
-        settings.composer.replace('\n', " ")
-    ));
-    out.push_str(&format!("bpm={}\n", bpm));
-    out.push_str(&format!("key={:?}\n", key_signature));
-    out.push_str(&format!("time={:?}\n", time_signature));
-    out.push_str(&format!("paper={:?}\n", paper_size));
-    out.push_str("notes:\n");
-    for note in &score.notes {
-        out.push_str(&format!(
-            "{},{:?},{},{:?}\n",
-            note.pitch.octave,
-            note.pitch.class,
-            note.duration.beats(),
-            note.instrument
-        ));
-    }
-    out
-}
-
-fn deserialize_ntr(contents: &str) -> Result<(ScoreSettings, Score, f32), String> {
-    let mut lines = contents.lines();
-    let Some(header) = lines.next() else {
-        return Err("arquivo vazio".to_owned());
-    };
-    if header.trim() != "NTR1" {
-        return Err("formato .ntr inválido".to_owned());
-    }
-
-    let mut title = "Nova Partitura".to_owned();
-    let mut composer = "Compositor".to_owned();
-    let mut bpm = 110.0_f32;
-    let mut key = KeySignature::C;
-    let mut time = TimeSignature::FourFour;
-    let mut paper = PaperSize::A4;
-    let mut notes = Vec::new();
-    let mut in_notes = false;
-
-    for line in lines {
-        if line.trim().is_empty() {
-            continue;
-        }
-
-        if line == "notes:" {
-            in_notes = true;
-            continue;
-        }
-
-        if !in_notes {
-            if let Some(rest) = line.strip_prefix("title=") {
-                title = rest.to_owned();
-            } else if let Some(rest) = line.strip_prefix("composer=") {
-                composer = rest.to_owned();
-            } else if let Some(rest) = line.strip_prefix("bpm=") {
-                bpm = rest.parse::<f32>().unwrap_or(110.0);
-            } else if let Some(rest) = line.strip_prefix("key=") {
-                key = parse_key(rest).unwrap_or(KeySignature::C);
-            } else if let Some(rest) = line.strip_prefix("time=") {
-                time = parse_time(rest).unwrap_or(TimeSignature::FourFour);
-            } else if let Some(rest) = line.strip_prefix("paper=") {
-                paper = parse_paper(rest).unwrap_or(PaperSize::A4);
-            }
-        } else {
-            let parts: Vec<&str> = line.split(',').collect();
-            if parts.len() != 4 {
-                continue;
-            }
-            let octave = parts[0].parse::<i8>().unwrap_or(4);
-            let class = parse_pitch(parts[1]).unwrap_or(PitchClass::C);
-            let beats = parts[2].parse::<f32>().unwrap_or(1.0);
-            let duration = parse_duration_from_beats(beats);
-            let instrument = parse_instrument(parts[3]).unwrap_or(Instrument::Piano);
-            notes.push(NoteEvent {
-                pitch: Pitch { class, octave },
-                duration,
-                instrument,
-            });
-        }
-    }
-
-    Ok((
-        ScoreSettings {
-            title,
-            composer,
-            key_signature: key,
-            time_signature: time,
-            paper_size: paper,
-        },
-        Score { notes },
-        bpm,
-    ))
-}
-
-fn parse_pitch(raw: &str) -> Option<PitchClass> {
-    match raw {
-        "C" => Some(PitchClass::C),
-        "D" => Some(PitchClass::D),
-        "E" => Some(PitchClass::E),
-        "F" => Some(PitchClass::F),
-        "G" => Some(PitchClass::G),
-        "A" => Some(PitchClass::A),
-        "B" => Some(PitchClass::B),
-        _ => None,
-    }
-}
-
-fn parse_duration_from_beats(beats: f32) -> DurationValue {
-    if (beats - 4.0).abs() < 0.1 {
-        DurationValue::Whole
-    } else if (beats - 2.0).abs() < 0.1 {
-        DurationValue::Half
-    } else if (beats - 0.5).abs() < 0.1 {
-        DurationValue::Eighth
+fn export_path(input: &str, extension: &str) -> PathBuf {
+    let trimmed = input.trim();
+    let base = if trimmed.is_empty() {
+        "notarium_score"
     } else {
-        DurationValue::Quarter
-    }
-}
-
-fn parse_instrument(raw: &str) -> Option<Instrument> {
-    match raw {
-        "Violin" => Some(Instrument::Violin),
-        "Viola" => Some(Instrument::Viola),
-        "Cello" => Some(Instrument::Cello),
-        "Flute" => Some(Instrument::Flute),
-        "Clarinet" => Some(Instrument::Clarinet),
-        "Trumpet" => Some(Instrument::Trumpet),
-        "Horn" => Some(Instrument::Horn),
-        "Timpani" => Some(Instrument::Timpani),
-        "Piano" => Some(Instrument::Piano),
-        _ => None,
-    }
-}
-
-fn parse_key(raw: &str) -> Option<KeySignature> {
-    match raw {
-        "C" => Some(KeySignature::C),
-        "G" => Some(KeySignature::G),
-        "D" => Some(KeySignature::D),
-        "A" => Some(KeySignature::A),
-        "E" => Some(KeySignature::E),
-        "F" => Some(KeySignature::F),
-        "Bb" => Some(KeySignature::Bb),
-        "Eb" => Some(KeySignature::Eb),
-        "Ab" => Some(KeySignature::Ab),
-        _ => None,
-    }
-}
-
-fn parse_time(raw: &str) -> Option<TimeSignature> {
-    match raw {
-        "FourFour" => Some(TimeSignature::FourFour),
-        "ThreeFour" => Some(TimeSignature::ThreeFour),
-        "TwoFour" => Some(TimeSignature::TwoFour),
-        "SixEight" => Some(TimeSignature::SixEight),
-        _ => None,
-    }
-}
+        trimmed
+    };
 
-fn parse_paper(raw: &str) -> Option<PaperSize> {
-    match raw {
-        "A4" => Some(PaperSize::A4),
-        "A3" => Some(PaperSize::A3),
-        "Letter" => Some(PaperSize::Letter),
-        _ => None,
-    }
+    PathBuf::from(base).with_extension(extension)
 }
@@ -0,0 +1,711 @@
+//! Where the barlines fall, and the commands that move them: Split and Join,
+//! inserting and deleting measures and adding a pickup.
+//!
+//! Like the rest of the model, measures are not stored with the notes: a
+//! [`MeasureMap`] turns beat positions into measures from the opening time
+//! signature and the meter changes in [`ScoreSettings`].
+
+use crate::music::{
+    Meter, MeterChange, NavigationMark, NoteEvent, ScoreSettings, TempoMark, TimeSignature,
+    BEAT_EPSILON,
+};
+use crate::rebar;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MeasureMap {
+    first: Meter,
+    changes: Vec<MeterChange>,
+}
+
+impl MeasureMap {
+    pub fn new(time_signature: TimeSignature, changes: &[MeterChange]) -> Self {
+        Self {
+            first: time_signature.into(),
+            changes: changes.to_vec(),
+        }
+    }
+
+    pub fn meter(&self, measure: usize) -> Meter {
+        self.changes
+            .iter()
+            .rev()
+            .find(|change| change.measure <= measure)
+            .map_or(self.first, |change| change.meter)
+    }
+
+    /// Length of `measure` in quarter-note beats.
+    pub fn length(&self, measure: usize) -> f32 {
+        self.meter(measure).beats_per_measure()
+    }
+
+    /// Beats from the start of the score to the downbeat of `measure`.
+    pub fn start(&self, measure: usize) -> f32 {
+        let mut start = 0.0;
+        let mut from = 0;
+        let mut meter = self.first;
+        for change in &self.changes {
+            if change.measure >= measure {
+                break;
+            }
+            start += (change.measure - from) as f32 * meter.beats_per_measure();
+            from = change.measure;
+            meter = change.meter;
+        }
+        start + (measure - from) as f32 * meter.beats_per_measure()
+    }
+
+    /// The measure `beat` falls in and the offset from its downbeat. A beat
+    /// a hair before a barline counts as the next downbeat.
+    pub fn locate(&self, beat: f32) -> (usize, f32) {
+        let mut start = 0.0;
+        let mut from = 0;
+        let mut meter = self.first;
+        for change in &self.changes {
+            let end = start + (change.measure - from) as f32 * meter.beats_per_measure();
+            if beat + BEAT_EPSILON < end {
+                break;
+            }
+            start = end;
+            from = change.measure;
+            meter = change.meter;
+        }
+        let length = meter.beats_per_measure();
+        let within = ((beat - start + BEAT_EPSILON) / length).floor().max(0.0);
+        (
+            from + within as usize,
+            (beat - start - within * length).max(0.0),
+        )
+    }
+
+    /// Measures needed to hold `beats`.
+    pub fn count(&self, beats: f32) -> usize {
+        let (measure, offset) = self.locate(beats);
+        measure + usize::from(offset > BEAT_EPSILON)
+    }
+
+    /// Measure 0 is a pickup when it is shorter than the opening meter that
+    /// resumes right after it.
+    pub fn has_pickup(&self) -> bool {
+        self.changes
+            .first()
+            .is_some_and(|change| change.measure == 0)
+            && self.meter(1) == self.first
+            && self.length(0) < self.first.beats_per_measure()
+    }
+
+    /// The time signature printed at the start of `measure`, if any. A pickup
+    /// shows the meter of the full measures that follow it.
+    pub fn printed_meter(&self, measure: usize) -> Option<Meter> {
+        match measure {
+            0 if self.has_pickup() => Some(self.first),
+            0 => Some(self.meter(0)),
+            1 if self.has_pickup() => None,
+            _ => (self.meter(measure) != self.meter(measure - 1)).then(|| self.meter(measure)),
+        }
+    }
+
+    /// Meter changes for `measure` split `offset` beats after its downbeat,
+    /// or `None` when the offset is not inside the measure. Splitting the
+    /// first measure turns its opening into a pickup and lets the music after
+    /// it run on in full measures.
+    pub fn split(&self, measure: usize, offset: f32) -> Option<Vec<MeterChange>> {
+        let meter = self.meter(measure);
+        let length = meter.beats_per_measure();
+        if offset < BEAT_EPSILON || offset > length - BEAT_EPSILON {
+            return None;
+        }
+        let head = Meter::from_beats(offset, meter.denominator)?;
+        let tail = Meter::from_beats(length - offset, meter.denominator)?;
+
+        let mut changes = self.shifted(measure + 1, 1);
+        if measure == 0 {
+            changes.push(MeterChange {
+                measure,
+                meter: head,
+            });
+            changes.push(MeterChange { measure: 1, meter });
+        } else {
+            changes.push(MeterChange {
+                measure,
+                meter: head,
+            });
+            changes.push(MeterChange {
+                measure: measure + 1,
+                meter: tail,
+            });
+            if self
+                .changes
+                .iter()
+                .all(|change| change.measure != measure + 1)
+            {
+                changes.push(MeterChange {
+                    measure: measure + 2,
+                    meter,
+                });
+            }
+        }
+        Some(self.normalized(changes))
+    }
+
+    /// Meter changes for `first..=last` merged into one measure, or `None`
+    /// when the range is empty or the result has no simple meter.
+    pub fn join(&self, first: usize, last: usize) -> Option<Vec<MeterChange>> {
+        if last <= first {
+            return None;
+        }
+        let beats: f32 = (first..=last).map(|measure| self.length(measure)).sum();
+        let denominator = (first..=last)
+            .map(|measure| self.meter(measure).denominator)
+            .max()?;
+        let after = self.meter(last + 1);
+        // Joining bars back into a whole one restores the meter around them.
+        let meter = first
+            .checked_sub(1)
+            .map(|before| self.meter(before))
+            .into_iter()
+            .chain([after])
+            .find(|meter| (meter.beats_per_measure() - beats).abs() < BEAT_EPSILON)
+            .or_else(|| Meter::from_beats(beats, denominator))?;
+
+        let mut changes: Vec<MeterChange> = self
+            .changes
+            .iter()
+            .filter(|change| change.measure < first)
+            .copied()
+            .collect();
+        changes.push(MeterChange {
+            measure: first,
+            meter,
+        });
+        changes.push(MeterChange {
+            measure: first + 1,
+            meter: after,
+        });
+        changes.extend(
+            self.changes
+                .iter()
+                .filter(|change| change.measure > last + 1)
+                .map(|change| MeterChange {
+                    measure: change.measure - (last - first),
+                    ..*change
+                }),
+        );
+        Some(self.normalized(changes))
+    }
+
+    /// Meter changes for `count` measures inserted before `measure`. They take
+    /// the meter of the measure they push along, or of the full measures when
+    /// that is the pickup.
+    pub fn insert(&self, measure: usize, count: usize) -> Vec<MeterChange> {
+        let source = if measure == 0 && self.has_pickup() {
+            1
+        } else {
+            measure
+        };
+        let mut changes = self.shifted(measure, count);
+        changes.push(MeterChange {
+            measure,
+            meter: self.meter(source),
+        });
+        self.normalized(changes)
+    }
+
+    /// Meter changes with `first..=last` removed; the measures after them keep
+    /// their meter.
+    pub fn delete(&self, first: usize, last: usize) -> Vec<MeterChange> {
+        let count = last + 1 - first;
+        let mut changes: Vec<MeterChange> = self
+            .changes
+            .iter()
+            .filter(|change| change.measure < first)
+            .copied()
+            .collect();
+        changes.push(MeterChange {
+            measure: first,
+            meter: self.meter(last + 1),
+        });
+        changes.extend(
+            self.changes
+                .iter()
+                .filter(|change| change.measure > last + 1)
+                .map(|change| MeterChange {
+                    measure: change.measure - count,
+                    ..*change
+                }),
+        );
+        self.normalized(changes)
+    }
+
+    /// Meter changes for a new pickup of `beats` before the first measure, or
+    /// `None` when there already is one or `beats` does not make a shorter
+    /// measure.
+    pub fn with_pickup(&self, beats: f32) -> Option<Vec<MeterChange>> {
+        let opening = self.meter(0);
+        if self.has_pickup() || beats >= opening.beats_per_measure() - BEAT_EPSILON {
+            return None;
+        }
+        let pickup = Meter::from_beats(beats, opening.denominator)?;
+        let mut changes = self.shifted(0, 1);
+        changes.push(MeterChange {
+            measure: 0,
+            meter: pickup,
+        });
+        changes.push(MeterChange {
+            measure: 1,
+            meter: opening,
+        });
+        Some(self.normalized(changes))
+    }
+
+    /// The changes before `measure`, plus those from it on moved by `by` measures.
+    fn shifted(&self, measure: usize, by: usize) -> Vec<MeterChange> {
+        self.changes
+            .iter()
+            .map(|change| MeterChange {
+                measure: if change.measure >= measure {
+                    change.measure + by
+                } else {
+                    change.measure
+                },
+                ..*change
+            })
+            .collect()
+    }
+
+    /// Sorts `changes`, keeps the last one given for a measure and drops
+    /// those that repeat the meter already in force.
+    fn normalized(&self, mut changes: Vec<MeterChange>) -> Vec<MeterChange> {
+        changes.reverse();
+        changes.sort_by_key(|change| change.measure);
+        changes.dedup_by_key(|change| change.measure);
+
+        let mut current = self.first;
+        changes.retain(|change| {
+            let keep = change.meter != current;
+            current = change.meter;
+            keep
+        });
+        changes
+    }
+}
+
+/// New meter changes and the notes re-barred to match them.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MeasureEdit {
+    pub meter_changes: Vec<MeterChange>,
+    pub notes: Vec<NoteEvent>,
+    /// Tempo marks moved along with the music they stand over.
+    pub tempo_marks: Vec<TempoMark>,
+    /// Repeats and navigation signs moved along with their measures.
+    pub navigation: Vec<NavigationMark>,
+}
+
+/// Splits `measure` `offset` beats after its downbeat; see [`MeasureMap::split`].
+pub fn split(
+    notes: &[NoteEvent],
+    settings: &ScoreSettings,
+    measure: usize,
+    offset: f32,
+) -> Option<MeasureEdit> {
+    let measures = settings.measures();
+    let meter_changes = measures.split(measure, offset)?;
+    let start = measures.start(measure);
+    // A pickup moves every later barline; other splits stay inside the measure.
+    let end = if measure == 0 {
+        f32::INFINITY
+    } else {
+        start + measures.length(measure)
+    };
+    let mut edit = reflowed(notes, settings, meter_changes, start, end);
+    // Signs at the end of the measure go with its second half.
+    edit.navigation = renumbered(&settings.navigation, |mark| {
+        Some(
+            if mark.measure > measure || (mark.measure == measure && mark.navigation.at_end()) {
+                mark.measure + 1
+            } else {
+                mark.measure
+            },
+        )
+    });
+    Some(edit)
+}
+
+/// Joins `first..=last` into one measure; see [`MeasureMap::join`].
+pub fn join(
+    notes: &[NoteEvent],
+    settings: &ScoreSettings,
+    first: usize,
+    last: usize,
+) -> Option<MeasureEdit> {
+    let measures = settings.measures();
+    let meter_changes = measures.join(first, last)?;
+    let (start, end) = (measures.start(first), measures.start(last + 1));
+    let mut edit = reflowed(notes, settings, meter_changes, start, end);
+    // The joined measure keeps the signs at its outer edges.
+    edit.navigation = renumbered(&settings.navigation, |mark| match mark.measure {
+        measure if measure < first => Some(measure),
+        measure if measure > last => Some(measure - (last - first)),
+        measure if mark.navigation.at_end() => (measure == last).then_some(first),
+        measure => (measure == first).then_some(first),
+    });
+    Some(edit)
+}
+
+/// Inserts `count` measures of rests on every staff before `measure`; a
+/// `measure` past the end appends them.
+pub fn insert(
+    notes: &[NoteEvent],
+    settings: &ScoreSettings,
+    measure: usize,
+    count: usize,
+) -> MeasureEdit {
+    let meter_changes = settings.measures().insert(measure, count);
+    let after = MeasureMap::new(settings.time_signature, &meter_changes);
+    let start = after.start(measure);
+    let beats = after.start(measure + count) - start;
+    let mut edit = respliced(notes, settings, meter_changes, start, start, beats);
+    edit.navigation = renumbered(&settings.navigation, |mark| {
+        Some(if mark.measure >= measure {
+            mark.measure + count
+        } else {
+            mark.measure
+        })
+    });
+    edit
+}
+
+/// Deletes `first..=last` from every staff.
+pub fn delete(
+    notes: &[NoteEvent],
+    settings: &ScoreSettings,
+    first: usize,
+    last: usize,
+) -> MeasureEdit {
+    let measures = settings.measures();
+    let (start, end) = (measures.start(first), measures.start(last + 1));
+    let mut edit = respliced(
+        notes,
+        settings,
+        measures.delete(first, last),
+        start,
+        end,
+        0.0,
+    );
+    edit.navigation = renumbered(&settings.navigation, |mark| match mark.measure {
+        measure if measure < first => Some(measure),
+        measure if measure > last => Some(measure - (last - first + 1)),
+        _ => None,
+    });
+    edit
+}
+
+/// Adds a pickup of `beats` filled with rests before the first measure.
+pub fn add_pickup(
+    notes: &[NoteEvent],
+    settings: &ScoreSettings,
+    beats: f32,
+) -> Option<MeasureEdit> {
+    let meter_changes = settings.measures().with_pickup(beats)?;
+    let mut edit = respliced(notes, settings, meter_changes, 0.0, 0.0, beats);
+    edit.navigation = renumbered(&settings.navigation, |mark| Some(mark.measure + 1));
+    Some(edit)
+}
+
+/// Navigation marks moved to the measure `to` gives them, or dropped where
+/// it gives none.
+fn renumbered(
+    marks: &[NavigationMark],
+    to: impl Fn(&NavigationMark) -> Option<usize>,
+) -> Vec<NavigationMark> {
+    let mut out: Vec<NavigationMark> = marks
+        .iter()
+        .filter_map(|mark| {
+            Some(NavigationMark {
+                measure: to(mark)?,
+                ..*mark
+            })
+        })
+        .collect();
+    out.sort_by_key(|mark| mark.measure);
+    out.dedup();
+    out
+}
+
+/// Re-bars every staff after the barlines between `from` and `to` beats
+/// moved: notes crossing a new barline are split into tied values, and ties
+/// across a barline that is gone are merged back into one value.
+fn reflowed(
+    notes: &[NoteEvent],
+    settings: &ScoreSettings,
+    meter_changes: Vec<MeterChange>,
+    from: f32,
+    to: f32,
+) -> MeasureEdit {
+    let measures = MeasureMap::new(settings.time_signature, &meter_changes);
+    let mut out = notes.to_vec();
+    for part in settings.parts() {
+        for voice in rebar::voices(&out, part) {
+            let stream = rebar::voice_stream(&out, part, voice);
+            let stream = rebar::reflow(&stream, &measures, from, to);
+            out = rebar::replace_stream(&out, part, voice, stream);
+        }
+    }
+    MeasureEdit {
+        meter_changes,
+        notes: out,
+        tempo_marks: settings.tempo_marks.clone(),
+        navigation: settings.navigation.clone(),
+    }
+}
+
+/// Replaces `[from, to)` in every voice of every staff with `beats` of rests.
+fn respliced(
+    notes: &[NoteEvent],
+    settings: &ScoreSettings,
+    meter_changes: Vec<MeterChange>,
+    from: f32,
+    to: f32,
+    beats: f32,
+) -> MeasureEdit {
+    let measures = MeasureMap::new(settings.time_signature, &meter_changes);
+    let mut out = notes.to_vec();
+    for part in settings.parts() {
+        for voice in rebar::voices(&out, part) {
+            let stream = rebar::voice_stream(&out, part, voice);
+            // Other voices are not padded out to music they never reached.
+            let length: f32 = stream.iter().map(NoteEvent::beats).sum();
+            if voice > 0 && length <= from {
+                continue;
+            }
+            let rests = rebar::rests(part, voice, from, beats, &measures);
+            let stream = rebar::splice(&stream, part, from, to, &rests, &measures);
+            out = rebar::replace_stream(&out, part, voice, stream);
+        }
+    }
+    // Marks over the replaced music go with it; later ones move with theirs.
+    let tempo_marks = settings
+        .tempo_marks
+        .iter()
+        .filter(|mark| mark.beat < from || mark.beat >= to)
+        .map(|mark| TempoMark {
+            beat: if mark.beat >= to {
+                mark.beat + beats - (to - from)
+            } else {
+                mark.beat
+            },
+            ..mark.clone()
+        })
+        .collect();
+    MeasureEdit {
+        meter_changes,
+        notes: out,
+        tempo_marks,
+        navigation: settings.navigation.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn meter(numerator: u8, denominator: u8) -> Meter {
+        Meter {
+            numerator,
+            denominator,
+        }
+    }
+
+    #[test]
+    fn measures_follow_meter_changes() {
+        let map = MeasureMap::new(
+            TimeSignature::FourFour,
+            &[MeterChange {
+                measure: 2,
+                meter: meter(6, 8),
+            }],
+        );
+
+        assert_eq!(map.start(2), 8.0);
+        assert_eq!(map.start(4), 14.0);
+        assert_eq!(map.locate(9.5), (2, 1.5));
+        assert_eq!(map.locate(10.99999), (3, 0.0));
+        assert_eq!(map.count(11.0), 3);
+        assert_eq!(map.count(11.5), 4);
+        assert_eq!(map.printed_meter(2), Some(meter(6, 8)));
+        assert_eq!(map.printed_meter(3), None);
+    }
+
+    #[test]
+    fn splitting_keeps_later_barlines_in_place() {
+        let map = MeasureMap::new(TimeSignature::FourFour, &[]);
+
+        let changes = map.split(1, 1.5).unwrap();
+        let split = MeasureMap::new(TimeSignature::FourFour, &changes);
+        assert_eq!(split.meter(1), meter(3, 8));
+        assert_eq!(split.meter(2), meter(5, 8));
+        assert_eq!(split.meter(3), meter(4, 4));
+        assert_eq!(split.start(3), map.start(2));
+
+        let joined = MeasureMap::new(TimeSignature::FourFour, &split.join(1, 2).unwrap());
+        assert_eq!(joined, map);
+        assert_eq!(map.split(1, 0.0), None);
+    }
+
+    #[test]
+    fn splitting_the_first_measure_makes_a_pickup() {
+        let map = MeasureMap::new(TimeSignature::ThreeFour, &[]);
+
+        let pickup = MeasureMap::new(TimeSignature::ThreeFour, &map.split(0, 1.0).unwrap());
+        assert!(pickup.has_pickup());
+        assert_eq!(pickup.length(0), 1.0);
+        assert_eq!(pickup.start(2), 4.0);
+        assert_eq!(
+            pickup.printed_meter(0),
+            Some(TimeSignature::ThreeFour.into())
+        );
+        assert_eq!(pickup.printed_meter(1), None);
+
+        let joined = MeasureMap::new(TimeSignature::ThreeFour, &pickup.join(0, 1).unwrap());
+        assert_eq!(joined.meter(0), meter(4, 4));
+        assert!(!joined.has_pickup());
+    }
+
+    #[test]
+    fn inserted_measures_are_rests_and_delete_restores_the_score() {
+        use crate::music::{DurationValue, Instrument, Pitch, PitchClass, Staff};
+
+        let settings = ScoreSettings {
+            staves: vec![
+                Staff::new(Instrument::VIOLIN),
+                Staff::new(Instrument::CELLO),
+            ],
+            ..ScoreSettings::default()
+        };
+        let mut tied = NoteEvent::new(
+            Pitch::new(PitchClass::D, 4),
+            DurationValue::Whole,
+            Instrument::VIOLIN,
+        );
+        tied.tie = true;
+        let notes = vec![tied.clone(), NoteEvent { tie: false, ..tied }];
+
+        let inserted = insert(&notes, &settings, 1, 2);
+        assert!(inserted.meter_changes.is_empty());
+        let violin: Vec<bool> = inserted
+            .notes
+            .iter()
+            .filter(|note| note.instrument == Instrument::VIOLIN)
+            .map(|note| note.rest)
+            .collect();
+        assert_eq!(violin, [false, true, true, false]);
+        assert!(!inserted.notes[0].tie);
+        // The empty cello staff is padded up to the new measures.
+        let cello = inserted
+            .notes
+            .iter()
+            .filter(|note| note.instrument == Instrument::CELLO);
+        assert_eq!(cello.map(NoteEvent::beats).sum::<f32>(), 12.0);
+
+        let deleted = delete(&inserted.notes, &settings, 1, 2);
+        let violin: Vec<&NoteEvent> = deleted
+            .notes
+            .iter()
+            .filter(|note| note.instrument == Instrument::VIOLIN)
+            .collect();
+        assert_eq!(violin.len(), 2);
+        assert!(violin.iter().all(|note| !note.rest));
+    }
+
+    #[test]
+    fn tempo_marks_move_with_their_measures() {
+        let mark = |beat| TempoMark {
+            beat,
+            text: "Lento".to_owned(),
+            bpm: Some(60.0),
+            change: None,
+        };
+        let settings = ScoreSettings {
+            tempo_marks: vec![mark(0.0), mark(4.0)],
+            ..ScoreSettings::default()
+        };
+
+        let inserted = insert(&[], &settings, 1, 2);
+        assert_eq!(inserted.tempo_marks, [mark(0.0), mark(12.0)]);
+
+        let deleted = delete(&[], &settings, 0, 0);
+        assert_eq!(deleted.tempo_marks, [mark(0.0)]);
+    }
+
+    #[test]
+    fn navigation_follows_split_join_and_delete() {
+        use crate::music::Navigation;
+
+        let mark = |measure, navigation| NavigationMark {
+            measure,
+            navigation,
+        };
+        let settings = ScoreSettings {
+            navigation: vec![
+                mark(1, Navigation::RepeatStart),
+                mark(1, Navigation::RepeatEnd { times: 2 }),
+                mark(3, Navigation::Fine),
+            ],
+            ..ScoreSettings::default()
+        };
+
+        let split = split(&[], &settings, 1, 2.0).unwrap();
+        assert_eq!(
+            split.navigation,
+            [
+                mark(1, Navigation::RepeatStart),
+                mark(2, Navigation::RepeatEnd { times: 2 }),
+                mark(4, Navigation::Fine),
+            ]
+        );
+
+        let joined = join(&[], &settings, 0, 1).unwrap();
+        assert_eq!(
+            joined.navigation,
+            [
+                mark(0, Navigation::RepeatEnd { times: 2 }),
+                mark(2, Navigation::Fine),
+            ]
+        );
+
+        let deleted = delete(&[], &settings, 1, 2);
+        assert_eq!(deleted.navigation, [mark(1, Navigation::Fine)]);
+    }
+
+    #[test]
+    fn a_pickup_pushes_the_music_along() {
+        use crate::music::{DurationValue, Instrument, Pitch, PitchClass, Staff};
+
+        let settings = ScoreSettings {
+            time_signature: TimeSignature::ThreeFour,
+            staves: vec![Staff::new(Instrument::FLUTE)],
+            ..ScoreSettings::default()
+        };
+        let notes = vec![NoteEvent::new(
+            Pitch::new(PitchClass::G, 5),
+            DurationValue::Quarter,
+            Instrument::FLUTE,
+        )];
+
+        let edit = add_pickup(&notes, &settings, 1.0).unwrap();
+        let map = MeasureMap::new(TimeSignature::ThreeFour, &edit.meter_changes);
+        assert!(map.has_pickup());
+        assert_eq!(map.length(0), 1.0);
+        assert!(edit.notes[0].rest);
+        assert_eq!(edit.notes[1], notes[0]);
+
+        let settings = ScoreSettings {
+            meter_changes: edit.meter_changes,
+            ..settings
+        };
+        assert_eq!(add_pickup(&edit.notes, &settings, 1.0), None);
+        // A full measure inserted before the pickup takes the opening meter.
+        let inserted = MeasureMap::new(TimeSignature::ThreeFour, &settings.measures().insert(0, 1));
+        assert_eq!(inserted.length(0), 3.0);
+        assert_eq!(inserted.length(1), 1.0);
+    }
+}
@@ -0,0 +1,285 @@
+//! Standard MIDI File export.
+//!
+//! Writes format 1: a conductor track with the meters, the key and the
+//! tempo map, then one track per staff on its own channel. The tempo map
+//! keeps gradual changes and fermata holds, dynamics set the velocities and
+//! articulations the length each note sounds. Repeats and jumps are
+//! written out as they are played.
+
+use crate::audio::Touch;
+use crate::dynamics;
+use crate::form;
+use crate::music::{Dynamic, NoteEvent, Score, ScoreSettings};
+use crate::rebar;
+use crate::tempo::Timeline;
+
+/// Resolution of the file: ticks in a quarter note, which is one beat.
+pub const TICKS_PER_QUARTER: u16 = 480;
+
+/// Channel General MIDI keeps for percussion, counted from 0.
+const PERCUSSION_CHANNEL: u8 = 9;
+
+/// The performance of `score`, taking repeat barlines when `repeats` is set.
+pub fn export_midi(settings: &ScoreSettings, score: &Score, repeats: bool) -> Vec<u8> {
+    let (settings, score) = form::unroll(settings, score, repeats);
+    let (settings, score) = (&settings, &score);
+    let staves: Vec<Vec<Vec<NoteEvent>>> = settings
+        .staves
+        .iter()
+        .map(|staff| {
+            rebar::voices(&score.notes, staff.part)
+                .into_iter()
+                .map(|voice| rebar::voice_stream(&score.notes, staff.part, voice))
+                .collect()
+        })
+        .collect();
+    let streams: Vec<Vec<NoteEvent>> = staves.iter().flatten().cloned().collect();
+    let timeline = Timeline::new(settings, &streams, 1.0);
+
+    let mut tracks = vec![conductor_track(settings, &timeline)];
+    let mut channels = (0..16)
+        .filter(|channel| *channel != PERCUSSION_CHANNEL)
+        .cycle();
+    for (staff, voices) in settings.staves.iter().zip(&staves) {
+        let channel = channels.next().unwrap_or(0);
+        let mut events = vec![
+            (0, meta(0x03, staff.labels(&settings.staves).0.as_bytes())),
+            (
+                0,
+                vec![
+                    0xC0 | channel,
+                    staff.part.instrument.midi_program().saturating_sub(1),
+                ],
+            ),
+        ];
+        for stream in voices {
+            events.extend(note_events(stream, channel));
+        }
+        tracks.push(events);
+    }
+
+    let mut out = Vec::new();
+    out.extend_from_slice(b"MThd");
+    out.extend_from_slice(&6_u32.to_be_bytes());
+    out.extend_from_slice(&1_u16.to_be_bytes());
+    out.extend_from_slice(&(tracks.len() as u16).to_be_bytes());
+    out.extend_from_slice(&TICKS_PER_QUARTER.to_be_bytes());
+    for events in tracks {
+        write_track(&mut out, events);
+    }
+    out
+}
+
+/// A timed event: the tick it happens on and its bytes.
+type Event = (u32, Vec<u8>);
+
+fn ticks(beat: f32) -> u32 {
+    (beat.max(0.0) * TICKS_PER_QUARTER as f32).round() as u32
+}
+
+fn meta(kind: u8, data: &[u8]) -> Vec<u8> {
+    let mut bytes = vec![0xFF, kind];
+    write_variable(&mut bytes, data.len() as u32);
+    bytes.extend_from_slice(data);
+    bytes
+}
+
+fn conductor_track(settings: &ScoreSettings, timeline: &Timeline) -> Vec<Event> {
+    let mut events = vec![
+        (0, meta(0x03, settings.title.as_bytes())),
+        (0, meta(0x59, &[settings.key_signature.fifths() as u8, 0])),
+    ];
+
+    let measures = settings.measures();
+    let opening = (
+        0,
+        settings.time_signature.numerator(),
+        settings.time_signature.denominator(),
+    );
+    let changes = settings.meter_changes.iter().map(|change| {
+        (
+            change.measure,
+            change.meter.numerator,
+            change.meter.denominator,
+        )
+    });
+    for (measure, numerator, denominator) in std::iter::once(opening).chain(changes) {
+        let power = denominator.max(1).ilog2() as u8;
+        events.push((
+            ticks(measures.start(measure)),
+            meta(0x58, &[numerator, power, 24, 8]),
+        ));
+    }
+
+    for (beat, bpm) in timeline.tempo_steps() {
+        let micros = (60_000_000.0 / bpm).round().clamp(1.0, 16_777_215.0) as u32;
+        events.push((ticks(beat), meta(0x51, &micros.to_be_bytes()[1..])));
+    }
+    for mark in &settings.tempo_marks {
+        if !mark.text.is_empty() {
+            events.push((ticks(mark.beat), meta(0x06, mark.text.as_bytes())));
+        }
+    }
+    events
+}
+
+/// Note on and off events of one voice. A tie chain sounds once, as its
+/// first note is marked.
+fn note_events(stream: &[NoteEvent], channel: u8) -> Vec<Event> {
+    let levels = dynamics::levels(stream);
+    let mut position = 0.0;
+    let mut chain: Option<(f32, f32, &NoteEvent)> = None;
+    let mut events = Vec::new();
+
+    for (note, (level, _)) in stream.iter().zip(levels) {
+        let start = position;
+        position += note.beats();
+        if note.tie && !note.rest {
+            chain.get_or_insert((start, level, note));
+            continue;
+        }
+        let (start, level, first) = chain.take().unwrap_or((start, level, note));
+        if note.rest {
+            continue;
+        }
+
+        let touch = Touch::of(first);
+        let key = first.pitch.midi_number().clamp(0, 127) as u8;
+        let velocity = (level / Dynamic::Fff.gain() * 127.0 * (1.0 + touch.accent * 0.25))
+            .round()
+            .clamp(1.0, 127.0) as u8;
+        let end = start + (position - start) * touch.gate;
+        events.push((ticks(start), vec![0x90 | channel, key, velocity]));
+        events.push((ticks(end), vec![0x80 | channel, key, 0]));
+    }
+
+    events
+}
+
+fn write_track(out: &mut Vec<u8>, mut events: Vec<Event>) {
+    // Stable, so the track name and program change stay ahead of the
+    // notes on the first tick.
+    events.sort_by_key(|(tick, bytes)| (*tick, note_off_first(bytes)));
+    let mut data = Vec::new();
+    let mut last = 0;
+    for (tick, bytes) in events {
+        write_variable(&mut data, tick - last);
+        data.extend_from_slice(&bytes);
+        last = tick;
+    }
+    data.extend_from_slice(&[0x00, 0xFF, 0x2F, 0x00]);
+
+    out.extend_from_slice(b"MTrk");
+    out.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    out.extend_from_slice(&data);
+}
+
+/// Sort key putting meta events, then note offs, ahead of the rest on the
+/// same tick, so a repeated key is released before it sounds again.
+fn note_off_first(bytes: &[u8]) -> u8 {
+    match bytes[0] {
+        0xFF => 0,
+        status if status & 0xF0 == 0x80 => 1,
+        _ => 2,
+    }
+}
+
+/// Writes `value` as a MIDI variable-length quantity.
+fn write_variable(out: &mut Vec<u8>, value: u32) {
+    let mut groups = vec![(value & 0x7F) as u8];
+    let mut rest = value >> 7;
+    while rest > 0 {
+        groups.push((rest & 0x7F) as u8 | 0x80);
+        rest >>= 7;
+    }
+    out.extend(groups.into_iter().rev());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::music::{
+        DurationValue, Instrument, Pitch, PitchClass, Staff, TempoChange, TempoMark,
+    };
+
+    fn contains(haystack: &[u8], needle: &[u8]) -> bool {
+        haystack
+            .windows(needle.len())
+            .any(|window| window == needle)
+    }
+
+    #[test]
+    fn variable_length_quantities() {
+        let encode = |value| {
+            let mut out = Vec::new();
+            write_variable(&mut out, value);
+            out
+        };
+        assert_eq!(encode(0), [0x00]);
+        assert_eq!(encode(0x7F), [0x7F]);
+        assert_eq!(encode(0x80), [0x81, 0x00]);
+        assert_eq!(encode(0x0FFF_FFFF), [0xFF, 0xFF, 0xFF, 0x7F]);
+    }
+
+    #[test]
+    fn writes_a_conductor_track_and_a_track_per_staff() {
+        let settings = ScoreSettings {
+            bpm: 120.0,
+            tempo_marks: vec![TempoMark {
+                beat: 2.0,
+                text: "Lento".to_owned(),
+                bpm: Some(60.0),
+                change: None,
+            }],
+            staves: vec![Staff::new(Instrument::FLUTE), Staff::new(Instrument::CELLO)],
+            ..ScoreSettings::default()
+        };
+        let mut note = NoteEvent::new(
+            Pitch::new(PitchClass::C, 4),
+            DurationValue::Half,
+            Instrument::FLUTE,
+        );
+        note.dynamic = Some(Dynamic::Fff);
+        let score = Score { notes: vec![note] };
+
+        let bytes = export_midi(&settings, &score, true);
+
+        assert_eq!(&bytes[..14], b"MThd\0\0\0\x06\0\x01\0\x03\x01\xE0");
+        // 500 000 and 1 000 000 microseconds per quarter.
+        assert!(contains(&bytes, &[0xFF, 0x51, 0x03, 0x07, 0xA1, 0x20]));
+        assert!(contains(&bytes, &[0xFF, 0x51, 0x03, 0x0F, 0x42, 0x40]));
+        assert!(contains(&bytes, b"\xFF\x06\x05Lento"));
+        assert!(contains(&bytes, &[0xC0, 73, 0x00, 0x90, 60, 127]));
+        // Released two beats later.
+        assert!(contains(&bytes, &[0x87, 0x40, 0x80, 60, 0]));
+        assert!(contains(&bytes, &[0xC1, 42]));
+    }
+
+    #[test]
+    fn gradual_changes_are_written_as_tempo_steps() {
+        let settings = ScoreSettings {
+            bpm: 100.0,
+            tempo_marks: vec![TempoMark {
+                beat: 0.0,
+                text: String::new(),
+                bpm: None,
+                change: Some(TempoChange::Ritardando),
+            }],
+            ..ScoreSettings::default()
+        };
+        let score = Score {
+            notes: vec![NoteEvent::new(
+                Pitch::new(PitchClass::C, 4),
+                DurationValue::Whole,
+                Instrument::FLUTE,
+            )],
+        };
+
+        let bytes = export_midi(&settings, &score, true);
+        let tempos = bytes
+            .windows(3)
+            .filter(|window| window == &[0xFF, 0x51, 0x03])
+            .count();
+        assert_eq!(tempos, 17);
+    }
+}
@@ -1,13 +1,29 @@
+use crate::audio::Synth;
+use crate::harmony::ChordSymbol;
+use crate::instruments::{self, InstrumentInfo, Language, Names};
+use crate::measures::MeasureMap;
+use crate::transpose::Interval;
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum DurationValue {
     Whole,
     Half,
     Quarter,
     Eighth,
+    Sixteenth,
 }
 
 impl DurationValue {
-    pub const ALL: [Self; 4] = [Self::Whole, Self::Half, Self::Quarter, Self::Eighth];
+    pub const ALL: [Self; 5] = [
+        Self::Whole,
+        Self::Half,
+        Self::Quarter,
+        Self::Eighth,
+        Self::Sixteenth,
+    ];
+
+    /// Most dots a written value may carry.
+    pub const MAX_DOTS: u8 = 2;
 
     pub fn beats(self) -> f32 {
         match self {
@@ -15,7 +31,19 @@ impl DurationValue {
             Self::Half => 2.0,
             Self::Quarter => 1.0,
             Self::Eighth => 0.5,
+            Self::Sixteenth => 0.25,
+        }
+    }
+
+    pub fn dotted_beats(self, dots: u8) -> f32 {
+        let base = self.beats();
+        let mut total = base;
+        let mut addition = base / 2.0;
+        for _ in 0..dots {
+            total += addition;
+            addition /= 2.0;
         }
+        total
     }
 
     pub fn label(self) -> &'static str {
@@ -24,47 +52,367 @@ impl DurationValue {
             Self::Half => "Mínima",
             Self::Quarter => "Semínima",
             Self::Eighth => "Colcheia",
+            Self::Sixteenth => "Semicolcheia",
+        }
+    }
+
+    /// Finds the written value and dot count that last exactly `beats`.
+    pub fn from_beats(beats: f32) -> Option<(Self, u8)> {
+        Self::ALL.into_iter().find_map(|value| {
+            (0..=Self::MAX_DOTS)
+                .find(|dots| (value.dotted_beats(*dots) - beats).abs() < BEAT_EPSILON)
+                .map(|dots| (value, dots))
+        })
+    }
+
+    /// Breaks `beats` into the fewest written values, largest first.
+    /// Anything shorter than a sixteenth is dropped, as is a length so large
+    /// that taking a whole note off no longer changes it in `f32`.
+    pub fn decompose(beats: f32) -> Vec<(Self, u8)> {
+        let mut remaining = beats;
+        let mut out = Vec::new();
+
+        while remaining > BEAT_EPSILON {
+            let Some(value) = Self::ALL
+                .into_iter()
+                .find(|value| value.beats() <= remaining + BEAT_EPSILON)
+            else {
+                break;
+            };
+            let dots = (0..=Self::MAX_DOTS)
+                .rev()
+                .find(|dots| value.dotted_beats(*dots) <= remaining + BEAT_EPSILON)
+                .unwrap_or(0);
+            let next = remaining - value.dotted_beats(dots);
+            if next >= remaining {
+                break;
+            }
+            out.push((value, dots));
+            remaining = next;
         }
+
+        out
     }
 }
 
+/// An instrument of the catalogue in [`crate::instruments`], by its id.
+/// The constants name the instruments the program itself relies on; the
+/// others come from [`Self::from_id`] and [`Self::all`].
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub enum Instrument {
-    Violin,
-    Viola,
-    Cello,
-    Flute,
-    Clarinet,
-    Trumpet,
-    Horn,
-    Timpani,
-    Piano,
-}
+pub struct Instrument(&'static str);
 
 impl Instrument {
-    pub const ALL: [Self; 9] = [
-        Self::Violin,
-        Self::Viola,
-        Self::Cello,
-        Self::Flute,
-        Self::Clarinet,
-        Self::Trumpet,
-        Self::Horn,
-        Self::Timpani,
-        Self::Piano,
+    pub const VIOLIN: Self = Self("Violin");
+    pub const VIOLA: Self = Self("Viola");
+    pub const CELLO: Self = Self("Cello");
+    pub const FLUTE: Self = Self("Flute");
+    pub const CLARINET: Self = Self("Clarinet");
+    pub const TRUMPET: Self = Self("Trumpet");
+    pub const HORN: Self = Self("Horn");
+    pub const TIMPANI: Self = Self("Timpani");
+    pub const PIANO: Self = Self("Piano");
+
+    /// Every constant above, which the catalogue must define.
+    #[cfg(test)]
+    pub const NAMED: [Self; 9] = [
+        Self::VIOLIN,
+        Self::VIOLA,
+        Self::CELLO,
+        Self::FLUTE,
+        Self::CLARINET,
+        Self::TRUMPET,
+        Self::HORN,
+        Self::TIMPANI,
+        Self::PIANO,
     ];
 
+    /// Every instrument of the catalogue, in score order.
+    pub fn all() -> impl Iterator<Item = Self> {
+        instruments::catalogue()
+            .iter()
+            .map(|info| Self(info.id.as_str()))
+    }
+
+    pub fn from_id(id: &str) -> Option<Self> {
+        instruments::catalogue()
+            .get(id)
+            .map(|info| Self(info.id.as_str()))
+    }
+
+    /// Name saved in `.ntr` files.
+    pub fn id(self) -> &'static str {
+        self.0
+    }
+
+    fn info(self) -> &'static InstrumentInfo {
+        instruments::catalogue()
+            .get(self.0)
+            .unwrap_or_else(|| panic!("instrumento \"{}\" fora do catálogo", self.0))
+    }
+
+    pub fn names(self, language: Language) -> &'static Names {
+        self.info().names(language)
+    }
+
+    pub fn label(self) -> &'static str {
+        &self.names(Language::Portuguese).long
+    }
+
+    pub fn short_label(self) -> &'static str {
+        &self.names(Language::Portuguese).short
+    }
+
+    pub fn clef(self) -> Clef {
+        self.info().clef
+    }
+
+    pub fn family(self) -> Family {
+        self.info().family
+    }
+
+    /// Staves a new player of the instrument gets, braced together, such as
+    /// the two of a piano and the pedal staff of the organ.
+    pub fn staff_count(self) -> u8 {
+        self.info().staves
+    }
+
+    /// General MIDI program number (1-based, as written in MusicXML).
+    pub fn midi_program(self) -> u8 {
+        self.info().program
+    }
+
+    /// Playback preset.
+    pub fn synth(self) -> Synth {
+        self.info().synth
+    }
+
+    /// Lowest and highest sounding pitches a player of `level` can be
+    /// expected to reach.
+    pub fn range(self, level: RangeLevel) -> (Pitch, Pitch) {
+        let (low, high) = self.info().range(level);
+        (Pitch::from_midi(low, false), Pitch::from_midi(high, false))
+    }
+
+    pub fn in_range(self, pitch: Pitch, level: RangeLevel) -> bool {
+        let (low, high) = self.info().range(level);
+        (low..=high).contains(&pitch.midi_number())
+    }
+
+    /// Interval from the written to the sounding pitch: a B♭ clarinet sounds
+    /// a major second below what it reads.
+    pub fn transposition(self) -> Interval {
+        self.info().transposition
+    }
+
+    /// Scores store sounding pitches; this is what the player reads.
+    pub fn written_pitch(self, sounding: Pitch) -> Pitch {
+        self.transposition().down().apply(sounding)
+    }
+
+    pub fn sounding_pitch(self, written: Pitch) -> Pitch {
+        self.transposition().apply(written)
+    }
+}
+
+/// Who the parts are written for: amateur players get narrower ranges.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RangeLevel {
+    Amateur,
+    #[default]
+    Professional,
+}
+
+impl RangeLevel {
+    /// Narrowest range first.
+    pub const ALL: [Self; 2] = [Self::Amateur, Self::Professional];
+
     pub fn label(self) -> &'static str {
         match self {
-            Self::Violin => "Violino",
-            Self::Viola => "Viola",
-            Self::Cello => "Violoncelo",
-            Self::Flute => "Flauta",
-            Self::Clarinet => "Clarinete",
-            Self::Trumpet => "Trompete",
-            Self::Horn => "Trompa",
-            Self::Timpani => "Tímpanos",
-            Self::Piano => "Piano",
+            Self::Amateur => "Amador",
+            Self::Professional => "Profissional",
+        }
+    }
+}
+
+/// Section of the orchestra an instrument sits in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Family {
+    Woodwinds,
+    Brass,
+    Percussion,
+    /// Harp and guitar, braced rather than bracketed like the keyboards.
+    Plucked,
+    Keyboards,
+    Voices,
+    Strings,
+}
+
+impl Family {
+    pub const ALL: [Self; 7] = [
+        Self::Woodwinds,
+        Self::Brass,
+        Self::Percussion,
+        Self::Plucked,
+        Self::Keyboards,
+        Self::Voices,
+        Self::Strings,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Woodwinds => "Madeiras",
+            Self::Brass => "Metais",
+            Self::Percussion => "Percussão",
+            Self::Plucked => "Cordas dedilhadas",
+            Self::Keyboards => "Teclados",
+            Self::Voices => "Vozes",
+            Self::Strings => "Cordas",
+        }
+    }
+
+    /// Whether the staves of the family are joined by a bracket.
+    pub fn bracketed(self) -> bool {
+        matches!(
+            self,
+            Self::Woodwinds | Self::Brass | Self::Voices | Self::Strings
+        )
+    }
+}
+
+/// Whose music an event is: the instrument, which of its players and, for
+/// instruments read from a grand staff, which of its staves. Violin I and
+/// Violin II keep separate staves, and so do the two hands of a piano.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Part {
+    pub instrument: Instrument,
+    /// 0 for the first player of the instrument, 1 for the second, and so on.
+    pub player: u8,
+    /// 0 for the top staff of the player, below [`Instrument::staff_count`].
+    pub sub_staff: u8,
+}
+
+impl Part {
+    /// The same player on another staff of its grand staff.
+    pub fn on_staff(self, sub_staff: u8) -> Self {
+        Self { sub_staff, ..self }
+    }
+
+    /// Lower staves of a grand staff read the bass clef.
+    pub fn clef(self) -> Clef {
+        match self.sub_staff {
+            0 => self.instrument.clef(),
+            _ => Clef::Bass,
+        }
+    }
+}
+
+impl From<Instrument> for Part {
+    fn from(instrument: Instrument) -> Self {
+        Self {
+            instrument,
+            player: 0,
+            sub_staff: 0,
+        }
+    }
+}
+
+/// One staff of the score, top to bottom in [`ScoreSettings::staves`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Staff {
+    pub part: Part,
+    /// Overrides the instrument name when not empty.
+    pub name: String,
+    /// Overrides the abbreviated name printed on later systems when not empty.
+    pub short_name: String,
+}
+
+impl Staff {
+    pub fn new(part: impl Into<Part>) -> Self {
+        Self {
+            part: part.into(),
+            name: String::new(),
+            short_name: String::new(),
+        }
+    }
+
+    /// Full and short names to print. Unnamed staves use the instrument's
+    /// names, numbered ("Violino II") when the score has more than one of it.
+    /// The staves of a grand staff share the name of the top one.
+    pub fn labels(&self, staves: &[Staff]) -> (String, String) {
+        let instrument = self.part.instrument;
+        if self.part.sub_staff > 0 {
+            if let Some(top) = staves
+                .iter()
+                .find(|staff| staff.part == self.part.on_staff(0))
+            {
+                return top.labels(staves);
+            }
+        }
+        let shared = staves
+            .iter()
+            .filter(|staff| staff.part.instrument == instrument && staff.part.sub_staff == 0)
+            .count()
+            > 1;
+        let number = if shared || self.part.player > 0 {
+            format!(" {}", roman_numeral(self.part.player + 1))
+        } else {
+            String::new()
+        };
+
+        let name = if self.name.is_empty() {
+            format!("{}{number}", instrument.label())
+        } else {
+            self.name.clone()
+        };
+        let short_name = if self.short_name.is_empty() {
+            format!("{}{number}", instrument.short_label())
+        } else {
+            self.short_name.clone()
+        };
+        (name, short_name)
+    }
+}
+
+fn roman_numeral(mut value: u8) -> String {
+    const NUMERALS: [(u8, &str); 9] = [
+        (100, "C"),
+        (90, "XC"),
+        (50, "L"),
+        (40, "XL"),
+        (10, "X"),
+        (9, "IX"),
+        (5, "V"),
+        (4, "IV"),
+        (1, "I"),
+    ];
+    let mut out = String::new();
+    for (amount, numeral) in NUMERALS {
+        while value >= amount {
+            out.push_str(numeral);
+            value -= amount;
+        }
+    }
+    out
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Clef {
+    Treble,
+    Alto,
+    Bass,
+    /// Unpitched percussion, read on the lines and spaces of the treble clef.
+    Percussion,
+}
+
+impl Clef {
+    pub const ALL: [Self; 4] = [Self::Treble, Self::Alto, Self::Bass, Self::Percussion];
+
+    /// Pitch written on the bottom staff line.
+    pub fn bottom_line(self) -> Pitch {
+        match self {
+            Self::Treble | Self::Percussion => Pitch::new(PitchClass::E, 4),
+            Self::Alto => Pitch::new(PitchClass::F, 3),
+            Self::Bass => Pitch::new(PitchClass::G, 2),
         }
     }
 }
@@ -123,23 +471,35 @@ pub enum KeySignature {
     D,
     A,
     E,
+    B,
+    FSharp,
+    CSharp,
     F,
     Bb,
     Eb,
     Ab,
+    Db,
+    Gb,
+    Cb,
 }
 
 impl KeySignature {
-    pub const ALL: [Self; 9] = [
+    pub const ALL: [Self; 15] = [
         Self::C,
         Self::G,
         Self::D,
         Self::A,
         Self::E,
+        Self::B,
+        Self::FSharp,
+        Self::CSharp,
         Self::F,
         Self::Bb,
         Self::Eb,
         Self::Ab,
+        Self::Db,
+        Self::Gb,
+        Self::Cb,
     ];
 
     pub fn label(self) -> &'static str {
@@ -149,10 +509,111 @@ impl KeySignature {
             Self::D => "Ré maior / Si menor",
             Self::A => "Lá maior / Fá# menor",
             Self::E => "Mi maior / Dó# menor",
+            Self::B => "Si maior / Sol# menor",
+            Self::FSharp => "Fá# maior / Ré# menor",
+            Self::CSharp => "Dó# maior / Lá# menor",
             Self::F => "Fá maior / Ré menor",
             Self::Bb => "Si♭ maior / Sol menor",
             Self::Eb => "Mi♭ maior / Dó menor",
             Self::Ab => "Lá♭ maior / Fá menor",
+            Self::Db => "Ré♭ maior / Si♭ menor",
+            Self::Gb => "Sol♭ maior / Mi♭ menor",
+            Self::Cb => "Dó♭ maior / Lá♭ menor",
+        }
+    }
+
+    /// Position on the circle of fifths: sharps are positive, flats negative.
+    pub fn fifths(self) -> i8 {
+        match self {
+            Self::C => 0,
+            Self::G => 1,
+            Self::D => 2,
+            Self::A => 3,
+            Self::E => 4,
+            Self::B => 5,
+            Self::FSharp => 6,
+            Self::CSharp => 7,
+            Self::F => -1,
+            Self::Bb => -2,
+            Self::Eb => -3,
+            Self::Ab => -4,
+            Self::Db => -5,
+            Self::Gb => -6,
+            Self::Cb => -7,
+        }
+    }
+
+    /// Tonic of the major key, in the fourth octave.
+    pub fn tonic(self) -> Pitch {
+        use PitchClass::{A, B, C, D, E, F, G};
+        let (class, alter) = match self {
+            Self::C => (C, 0),
+            Self::G => (G, 0),
+            Self::D => (D, 0),
+            Self::A => (A, 0),
+            Self::E => (E, 0),
+            Self::B => (B, 0),
+            Self::FSharp => (F, 1),
+            Self::CSharp => (C, 1),
+            Self::F => (F, 0),
+            Self::Bb => (B, -1),
+            Self::Eb => (E, -1),
+            Self::Ab => (A, -1),
+            Self::Db => (D, -1),
+            Self::Gb => (G, -1),
+            Self::Cb => (C, -1),
+        };
+        Pitch {
+            class,
+            alter,
+            octave: 4,
+        }
+    }
+
+    /// The key a transposition by `interval` leads to, or its enharmonic
+    /// equivalent when that key would need double sharps or flats.
+    pub fn transposed(self, interval: Interval) -> Self {
+        let tonic = interval.apply(self.tonic());
+        let same_class =
+            |key: &Self| (key.tonic().midi_number() - tonic.midi_number()).rem_euclid(12) == 0;
+        Self::ALL
+            .into_iter()
+            .find(|key| {
+                let other = key.tonic();
+                other.class == tonic.class && other.alter == tonic.alter
+            })
+            .or_else(|| Self::ALL.into_iter().find(same_class))
+            .unwrap_or(self)
+    }
+
+    pub fn from_fifths(fifths: i8) -> Option<Self> {
+        Self::ALL.into_iter().find(|key| key.fifths() == fifths)
+    }
+
+    /// Sharp (1), flat (-1) or nothing (0) the signature puts on `class`.
+    pub fn alteration(self, class: PitchClass) -> i8 {
+        // Sharps are added in this order; flats in the reverse one.
+        const SHARP_ORDER: [PitchClass; 7] = [
+            PitchClass::F,
+            PitchClass::C,
+            PitchClass::G,
+            PitchClass::D,
+            PitchClass::A,
+            PitchClass::E,
+            PitchClass::B,
+        ];
+        let position = SHARP_ORDER
+            .iter()
+            .position(|sharp| *sharp == class)
+            .unwrap_or(0) as i8;
+        let fifths = self.fifths();
+
+        if position < fifths {
+            1
+        } else if 6 - position < -fifths {
+            -1
+        } else {
+            0
         }
     }
 }
@@ -182,16 +643,217 @@ impl TimeSignature {
         }
     }
 
+    pub fn numerator(self) -> u8 {
+        match self {
+            Self::FourFour => 4,
+            Self::ThreeFour => 3,
+            Self::TwoFour => 2,
+            Self::SixEight => 6,
+        }
+    }
+
+    pub fn denominator(self) -> u8 {
+        match self {
+            Self::FourFour | Self::ThreeFour | Self::TwoFour => 4,
+            Self::SixEight => 8,
+        }
+    }
+
+    pub fn from_fraction(numerator: u8, denominator: u8) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|time| time.numerator() == numerator && time.denominator() == denominator)
+    }
+}
+
+/// Any time signature, as bars that were split or joined end up with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Meter {
+    pub numerator: u8,
+    pub denominator: u8,
+}
+
+impl Meter {
     pub fn beats_per_measure(self) -> f32 {
+        self.numerator as f32 * 4.0 / self.denominator as f32
+    }
+
+    pub fn label(self) -> String {
+        format!("{}/{}", self.numerator, self.denominator)
+    }
+
+    /// Beats one beam groups: a dotted quarter in compound meters such as
+    /// 6/8, otherwise a quarter.
+    pub fn beam_beats(self) -> f32 {
+        if self.denominator == 8 && self.numerator.is_multiple_of(3) {
+            1.5
+        } else {
+            1.0
+        }
+    }
+
+    /// The meter lasting `beats`, counted in `denominator` notes when they
+    /// fit and otherwise in the shortest of quarters, eighths or sixteenths.
+    pub fn from_beats(beats: f32, denominator: u8) -> Option<Self> {
+        [denominator, 4, 8, 16].into_iter().find_map(|denominator| {
+            let count = beats * denominator as f32 / 4.0;
+            let numerator = count.round();
+            ((count - numerator).abs() < 1e-3 && (1.0..=99.0).contains(&numerator)).then_some(
+                Self {
+                    numerator: numerator as u8,
+                    denominator,
+                },
+            )
+        })
+    }
+}
+
+impl From<TimeSignature> for Meter {
+    fn from(time: TimeSignature) -> Self {
+        Self {
+            numerator: time.numerator(),
+            denominator: time.denominator(),
+        }
+    }
+}
+
+/// A new meter from `measure` (counted from 0) until the next change.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MeterChange {
+    pub measure: usize,
+    pub meter: Meter,
+}
+
+/// Where the music goes on to after a D.C. or D.S. jump.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Until {
+    /// Plays on to the end, or to a Fine.
+    End,
+    Fine,
+    /// Plays up to the To Coda sign and goes on from the Coda.
+    Coda,
+}
+
+impl Until {
+    pub const ALL: [Self; 3] = [Self::End, Self::Fine, Self::Coda];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::End => "Até o fim",
+            Self::Fine => "al Fine",
+            Self::Coda => "al Coda",
+        }
+    }
+}
+
+/// Repeat barlines, endings and the signs that send the music elsewhere.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Navigation {
+    /// Repeat barline at the start of the measure.
+    RepeatStart,
+    /// Repeat barline at the end of the measure: the passage is played
+    /// `times` times in all.
+    RepeatEnd {
+        times: u8,
+    },
+    /// Volta bracket over the measure, taken on the passes set in `passes`
+    /// (bit 0 is the first pass).
+    Ending {
+        passes: u8,
+    },
+    Segno,
+    Coda,
+    Fine,
+    ToCoda,
+    DaCapo(Until),
+    DalSegno(Until),
+}
+
+impl Navigation {
+    /// One mark of each kind, as first added.
+    pub const ALL: [Self; 9] = [
+        Self::RepeatStart,
+        Self::RepeatEnd { times: 2 },
+        Self::Ending { passes: 0b1 },
+        Self::Segno,
+        Self::Coda,
+        Self::Fine,
+        Self::ToCoda,
+        Self::DaCapo(Until::End),
+        Self::DalSegno(Until::End),
+    ];
+
+    /// Whether `other` is the same kind of mark, whatever its settings.
+    pub fn same_kind(self, other: Self) -> bool {
+        std::mem::discriminant(&self) == std::mem::discriminant(&other)
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::RepeatStart => "Início de repetição",
+            Self::RepeatEnd { .. } => "Fim de repetição",
+            Self::Ending { .. } => "Casa",
+            Self::Segno => "Segno",
+            Self::Coda => "Coda",
+            Self::Fine => "Fine",
+            Self::ToCoda => "To Coda",
+            Self::DaCapo(_) => "D.C.",
+            Self::DalSegno(_) => "D.S.",
+        }
+    }
+
+    /// Whether the mark belongs to the end of its measure rather than the start.
+    pub fn at_end(self) -> bool {
+        matches!(
+            self,
+            Self::RepeatEnd { .. }
+                | Self::Fine
+                | Self::ToCoda
+                | Self::DaCapo(_)
+                | Self::DalSegno(_)
+        )
+    }
+
+    /// Words written in the score for the marks that are words.
+    pub fn words(self) -> Option<&'static str> {
+        Some(match self {
+            Self::Fine => "Fine",
+            Self::ToCoda => "To Coda",
+            Self::DaCapo(Until::End) => "D.C.",
+            Self::DaCapo(Until::Fine) => "D.C. al Fine",
+            Self::DaCapo(Until::Coda) => "D.C. al Coda",
+            Self::DalSegno(Until::End) => "D.S.",
+            Self::DalSegno(Until::Fine) => "D.S. al Fine",
+            Self::DalSegno(Until::Coda) => "D.S. al Coda",
+            _ => return None,
+        })
+    }
+
+    pub fn label(self) -> String {
         match self {
-            Self::FourFour => 4.0,
-            Self::ThreeFour => 3.0,
-            Self::TwoFour => 2.0,
-            Self::SixEight => 3.0,
+            Self::RepeatEnd { times } => format!("{} ({times}x)", self.name()),
+            Self::Ending { passes } => format!("{} {}", self.name(), ending_numbers(passes)),
+            other => other.words().unwrap_or(other.name()).to_owned(),
         }
     }
 }
 
+/// The passes of an ending as written over its bracket, such as "1., 2.".
+pub fn ending_numbers(passes: u8) -> String {
+    (0..8)
+        .filter(|pass| passes & (1 << pass) != 0)
+        .map(|pass| format!("{}.", pass + 1))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// A repeat, ending or navigation sign on `measure` (counted from 0).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NavigationMark {
+    pub measure: usize,
+    pub navigation: Navigation,
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum PaperSize {
     A4,
@@ -211,13 +873,84 @@ impl PaperSize {
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+/// A gradual change of tempo running from its mark to the next one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TempoChange {
+    Ritardando,
+    Accelerando,
+}
+
+impl TempoChange {
+    pub const ALL: [Self; 2] = [Self::Ritardando, Self::Accelerando];
+
+    /// Abbreviation written in the score.
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Ritardando => "rit.",
+            Self::Accelerando => "accel.",
+        }
+    }
+}
+
+/// Tempo text, a metronome mark or a gradual change written above the
+/// score at `beat`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TempoMark {
+    /// Position in beats from the start of the score.
+    pub beat: f32,
+    /// Words such as "Allegro"; may be empty.
+    pub text: String,
+    /// Quarter notes per minute from the mark on.
+    pub bpm: Option<f32>,
+    pub change: Option<TempoChange>,
+}
+
+impl TempoMark {
+    /// What the score shows: the words, or the change's abbreviation without
+    /// them, followed by the metronome mark.
+    pub fn label(&self) -> String {
+        let words = match (self.text.trim(), self.change) {
+            ("", Some(change)) => change.label(),
+            (text, _) => text,
+        };
+        match self.bpm {
+            Some(bpm) if words.is_empty() => format!("♩ = {}", bpm.round()),
+            Some(bpm) => format!("{words} ♩ = {}", bpm.round()),
+            None => words.to_owned(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct ScoreSettings {
     pub title: String,
     pub composer: String,
     pub key_signature: KeySignature,
+    /// Meter of the opening measures.
     pub time_signature: TimeSignature,
+    /// Later meters, sorted by measure; see [`MeasureMap`].
+    pub meter_changes: Vec<MeterChange>,
     pub paper_size: PaperSize,
+    /// Staff order, top to bottom.
+    pub staves: Vec<Staff>,
+    /// Quarter notes per minute at the start, until the first tempo mark.
+    pub bpm: f32,
+    /// Sorted by beat; see [`crate::tempo::TempoMap`].
+    pub tempo_marks: Vec<TempoMark>,
+    /// Sorted by measure; see [`crate::form`].
+    pub navigation: Vec<NavigationMark>,
+    /// Instrument ranges notes are checked against; see [`crate::ranges`].
+    pub range_level: RangeLevel,
+}
+
+impl ScoreSettings {
+    pub fn parts(&self) -> Vec<Part> {
+        self.staves.iter().map(|staff| staff.part).collect()
+    }
+
+    pub fn measures(&self) -> MeasureMap {
+        MeasureMap::new(self.time_signature, &self.meter_changes)
+    }
 }
 
 impl Default for ScoreSettings {
@@ -227,7 +960,29 @@ impl Default for ScoreSettings {
             composer: "Compositor".to_owned(),
             key_signature: KeySignature::C,
             time_signature: TimeSignature::FourFour,
+            meter_changes: Vec::new(),
             paper_size: PaperSize::A4,
+            staves: [
+                Instrument::FLUTE,
+                Instrument::CLARINET,
+                Instrument::HORN,
+                Instrument::TRUMPET,
+                Instrument::VIOLIN,
+                Instrument::VIOLA,
+                Instrument::CELLO,
+                Instrument::TIMPANI,
+                Instrument::PIANO,
+            ]
+            .into_iter()
+            .flat_map(|instrument| {
+                (0..instrument.staff_count())
+                    .map(move |sub_staff| Staff::new(Part::from(instrument).on_staff(sub_staff)))
+            })
+            .collect(),
+            bpm: 110.0,
+            tempo_marks: Vec::new(),
+            navigation: Vec::new(),
+            range_level: RangeLevel::default(),
         }
     }
 }
@@ -235,36 +990,368 @@ impl Default for ScoreSettings {
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct Pitch {
     pub class: PitchClass,
+    /// Chromatic alteration in semitones: 1 is a sharp, -1 a flat.
+    pub alter: i8,
     pub octave: i8,
 }
 
 impl Pitch {
+    pub fn new(class: PitchClass, octave: i8) -> Self {
+        Self {
+            class,
+            alter: 0,
+            octave,
+        }
+    }
+
+    /// Staff position counted in lines and spaces from C0, ignoring alteration.
+    pub fn diatonic_number(self) -> i32 {
+        let step = PitchClass::ALL
+            .iter()
+            .position(|class| *class == self.class)
+            .unwrap_or(0) as i32;
+        self.octave as i32 * 7 + step
+    }
+
+    /// Natural pitch at a staff position counted from C0; inverse of [`Self::diatonic_number`].
+    pub fn from_diatonic_number(number: i32) -> Self {
+        Self::new(
+            PitchClass::ALL[number.rem_euclid(7) as usize],
+            number.div_euclid(7) as i8,
+        )
+    }
+
+    /// Spells a MIDI note number, using flats instead of sharps when asked.
+    pub fn from_midi(midi: i32, prefer_flats: bool) -> Self {
+        use PitchClass::{A, B, C, D, E, F, G};
+        const SHARPS: [(PitchClass, i8); 12] = [
+            (C, 0),
+            (C, 1),
+            (D, 0),
+            (D, 1),
+            (E, 0),
+            (F, 0),
+            (F, 1),
+            (G, 0),
+            (G, 1),
+            (A, 0),
+            (A, 1),
+            (B, 0),
+        ];
+        const FLATS: [(PitchClass, i8); 12] = [
+            (C, 0),
+            (D, -1),
+            (D, 0),
+            (E, -1),
+            (E, 0),
+            (F, 0),
+            (G, -1),
+            (G, 0),
+            (A, -1),
+            (A, 0),
+            (B, -1),
+            (B, 0),
+        ];
+
+        let table = if prefer_flats { &FLATS } else { &SHARPS };
+        let (class, alter) = table[midi.rem_euclid(12) as usize];
+        Self {
+            class,
+            alter,
+            octave: (midi.div_euclid(12) - 1) as i8,
+        }
+    }
+
+    pub fn midi_number(self) -> i32 {
+        (self.octave as i32 + 1) * 12 + self.class.semitone_offset() + self.alter as i32
+    }
+
     pub fn frequency_hz(self) -> f32 {
-        let midi = (self.octave as i32 + 1) * 12 + self.class.semitone_offset();
-        let semitones_from_a4 = midi - 69;
+        let semitones_from_a4 = self.midi_number() - 69;
         440.0 * 2.0_f32.powf(semitones_from_a4 as f32 / 12.0)
     }
 }
 
-#[derive(Debug, Clone)]
+/// A dynamic marking, written under the event it starts on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Dynamic {
+    Ppp,
+    Pp,
+    P,
+    Mp,
+    Mf,
+    F,
+    Ff,
+    Fff,
+    Sfz,
+}
+
+impl Dynamic {
+    pub const ALL: [Self; 9] = [
+        Self::Ppp,
+        Self::Pp,
+        Self::P,
+        Self::Mp,
+        Self::Mf,
+        Self::F,
+        Self::Ff,
+        Self::Fff,
+        Self::Sfz,
+    ];
+
+    /// The marking as engraved, which is also its MusicXML element name.
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Ppp => "ppp",
+            Self::Pp => "pp",
+            Self::P => "p",
+            Self::Mp => "mp",
+            Self::Mf => "mf",
+            Self::F => "f",
+            Self::Ff => "ff",
+            Self::Fff => "fff",
+            Self::Sfz => "sfz",
+        }
+    }
+
+    /// Playback amplitude. Music without markings plays at mezzo-forte.
+    pub fn gain(self) -> f32 {
+        match self {
+            Self::Ppp => 0.08,
+            Self::Pp => 0.12,
+            Self::P => 0.18,
+            Self::Mp => 0.26,
+            Self::Mf => 0.35,
+            Self::F => 0.46,
+            Self::Ff => 0.58,
+            Self::Fff => 0.72,
+            Self::Sfz => 0.65,
+        }
+    }
+
+    /// A sforzando accents its own event only; the level before it goes on.
+    pub fn is_accent(self) -> bool {
+        self == Self::Sfz
+    }
+}
+
+/// A crescendo or diminuendo wedge. Neighbouring events of one voice carrying
+/// the same hairpin are spanned by a single wedge.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Hairpin {
+    Crescendo,
+    Diminuendo,
+}
+
+impl Hairpin {
+    pub const ALL: [Self; 2] = [Self::Crescendo, Self::Diminuendo];
+}
+
+/// A mark on a single note telling how it is played.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Articulation {
+    Staccato,
+    Staccatissimo,
+    Tenuto,
+    Accent,
+    Marcato,
+    Fermata,
+}
+
+impl Articulation {
+    pub const ALL: [Self; 6] = [
+        Self::Staccato,
+        Self::Staccatissimo,
+        Self::Tenuto,
+        Self::Accent,
+        Self::Marcato,
+        Self::Fermata,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Staccato => "Staccato",
+            Self::Staccatissimo => "Staccatissimo",
+            Self::Tenuto => "Tenuto",
+            Self::Accent => "Acento",
+            Self::Marcato => "Marcato",
+            Self::Fermata => "Fermata",
+        }
+    }
+}
+
+/// Where a syllable falls in its word, as MusicXML names it. A hyphen runs
+/// on from `Begin` and `Middle` syllables to the next one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Syllabic {
+    Single,
+    Begin,
+    Middle,
+    End,
+}
+
+impl Syllabic {
+    pub const ALL: [Self; 4] = [Self::Single, Self::Begin, Self::Middle, Self::End];
+
+    /// Whether a hyphen joins the syllable to the next.
+    pub fn hyphenated(self) -> bool {
+        matches!(self, Self::Begin | Self::Middle)
+    }
+}
+
+/// One verse's syllable under a note.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Lyric {
+    /// Counted from 0.
+    pub verse: u8,
+    pub text: String,
+    pub syllabic: Syllabic,
+    /// Melisma: an extender line is held under the following notes until
+    /// the next syllable of the verse.
+    pub extend: bool,
+}
+
+impl Lyric {
+    /// Verses a note can carry.
+    pub const VERSES: u8 = 8;
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct NoteEvent {
     pub pitch: Pitch,
     pub duration: DurationValue,
+    pub dots: u8,
     pub instrument: Instrument,
+    /// Which player of `instrument` the event belongs to; see [`Part`].
+    pub player: u8,
+    /// Staff of the player's grand staff whose rhythm the event is part of.
+    pub sub_staff: u8,
+    /// Staves up (negative) or down from `sub_staff` the event is drawn on,
+    /// for music that crosses between the hands.
+    pub cross_staff: i8,
+    /// Independent rhythmic line of the staff, from 0 to [`NoteEvent::VOICES`] - 1.
+    /// Each voice runs its own beat count from the start of the score.
+    pub voice: u8,
+    /// Rests keep a pitch only so the event layout stays uniform; it is never sounded.
+    pub rest: bool,
+    /// Tied into the next event of the same part and voice.
+    pub tie: bool,
+    /// Marking written under the event.
+    pub dynamic: Option<Dynamic>,
+    /// Wedge the event sits under; see [`Hairpin`].
+    pub hairpin: Option<Hairpin>,
+    /// Marks on the note, in the order they were added. Rests only take a fermata.
+    pub articulations: Vec<Articulation>,
+    /// One syllable per verse, sorted by verse. Rests take none.
+    pub lyrics: Vec<Lyric>,
+    /// Chord symbol written above the event, sounding until the next one.
+    pub chord: Option<ChordSymbol>,
+}
+
+/// Tolerance for comparing beat positions and lengths summed from
+/// [`NoteEvent::beats`].
+pub const BEAT_EPSILON: f32 = 1e-4;
+
+impl NoteEvent {
+    /// Voices a staff can hold.
+    pub const VOICES: u8 = 4;
+
+    pub fn new(pitch: Pitch, duration: DurationValue, part: impl Into<Part>) -> Self {
+        let part = part.into();
+        Self {
+            pitch,
+            duration,
+            dots: 0,
+            instrument: part.instrument,
+            player: part.player,
+            sub_staff: part.sub_staff,
+            cross_staff: 0,
+            voice: 0,
+            rest: false,
+            tie: false,
+            dynamic: None,
+            hairpin: None,
+            articulations: Vec::new(),
+            lyrics: Vec::new(),
+            chord: None,
+        }
+    }
+
+    pub fn rest(duration: DurationValue, part: impl Into<Part>) -> Self {
+        Self {
+            rest: true,
+            ..Self::new(Pitch::new(PitchClass::B, 4), duration, part)
+        }
+    }
+
+    pub fn part(&self) -> Part {
+        Part {
+            instrument: self.instrument,
+            player: self.player,
+            sub_staff: self.sub_staff,
+        }
+    }
+
+    /// Moves the event to another staff, back on its own lines.
+    pub fn set_part(&mut self, part: Part) {
+        self.instrument = part.instrument;
+        self.player = part.player;
+        self.sub_staff = part.sub_staff;
+        self.cross_staff = 0;
+    }
+
+    /// The part whose staff the event is drawn on.
+    pub fn shown_part(&self) -> Part {
+        let sub_staff = i16::from(self.sub_staff) + i16::from(self.cross_staff);
+        self.part()
+            .on_staff(u8::try_from(sub_staff).unwrap_or(self.sub_staff))
+    }
+
+    pub fn beats(&self) -> f32 {
+        self.duration.dotted_beats(self.dots)
+    }
+
+    pub fn lyric(&self, verse: u8) -> Option<&Lyric> {
+        self.lyrics.iter().find(|lyric| lyric.verse == verse)
+    }
+
+    /// Puts `lyric` in place of the event's syllable of the same verse; an
+    /// empty text just takes that syllable off.
+    pub fn set_lyric(&mut self, lyric: Lyric) {
+        self.lyrics.retain(|old| old.verse != lyric.verse);
+        if !lyric.text.is_empty() {
+            let at = self.lyrics.partition_point(|old| old.verse < lyric.verse);
+            self.lyrics.insert(at, lyric);
+        }
+    }
 }
 
-#[derive(Debug, Clone, Default)]
+#[derive(Debug, Clone, Default, PartialEq)]
 pub struct Score {
     pub notes: Vec<NoteEvent>,
 }
 
 impl Score {
+    /// How long the score lasts. Staves and their voices play together, so
+    /// this is the length of the longest voice.
     pub fn total_beats(&self) -> f32 {
-        self.notes.iter().map(|n| n.duration.beats()).sum()
+        let mut totals: Vec<((Part, u8), f32)> = Vec::new();
+        for note in &self.notes {
+            let key = (note.part(), note.voice);
+            match totals.iter_mut().find(|(voice, _)| *voice == key) {
+                Some((_, total)) => *total += note.beats(),
+                None => totals.push((key, note.beats())),
+            }
+        }
+        totals
+            .into_iter()
+            .map(|(_, total)| total)
+            .fold(0.0, f32::max)
     }
 
-    pub fn total_measures(&self, time_signature: TimeSignature) -> f32 {
-        self.total_beats() / time_signature.beats_per_measure()
+    pub fn total_measures(&self, measures: &MeasureMap) -> f32 {
+        let (measure, beat) = measures.locate(self.total_beats());
+        measure as f32 + beat / measures.length(measure)
     }
 }
 
@@ -275,42 +1362,145 @@ mod tests {
 
     #[test]
     fn a4_frequency_is_440() {
-        let pitch = Pitch {
-            class: PitchClass::A,
-            octave: 4,
-        };
+        let pitch = Pitch::new(PitchClass::A, 4);
 
         assert_relative_eq!(pitch.frequency_hz(), 440.0, epsilon = 0.001);
     }
 
+    #[test]
+    fn alteration_shifts_frequency_by_semitones() {
+        let b_flat = Pitch {
+            alter: -1,
+            ..Pitch::new(PitchClass::B, 4)
+        };
+        let a_sharp = Pitch {
+            alter: 1,
+            ..Pitch::new(PitchClass::A, 4)
+        };
+
+        assert_eq!(b_flat.midi_number(), a_sharp.midi_number());
+        assert_relative_eq!(b_flat.frequency_hz(), 466.164, epsilon = 0.01);
+    }
+
+    #[test]
+    fn key_signatures_alter_the_right_steps() {
+        assert_eq!(KeySignature::D.alteration(PitchClass::F), 1);
+        assert_eq!(KeySignature::D.alteration(PitchClass::C), 1);
+        assert_eq!(KeySignature::D.alteration(PitchClass::G), 0);
+        assert_eq!(KeySignature::Eb.alteration(PitchClass::A), -1);
+        assert_eq!(KeySignature::Eb.alteration(PitchClass::D), 0);
+        assert_eq!(KeySignature::Cb.alteration(PitchClass::F), -1);
+        assert_eq!(KeySignature::C.alteration(PitchClass::B), 0);
+    }
+
+    #[test]
+    fn midi_numbers_are_spelled_back() {
+        let c_sharp = Pitch::from_midi(61, false);
+        assert_eq!(
+            c_sharp,
+            Pitch {
+                alter: 1,
+                ..Pitch::new(PitchClass::C, 4)
+            }
+        );
+        assert_eq!(Pitch::from_midi(61, true).class, PitchClass::D);
+        assert_eq!(Pitch::from_midi(59, true), Pitch::new(PitchClass::B, 3));
+    }
+
+    #[test]
+    fn transposing_instruments_read_above_or_below_concert_pitch() {
+        let concert_c = Pitch::new(PitchClass::C, 4);
+        let written = |instrument: Instrument| instrument.written_pitch(concert_c);
+
+        assert_eq!(written(Instrument::CLARINET), Pitch::new(PitchClass::D, 4));
+        assert_eq!(written(Instrument::HORN), Pitch::new(PitchClass::G, 4));
+        assert_eq!(
+            written(Instrument::from_id("Contrabass").unwrap()),
+            Pitch::new(PitchClass::C, 5)
+        );
+        assert_eq!(
+            written(Instrument::from_id("Piccolo").unwrap()),
+            Pitch::new(PitchClass::C, 3)
+        );
+        assert_eq!(written(Instrument::VIOLIN), concert_c);
+        assert_eq!(
+            Instrument::TRUMPET.sounding_pitch(Pitch::new(PitchClass::C, 5)),
+            Pitch {
+                alter: -1,
+                ..Pitch::new(PitchClass::B, 4)
+            }
+        );
+
+        let to_clarinet = Instrument::CLARINET.transposition().down();
+        assert_eq!(KeySignature::Eb.transposed(to_clarinet), KeySignature::F);
+        // D♯ major would need a double sharp; E♭ is written instead.
+        assert_eq!(
+            KeySignature::CSharp.transposed(to_clarinet),
+            KeySignature::Eb
+        );
+    }
+
     #[test]
     fn score_beats_sum_correctly() {
         let score = Score {
             notes: vec![
+                NoteEvent::new(
+                    Pitch::new(PitchClass::C, 4),
+                    DurationValue::Half,
+                    Instrument::PIANO,
+                ),
+                NoteEvent::new(
+                    Pitch::new(PitchClass::G, 4),
+                    DurationValue::Quarter,
+                    Instrument::PIANO,
+                ),
+                // Other staves and voices sound at the same time.
+                NoteEvent::new(
+                    Pitch::new(PitchClass::E, 5),
+                    DurationValue::Half,
+                    Instrument::FLUTE,
+                ),
                 NoteEvent {
-                    pitch: Pitch {
-                        class: PitchClass::C,
-                        octave: 4,
-                    },
-                    duration: DurationValue::Half,
-                    instrument: Instrument::Piano,
-                },
-                NoteEvent {
-                    pitch: Pitch {
-                        class: PitchClass::G,
-                        octave: 4,
-                    },
-                    duration: DurationValue::Quarter,
-                    instrument: Instrument::Piano,
+                    voice: 1,
+                    ..NoteEvent::new(
+                        Pitch::new(PitchClass::C, 3),
+                        DurationValue::Half,
+                        Instrument::PIANO,
+                    )
                 },
             ],
         };
 
         assert_relative_eq!(score.total_beats(), 3.0, epsilon = f32::EPSILON);
         assert_relative_eq!(
-            score.total_measures(TimeSignature::ThreeFour),
+            score.total_measures(&MeasureMap::new(TimeSignature::ThreeFour, &[])),
             1.0,
             epsilon = f32::EPSILON
         );
     }
+
+    #[test]
+    fn dotted_values_round_trip_through_beats() {
+        assert_relative_eq!(DurationValue::Half.dotted_beats(1), 3.0);
+        assert_relative_eq!(DurationValue::Quarter.dotted_beats(2), 1.75);
+        assert_eq!(
+            DurationValue::from_beats(0.75),
+            Some((DurationValue::Eighth, 1))
+        );
+        assert_eq!(DurationValue::from_beats(5.0), None);
+    }
+
+    #[test]
+    fn decompose_prefers_dots_over_extra_values() {
+        assert_eq!(
+            DurationValue::decompose(3.0),
+            vec![(DurationValue::Half, 1)]
+        );
+        assert_eq!(
+            DurationValue::decompose(5.0),
+            vec![(DurationValue::Whole, 0), (DurationValue::Quarter, 0)]
+        );
+        // Too large for a whole note to change in f32.
+        assert!(DurationValue::decompose(2.0e8).is_empty());
+    }
 }
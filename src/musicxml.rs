@@ -0,0 +1,2901 @@
+use std::io::{Read, Write};
+use std::ops::Range;
+
+use crate::dynamics;
+use crate::harmony::{Alteration, ChordQuality, ChordSymbol, Extension, Suspension, Tone};
+use crate::instruments;
+use crate::measures::MeasureMap;
+use crate::music::{
+    Articulation, Clef, DurationValue, Dynamic, Hairpin, Instrument, KeySignature, Lyric, Meter,
+    MeterChange, NoteEvent, Part, Pitch, PitchClass, Score, ScoreSettings, Staff, Syllabic,
+    TempoChange, TempoMark, TimeSignature,
+};
+use crate::rebar;
+use crate::staves;
+use crate::transpose::Interval;
+
+/// Divisions per quarter note used for every `<duration>` we write.
+const DIVISIONS: u32 = 8;
+
+const MXL_MIMETYPE: &str = "application/vnd.recordare.musicxml";
+const MXL_ROOT_FILE: &str = "score.musicxml";
+
+/// Exports the score as uncompressed partwise MusicXML: one part per player,
+/// with all the staves of a grand staff in it.
+pub fn export_musicxml(settings: &ScoreSettings, score: &Score) -> String {
+    let staves = &settings.staves;
+    let measure_map = settings.measures();
+    let mut groups: Vec<Range<usize>> = Vec::new();
+    while groups.last().map_or(0, |group| group.end) < staves.len() {
+        let start = groups.last().map_or(0, |group| group.end);
+        groups.push(staves::grand_staff(staves, start));
+    }
+    let parts: Vec<Vec<Lane>> = groups
+        .iter()
+        .map(|group| {
+            let grand = group.len() > 1;
+            staves[group.clone()]
+                .iter()
+                .flat_map(|staff| {
+                    let sub_staff = staff.part.sub_staff;
+                    rebar::voices(&score.notes, staff.part)
+                        .into_iter()
+                        .map(move |voice| (staff.part, sub_staff, voice))
+                })
+                .map(|(part, sub_staff, voice)| {
+                    let stream = rebar::voice_stream(&score.notes, part, voice);
+                    let events: Vec<&NoteEvent> = stream.iter().collect();
+                    Lane {
+                        staff: grand.then_some(sub_staff + 1),
+                        voice: sub_staff * NoteEvent::VOICES + voice + 1,
+                        first: voice == 0,
+                        measures: split_into_measures(&events, &measure_map),
+                    }
+                })
+                .collect()
+        })
+        .collect();
+    let measure_count = parts
+        .iter()
+        .flatten()
+        .map(|lane| lane.measures.len())
+        .max()
+        .unwrap_or(0)
+        .max(1);
+
+    let mut xml = XmlWriter::default();
+    xml.raw(r#"<?xml version="1.0" encoding="UTF-8" standalone="no"?>"#);
+    xml.raw(r#"<!DOCTYPE score-partwise PUBLIC "-//Recordare//DTD MusicXML 4.0 Partwise//EN" "http://www.musicxml.org/dtds/partwise.dtd">"#);
+    xml.open("score-partwise", &[("version", "4.0")]);
+
+    xml.open("work", &[]);
+    xml.leaf("work-title", &settings.title);
+    xml.close("work");
+
+    xml.open("identification", &[]);
+    xml.leaf_with("creator", &[("type", "composer")], &settings.composer);
+    xml.open("encoding", &[]);
+    xml.leaf("software", "Notarium");
+    xml.close("encoding");
+    xml.close("identification");
+
+    xml.open("part-list", &[]);
+    for (index, group) in groups.iter().enumerate() {
+        let staff = &staves[group.start];
+        let (name, short_name) = staff.labels(staves);
+        write_score_part(&mut xml, index, staff.part.instrument, &name, &short_name);
+    }
+    xml.close("part-list");
+
+    for (index, (group, lanes)) in groups.iter().zip(&parts).enumerate() {
+        let part_id = part_id(index);
+        xml.open("part", &[("id", &part_id)]);
+        for number in 0..measure_count {
+            let measure_ticks = beats_to_ticks(measure_map.length(number));
+            // A pickup is measure 0 and does not count towards the numbering.
+            let pickup = measure_map.has_pickup();
+            let measure_number = (number + usize::from(!pickup)).to_string();
+            let mut attributes = vec![("number", measure_number.as_str())];
+            if pickup && number == 0 {
+                attributes.push(("implicit", "yes"));
+            }
+            xml.open("measure", &attributes);
+            if number == 0 {
+                write_attributes(&mut xml, settings, &staves[group.clone()]);
+            } else if let Some(meter) = measure_map.printed_meter(number) {
+                xml.open("attributes", &[]);
+                write_time(&mut xml, meter);
+                xml.close("attributes");
+            }
+            // Tempo belongs to the whole score and is written in the first part.
+            if index == 0 {
+                if number == 0 {
+                    xml.empty("sound", &[("tempo", &settings.bpm.to_string())]);
+                }
+                let staff = lanes.first().and_then(|lane| lane.staff);
+                for mark in &settings.tempo_marks {
+                    let (measure, offset) = measure_map.locate(mark.beat);
+                    if measure == number {
+                        write_tempo_mark(&mut xml, mark, beats_to_ticks(offset), staff);
+                    }
+                }
+            }
+            // Every lane after the first rewinds to the barline and fills the measure too.
+            let mut rewind = false;
+            for lane in lanes {
+                let segments = lane.measures.get(number);
+                if segments.is_none() && !lane.first {
+                    continue;
+                }
+                if rewind {
+                    xml.open("backup", &[]);
+                    xml.leaf("duration", &measure_ticks.to_string());
+                    xml.close("backup");
+                }
+                rewind = true;
+                match segments {
+                    Some(segments) => {
+                        write_measure_content(&mut xml, segments, measure_ticks, lane)
+                    }
+                    None => write_measure_rest(&mut xml, measure_ticks, lane),
+                }
+            }
+            xml.close("measure");
+        }
+        xml.close("part");
+    }
+
+    xml.close("score-partwise");
+    xml.finish()
+}
+
+/// Exports the score as a compressed MusicXML container (`.mxl`).
+pub fn export_mxl(settings: &ScoreSettings, score: &Score) -> Result<Vec<u8>, String> {
+    let document = export_musicxml(settings, score);
+    let mut archive = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+
+    let stored =
+        zip::write::SimpleFileOptions::default().compression_method(zip::CompressionMethod::Stored);
+    let deflated = zip::write::SimpleFileOptions::default()
+        .compression_method(zip::CompressionMethod::Deflated);
+
+    let container = format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <container>\n  <rootfiles>\n    \
+         <rootfile full-path=\"{MXL_ROOT_FILE}\" media-type=\"{MXL_MIMETYPE}\"/>\n  \
+         </rootfiles>\n</container>\n"
+    );
+
+    let entries = [
+        ("mimetype", MXL_MIMETYPE.as_bytes(), stored),
+        ("META-INF/container.xml", container.as_bytes(), deflated),
+        (MXL_ROOT_FILE, document.as_bytes(), deflated),
+    ];
+
+    for (name, contents, options) in entries {
+        archive
+            .start_file(name, options)
+            .map_err(|err| format!("Falha ao criar {name} no .mxl: {err}"))?;
+        archive
+            .write_all(contents)
+            .map_err(|err| format!("Falha ao gravar {name} no .mxl: {err}"))?;
+    }
+
+    archive
+        .finish()
+        .map(|cursor| cursor.into_inner())
+        .map_err(|err| format!("Falha ao finalizar .mxl: {err}"))
+}
+
+/// Result of reading a MusicXML document into Notarium's model.
+#[derive(Debug, Clone)]
+pub struct ImportedScore {
+    /// Carries one staff per imported part, in part order.
+    pub settings: ScoreSettings,
+    pub score: Score,
+    pub log: ImportLog,
+}
+
+/// Everything the importer could not carry over into the model.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ImportLog {
+    pub issues: Vec<ImportIssue>,
+}
+
+impl ImportLog {
+    pub fn is_empty(&self) -> bool {
+        self.issues.is_empty()
+    }
+
+    fn push(&mut self, location: &Location, kind: ImportIssueKind) {
+        self.issues.push(ImportIssue {
+            part: location.part.clone(),
+            measure: location.measure.clone(),
+            kind,
+        });
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImportIssue {
+    /// MusicXML part id, when the issue belongs to a part.
+    pub part: Option<String>,
+    /// Measure number as written in the file.
+    pub measure: Option<String>,
+    pub kind: ImportIssueKind,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ImportIssueKind {
+    /// An element Notarium has no model for; it was skipped.
+    UnsupportedElement(String),
+    /// A supported element whose value cannot be represented; a fallback was used.
+    UnsupportedValue { element: String, value: String },
+    /// The part name and MIDI program matched no instrument; Piano was used.
+    UnknownInstrument(String),
+    /// A duration that no combination of written values reproduces exactly.
+    ApproximatedDuration { duration: u32, divisions: u32 },
+    /// Notes beyond the fourth voice of the part were skipped.
+    ExtraVoice(String),
+    /// A short measure was padded with rests to keep the barlines aligned.
+    IncompleteMeasure { missing_beats: f32 },
+}
+
+impl std::fmt::Display for ImportIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match (&self.part, &self.measure) {
+            (Some(part), Some(measure)) => write!(f, "[{part}, compasso {measure}] ")?,
+            (Some(part), None) => write!(f, "[{part}] ")?,
+            _ => {}
+        }
+
+        match &self.kind {
+            ImportIssueKind::UnsupportedElement(element) => {
+                write!(f, "elemento <{element}> não suportado foi ignorado")
+            }
+            ImportIssueKind::UnsupportedValue { element, value } => {
+                write!(f, "valor \"{value}\" de <{element}> não suportado")
+            }
+            ImportIssueKind::UnknownInstrument(name) => {
+                write!(f, "instrumento \"{name}\" desconhecido, usando Piano")
+            }
+            ImportIssueKind::ApproximatedDuration {
+                duration,
+                divisions,
+            } => write!(
+                f,
+                "duração {duration}/{divisions} aproximada para valores suportados"
+            ),
+            ImportIssueKind::ExtraVoice(voice) => {
+                write!(f, "voz {voice} ignorada (no máximo quatro vozes por pauta)")
+            }
+            ImportIssueKind::IncompleteMeasure { missing_beats } => write!(
+                f,
+                "compasso incompleto completado com {missing_beats} tempo(s) de pausa"
+            ),
+        }
+    }
+}
+
+/// Reads an uncompressed partwise MusicXML document.
+pub fn import_musicxml(xml: &str) -> Result<ImportedScore, String> {
+    let options = roxmltree::ParsingOptions {
+        allow_dtd: true,
+        ..roxmltree::ParsingOptions::default()
+    };
+    let document = roxmltree::Document::parse_with_options(xml, options)
+        .map_err(|err| format!("XML inválido: {err}"))?;
+    let root = document.root_element();
+
+    match root.tag_name().name() {
+        "score-partwise" => Ok(Importer::default().run(root)),
+        "score-timewise" => Err("MusicXML timewise não é suportado".to_owned()),
+        other => Err(format!("elemento raiz <{other}> não é MusicXML")),
+    }
+}
+
+/// Reads a compressed MusicXML container (`.mxl`), following `META-INF/container.xml`.
+pub fn import_mxl(bytes: &[u8]) -> Result<ImportedScore, String> {
+    let mut archive = zip::ZipArchive::new(std::io::Cursor::new(bytes))
+        .map_err(|err| format!("arquivo .mxl inválido: {err}"))?;
+
+    let root_file = read_archive_entry(&mut archive, "META-INF/container.xml")
+        .ok()
+        .and_then(|container| root_file_path(&container))
+        .or_else(|| {
+            archive
+                .file_names()
+                .find(|name| {
+                    !name.starts_with("META-INF/")
+                        && (name.ends_with(".musicxml") || name.ends_with(".xml"))
+                })
+                .map(str::to_owned)
+        })
+        .ok_or_else(|| "arquivo .mxl sem partitura MusicXML".to_owned())?;
+
+    let document = read_archive_entry(&mut archive, &root_file)?;
+    import_musicxml(&document)
+}
+
+/// Decodes a MusicXML file, honouring the UTF-16 byte order marks some editors write.
+pub fn decode_document(bytes: &[u8]) -> Result<String, String> {
+    let utf16 = |chunks: Vec<u16>| {
+        String::from_utf16(&chunks).map_err(|err| format!("texto UTF-16 inválido: {err}"))
+    };
+
+    match bytes {
+        [0xFF, 0xFE, rest @ ..] => utf16(
+            rest.chunks_exact(2)
+                .map(|pair| u16::from_le_bytes([pair[0], pair[1]]))
+                .collect(),
+        ),
+        [0xFE, 0xFF, rest @ ..] => utf16(
+            rest.chunks_exact(2)
+                .map(|pair| u16::from_be_bytes([pair[0], pair[1]]))
+                .collect(),
+        ),
+        [0xEF, 0xBB, 0xBF, rest @ ..] => {
+            String::from_utf8(rest.to_vec()).map_err(|err| format!("texto UTF-8 inválido: {err}"))
+        }
+        _ => {
+            String::from_utf8(bytes.to_vec()).map_err(|err| format!("texto UTF-8 inválido: {err}"))
+        }
+    }
+}
+
+/// One voice of one staff of a part, cut into measures.
+struct Lane {
+    /// `<staff>` number, written only in parts with several staves.
+    staff: Option<u8>,
+    /// `<voice>` number; each staff of a part counts its voices from a fresh four.
+    voice: u8,
+    /// The staff's first voice, which gets a whole-measure rest in empty measures.
+    first: bool,
+    measures: Vec<Vec<Segment>>,
+}
+
+/// One written note or rest inside a measure, after splitting at barlines.
+#[derive(Debug, Clone)]
+struct Segment {
+    note: NoteEvent,
+    ticks: u32,
+    /// The event fits in its measure, so its own value and dots are written unchanged.
+    whole_event: bool,
+    tie_start: bool,
+    tie_stop: bool,
+    /// A wedge starts with this segment.
+    wedge_start: Option<Hairpin>,
+    /// A wedge ends with this segment.
+    wedge_stop: bool,
+}
+
+fn beats_to_ticks(beats: f32) -> u32 {
+    (beats * DIVISIONS as f32).round() as u32
+}
+
+fn ticks_to_beats(ticks: u32) -> f32 {
+    ticks as f32 / DIVISIONS as f32
+}
+
+fn split_into_measures(events: &[&NoteEvent], measure_map: &MeasureMap) -> Vec<Vec<Segment>> {
+    let mut measures: Vec<Vec<Segment>> = Vec::new();
+    let mut position = 0_u32;
+    let mut tied_from_previous = false;
+    let stream: Vec<NoteEvent> = events.iter().map(|event| (*event).clone()).collect();
+    let wedges = dynamics::wedges(&stream);
+
+    for (index, event) in events.iter().enumerate() {
+        let wedge_start = wedges
+            .iter()
+            .find(|(_, run)| run.start == index)
+            .map(|(hairpin, _)| *hairpin);
+        let wedge_stop = wedges.iter().any(|(_, run)| run.end == index + 1);
+        let event_ticks = beats_to_ticks(event.beats());
+        let mut remaining = event_ticks;
+        let mut first = true;
+
+        while remaining > 0 {
+            let (measure, offset) = measure_map.locate(ticks_to_beats(position));
+            let offset = beats_to_ticks(offset);
+            let measure_ticks = beats_to_ticks(measure_map.length(measure));
+            if measures.len() <= measure {
+                measures.resize_with(measure + 1, Vec::new);
+            }
+            let taken = remaining.min(measure_ticks - offset);
+            remaining -= taken;
+            position += taken;
+
+            if let Some(measure) = measures.get_mut(measure) {
+                measure.push(Segment {
+                    note: NoteEvent {
+                        dynamic: event.dynamic.filter(|_| first),
+                        articulations: if first {
+                            event.articulations.clone()
+                        } else {
+                            Vec::new()
+                        },
+                        lyrics: if first {
+                            event.lyrics.clone()
+                        } else {
+                            Vec::new()
+                        },
+                        chord: event.chord.clone().filter(|_| first),
+                        ..(*event).clone()
+                    },
+                    ticks: taken,
+                    whole_event: taken == event_ticks,
+                    tie_start: !event.rest && (remaining > 0 || event.tie),
+                    tie_stop: !event.rest && (!first || tied_from_previous),
+                    wedge_start: wedge_start.filter(|_| first),
+                    wedge_stop: wedge_stop && remaining == 0,
+                });
+            }
+            first = false;
+        }
+
+        tied_from_previous = event.tie && !event.rest;
+    }
+
+    measures
+}
+
+fn write_score_part(
+    xml: &mut XmlWriter,
+    index: usize,
+    instrument: Instrument,
+    name: &str,
+    short_name: &str,
+) {
+    let part_id = part_id(index);
+    let instrument_id = format!("{part_id}-I1");
+    // Channel 10 is reserved for General MIDI percussion.
+    let channel = match index % 15 + 1 {
+        channel if channel >= 10 => channel + 1,
+        channel => channel,
+    };
+
+    xml.open("score-part", &[("id", &part_id)]);
+    xml.leaf("part-name", name);
+    xml.leaf("part-abbreviation", short_name);
+    xml.open("score-instrument", &[("id", &instrument_id)]);
+    xml.leaf("instrument-name", instrument.label());
+    xml.close("score-instrument");
+    xml.open("midi-instrument", &[("id", &instrument_id)]);
+    xml.leaf("midi-channel", &channel.to_string());
+    xml.leaf("midi-program", &instrument.midi_program().to_string());
+    xml.close("midi-instrument");
+    xml.close("score-part");
+}
+
+fn write_attributes(xml: &mut XmlWriter, settings: &ScoreSettings, staves: &[Staff]) {
+    let Some(top) = staves.first() else {
+        return;
+    };
+    let transposition = top.part.instrument.transposition();
+    let key = settings.key_signature.transposed(transposition.down());
+
+    xml.open("attributes", &[]);
+    xml.leaf("divisions", &DIVISIONS.to_string());
+    xml.open("key", &[]);
+    xml.leaf("fifths", &key.fifths().to_string());
+    xml.close("key");
+    write_time(
+        xml,
+        settings
+            .measures()
+            .printed_meter(0)
+            .unwrap_or(settings.time_signature.into()),
+    );
+    if staves.len() > 1 {
+        xml.leaf("staves", &staves.len().to_string());
+    }
+    for (number, staff) in (1_usize..).zip(staves) {
+        let (sign, line) = clef_sign_and_line(staff.part.clef());
+        let number = number.to_string();
+        let attributes = [("number", number.as_str())];
+        xml.open("clef", &attributes[..usize::from(staves.len() > 1)]);
+        xml.leaf("sign", sign);
+        xml.leaf("line", line);
+        xml.close("clef");
+    }
+    if transposition != Interval::default() {
+        // Whole octaves go in `<octave-change>`, as MusicXML recommends.
+        let octaves = transposition.semitones / 12;
+        xml.open("transpose", &[]);
+        xml.leaf("diatonic", &(transposition.steps - 7 * octaves).to_string());
+        xml.leaf(
+            "chromatic",
+            &(transposition.semitones - 12 * octaves).to_string(),
+        );
+        if octaves != 0 {
+            xml.leaf("octave-change", &octaves.to_string());
+        }
+        xml.close("transpose");
+    }
+    xml.close("attributes");
+}
+
+fn write_time(xml: &mut XmlWriter, meter: Meter) {
+    xml.open("time", &[]);
+    xml.leaf("beats", &meter.numerator.to_string());
+    xml.leaf("beat-type", &meter.denominator.to_string());
+    xml.close("time");
+}
+
+fn write_measure_content(
+    xml: &mut XmlWriter,
+    segments: &[Segment],
+    measure_ticks: u32,
+    lane: &Lane,
+) {
+    let mut filled = 0;
+    for segment in segments {
+        let values = if segment.whole_event {
+            vec![(segment.note.duration, segment.note.dots)]
+        } else {
+            DurationValue::decompose(ticks_to_beats(segment.ticks))
+        };
+        let last = values.len().saturating_sub(1);
+        if let Some(dynamic) = segment.note.dynamic {
+            write_direction(xml, lane, |xml| {
+                xml.open("dynamics", &[]);
+                xml.empty(dynamic.label(), &[]);
+                xml.close("dynamics");
+            });
+        }
+        if let Some(chord) = &segment.note.chord {
+            let instrument = segment.note.instrument;
+            write_harmony(
+                xml,
+                &chord.transposed(|pitch| instrument.written_pitch(pitch)),
+                lane.staff,
+            );
+        }
+        if let Some(hairpin) = segment.wedge_start {
+            let kind = match hairpin {
+                Hairpin::Crescendo => "crescendo",
+                Hairpin::Diminuendo => "diminuendo",
+            };
+            write_wedge(xml, lane, kind);
+        }
+        // Only the first of several written values keeps the articulations
+        // and lyrics.
+        let unmarked = NoteEvent {
+            articulations: Vec::new(),
+            lyrics: Vec::new(),
+            ..segment.note.clone()
+        };
+        // Notes crossing to another staff name the staff they are drawn on.
+        let staff = lane.staff.map(|_| segment.note.shown_part().sub_staff + 1);
+        for (index, value) in values.into_iter().enumerate() {
+            let tied = !segment.note.rest;
+            write_note(
+                xml,
+                Some(if index == 0 { &segment.note } else { &unmarked }),
+                value,
+                tied && (segment.tie_start || index < last),
+                tied && (segment.tie_stop || index > 0),
+                lane.voice,
+                staff,
+            );
+        }
+        if segment.wedge_stop {
+            write_wedge(xml, lane, "stop");
+        }
+        filled += segment.ticks;
+    }
+
+    let padding = ticks_to_beats(measure_ticks.saturating_sub(filled));
+    for value in DurationValue::decompose(padding) {
+        write_note(xml, None, value, false, false, lane.voice, lane.staff);
+    }
+}
+
+/// A `<direction>` below the lane's staff, its `<direction-type>` written by `content`.
+fn write_direction(xml: &mut XmlWriter, lane: &Lane, content: impl FnOnce(&mut XmlWriter)) {
+    xml.open("direction", &[("placement", "below")]);
+    xml.open("direction-type", &[]);
+    content(xml);
+    xml.close("direction-type");
+    xml.leaf("voice", &lane.voice.to_string());
+    if let Some(staff) = lane.staff {
+        xml.leaf("staff", &staff.to_string());
+    }
+    xml.close("direction");
+}
+
+/// Words and a metronome mark above the staff, `offset` ticks after the
+/// measure's current position, with the tempo for playback.
+fn write_tempo_mark(xml: &mut XmlWriter, mark: &TempoMark, offset: u32, staff: Option<u8>) {
+    xml.open("direction", &[("placement", "above")]);
+    let words = match (mark.text.as_str(), mark.change) {
+        ("", Some(change)) => change.label(),
+        (text, _) => text,
+    };
+    if !words.is_empty() {
+        xml.open("direction-type", &[]);
+        xml.leaf("words", words);
+        xml.close("direction-type");
+    }
+    if let Some(bpm) = mark.bpm {
+        xml.open("direction-type", &[]);
+        xml.open("metronome", &[]);
+        xml.leaf("beat-unit", "quarter");
+        xml.leaf("per-minute", &bpm.to_string());
+        xml.close("metronome");
+        xml.close("direction-type");
+    }
+    if offset > 0 {
+        xml.leaf("offset", &offset.to_string());
+    }
+    if let Some(staff) = staff {
+        xml.leaf("staff", &staff.to_string());
+    }
+    if let Some(bpm) = mark.bpm {
+        xml.empty("sound", &[("tempo", &bpm.to_string())]);
+    }
+    xml.close("direction");
+}
+
+/// A chord symbol as a `<harmony>`, spelled at written pitch.
+fn write_harmony(xml: &mut XmlWriter, chord: &ChordSymbol, staff: Option<u8>) {
+    let write_tone = |xml: &mut XmlWriter, element: &str, tone: Tone| {
+        xml.open(element, &[]);
+        xml.leaf(&format!("{element}-step"), tone.class.label());
+        if tone.alter != 0 {
+            xml.leaf(&format!("{element}-alter"), &tone.alter.to_string());
+        }
+        xml.close(element);
+    };
+    let (kind, degrees) = harmony_kind(chord);
+    xml.open("harmony", &[]);
+    write_tone(xml, "root", chord.root);
+    xml.leaf("kind", kind);
+    if let Some(bass) = chord.bass {
+        write_tone(xml, "bass", bass);
+    }
+    for (value, alter, kind) in degrees {
+        xml.open("degree", &[]);
+        xml.leaf("degree-value", &value.to_string());
+        xml.leaf("degree-alter", &alter.to_string());
+        xml.leaf("degree-type", kind);
+        xml.close("degree");
+    }
+    if let Some(staff) = staff {
+        xml.leaf("staff", &staff.to_string());
+    }
+    xml.close("harmony");
+}
+
+/// The `<kind>` closest to `chord` and the `<degree>`s that make up the
+/// difference, as degree, alteration and degree type.
+fn harmony_kind(chord: &ChordSymbol) -> (&'static str, Vec<(u8, i8, &'static str)>) {
+    use ChordQuality::{Augmented, Diminished, Major, Minor, Power};
+    use Extension::{Eleventh, Ninth, Seventh, SixNine, Sixth, Thirteenth};
+
+    let mut degrees = Vec::new();
+    let extension = chord.extension;
+    let (kind, covered) = match (chord.quality, extension, chord.major_seventh) {
+        (Power, ..) => ("power", None),
+        (Major, None, _) => match chord.suspension {
+            Some(Suspension::Second) => ("suspended-second", None),
+            Some(Suspension::Fourth) => ("suspended-fourth", None),
+            None => ("major", None),
+        },
+        (Minor, None, _) => ("minor", None),
+        (Diminished, None | Some(Sixth | SixNine), _) => ("diminished", None),
+        (Augmented, None | Some(Sixth | SixNine), _) => ("augmented", None),
+        (Major, Some(Sixth | SixNine), _) => ("major-sixth", Some(Sixth)),
+        (Minor, Some(Sixth | SixNine), _) => ("minor-sixth", Some(Sixth)),
+        (Major, Some(Ninth), false) => ("dominant-ninth", Some(Ninth)),
+        (Major, Some(Ninth), true) => ("major-ninth", Some(Ninth)),
+        (Minor, Some(Ninth), false) => ("minor-ninth", Some(Ninth)),
+        (Major, Some(Eleventh), false) => ("dominant-11th", Some(Eleventh)),
+        (Major, Some(Eleventh), true) => ("major-11th", Some(Eleventh)),
+        (Minor, Some(Eleventh), false) => ("minor-11th", Some(Eleventh)),
+        (Major, Some(Thirteenth), false) => ("dominant-13th", Some(Thirteenth)),
+        (Major, Some(Thirteenth), true) => ("major-13th", Some(Thirteenth)),
+        (Minor, Some(Thirteenth), false) => ("minor-13th", Some(Thirteenth)),
+        (Major, Some(_), false) => ("dominant", Some(Seventh)),
+        (Major, Some(_), true) => ("major-seventh", Some(Seventh)),
+        (Minor, Some(_), false) => ("minor-seventh", Some(Seventh)),
+        (Minor, Some(_), true) => ("major-minor", Some(Seventh)),
+        (Diminished, Some(_), _) => ("diminished-seventh", Some(Seventh)),
+        (Augmented, Some(_), _) => ("augmented-seventh", Some(Seventh)),
+    };
+
+    if covered.is_none() && matches!(extension, Some(Sixth | SixNine)) {
+        degrees.push((6, 0, "add"));
+    }
+    if extension == Some(SixNine) {
+        degrees.push((9, 0, "add"));
+    }
+    for (value, degree) in [(9, Ninth), (11, Eleventh), (13, Thirteenth)] {
+        if extension.is_some_and(|extension| extension >= degree) && covered < Some(degree) {
+            degrees.push((value, 0, "add"));
+        }
+    }
+    if kind != "suspended-second" && kind != "suspended-fourth" {
+        if let Some(suspension) = chord.suspension {
+            degrees.push((3, 0, "subtract"));
+            degrees.push((
+                if suspension == Suspension::Second {
+                    2
+                } else {
+                    4
+                },
+                0,
+                "add",
+            ));
+        }
+    }
+    for alteration in &chord.alterations {
+        let (value, alter) = match alteration {
+            Alteration::FlatFifth => (5, -1),
+            Alteration::SharpFifth => (5, 1),
+            Alteration::FlatNinth => (9, -1),
+            Alteration::SharpNinth => (9, 1),
+            Alteration::SharpEleventh => (11, 1),
+            Alteration::FlatThirteenth => (13, -1),
+            Alteration::AddNinth => (9, 0),
+            Alteration::AddEleventh => (11, 0),
+        };
+        // Fifths are always there to alter; higher degrees only when stacked.
+        let top = match extension {
+            Some(Ninth) => 9,
+            Some(Eleventh) => 11,
+            Some(Thirteenth) => 13,
+            _ => 5,
+        };
+        let kind = if value <= top && alter != 0 {
+            "alter"
+        } else {
+            "add"
+        };
+        degrees.push((value, alter, kind));
+    }
+    (kind, degrees)
+}
+
+/// Starts or stops the lane's wedge; each lane numbers its wedges by its voice.
+fn write_wedge(xml: &mut XmlWriter, lane: &Lane, kind: &str) {
+    let number = lane.voice.to_string();
+    write_direction(xml, lane, |xml| {
+        xml.empty("wedge", &[("type", kind), ("number", &number)]);
+    });
+}
+
+fn write_measure_rest(xml: &mut XmlWriter, measure_ticks: u32, lane: &Lane) {
+    xml.open("note", &[]);
+    xml.empty("rest", &[("measure", "yes")]);
+    xml.leaf("duration", &measure_ticks.to_string());
+    xml.leaf("voice", &lane.voice.to_string());
+    if let Some(staff) = lane.staff {
+        xml.leaf("staff", &staff.to_string());
+    }
+    xml.close("note");
+}
+
+fn write_note(
+    xml: &mut XmlWriter,
+    note: Option<&NoteEvent>,
+    (value, dots): (DurationValue, u8),
+    tie_start: bool,
+    tie_stop: bool,
+    voice: u8,
+    staff: Option<u8>,
+) {
+    xml.open("note", &[]);
+    match note {
+        Some(note) if !note.rest => {
+            let pitch = note.instrument.written_pitch(note.pitch);
+            xml.open("pitch", &[]);
+            xml.leaf("step", pitch.class.label());
+            if pitch.alter != 0 {
+                xml.leaf("alter", &pitch.alter.to_string());
+            }
+            xml.leaf("octave", &pitch.octave.to_string());
+            xml.close("pitch");
+        }
+        _ => xml.empty("rest", &[]),
+    }
+    xml.leaf(
+        "duration",
+        &beats_to_ticks(value.dotted_beats(dots)).to_string(),
+    );
+    if tie_stop {
+        xml.empty("tie", &[("type", "stop")]);
+    }
+    if tie_start {
+        xml.empty("tie", &[("type", "start")]);
+    }
+    xml.leaf("voice", &voice.to_string());
+    xml.leaf("type", type_name(value));
+    for _ in 0..dots {
+        xml.empty("dot", &[]);
+    }
+    if let Some(staff) = staff {
+        xml.leaf("staff", &staff.to_string());
+    }
+    let articulations = note.map_or(&[][..], |note| &note.articulations[..]);
+    if tie_start || tie_stop || !articulations.is_empty() {
+        xml.open("notations", &[]);
+        if tie_stop {
+            xml.empty("tied", &[("type", "stop")]);
+        }
+        if tie_start {
+            xml.empty("tied", &[("type", "start")]);
+        }
+        let marks: Vec<Articulation> = articulations
+            .iter()
+            .copied()
+            .filter(|mark| *mark != Articulation::Fermata)
+            .collect();
+        if !marks.is_empty() {
+            xml.open("articulations", &[]);
+            for mark in marks {
+                match articulation_name(mark) {
+                    "strong-accent" => xml.empty("strong-accent", &[("type", "up")]),
+                    name => xml.empty(name, &[]),
+                }
+            }
+            xml.close("articulations");
+        }
+        if articulations.contains(&Articulation::Fermata) {
+            xml.empty("fermata", &[("type", "upright")]);
+        }
+        xml.close("notations");
+    }
+    for lyric in note.map_or(&[][..], |note| &note.lyrics[..]) {
+        let number = (lyric.verse + 1).to_string();
+        xml.open("lyric", &[("number", &number)]);
+        xml.leaf("syllabic", syllabic_name(lyric.syllabic));
+        xml.leaf("text", &lyric.text);
+        if lyric.extend {
+            xml.empty("extend", &[]);
+        }
+        xml.close("lyric");
+    }
+    xml.close("note");
+}
+
+fn syllabic_name(syllabic: Syllabic) -> &'static str {
+    match syllabic {
+        Syllabic::Single => "single",
+        Syllabic::Begin => "begin",
+        Syllabic::Middle => "middle",
+        Syllabic::End => "end",
+    }
+}
+
+/// Element name of a mark inside `<articulations>`, or `fermata` for the
+/// fermata, which sits directly in `<notations>`.
+fn articulation_name(articulation: Articulation) -> &'static str {
+    match articulation {
+        Articulation::Staccato => "staccato",
+        Articulation::Staccatissimo => "staccatissimo",
+        Articulation::Tenuto => "tenuto",
+        Articulation::Accent => "accent",
+        Articulation::Marcato => "strong-accent",
+        Articulation::Fermata => "fermata",
+    }
+}
+
+fn type_name(value: DurationValue) -> &'static str {
+    match value {
+        DurationValue::Whole => "whole",
+        DurationValue::Half => "half",
+        DurationValue::Quarter => "quarter",
+        DurationValue::Eighth => "eighth",
+        DurationValue::Sixteenth => "16th",
+    }
+}
+
+fn value_from_type_name(name: &str) -> Option<DurationValue> {
+    DurationValue::ALL
+        .into_iter()
+        .find(|value| type_name(*value) == name)
+}
+
+fn read_archive_entry(
+    archive: &mut zip::ZipArchive<std::io::Cursor<&[u8]>>,
+    name: &str,
+) -> Result<String, String> {
+    let mut entry = archive
+        .by_name(name)
+        .map_err(|err| format!("{name} ausente no .mxl: {err}"))?;
+    let mut bytes = Vec::new();
+    entry
+        .read_to_end(&mut bytes)
+        .map_err(|err| format!("Falha ao ler {name} do .mxl: {err}"))?;
+    decode_document(&bytes)
+}
+
+fn root_file_path(container: &str) -> Option<String> {
+    let document = roxmltree::Document::parse(container).ok()?;
+    document
+        .descendants()
+        .find(|node| node.has_tag_name("rootfile"))
+        .and_then(|node| node.attribute("full-path"))
+        .map(str::to_owned)
+}
+
+#[derive(Debug, Clone, Default)]
+struct Location {
+    part: Option<String>,
+    measure: Option<String>,
+}
+
+/// A `<score-part>` of the part list.
+struct PartListEntry {
+    id: String,
+    instrument: Instrument,
+    name: String,
+    abbreviation: String,
+}
+
+/// The `<duration>` of a note or forward as written, and the beats read
+/// from it.
+#[derive(Debug, Clone, Copy)]
+struct Duration {
+    duration: u32,
+    divisions: u32,
+    beats: f32,
+}
+
+/// What a `<direction>` says about the events after it.
+enum Mark {
+    Dynamic(Dynamic),
+    WedgeStart(Hairpin),
+    WedgeStop,
+    Words(String),
+    /// Quarter notes per minute.
+    Metronome(f32),
+}
+
+#[derive(Default)]
+struct Importer {
+    settings: ScoreSettings,
+    key_found: bool,
+    /// Whether the part being read is the first, whose `<time>` elements
+    /// set the meter of the whole score.
+    first_part: bool,
+    /// Index of the measure being read.
+    measure: usize,
+    /// Written-to-sounding interval of the part being read.
+    transposition: Interval,
+    /// Staves of the part being read, as many as its instrument can have.
+    staff_count: u8,
+    measure_beats: f32,
+    notes: Vec<NoteEvent>,
+    log: ImportLog,
+}
+
+impl Importer {
+    fn run(mut self, root: roxmltree::Node) -> ImportedScore {
+        let document_location = Location::default();
+        let mut title_found = false;
+        let mut part_list: Vec<PartListEntry> = Vec::new();
+
+        for child in root.children().filter(roxmltree::Node::is_element) {
+            match child.tag_name().name() {
+                "work" => {
+                    if let Some(title) = child_text(child, "work-title") {
+                        self.settings.title = title.to_owned();
+                        title_found = true;
+                    }
+                }
+                "movement-title" if !title_found => {
+                    self.settings.title = text_of(child).to_owned();
+                    title_found = true;
+                }
+                "identification" => self.read_identification(child),
+                "part-list" => part_list = self.read_part_list(child),
+                "part" => {}
+                // Page layout and credits are recomputed by Notarium.
+                "defaults" | "credit" | "movement-number" => {}
+                other => self.log.push(
+                    &document_location,
+                    ImportIssueKind::UnsupportedElement(other.to_owned()),
+                ),
+            }
+        }
+
+        let mut staves: Vec<Staff> = Vec::new();
+        for part in root.children().filter(|node| node.has_tag_name("part")) {
+            let part_id = part.attribute("id").unwrap_or_default().to_owned();
+            let entry = part_list.iter().find(|entry| entry.id == part_id);
+            let instrument = match entry {
+                Some(entry) => entry.instrument,
+                // A part missing from <part-list> has no name to go by.
+                None => {
+                    self.log.push(
+                        &Location {
+                            part: Some(part_id.clone()),
+                            measure: None,
+                        },
+                        ImportIssueKind::UnknownInstrument(part_id.clone()),
+                    );
+                    Instrument::PIANO
+                }
+            };
+            // Later parts of the same instrument become its next players.
+            let player = staves
+                .iter()
+                .filter(|staff| staff.part.instrument == instrument && staff.part.sub_staff == 0)
+                .count();
+            let staff = Staff {
+                part: Part {
+                    instrument,
+                    player: u8::try_from(player).unwrap_or(u8::MAX),
+                    sub_staff: 0,
+                },
+                name: entry.map(|entry| entry.name.clone()).unwrap_or_default(),
+                short_name: entry
+                    .map(|entry| entry.abbreviation.clone())
+                    .unwrap_or_default(),
+            };
+            self.first_part = staves.is_empty();
+            self.staff_count = part
+                .descendants()
+                .find(|node| node.has_tag_name("staves"))
+                .and_then(|node| text_of(node).parse::<u8>().ok())
+                .unwrap_or(1)
+                .clamp(1, instrument.staff_count());
+            self.read_part(part, &part_id, staff.part);
+            let top = staff.part;
+            staves.push(staff);
+            staves
+                .extend((1..self.staff_count).map(|sub_staff| Staff::new(top.on_staff(sub_staff))));
+        }
+
+        // Names Notarium would print anyway are left automatic.
+        let defaults: Vec<(String, String)> = staves
+            .iter()
+            .map(|staff| Staff::new(staff.part).labels(&staves))
+            .collect();
+        for (staff, (name, short_name)) in staves.iter_mut().zip(defaults) {
+            if staff.name == name {
+                staff.name.clear();
+            }
+            if staff.short_name == short_name {
+                staff.short_name.clear();
+            }
+        }
+        if !staves.is_empty() {
+            self.settings.staves = staves;
+        }
+        self.settings
+            .tempo_marks
+            .sort_by(|a, b| a.beat.total_cmp(&b.beat));
+
+        ImportedScore {
+            settings: self.settings,
+            score: Score { notes: self.notes },
+            log: self.log,
+        }
+    }
+
+    fn read_identification(&mut self, node: roxmltree::Node) {
+        let location = Location::default();
+        for child in node.children().filter(roxmltree::Node::is_element) {
+            match (child.tag_name().name(), child.attribute("type")) {
+                ("creator", Some("composer")) => self.settings.composer = text_of(child).to_owned(),
+                ("creator", kind) => self.log.push(
+                    &location,
+                    ImportIssueKind::UnsupportedValue {
+                        element: "creator".to_owned(),
+                        value: kind.unwrap_or_default().to_owned(),
+                    },
+                ),
+                ("encoding" | "miscellaneous" | "source", _) => {}
+                (other, _) => self.log.push(
+                    &location,
+                    ImportIssueKind::UnsupportedElement(other.to_owned()),
+                ),
+            }
+        }
+    }
+
+    fn read_part_list(&mut self, node: roxmltree::Node) -> Vec<PartListEntry> {
+        let mut parts = Vec::new();
+        for child in node.children().filter(roxmltree::Node::is_element) {
+            if !child.has_tag_name("score-part") {
+                self.log.push(
+                    &Location::default(),
+                    ImportIssueKind::UnsupportedElement(child.tag_name().name().to_owned()),
+                );
+                continue;
+            }
+
+            let id = child.attribute("id").unwrap_or_default().to_owned();
+            let name = child_text(child, "part-name").unwrap_or_default();
+            let program = child
+                .descendants()
+                .find(|node| node.has_tag_name("midi-program"))
+                .and_then(|node| text_of(node).parse::<u8>().ok());
+
+            let instrument = instrument_for_part(name, program).unwrap_or_else(|| {
+                self.log.push(
+                    &Location {
+                        part: Some(id.clone()),
+                        measure: None,
+                    },
+                    ImportIssueKind::UnknownInstrument(name.to_owned()),
+                );
+                Instrument::PIANO
+            });
+            parts.push(PartListEntry {
+                id,
+                instrument,
+                name: name.to_owned(),
+                abbreviation: child_text(child, "part-abbreviation")
+                    .unwrap_or_default()
+                    .to_owned(),
+            });
+        }
+        parts
+    }
+
+    fn read_part(&mut self, part: roxmltree::Node, part_id: &str, staff_part: Part) {
+        self.transposition = Interval::default();
+        let mut divisions = 1_u32;
+        let staves = usize::from(self.staff_count);
+        // MusicXML voice names in the order they turn up, with the staff they
+        // belong to and the Notarium voice they get on it.
+        let mut voices: Vec<(String, usize, usize)> = Vec::new();
+        let mut lengths = vec![[0.0_f32; NoteEvent::VOICES as usize]; staves];
+        // Markings waiting for the next event of their staff and voice, and
+        // the wedges open in each.
+        let mut pending: Vec<((usize, usize), Dynamic)> = Vec::new();
+        let mut wedges: Vec<((usize, usize), Hairpin)> = Vec::new();
+        // Chord symbols waiting for the next event of their staff.
+        let mut chords: Vec<(usize, ChordSymbol)> = Vec::new();
+        let measures: Vec<roxmltree::Node> = part
+            .children()
+            .filter(|node| node.has_tag_name("measure"))
+            .collect();
+
+        for (index, measure) in measures.iter().enumerate() {
+            let location = Location {
+                part: Some(part_id.to_owned()),
+                measure: measure.attribute("number").map(str::to_owned),
+            };
+            self.measure = index;
+            self.measure_beats = self.settings.measures().length(index);
+            let start = self.settings.measures().start(index);
+            let mut filled = vec![[0.0_f32; NoteEvent::VOICES as usize]; staves];
+            let mut extra_voice_logged = false;
+
+            for child in measure.children().filter(roxmltree::Node::is_element) {
+                let name = child.tag_name().name();
+                if name == "attributes" {
+                    self.read_attributes(child, &location, &mut divisions);
+                    continue;
+                }
+                if name == "direction" {
+                    // Directions go to the voice they name, or else to the
+                    // first voice of their staff.
+                    let lane = child_text(child, "voice")
+                        .and_then(|name| voices.iter().find(|(voice, ..)| voice == name))
+                        .map(|(_, staff, voice)| (*staff, *voice))
+                        .unwrap_or_else(|| {
+                            let staff = child_text(child, "staff")
+                                .and_then(|staff| staff.parse::<usize>().ok())
+                                .map_or(0, |staff| staff.clamp(1, staves) - 1);
+                            (staff, 0)
+                        });
+                    let mut words = Vec::new();
+                    let mut metronome = None;
+                    for mark in self.read_direction(child, &location) {
+                        match mark {
+                            Mark::Dynamic(dynamic) => pending.push((lane, dynamic)),
+                            Mark::WedgeStart(hairpin) => {
+                                wedges.retain(|(open, _)| *open != lane);
+                                wedges.push((lane, hairpin));
+                            }
+                            Mark::WedgeStop => wedges.retain(|(open, _)| *open != lane),
+                            Mark::Words(text) => words.push(text),
+                            Mark::Metronome(bpm) => metronome = Some(bpm),
+                        }
+                    }
+                    let offset = child_text(child, "offset")
+                        .and_then(|offset| offset.parse::<f32>().ok())
+                        .map_or(0.0, |offset| offset / divisions as f32);
+                    let beat = start + filled[lane.0][lane.1] + offset;
+                    let sound = child.children().find(|n| n.has_tag_name("sound"));
+                    let sound = sound.and_then(sound_tempo);
+                    if !self.read_tempo(beat, words, metronome, sound).is_empty() {
+                        self.log.push(
+                            &location,
+                            ImportIssueKind::UnsupportedElement("direction/words".to_owned()),
+                        );
+                    }
+                    continue;
+                }
+                if name == "harmony" {
+                    let staff = child_text(child, "staff")
+                        .and_then(|staff| staff.parse::<usize>().ok())
+                        .map_or(0, |staff| staff.clamp(1, staves) - 1);
+                    if let Some(chord) = self.read_harmony(child, &location) {
+                        chords.retain(|(waiting, _)| *waiting != staff);
+                        chords.push((staff, chord));
+                    }
+                    continue;
+                }
+                if name == "sound" {
+                    let beat = start + filled[0][0];
+                    self.read_tempo(beat, Vec::new(), None, sound_tempo(child));
+                    continue;
+                }
+                if !matches!(name, "note" | "forward") {
+                    // Voices are read note by note, so rewinding needs no bookkeeping.
+                    if !matches!(name, "backup" | "print") {
+                        self.log.push(
+                            &location,
+                            ImportIssueKind::UnsupportedElement(name.to_owned()),
+                        );
+                    }
+                    continue;
+                }
+
+                // Staves beyond what the instrument has are read onto its lowest.
+                let shown_staff = child_text(child, "staff")
+                    .and_then(|staff| staff.parse::<usize>().ok())
+                    .map_or(0, |staff| staff.clamp(1, staves) - 1);
+                // A `<forward>` without a voice moves the first one along.
+                let voice_name = child_text(child, "voice")
+                    .or(if name == "note" { Some("1") } else { None })
+                    .map(str::to_owned);
+                let lane = match voice_name {
+                    None => Some((shown_staff, 0)),
+                    Some(voice_name) => match voices.iter().find(|(v, ..)| *v == voice_name) {
+                        Some((_, staff, voice)) => Some((*staff, *voice)),
+                        None => {
+                            // Voices 1 to 4 belong to the first staff, 5 to 8 to the
+                            // second and so on, as notation programs number them.
+                            let home = voice_name.parse::<usize>().map_or(shown_staff, |number| {
+                                (number.saturating_sub(1) / NoteEvent::VOICES as usize)
+                                    .min(staves - 1)
+                            });
+                            let taken =
+                                voices.iter().filter(|(_, staff, _)| *staff == home).count();
+                            if taken < NoteEvent::VOICES as usize {
+                                voices.push((voice_name, home, taken));
+                                Some((home, taken))
+                            } else {
+                                if !extra_voice_logged {
+                                    self.log
+                                        .push(&location, ImportIssueKind::ExtraVoice(voice_name));
+                                    extra_voice_logged = true;
+                                }
+                                None
+                            }
+                        }
+                    },
+                };
+                let Some((staff, voice)) = lane else {
+                    continue;
+                };
+                let staff_part = staff_part.on_staff(staff as u8);
+
+                // A voice that joins later starts with rests up to this measure.
+                if lengths[staff][voice] < start - 1e-3 {
+                    let measures = self.settings.measures();
+                    self.notes.extend(rebar::rests(
+                        staff_part,
+                        voice as u8,
+                        lengths[staff][voice],
+                        start - lengths[staff][voice],
+                        &measures,
+                    ));
+                    lengths[staff][voice] = start;
+                }
+                let first_event = self.notes.len();
+                let remaining = self.measure_beats - filled[staff][voice];
+                let duration = if name == "note" && self.skips_note(child, &location) {
+                    None
+                } else {
+                    self.read_duration(child, &location, divisions, remaining)
+                };
+                let beats = match duration {
+                    Some(duration) if name == "note" => {
+                        self.read_note(child, &location, duration, staff_part, voice as u8)
+                    }
+                    Some(duration) => self.push_rests(duration, &location, staff_part, voice as u8),
+                    None => 0.0,
+                };
+                // Notes written on another staff than their voice's cross over.
+                let cross_staff = shown_staff as i8 - staff as i8;
+                let hairpin = wedges
+                    .iter()
+                    .find(|(open, _)| *open == (staff, voice))
+                    .map(|(_, hairpin)| *hairpin);
+                for event in &mut self.notes[first_event..] {
+                    if !event.rest {
+                        event.cross_staff = cross_staff;
+                    }
+                    event.hairpin = hairpin;
+                }
+                if let Some(first) = self.notes.get_mut(first_event) {
+                    if let Some(at) = pending.iter().position(|(lane, _)| *lane == (staff, voice)) {
+                        first.dynamic = Some(pending.remove(at).1);
+                    }
+                    if let Some(at) = chords.iter().position(|(waiting, _)| *waiting == staff) {
+                        first.chord = Some(chords.remove(at).1);
+                    }
+                }
+                filled[staff][voice] += beats;
+                lengths[staff][voice] += beats;
+            }
+
+            // An implicit opening measure that is not full is a pickup.
+            let pickup = index == 0 && measure.attribute("implicit") == Some("yes");
+            let opening = filled[0][0];
+            if pickup && self.first_part && opening > 1e-3 && opening < self.measure_beats {
+                let meter = self.settings.measures().meter(0);
+                if let Some(pickup) = Meter::from_beats(opening, meter.denominator) {
+                    let changes = &mut self.settings.meter_changes;
+                    changes.retain(|change| change.measure > 1);
+                    changes.splice(
+                        0..0,
+                        [
+                            MeterChange {
+                                measure: 0,
+                                meter: pickup,
+                            },
+                            MeterChange { measure: 1, meter },
+                        ],
+                    );
+                    continue;
+                }
+            }
+
+            let is_last = index + 1 == measures.len();
+            for (staff, filled) in filled.iter().enumerate() {
+                let missing_beats = self.measure_beats - filled[0];
+                if missing_beats <= 1e-3 || is_last {
+                    continue;
+                }
+                self.log.push(
+                    &location,
+                    ImportIssueKind::IncompleteMeasure { missing_beats },
+                );
+                for (value, dots) in DurationValue::decompose(missing_beats) {
+                    self.notes.push(NoteEvent {
+                        dots,
+                        ..NoteEvent::rest(value, staff_part.on_staff(staff as u8))
+                    });
+                }
+                lengths[staff][0] += missing_beats;
+            }
+        }
+    }
+
+    fn read_attributes(&mut self, node: roxmltree::Node, location: &Location, divisions: &mut u32) {
+        // `<transpose>` comes after `<key>`, which is written in the part's own key.
+        if let Some(transpose) = node.children().find(|n| n.has_tag_name("transpose")) {
+            let number = |name| child_text(transpose, name).and_then(|v| v.parse::<i32>().ok());
+            let octaves = Interval::octaves(number("octave-change").unwrap_or(0));
+            self.transposition = Interval::new(
+                number("diatonic").unwrap_or(0) + octaves.steps,
+                number("chromatic").unwrap_or(0) + octaves.semitones,
+            );
+        }
+        for child in node.children().filter(roxmltree::Node::is_element) {
+            match child.tag_name().name() {
+                "divisions" => match text_of(child).parse::<u32>() {
+                    Ok(value) if value > 0 => *divisions = value,
+                    _ => self.log.push(location, unsupported_value(child)),
+                },
+                "key" => {
+                    let fifths = child_text(child, "fifths").and_then(|v| v.parse::<i8>().ok());
+                    let key = fifths
+                        .and_then(KeySignature::from_fifths)
+                        .map(|key| key.transposed(self.transposition));
+                    match key {
+                        Some(key) if !self.key_found => {
+                            self.settings.key_signature = key;
+                            self.key_found = true;
+                        }
+                        Some(key) if key == self.settings.key_signature => {}
+                        _ => self.log.push(location, unsupported_value(child)),
+                    }
+                }
+                "time" => {
+                    let number = |name| {
+                        child_text(child, name)
+                            .and_then(|v| v.parse::<u8>().ok())
+                            .filter(|value| *value > 0)
+                    };
+                    let meter =
+                        number("beats")
+                            .zip(number("beat-type"))
+                            .map(|(numerator, denominator)| Meter {
+                                numerator,
+                                denominator,
+                            });
+                    match meter {
+                        Some(meter) if meter == self.settings.measures().meter(self.measure) => {}
+                        Some(meter) if self.first_part => {
+                            self.set_meter(self.measure, meter);
+                            self.measure_beats = meter.beats_per_measure();
+                        }
+                        _ => self.log.push(location, unsupported_value(child)),
+                    }
+                }
+                // Clefs follow the instrument and its staves.
+                "clef" | "staves" | "transpose" => {
+                    if child.has_tag_name("staves")
+                        && text_of(child) != self.staff_count.to_string()
+                    {
+                        self.log.push(location, unsupported_value(child));
+                    }
+                }
+                other => self.log.push(
+                    location,
+                    ImportIssueKind::UnsupportedElement(other.to_owned()),
+                ),
+            }
+        }
+    }
+
+    /// Makes `meter` start at `measure`. The opening meter becomes the time
+    /// signature when Notarium has one like it.
+    fn set_meter(&mut self, measure: usize, meter: Meter) {
+        let changes = &mut self.settings.meter_changes;
+        changes.retain(|change| change.measure != measure);
+        match TimeSignature::from_fraction(meter.numerator, meter.denominator) {
+            Some(time) if measure == 0 => self.settings.time_signature = time,
+            _ => {
+                changes.push(MeterChange { measure, meter });
+                changes.sort_by_key(|change| change.measure);
+            }
+        }
+    }
+
+    /// Whether the note is one Notarium skips, after logging it.
+    fn skips_note(&mut self, node: roxmltree::Node, location: &Location) -> bool {
+        let skipped = ["grace", "cue", "chord"]
+            .into_iter()
+            .find(|skipped| has_child(node, skipped));
+        if let Some(skipped) = skipped {
+            self.log.push(
+                location,
+                ImportIssueKind::UnsupportedElement(skipped.to_owned()),
+            );
+        }
+        skipped.is_some()
+    }
+
+    /// The `<duration>` of a note or forward, cut to the `remaining` beats
+    /// of the measure. `None`, after logging, when there is nothing to read.
+    fn read_duration(
+        &mut self,
+        node: roxmltree::Node,
+        location: &Location,
+        divisions: u32,
+        remaining: f32,
+    ) -> Option<Duration> {
+        let text = child_text(node, "duration").unwrap_or_default();
+        let duration = match text.parse::<u32>() {
+            Ok(duration) if duration > 0 => duration,
+            _ => {
+                self.log.push(
+                    location,
+                    ImportIssueKind::UnsupportedValue {
+                        element: "duration".to_owned(),
+                        value: text.to_owned(),
+                    },
+                );
+                return None;
+            }
+        };
+        let beats = duration as f32 / divisions as f32;
+        if beats <= remaining + 1e-3 {
+            return Some(Duration {
+                duration,
+                divisions,
+                beats,
+            });
+        }
+        // Longer than what is left of the measure; a corrupt file can ask
+        // for more notes than memory holds.
+        self.log.push(
+            location,
+            ImportIssueKind::ApproximatedDuration {
+                duration,
+                divisions,
+            },
+        );
+        (remaining > 1e-3).then_some(Duration {
+            duration,
+            divisions,
+            beats: remaining,
+        })
+    }
+
+    /// Appends the note (split into tied values when needed) and returns the
+    /// beats of what was written.
+    fn read_note(
+        &mut self,
+        node: roxmltree::Node,
+        location: &Location,
+        duration: Duration,
+        staff_part: Part,
+        voice: u8,
+    ) -> f32 {
+        let Duration {
+            duration,
+            divisions,
+            beats,
+        } = duration;
+        let written = child_text(node, "type")
+            .and_then(value_from_type_name)
+            .map(|value| {
+                (
+                    value,
+                    node.children().filter(|n| n.has_tag_name("dot")).count() as u8,
+                )
+            })
+            .filter(|(value, dots)| (value.dotted_beats(*dots) - beats).abs() < 1e-3);
+        let values = match written.or_else(|| DurationValue::from_beats(beats)) {
+            Some(value) => vec![value],
+            None => {
+                let values = DurationValue::decompose(beats);
+                let covered: f32 = values
+                    .iter()
+                    .map(|(value, dots)| value.dotted_beats(*dots))
+                    .sum();
+                if (covered - beats).abs() > 1e-3 || values.is_empty() {
+                    self.log.push(
+                        location,
+                        ImportIssueKind::ApproximatedDuration {
+                            duration,
+                            divisions,
+                        },
+                    );
+                }
+                if values.is_empty() {
+                    vec![(DurationValue::Sixteenth, 0)]
+                } else {
+                    values
+                }
+            }
+        };
+
+        let pitch = node
+            .children()
+            .find(|n| n.has_tag_name("pitch"))
+            .and_then(|pitch| self.read_pitch(pitch, location));
+        let tie_start = node.descendants().any(|n| {
+            (n.has_tag_name("tie") || n.has_tag_name("tied"))
+                && n.attribute("type") == Some("start")
+        });
+
+        let mut articulations = Vec::new();
+        let mut lyrics = Vec::new();
+        for child in node.children().filter(roxmltree::Node::is_element) {
+            match child.tag_name().name() {
+                "notations" => articulations.extend(self.read_notations(child, location)),
+                "lyric" if pitch.is_some() => lyrics.extend(self.read_lyric(child, location)),
+                "unpitched" | "lyric" | "time-modification" | "notehead" => self.log.push(
+                    location,
+                    ImportIssueKind::UnsupportedElement(child.tag_name().name().to_owned()),
+                ),
+                _ => {}
+            }
+        }
+
+        let last = values.len() - 1;
+        let mut written_beats = 0.0;
+        for (index, (value, dots)) in values.into_iter().enumerate() {
+            written_beats += value.dotted_beats(dots);
+            let mut event = match pitch {
+                Some(pitch) => NoteEvent::new(pitch, value, staff_part),
+                None => NoteEvent::rest(value, staff_part),
+            };
+            event.dots = dots;
+            event.voice = voice;
+            event.tie = pitch.is_some() && (index < last || tie_start);
+            if index == 0 {
+                event.articulations = articulations
+                    .iter()
+                    .copied()
+                    .filter(|mark| pitch.is_some() || *mark == Articulation::Fermata)
+                    .collect();
+                for lyric in &lyrics {
+                    event.set_lyric(lyric.clone());
+                }
+            }
+            self.notes.push(event);
+        }
+
+        written_beats
+    }
+
+    fn read_direction(&mut self, node: roxmltree::Node, location: &Location) -> Vec<Mark> {
+        let mut marks = Vec::new();
+        let kinds = node
+            .children()
+            .filter(|n| n.has_tag_name("direction-type"))
+            .flat_map(|n| n.children().filter(roxmltree::Node::is_element));
+        for kind in kinds {
+            match kind.tag_name().name() {
+                "dynamics" => {
+                    for marking in kind.children().filter(roxmltree::Node::is_element) {
+                        let name = marking.tag_name().name();
+                        match Dynamic::ALL.into_iter().find(|d| d.label() == name) {
+                            Some(dynamic) => marks.push(Mark::Dynamic(dynamic)),
+                            None => self.log.push(
+                                location,
+                                ImportIssueKind::UnsupportedValue {
+                                    element: "dynamics".to_owned(),
+                                    value: name.to_owned(),
+                                },
+                            ),
+                        }
+                    }
+                }
+                "wedge" => match kind.attribute("type") {
+                    Some("crescendo") => marks.push(Mark::WedgeStart(Hairpin::Crescendo)),
+                    Some("diminuendo") => marks.push(Mark::WedgeStart(Hairpin::Diminuendo)),
+                    Some("stop") => marks.push(Mark::WedgeStop),
+                    other => self.log.push(
+                        location,
+                        ImportIssueKind::UnsupportedValue {
+                            element: "wedge".to_owned(),
+                            value: other.unwrap_or_default().to_owned(),
+                        },
+                    ),
+                },
+                "words" => marks.push(Mark::Words(text_of(kind).to_owned())),
+                "metronome" => {
+                    let unit = child_text(kind, "beat-unit").and_then(value_from_type_name);
+                    let dots = kind
+                        .children()
+                        .filter(|n| n.has_tag_name("beat-unit-dot"))
+                        .count();
+                    let per_minute = child_text(kind, "per-minute")
+                        .and_then(|value| value.parse::<f32>().ok())
+                        .filter(|value| *value > 0.0);
+                    match (unit, per_minute) {
+                        (Some(unit), Some(per_minute)) => {
+                            marks.push(Mark::Metronome(per_minute * unit.dotted_beats(dots as u8)))
+                        }
+                        _ => self.log.push(location, unsupported_value(kind)),
+                    }
+                }
+                name => self.log.push(
+                    location,
+                    ImportIssueKind::UnsupportedElement(format!("direction/{name}")),
+                ),
+            }
+        }
+        marks
+    }
+
+    /// Takes the words, metronome mark and playback tempo (from `<sound>`)
+    /// of a direction in the first part as a tempo mark at `beat`. Tempo in
+    /// later parts repeats the first part's and is skipped. Returns the words
+    /// that say nothing about tempo.
+    fn read_tempo(
+        &mut self,
+        beat: f32,
+        words: Vec<String>,
+        metronome: Option<f32>,
+        sound: Option<f32>,
+    ) -> Vec<String> {
+        let (tempo_words, other): (Vec<String>, Vec<String>) =
+            words.into_iter().partition(|words| is_tempo_words(words));
+        let bpm = metronome.or(sound);
+        if !self.first_part || (tempo_words.is_empty() && bpm.is_none()) {
+            return other;
+        }
+
+        let text = tempo_words.join(" ");
+        let change = tempo_words.iter().find_map(|words| tempo_change(words));
+        if metronome.is_none() && text.is_empty() && beat < 1e-3 {
+            // A bare playback tempo at the start is the opening tempo.
+            self.settings.bpm = bpm.unwrap_or(self.settings.bpm);
+        } else {
+            self.settings.tempo_marks.push(TempoMark {
+                beat,
+                text: if change.map(TempoChange::label) == Some(text.as_str()) {
+                    String::new()
+                } else {
+                    text
+                },
+                bpm,
+                change,
+            });
+        }
+        other
+    }
+
+    fn read_pitch(&mut self, node: roxmltree::Node, location: &Location) -> Option<Pitch> {
+        let class = child_text(node, "step").and_then(pitch_class_from_step)?;
+        let octave = child_text(node, "octave").and_then(|v| v.parse::<i8>().ok())?;
+        let alter = match child_text(node, "alter").map(str::parse::<f32>) {
+            Some(Ok(alter)) => {
+                if alter.fract() != 0.0 {
+                    self.log.push(
+                        location,
+                        ImportIssueKind::UnsupportedValue {
+                            element: "alter".to_owned(),
+                            value: alter.to_string(),
+                        },
+                    );
+                }
+                alter.round() as i8
+            }
+            _ => 0,
+        };
+
+        Some(self.transposition.apply(Pitch {
+            class,
+            alter,
+            octave,
+        }))
+    }
+
+    /// A `<harmony>` as a chord symbol at sounding pitch. Kinds and degrees
+    /// Notarium cannot write are logged and left out of the symbol.
+    fn read_harmony(&mut self, node: roxmltree::Node, location: &Location) -> Option<ChordSymbol> {
+        let tone = |element: &str| {
+            let node = node.children().find(|n| n.has_tag_name(element))?;
+            let class =
+                child_text(node, &format!("{element}-step")).and_then(pitch_class_from_step)?;
+            let alter = child_text(node, &format!("{element}-alter"))
+                .and_then(|alter| alter.parse::<f32>().ok())
+                .map_or(0, |alter| alter.round() as i8);
+            Some(Tone::new(class, alter))
+        };
+        let Some(root) = tone("root") else {
+            self.log.push(
+                location,
+                ImportIssueKind::UnsupportedElement("harmony".to_owned()),
+            );
+            return None;
+        };
+        let kind = child_text(node, "kind").unwrap_or("major");
+        let (quality, extension, major_seventh) = match kind {
+            "major" | "suspended-second" | "suspended-fourth" => (ChordQuality::Major, None, false),
+            "minor" => (ChordQuality::Minor, None, false),
+            "diminished" => (ChordQuality::Diminished, None, false),
+            "augmented" => (ChordQuality::Augmented, None, false),
+            "power" => (ChordQuality::Power, None, false),
+            "major-sixth" => (ChordQuality::Major, Some(Extension::Sixth), false),
+            "minor-sixth" => (ChordQuality::Minor, Some(Extension::Sixth), false),
+            "dominant" => (ChordQuality::Major, Some(Extension::Seventh), false),
+            "major-seventh" => (ChordQuality::Major, Some(Extension::Seventh), true),
+            "minor-seventh" | "half-diminished" => {
+                (ChordQuality::Minor, Some(Extension::Seventh), false)
+            }
+            "major-minor" => (ChordQuality::Minor, Some(Extension::Seventh), true),
+            "diminished-seventh" => (ChordQuality::Diminished, Some(Extension::Seventh), false),
+            "augmented-seventh" => (ChordQuality::Augmented, Some(Extension::Seventh), false),
+            "dominant-ninth" => (ChordQuality::Major, Some(Extension::Ninth), false),
+            "major-ninth" => (ChordQuality::Major, Some(Extension::Ninth), true),
+            "minor-ninth" => (ChordQuality::Minor, Some(Extension::Ninth), false),
+            "dominant-11th" => (ChordQuality::Major, Some(Extension::Eleventh), false),
+            "major-11th" => (ChordQuality::Major, Some(Extension::Eleventh), true),
+            "minor-11th" => (ChordQuality::Minor, Some(Extension::Eleventh), false),
+            "dominant-13th" => (ChordQuality::Major, Some(Extension::Thirteenth), false),
+            "major-13th" => (ChordQuality::Major, Some(Extension::Thirteenth), true),
+            "minor-13th" => (ChordQuality::Minor, Some(Extension::Thirteenth), false),
+            other => {
+                self.log.push(
+                    location,
+                    ImportIssueKind::UnsupportedValue {
+                        element: "kind".to_owned(),
+                        value: other.to_owned(),
+                    },
+                );
+                (ChordQuality::Major, None, false)
+            }
+        };
+        let mut chord = ChordSymbol {
+            extension,
+            major_seventh,
+            bass: tone("bass"),
+            ..ChordSymbol::triad(root, quality)
+        };
+        match kind {
+            "suspended-second" => chord.suspension = Some(Suspension::Second),
+            "suspended-fourth" => chord.suspension = Some(Suspension::Fourth),
+            "half-diminished" => chord.alterations.push(Alteration::FlatFifth),
+            _ => {}
+        }
+
+        for degree in node.children().filter(|n| n.has_tag_name("degree")) {
+            let value = child_text(degree, "degree-value").and_then(|v| v.parse::<u8>().ok());
+            let alter = child_text(degree, "degree-alter")
+                .and_then(|alter| alter.parse::<f32>().ok())
+                .map_or(0, |alter| alter.round() as i8);
+            let kind = child_text(degree, "degree-type").unwrap_or("add");
+            let alteration = match (kind, value, alter) {
+                ("subtract", Some(3), _) => continue,
+                ("add", Some(2), 0) => {
+                    chord.suspension = Some(Suspension::Second);
+                    continue;
+                }
+                ("add", Some(4), 0) => {
+                    chord.suspension = Some(Suspension::Fourth);
+                    continue;
+                }
+                ("add", Some(6), 0) if chord.extension.is_none() => {
+                    chord.extension = Some(Extension::Sixth);
+                    continue;
+                }
+                ("add", Some(9), 0) if chord.extension == Some(Extension::Sixth) => {
+                    chord.extension = Some(Extension::SixNine);
+                    continue;
+                }
+                ("add", Some(9), 0) if chord.extension == Some(Extension::Seventh) => {
+                    chord.extension = Some(Extension::Ninth);
+                    continue;
+                }
+                ("add", Some(11), 0) if chord.extension == Some(Extension::Ninth) => {
+                    chord.extension = Some(Extension::Eleventh);
+                    continue;
+                }
+                ("add", Some(13), 0) if chord.extension == Some(Extension::Eleventh) => {
+                    chord.extension = Some(Extension::Thirteenth);
+                    continue;
+                }
+                (_, Some(5), -1) => Some(Alteration::FlatFifth),
+                (_, Some(5), 1) => Some(Alteration::SharpFifth),
+                (_, Some(9), -1) => Some(Alteration::FlatNinth),
+                (_, Some(9), 1) => Some(Alteration::SharpNinth),
+                (_, Some(11), 1) => Some(Alteration::SharpEleventh),
+                (_, Some(13), -1) => Some(Alteration::FlatThirteenth),
+                ("add", Some(9), 0) => Some(Alteration::AddNinth),
+                ("add", Some(11), 0) => Some(Alteration::AddEleventh),
+                _ => None,
+            };
+            match alteration {
+                Some(alteration) if !chord.alterations.contains(&alteration) => {
+                    chord.alterations.push(alteration);
+                }
+                Some(_) => {}
+                None => self.log.push(
+                    location,
+                    ImportIssueKind::UnsupportedValue {
+                        element: "degree".to_owned(),
+                        value: format!(
+                            "{kind} {}{}",
+                            value.map_or(String::new(), |value| value.to_string()),
+                            match alter {
+                                0 => String::new(),
+                                alter => format!(" ({alter:+})"),
+                            }
+                        ),
+                    },
+                ),
+            }
+        }
+
+        let transposition = self.transposition;
+        Some(chord.transposed(|pitch| transposition.apply(pitch)))
+    }
+
+    /// A `<lyric>` as the syllable of its verse. Verses past the ones a note
+    /// holds and elided syllables are logged.
+    fn read_lyric(&mut self, node: roxmltree::Node, location: &Location) -> Option<Lyric> {
+        let verse = node
+            .attribute("number")
+            .and_then(|number| number.parse::<u8>().ok())
+            .unwrap_or(1)
+            .saturating_sub(1);
+        if verse >= Lyric::VERSES {
+            self.log.push(
+                location,
+                ImportIssueKind::UnsupportedElement("lyric".to_owned()),
+            );
+            return None;
+        }
+        if has_child(node, "elision") {
+            self.log.push(
+                location,
+                ImportIssueKind::UnsupportedElement("lyric/elision".to_owned()),
+            );
+        }
+        let text: String = node
+            .children()
+            .filter(|n| n.has_tag_name("text"))
+            .map(text_of)
+            .collect::<Vec<_>>()
+            .join(" ");
+        let syllabic = match child_text(node, "syllabic") {
+            Some("begin") => Syllabic::Begin,
+            Some("middle") => Syllabic::Middle,
+            Some("end") => Syllabic::End,
+            _ => Syllabic::Single,
+        };
+        let extend = node.children().any(|n| {
+            n.has_tag_name("extend")
+                && !matches!(n.attribute("type"), Some("stop") | Some("continue"))
+        });
+        Some(Lyric {
+            verse,
+            text,
+            syllabic,
+            extend,
+        })
+    }
+
+    /// The articulations in `<notations>`; anything else but ties is logged.
+    fn read_notations(&mut self, node: roxmltree::Node, location: &Location) -> Vec<Articulation> {
+        let known = |name: &str| {
+            Articulation::ALL
+                .into_iter()
+                .find(|articulation| articulation_name(*articulation) == name)
+        };
+        let mut articulations = Vec::new();
+        for child in node.children().filter(roxmltree::Node::is_element) {
+            let name = child.tag_name().name();
+            match name {
+                "tied" => {}
+                "fermata" => articulations.push(Articulation::Fermata),
+                "articulations" | "ornaments" | "technical" => {
+                    for mark in child.children().filter(roxmltree::Node::is_element) {
+                        if name == "articulations" {
+                            if let Some(articulation) = known(mark.tag_name().name()) {
+                                articulations.push(articulation);
+                                continue;
+                            }
+                        }
+                        self.log.push(
+                            location,
+                            ImportIssueKind::UnsupportedElement(format!(
+                                "{name}/{}",
+                                mark.tag_name().name()
+                            )),
+                        );
+                    }
+                }
+                _ => self.log.push(
+                    location,
+                    ImportIssueKind::UnsupportedElement(format!("notations/{name}")),
+                ),
+            }
+        }
+        articulations
+    }
+
+    /// Appends rests for a `<forward>` and returns the beats they cover.
+    fn push_rests(
+        &mut self,
+        duration: Duration,
+        location: &Location,
+        staff_part: Part,
+        voice: u8,
+    ) -> f32 {
+        let values = DurationValue::decompose(duration.beats);
+        let covered: f32 = values
+            .iter()
+            .map(|(value, dots)| value.dotted_beats(*dots))
+            .sum();
+        if (covered - duration.beats).abs() > 1e-3 {
+            self.log.push(
+                location,
+                ImportIssueKind::ApproximatedDuration {
+                    duration: duration.duration,
+                    divisions: duration.divisions,
+                },
+            );
+        }
+        for (value, dots) in values {
+            self.notes.push(NoteEvent {
+                dots,
+                voice,
+                ..NoteEvent::rest(value, staff_part)
+            });
+        }
+        covered
+    }
+}
+
+fn instrument_for_part(name: &str, program: Option<u8>) -> Option<Instrument> {
+    instruments::find_by_name(name).or_else(|| {
+        let program = program?;
+        Instrument::all().find(|instrument| instrument.midi_program() == program)
+    })
+}
+
+fn pitch_class_from_step(step: &str) -> Option<PitchClass> {
+    PitchClass::ALL
+        .into_iter()
+        .find(|class| class.label() == step.trim())
+}
+
+/// Words that start a tempo indication, lowercase.
+const TEMPO_WORDS: [&str; 20] = [
+    "grave",
+    "largo",
+    "larghetto",
+    "lento",
+    "adagio",
+    "andante",
+    "moderato",
+    "allegr",
+    "vivace",
+    "presto",
+    "tempo",
+    "a tempo",
+    "rit",
+    "rall",
+    "accel",
+    "più",
+    "piu",
+    "meno",
+    "maestoso",
+    "ritenuto",
+];
+
+fn is_tempo_words(words: &str) -> bool {
+    let words = words.trim().to_lowercase();
+    TEMPO_WORDS.iter().any(|start| words.starts_with(start))
+}
+
+/// The `tempo` attribute of a `<sound>` element.
+fn sound_tempo(sound: roxmltree::Node) -> Option<f32> {
+    sound
+        .attribute("tempo")
+        .and_then(|tempo| tempo.parse::<f32>().ok())
+        .filter(|tempo| *tempo > 0.0)
+}
+
+fn tempo_change(words: &str) -> Option<TempoChange> {
+    let words = words.trim().to_lowercase();
+    if words.starts_with("rit") || words.starts_with("rall") {
+        Some(TempoChange::Ritardando)
+    } else if words.starts_with("accel") {
+        Some(TempoChange::Accelerando)
+    } else {
+        None
+    }
+}
+
+fn unsupported_value(node: roxmltree::Node) -> ImportIssueKind {
+    let value = node
+        .children()
+        .filter(roxmltree::Node::is_element)
+        .map(|child| format!("{}={}", child.tag_name().name(), text_of(child)))
+        .collect::<Vec<_>>()
+        .join(", ");
+    ImportIssueKind::UnsupportedValue {
+        element: node.tag_name().name().to_owned(),
+        value: if value.is_empty() {
+            text_of(node).to_owned()
+        } else {
+            value
+        },
+    }
+}
+
+fn text_of<'a>(node: roxmltree::Node<'a, '_>) -> &'a str {
+    node.text().map(str::trim).unwrap_or_default()
+}
+
+fn child_text<'a>(node: roxmltree::Node<'a, '_>, name: &str) -> Option<&'a str> {
+    node.children()
+        .find(|child| child.has_tag_name(name))
+        .map(text_of)
+}
+
+fn has_child(node: roxmltree::Node, name: &str) -> bool {
+    node.children().any(|child| child.has_tag_name(name))
+}
+
+fn clef_sign_and_line(clef: Clef) -> (&'static str, &'static str) {
+    match clef {
+        Clef::Treble => ("G", "2"),
+        Clef::Alto => ("C", "3"),
+        Clef::Bass => ("F", "4"),
+        Clef::Percussion => ("percussion", "2"),
+    }
+}
+
+fn part_id(index: usize) -> String {
+    format!("P{}", index + 1)
+}
+
+#[derive(Default)]
+struct XmlWriter {
+    out: String,
+    depth: usize,
+}
+
+impl XmlWriter {
+    fn raw(&mut self, line: &str) {
+        self.out.push_str(line);
+        self.out.push('\n');
+    }
+
+    fn indent(&mut self) {
+        for _ in 0..self.depth {
+            self.out.push_str("  ");
+        }
+    }
+
+    fn start_tag(&mut self, name: &str, attributes: &[(&str, &str)]) {
+        self.indent();
+        self.out.push('<');
+        self.out.push_str(name);
+        for (key, value) in attributes {
+            self.out
+                .push_str(&format!(" {key}=\"{}\"", escape_xml(value)));
+        }
+    }
+
+    fn open(&mut self, name: &str, attributes: &[(&str, &str)]) {
+        self.start_tag(name, attributes);
+        self.out.push_str(">\n");
+        self.depth += 1;
+    }
+
+    fn close(&mut self, name: &str) {
+        self.depth = self.depth.saturating_sub(1);
+        self.indent();
+        self.out.push_str(&format!("</{name}>\n"));
+    }
+
+    fn empty(&mut self, name: &str, attributes: &[(&str, &str)]) {
+        self.start_tag(name, attributes);
+        self.out.push_str("/>\n");
+    }
+
+    fn leaf(&mut self, name: &str, text: &str) {
+        self.leaf_with(name, &[], text);
+    }
+
+    fn leaf_with(&mut self, name: &str, attributes: &[(&str, &str)], text: &str) {
+        self.start_tag(name, attributes);
+        self.out
+            .push_str(&format!(">{}</{name}>\n", escape_xml(text)));
+    }
+
+    fn finish(self) -> String {
+        self.out
+    }
+}
+
+fn escape_xml(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for ch in text.chars() {
+        match ch {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&apos;"),
+            _ => out.push(ch),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn note(class: PitchClass, octave: i8, duration: DurationValue) -> NoteEvent {
+        NoteEvent::new(Pitch::new(class, octave), duration, Instrument::VIOLIN)
+    }
+
+    fn sample_settings() -> ScoreSettings {
+        ScoreSettings {
+            title: "Pequena Peça".to_owned(),
+            composer: "Autor & Filhos".to_owned(),
+            key_signature: KeySignature::G,
+            time_signature: TimeSignature::ThreeFour,
+            staves: vec![Staff::new(Instrument::VIOLIN)],
+            ..ScoreSettings::default()
+        }
+    }
+
+    #[test]
+    fn matches_sample_file() {
+        let score = Score {
+            notes: vec![
+                note(PitchClass::G, 4, DurationValue::Half),
+                note(PitchClass::A, 4, DurationValue::Half),
+                note(PitchClass::B, 4, DurationValue::Eighth),
+                NoteEvent {
+                    instrument: Instrument::CELLO,
+                    ..note(PitchClass::G, 2, DurationValue::Whole)
+                },
+            ],
+        };
+
+        let settings = ScoreSettings {
+            staves: [Instrument::VIOLIN, Instrument::CELLO, Instrument::FLUTE]
+                .into_iter()
+                .map(Staff::new)
+                .collect(),
+            ..sample_settings()
+        };
+
+        let xml = export_musicxml(&settings, &score);
+
+        assert_eq!(
+            xml,
+            include_str!("../tests/fixtures/musicxml/export_three_parts.musicxml")
+        );
+    }
+
+    #[test]
+    fn empty_score_still_has_one_measure_per_part() {
+        let settings = ScoreSettings {
+            staves: vec![Staff::new(Instrument::PIANO)],
+            ..ScoreSettings::default()
+        };
+        let xml = export_musicxml(&settings, &Score::default());
+
+        assert_eq!(xml.matches("<measure ").count(), 1);
+        assert!(xml.contains(r#"<rest measure="yes"/>"#));
+        assert!(xml.contains("<duration>32</duration>"));
+    }
+
+    #[test]
+    fn notes_crossing_barlines_are_tied() {
+        let score = Score {
+            notes: vec![
+                note(PitchClass::C, 5, DurationValue::Half),
+                note(PitchClass::D, 5, DurationValue::Half),
+            ],
+        };
+        let settings = ScoreSettings {
+            time_signature: TimeSignature::ThreeFour,
+            staves: vec![Staff::new(Instrument::VIOLIN)],
+            ..ScoreSettings::default()
+        };
+
+        let xml = export_musicxml(&settings, &score);
+
+        assert_eq!(xml.matches("<measure ").count(), 2);
+        assert_eq!(xml.matches(r#"<tied type="start"/>"#).count(), 1);
+        assert_eq!(xml.matches(r#"<tied type="stop"/>"#).count(), 1);
+    }
+
+    #[test]
+    fn mxl_container_has_mimetype_first_and_root_file() {
+        let score = Score {
+            notes: vec![note(PitchClass::E, 4, DurationValue::Quarter)],
+        };
+        let bytes = export_mxl(&sample_settings(), &score).unwrap();
+
+        let mut archive = zip::ZipArchive::new(std::io::Cursor::new(bytes)).unwrap();
+        assert_eq!(archive.by_index(0).unwrap().name(), "mimetype");
+
+        let mut container = String::new();
+        archive
+            .by_name("META-INF/container.xml")
+            .unwrap()
+            .read_to_string(&mut container)
+            .unwrap();
+        assert!(container.contains(r#"full-path="score.musicxml""#));
+
+        let mut document = String::new();
+        archive
+            .by_name("score.musicxml")
+            .unwrap()
+            .read_to_string(&mut document)
+            .unwrap();
+        assert_eq!(document, export_musicxml(&sample_settings(), &score));
+    }
+
+    fn import_sample() -> ImportedScore {
+        import_musicxml(include_str!(
+            "../tests/fixtures/musicxml/import_two_parts.musicxml"
+        ))
+        .unwrap()
+    }
+
+    fn part_notes(imported: &ImportedScore, instrument: Instrument) -> Vec<NoteEvent> {
+        imported
+            .score
+            .notes
+            .iter()
+            .filter(|note| note.instrument == instrument && note.voice == 0)
+            .cloned()
+            .collect()
+    }
+
+    #[test]
+    fn import_reads_header_into_settings() {
+        let imported = import_sample();
+
+        assert_eq!(imported.settings.title, "Romance em Mi bemol");
+        assert_eq!(imported.settings.composer, "Clara Exemplo");
+        assert_eq!(imported.settings.key_signature, KeySignature::Eb);
+        assert_eq!(imported.settings.time_signature, TimeSignature::ThreeFour);
+    }
+
+    #[test]
+    fn import_maps_part_list_to_instruments() {
+        let imported = import_sample();
+
+        assert_eq!(
+            imported.settings.parts(),
+            [Instrument::VIOLIN, Instrument::CELLO, Instrument::PIANO].map(Part::from)
+        );
+        assert!(imported
+            .log
+            .issues
+            .iter()
+            .any(|issue| issue.kind == ImportIssueKind::UnknownInstrument("Theremin".to_owned())));
+    }
+
+    #[test]
+    fn import_reads_pitches_durations_and_ties() {
+        let violin = part_notes(&import_sample(), Instrument::VIOLIN);
+
+        let e_flat = Pitch {
+            alter: -1,
+            ..Pitch::new(PitchClass::E, 5)
+        };
+        assert_eq!(
+            violin[0],
+            NoteEvent {
+                dots: 1,
+                dynamic: Some(Dynamic::P),
+                ..NoteEvent::new(e_flat, DurationValue::Quarter, Instrument::VIOLIN)
+            }
+        );
+        assert_eq!(violin[1].duration, DurationValue::Eighth);
+        assert_eq!(violin[1].articulations, vec![Articulation::Staccato]);
+        assert!(violin[2].tie);
+        assert_eq!(violin[3].pitch, Pitch::new(PitchClass::C, 5));
+        assert!(!violin[3].tie);
+        assert!(violin[4].rest);
+        assert_eq!(violin.len(), 5);
+    }
+
+    #[test]
+    fn import_gives_each_voice_its_own_stream() {
+        let imported = import_sample();
+        let second: Vec<&NoteEvent> = imported
+            .score
+            .notes
+            .iter()
+            .filter(|note| note.instrument == Instrument::VIOLIN && note.voice == 1)
+            .collect();
+
+        // The second voice only starts in measure 2, so it opens with a measure of rest.
+        assert!(second[0].rest);
+        assert_eq!(second[0].beats(), 3.0);
+        assert_eq!(second[1].pitch, Pitch::new(PitchClass::G, 4));
+        assert_eq!(second[1].duration, DurationValue::Half);
+        assert_eq!(second.len(), 2);
+    }
+
+    #[test]
+    fn second_voice_is_written_after_a_backup() {
+        let high = NoteEvent {
+            dots: 1,
+            ..note(PitchClass::E, 5, DurationValue::Half)
+        };
+        let low = NoteEvent {
+            voice: 1,
+            ..note(PitchClass::C, 4, DurationValue::Half)
+        };
+        let score = Score {
+            notes: vec![
+                high,
+                low.clone(),
+                NoteEvent {
+                    voice: 1,
+                    ..NoteEvent::rest(DurationValue::Quarter, low.part())
+                },
+            ],
+        };
+
+        let xml = export_musicxml(&sample_settings(), &score);
+        let imported = import_musicxml(&xml).unwrap();
+
+        assert!(xml.contains("<voice>2</voice>"));
+        assert_eq!(imported.score.notes, score.notes);
+    }
+
+    #[test]
+    fn grand_staff_is_one_part_with_two_staves() {
+        let piano = Part::from(Instrument::PIANO);
+        let quarter = |class, octave, part| {
+            NoteEvent::new(Pitch::new(class, octave), DurationValue::Quarter, part)
+        };
+        let settings = ScoreSettings {
+            staves: vec![Staff::new(piano), Staff::new(piano.on_staff(1))],
+            ..sample_settings()
+        };
+        let score = Score {
+            notes: vec![
+                quarter(PitchClass::E, 5, piano),
+                NoteEvent {
+                    cross_staff: 1,
+                    ..quarter(PitchClass::C, 3, piano)
+                },
+                quarter(PitchClass::G, 4, piano),
+                NoteEvent {
+                    dots: 1,
+                    ..NoteEvent::new(
+                        Pitch::new(PitchClass::C, 3),
+                        DurationValue::Half,
+                        piano.on_staff(1),
+                    )
+                },
+            ],
+        };
+
+        let xml = export_musicxml(&settings, &score);
+        let imported = import_musicxml(&xml).unwrap();
+
+        assert_eq!(xml.matches("<score-part ").count(), 1);
+        assert!(xml.contains("<staves>2</staves>"));
+        assert!(xml.contains("<voice>5</voice>"));
+        assert_eq!(imported.settings.staves, settings.staves);
+        assert_eq!(imported.score.notes, score.notes);
+    }
+
+    #[test]
+    fn import_splits_unwritable_durations_into_tied_values() {
+        let cello = part_notes(&import_sample(), Instrument::CELLO);
+
+        assert_eq!(cello[0].duration, DurationValue::Half);
+        assert_eq!(cello[2].duration, DurationValue::Half);
+        assert!(cello[2].tie);
+        assert_eq!(cello[3].duration, DurationValue::Eighth);
+        assert_eq!(cello[3].pitch, cello[2].pitch);
+        assert!(cello[4].rest);
+    }
+
+    #[test]
+    fn import_bounds_corrupt_durations_and_logs_unlisted_parts() {
+        let xml = r#"<?xml version="1.0" encoding="UTF-8"?>
+<score-partwise version="4.0">
+  <part-list><score-part id="P1"><part-name>Violin</part-name></score-part></part-list>
+  <part id="P1">
+    <measure number="1">
+      <attributes><divisions>1</divisions><time><beats>4</beats><beat-type>4</beat-type></time></attributes>
+      <note><rest/><duration>200000000</duration></note>
+    </measure>
+    <measure number="2">
+      <note><pitch><step>C</step><octave>5</octave></pitch><type>16th</type></note>
+      <note><pitch><step>D</step><octave>5</octave></pitch><duration>4</duration><type>whole</type></note>
+    </measure>
+  </part>
+  <part id="P9">
+    <measure number="1"><note><rest/><duration>4</duration></note></measure>
+  </part>
+</score-partwise>"#;
+
+        let imported = import_musicxml(xml).unwrap();
+
+        let violin: Vec<(bool, DurationValue)> = part_notes(&imported, Instrument::VIOLIN)
+            .iter()
+            .map(|note| (note.rest, note.duration))
+            .collect();
+        assert_eq!(
+            violin,
+            [(true, DurationValue::Whole), (false, DurationValue::Whole)]
+        );
+        let issue = |part: &str, measure: Option<&str>, kind| ImportIssue {
+            part: Some(part.to_owned()),
+            measure: measure.map(str::to_owned),
+            kind,
+        };
+        let log = &imported.log.issues;
+        assert!(log.contains(&issue(
+            "P1",
+            Some("1"),
+            ImportIssueKind::ApproximatedDuration {
+                duration: 200_000_000,
+                divisions: 1,
+            }
+        )));
+        assert!(log.contains(&issue(
+            "P1",
+            Some("2"),
+            ImportIssueKind::UnsupportedValue {
+                element: "duration".to_owned(),
+                value: String::new(),
+            }
+        )));
+        assert!(log.contains(&issue(
+            "P9",
+            None,
+            ImportIssueKind::UnknownInstrument("P9".to_owned())
+        )));
+        assert_eq!(part_notes(&imported, Instrument::PIANO).len(), 1);
+    }
+
+    #[test]
+    fn import_reports_unsupported_elements_with_location() {
+        let log = import_sample().log;
+        let has = |part: &str, measure: &str, kind: ImportIssueKind| {
+            log.issues.iter().any(|issue| {
+                issue.part.as_deref() == Some(part)
+                    && issue.measure.as_deref() == Some(measure)
+                    && issue.kind == kind
+            })
+        };
+        let unsupported = |name: &str| ImportIssueKind::UnsupportedElement(name.to_owned());
+
+        assert!(has("P1", "1", unsupported("chord")));
+        assert!(has("P1", "2", unsupported("barline")));
+        assert!(has(
+            "P3",
+            "1",
+            ImportIssueKind::IncompleteMeasure { missing_beats: 1.0 }
+        ));
+        assert!(log.issues.contains(&ImportIssue {
+            part: None,
+            measure: None,
+            kind: unsupported("movement-title"),
+        }));
+        assert!(log.issues.contains(&ImportIssue {
+            part: None,
+            measure: None,
+            kind: ImportIssueKind::UnsupportedValue {
+                element: "creator".to_owned(),
+                value: "lyricist".to_owned(),
+            },
+        }));
+    }
+
+    #[test]
+    fn export_then_import_round_trips_notes() {
+        let mut tied = note(PitchClass::F, 4, DurationValue::Quarter);
+        tied.pitch.alter = 1;
+        tied.tie = true;
+        let score = Score {
+            notes: vec![
+                NoteEvent {
+                    dots: 1,
+                    ..note(PitchClass::G, 4, DurationValue::Half)
+                },
+                tied,
+                NoteEvent {
+                    pitch: Pitch {
+                        alter: 1,
+                        ..Pitch::new(PitchClass::F, 4)
+                    },
+                    ..note(PitchClass::F, 4, DurationValue::Quarter)
+                },
+                NoteEvent::rest(DurationValue::Quarter, Instrument::VIOLIN),
+                note(PitchClass::C, 5, DurationValue::Sixteenth),
+            ],
+        };
+
+        let xml = export_musicxml(&sample_settings(), &score);
+        let imported = import_musicxml(&xml).unwrap();
+
+        let mut expected = score.notes.clone();
+        // The trailing rest pads the last bar: 3/4 minus the sixteenth.
+        for (value, dots) in DurationValue::decompose(2.75) {
+            expected.push(NoteEvent {
+                dots,
+                ..NoteEvent::rest(value, Instrument::VIOLIN)
+            });
+        }
+        assert_eq!(imported.score.notes, expected);
+        assert!(imported.log.is_empty(), "{:?}", imported.log);
+        assert_eq!(imported.settings, sample_settings());
+    }
+
+    #[test]
+    fn dynamics_and_wedges_round_trip() {
+        let mut notes = vec![note(PitchClass::C, 4, DurationValue::Quarter); 6];
+        notes[0].dynamic = Some(Dynamic::P);
+        notes[0].hairpin = Some(Hairpin::Crescendo);
+        notes[1].hairpin = Some(Hairpin::Crescendo);
+        notes[2].dynamic = Some(Dynamic::Ff);
+        notes[3].hairpin = Some(Hairpin::Diminuendo);
+        let score = Score { notes };
+
+        let xml = export_musicxml(&sample_settings(), &score);
+        assert!(xml.contains("<wedge type=\"crescendo\" number=\"1\"/>"));
+        assert_eq!(
+            xml.matches("<wedge type=\"stop\" number=\"1\"/>").count(),
+            2
+        );
+
+        let imported = import_musicxml(&xml).unwrap();
+        assert_eq!(part_notes(&imported, Instrument::VIOLIN), score.notes);
+    }
+
+    #[test]
+    fn tempo_marks_round_trip_through_the_first_part() {
+        let settings = ScoreSettings {
+            staves: vec![
+                Staff::new(Instrument::VIOLIN),
+                Staff::new(Instrument::CELLO),
+            ],
+            bpm: 72.0,
+            tempo_marks: vec![
+                TempoMark {
+                    beat: 3.0,
+                    text: "Allegro".to_owned(),
+                    bpm: Some(132.0),
+                    change: None,
+                },
+                TempoMark {
+                    beat: 7.5,
+                    text: String::new(),
+                    bpm: None,
+                    change: Some(TempoChange::Ritardando),
+                },
+            ],
+            ..sample_settings()
+        };
+        let score = Score {
+            notes: vec![
+                note(PitchClass::G, 4, DurationValue::Whole),
+                note(PitchClass::G, 4, DurationValue::Whole),
+                note(PitchClass::G, 4, DurationValue::Whole),
+            ],
+        };
+
+        let xml = export_musicxml(&settings, &score);
+        let imported = import_musicxml(&xml).unwrap();
+
+        assert_eq!(xml.matches("<words>").count(), 2);
+        assert!(xml.contains("<offset>12</offset>"));
+        assert_eq!(imported.settings.bpm, 72.0);
+        assert_eq!(imported.settings.tempo_marks, settings.tempo_marks);
+        assert!(imported.log.is_empty(), "{:?}", imported.log.issues);
+    }
+
+    #[test]
+    fn articulations_round_trip_on_the_first_written_value() {
+        let mut notes = vec![
+            note(PitchClass::D, 5, DurationValue::Half),
+            note(PitchClass::E, 5, DurationValue::Half),
+            NoteEvent::rest(DurationValue::Half, Instrument::VIOLIN),
+        ];
+        notes[0].articulations = vec![Articulation::Marcato, Articulation::Tenuto];
+        // Crosses the barline of 3/4, so it is written as two tied values.
+        notes[1].articulations = vec![Articulation::Accent, Articulation::Fermata];
+        notes[2].articulations = vec![Articulation::Fermata];
+        let score = Score { notes };
+
+        let xml = export_musicxml(&sample_settings(), &score);
+        assert!(xml.contains("<strong-accent type=\"up\"/>"));
+        assert_eq!(xml.matches("<fermata type=\"upright\"/>").count(), 2);
+
+        let imported = import_musicxml(&xml).unwrap();
+        let violin = part_notes(&imported, Instrument::VIOLIN);
+        let marks: Vec<&[Articulation]> =
+            violin.iter().map(|note| &note.articulations[..]).collect();
+        assert_eq!(
+            marks,
+            vec![
+                &[Articulation::Marcato, Articulation::Tenuto][..],
+                &[Articulation::Accent, Articulation::Fermata][..],
+                &[][..],
+                &[Articulation::Fermata][..],
+            ]
+        );
+    }
+
+    #[test]
+    fn lyrics_round_trip_with_verses_hyphens_and_extenders() {
+        let lyric = |verse, text: &str, syllabic, extend| Lyric {
+            verse,
+            text: text.to_owned(),
+            syllabic,
+            extend,
+        };
+        let mut notes = vec![
+            note(PitchClass::D, 5, DurationValue::Quarter),
+            note(PitchClass::E, 5, DurationValue::Quarter),
+            note(PitchClass::F, 5, DurationValue::Quarter),
+        ];
+        notes[0].lyrics = vec![
+            lyric(0, "Glo", Syllabic::Begin, false),
+            lyric(1, "Ky", Syllabic::Begin, false),
+        ];
+        notes[1].lyrics = vec![lyric(0, "ri", Syllabic::Middle, false)];
+        notes[2].lyrics = vec![lyric(0, "a", Syllabic::End, true)];
+        let score = Score { notes };
+
+        let xml = export_musicxml(&sample_settings(), &score);
+        assert!(xml.contains("<lyric number=\"2\">"));
+        assert_eq!(xml.matches("<extend/>").count(), 1);
+
+        let imported = import_musicxml(&xml).unwrap();
+        let violin = part_notes(&imported, Instrument::VIOLIN);
+        let lyrics: Vec<&[Lyric]> = violin.iter().map(|note| &note.lyrics[..]).collect();
+        let expected: Vec<&[Lyric]> = score.notes.iter().map(|note| &note.lyrics[..]).collect();
+        assert_eq!(lyrics, expected);
+    }
+
+    #[test]
+    fn chord_symbols_round_trip_as_harmony_at_written_pitch() {
+        let symbols = [
+            "Cmaj7",
+            "F#m7b5/C",
+            "Bb13sus4",
+            "C6/9",
+            "G7#9b13",
+            "Ebm(maj9)",
+            "Adim7",
+        ];
+        let notes: Vec<NoteEvent> = symbols
+            .iter()
+            .map(|symbol| NoteEvent {
+                chord: Some(symbol.parse().unwrap()),
+                ..NoteEvent::new(
+                    Pitch::new(PitchClass::C, 5),
+                    DurationValue::Quarter,
+                    Instrument::CLARINET,
+                )
+            })
+            .collect();
+        let settings = ScoreSettings {
+            staves: vec![Staff::new(Instrument::CLARINET)],
+            ..sample_settings()
+        };
+
+        let xml = export_musicxml(&settings, &Score { notes });
+        // The B♭ clarinet reads its Cmaj7 a tone higher.
+        assert!(xml.contains("<root-step>D</root-step>"));
+        assert!(xml.contains("<kind>major-seventh</kind>"));
+
+        let imported = import_musicxml(&xml).unwrap();
+        assert!(imported.log.is_empty(), "{:?}", imported.log);
+        let read: Vec<String> = part_notes(&imported, Instrument::CLARINET)
+            .iter()
+            .filter_map(|note| note.chord.as_ref().map(ToString::to_string))
+            .collect();
+        assert_eq!(read, symbols);
+    }
+
+    #[test]
+    fn mxl_round_trip_uses_container_root_file() {
+        let score = Score {
+            notes: vec![note(PitchClass::A, 4, DurationValue::Whole)],
+        };
+        let settings = ScoreSettings {
+            staves: vec![Staff::new(Instrument::VIOLIN)],
+            ..ScoreSettings::default()
+        };
+        let bytes = export_mxl(&settings, &score).unwrap();
+
+        let imported = import_mxl(&bytes).unwrap();
+
+        assert_eq!(imported.score.notes, score.notes);
+        assert_eq!(imported.settings.staves, settings.staves);
+    }
+
+    #[test]
+    fn parts_of_the_same_instrument_become_players() {
+        let second = Part {
+            player: 1,
+            ..Instrument::VIOLIN.into()
+        };
+        let settings = ScoreSettings {
+            staves: vec![
+                Staff::new(Instrument::VIOLIN),
+                Staff {
+                    name: "Violino solista".to_owned(),
+                    ..Staff::new(second)
+                },
+            ],
+            ..sample_settings()
+        };
+        let score = Score {
+            notes: vec![NoteEvent::new(
+                Pitch::new(PitchClass::A, 4),
+                DurationValue::Half,
+                second,
+            )],
+        };
+
+        let xml = export_musicxml(&settings, &score);
+        assert!(xml.contains("<part-name>Violino I</part-name>"));
+        assert!(xml.contains("<part-abbreviation>Vln. II</part-abbreviation>"));
+
+        let imported = import_musicxml(&xml).unwrap();
+        assert_eq!(imported.settings.staves, settings.staves);
+        assert_eq!(imported.score.notes[1].part(), second);
+        assert!(imported.log.is_empty(), "{:?}", imported.log);
+    }
+
+    #[test]
+    fn transposing_parts_are_written_and_read_back_at_sounding_pitch() {
+        let settings = ScoreSettings {
+            key_signature: KeySignature::Eb,
+            staves: vec![
+                Staff::new(Instrument::CLARINET),
+                Staff::new(Instrument::from_id("Contrabass").unwrap()),
+            ],
+            ..ScoreSettings::default()
+        };
+        let score = Score {
+            notes: vec![
+                NoteEvent::new(
+                    Pitch::new(PitchClass::C, 4),
+                    DurationValue::Whole,
+                    Instrument::CLARINET,
+                ),
+                NoteEvent::new(
+                    Pitch::new(PitchClass::C, 2),
+                    DurationValue::Whole,
+                    Instrument::from_id("Contrabass").unwrap(),
+                ),
+            ],
+        };
+
+        let xml = export_musicxml(&settings, &score);
+        assert!(xml.contains("<fifths>-1</fifths>"));
+        assert!(xml.contains("<step>D</step>"));
+        assert!(xml.contains("<octave-change>-1</octave-change>"));
+        assert!(xml.contains("<octave>3</octave>"));
+
+        let imported = import_musicxml(&xml).unwrap();
+        assert_eq!(imported.settings.key_signature, KeySignature::Eb);
+        assert_eq!(imported.score.notes, score.notes);
+        assert!(imported.log.is_empty(), "{:?}", imported.log);
+    }
+
+    #[test]
+    fn pickups_and_meter_changes_survive_a_round_trip() {
+        let settings = ScoreSettings {
+            time_signature: TimeSignature::ThreeFour,
+            meter_changes: vec![
+                MeterChange {
+                    measure: 0,
+                    meter: Meter {
+                        numerator: 1,
+                        denominator: 4,
+                    },
+                },
+                MeterChange {
+                    measure: 1,
+                    meter: TimeSignature::ThreeFour.into(),
+                },
+                MeterChange {
+                    measure: 2,
+                    meter: Meter {
+                        numerator: 5,
+                        denominator: 8,
+                    },
+                },
+            ],
+            staves: vec![Staff::new(Instrument::VIOLIN)],
+            ..ScoreSettings::default()
+        };
+        let score = Score {
+            notes: vec![
+                note(PitchClass::A, 4, DurationValue::Quarter),
+                note(PitchClass::B, 4, DurationValue::Half),
+                note(PitchClass::C, 5, DurationValue::Quarter),
+                note(PitchClass::D, 5, DurationValue::Half),
+                note(PitchClass::E, 5, DurationValue::Eighth),
+            ],
+        };
+
+        let xml = export_musicxml(&settings, &score);
+        assert!(xml.contains(r#"<measure number="0" implicit="yes">"#));
+        assert!(xml.contains("<beat-type>8</beat-type>"));
+
+        let imported = import_musicxml(&xml).unwrap();
+        assert_eq!(imported.settings.time_signature, TimeSignature::ThreeFour);
+        assert_eq!(imported.settings.meter_changes, settings.meter_changes);
+        assert_eq!(imported.score.notes, score.notes);
+        assert!(imported.log.is_empty(), "{:?}", imported.log);
+    }
+
+    #[test]
+    fn decode_document_handles_utf16_byte_order_mark() {
+        let mut bytes = vec![0xFF, 0xFE];
+        for unit in "<a>é</a>".encode_utf16() {
+            bytes.extend_from_slice(&unit.to_le_bytes());
+        }
+
+        assert_eq!(decode_document(&bytes).unwrap(), "<a>é</a>");
+    }
+}
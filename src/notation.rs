@@ -1,18 +1,82 @@
-use egui::{self, Align2, Color32, FontId, Pos2, Rect, Stroke, Vec2};
+use egui::{self, Align2, Color32, FontId, Pos2, Rect, Shape, Stroke, Vec2};
 
-use crate::music::{DurationValue, Instrument, NoteEvent, PitchClass, Score};
+use crate::harmony::ChordSymbol;
+use crate::layout::{
+    self, ArticulationLayout, ChordLayout, HairpinLayout, HitTarget, InsertPoint, LyricLayout,
+    NavigationLayout, NoteLayout, PageLayout, StaffLayout, CHORD_FONT_SIZE, LYRIC_FONT_SIZE,
+};
+use crate::measures::MeasureMap;
+use crate::music::{
+    self, Articulation, DurationValue, Hairpin, Meter, Navigation, NavigationMark, NoteEvent, Part,
+    RangeLevel, Score, Staff, TempoMark,
+};
+use crate::ranges;
+use crate::selection::Selection;
 
-pub fn draw_orchestral_page(
-    ui: &mut egui::Ui,
-    score: &Score,
-    instruments: &[Instrument],
-    page_label: &str,
-    zoom_percent: f32,
-) {
-    let zoom = (zoom_percent / 100.0).clamp(0.5, 2.0);
+const SELECTION_COLOR: Color32 = Color32::from_rgb(30, 90, 200);
+/// Notes no player can reach.
+const OUT_OF_RANGE_COLOR: Color32 = Color32::from_rgb(200, 30, 30);
+/// Notes beyond an amateur player but within a professional's range.
+const AMATEUR_RANGE_COLOR: Color32 = Color32::from_rgb(215, 120, 0);
+
+/// Pointer input on a page, already resolved against its layout.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PageInteraction {
+    Click {
+        target: Option<HitTarget>,
+        modifiers: egui::Modifiers,
+    },
+    /// A click on a staff while mouse insertion is on, with the pitch already
+    /// converted to sounding pitch.
+    Insert(InsertPoint),
+    /// A finished rubber-band drag, with the notes it touched.
+    Marquee {
+        notes: Vec<HitTarget>,
+        modifiers: egui::Modifiers,
+    },
+}
+
+pub struct PageView<'a> {
+    pub score: &'a Score,
+    pub staves: &'a [Staff],
+    pub measures: &'a MeasureMap,
+    pub tempo_marks: &'a [TempoMark],
+    pub navigation: &'a [NavigationMark],
+    pub selection: &'a Selection,
+    /// Note-input caret as staff and beat from the start of the score.
+    pub caret: Option<(usize, f32)>,
+    /// Syllable being typed, with the note and verse it goes under.
+    pub lyric_caret: Option<(usize, u8, &'a str)>,
+    /// Value of the note a click would insert, or `None` when clicks select.
+    pub insert_duration: Option<DurationValue>,
+    /// Shows sounding pitches instead of what transposing instruments read.
+    pub concert_pitch: bool,
+    /// Notes outside the ranges of this level are colored.
+    pub range_level: RangeLevel,
+    pub page: usize,
+    pub page_label: &'a str,
+    pub zoom_percent: f32,
+}
+
+pub fn draw_orchestral_page(ui: &mut egui::Ui, view: PageView<'_>) -> Option<PageInteraction> {
+    let zoom = (view.zoom_percent / 100.0).clamp(0.5, 2.0);
     let desired_size = Vec2::new((860.0 * zoom).max(ui.available_width()), 1180.0 * zoom);
-    let (rect, _) = ui.allocate_exact_size(desired_size, egui::Sense::hover());
+    let (rect, response) = ui.allocate_exact_size(desired_size, egui::Sense::click_and_drag());
     let painter = ui.painter_at(rect);
+    let parts: Vec<Part> = view.staves.iter().map(|staff| staff.part).collect();
+    let written;
+    let score = if view.concert_pitch {
+        view.score
+    } else {
+        written = written_score(view.score);
+        &written
+    };
+    let mut layout = PageLayout::new(rect, zoom, view.page, score, &parts, view.measures);
+    let end = view
+        .measures
+        .start(layout::measure_count(score, &parts, view.measures));
+    layout.place_tempo_marks(view.tempo_marks, end);
+    layout.place_navigation(view.navigation);
 
     painter.rect_filled(rect, 6.0, Color32::from_rgb(250, 248, 242));
     painter.rect_stroke(
@@ -25,49 +89,318 @@ pub fn draw_orchestral_page(
     painter.text(
         Pos2::new(rect.center().x, rect.top() + 36.0 * zoom),
         Align2::CENTER_CENTER,
-        "Notarium - Transposing Score",
+        if view.concert_pitch {
+            "Notarium - Concert Pitch Score"
+        } else {
+            "Notarium - Transposing Score"
+        },
         FontId::proportional(18.0 * zoom),
         Color32::BLACK,
     );
     painter.text(
         Pos2::new(rect.center().x, rect.top() + 64.0 * zoom),
         Align2::CENTER_CENTER,
-        page_label,
+        view.page_label,
         FontId::proportional(14.0 * zoom),
         Color32::DARK_GRAY,
     );
 
-    let mut y = rect.top() + 120.0 * zoom;
-    let left_name_x = rect.left() + 18.0 * zoom;
-    let staff_left = rect.left() + 95.0 * zoom;
-    let staff_right = rect.right() - 24.0 * zoom;
+    for measure in &layout.measures {
+        if view
+            .selection
+            .contains_measure(measure.staff, measure.measure)
+        {
+            painter.rect_filled(measure.rect, 2.0, SELECTION_COLOR.gamma_multiply(0.12));
+        }
+    }
 
-    for (idx, instrument) in instruments.iter().enumerate() {
-        let staff_rect = Rect::from_min_max(
-            Pos2::new(staff_left, y),
-            Pos2::new(staff_right, y + 54.0 * zoom),
-        );
+    for staff in &layout.staves {
+        draw_staff(&painter, staff.rect);
+        draw_measure_lines(&painter, staff.rect, layout.barlines());
 
-        draw_staff(&painter, staff_rect);
+        // A grand staff is named once, level with the middle of its brace.
+        if staff.part.sub_staff > 0 {
+            continue;
+        }
+        let name_y = layout
+            .braces
+            .iter()
+            .find(|brace| brace.top() == staff.rect.top())
+            .map_or(staff.rect.center().y, |brace| brace.center().y);
 
+        // Full names on the first page, abbreviations after it, clear of the
+        // brackets.
+        let (name, short_name) = view.staves[staff.staff].labels(view.staves);
         painter.text(
-            Pos2::new(left_name_x, y + 24.0 * zoom),
-            Align2::LEFT_CENTER,
-            instrument_short_name(*instrument),
+            Pos2::new(staff.name_rect.right() - 18.0 * zoom, name_y),
+            Align2::RIGHT_CENTER,
+            if view.page == 0 { name } else { short_name },
             FontId::proportional(14.0 * zoom),
             Color32::BLACK,
         );
+    }
+
+    // Barlines run through the gaps of each brace and bracket; the system
+    // line joins every staff.
+    for brace in &layout.braces {
+        draw_brace(&painter, *brace, zoom);
+        draw_measure_lines(&painter, *brace, layout.barlines());
+    }
+    for bracket in &layout.brackets {
+        draw_bracket(&painter, *bracket, 10.0 * zoom, 3.0 * zoom);
+        draw_measure_lines(&painter, *bracket, layout.barlines());
+    }
+    for bracket in &layout.sub_brackets {
+        draw_bracket(&painter, *bracket, 4.0 * zoom, 1.0 * zoom);
+    }
+    if let Some(system) = layout.system {
+        painter.line_segment(
+            [system.left_top(), system.left_bottom()],
+            Stroke::new(1.5 * zoom, Color32::BLACK),
+        );
+    }
+
+    for measure in &layout.measures {
+        let staff = layout.staves.iter().find(|s| s.staff == measure.staff);
+        if let (Some(staff), Some(meter)) = (staff, view.measures.printed_meter(measure.measure)) {
+            draw_meter(&painter, staff, measure.rect.left(), meter);
+        }
+    }
+
+    for note in &layout.notes {
+        let Some(staff) = layout.staves.iter().find(|s| s.staff == note.shown_staff) else {
+            continue;
+        };
+        let color = if view
+            .selection
+            .contains_note(note.index, note.staff, note.measure)
+        {
+            SELECTION_COLOR
+        } else {
+            // Ranges are in sounding pitch, whichever way the score is shown.
+            match ranges::beyond(&view.score.notes[note.index], view.range_level) {
+                Some(RangeLevel::Professional) => OUT_OF_RANGE_COLOR,
+                Some(RangeLevel::Amateur) => AMATEUR_RANGE_COLOR,
+                None => Color32::BLACK,
+            }
+        };
+        draw_event(&painter, staff, note, &score.notes[note.index], color);
+    }
+    for beam in &layout.beams {
+        painter.line_segment(*beam, Stroke::new(4.0 * zoom, Color32::BLACK));
+    }
+    for mark in &layout.articulations {
+        if let Some(staff) = layout.staves.iter().find(|s| s.staff == mark.staff) {
+            draw_articulation(&painter, mark, staff.line_spacing());
+        }
+    }
+    for dynamic in &layout.dynamics {
+        let galley = painter.layout_job(egui::text::LayoutJob::single_section(
+            dynamic.dynamic.label().to_owned(),
+            egui::TextFormat {
+                font_id: FontId::proportional(16.0 * zoom),
+                color: Color32::BLACK,
+                italics: true,
+                ..Default::default()
+            },
+        ));
+        let corner = dynamic.center - galley.size() / 2.0;
+        painter.galley(corner, galley, Color32::BLACK);
+    }
+    for hairpin in &layout.hairpins {
+        draw_hairpin(&painter, hairpin, 5.0 * zoom);
+    }
+    for tempo in &layout.tempos {
+        let text_end = tempo.anchor.map(|anchor| {
+            painter
+                .text(
+                    anchor,
+                    Align2::LEFT_BOTTOM,
+                    view.tempo_marks[tempo.mark].label(),
+                    FontId::proportional(15.0 * zoom),
+                    Color32::BLACK,
+                )
+                .right()
+                + 6.0 * zoom
+        });
+        if let (Some(to), Some(staff)) = (tempo.extend_to, layout.staves.first()) {
+            let from = Pos2::new(text_end.unwrap_or(staff.rect.left()), to.y - 5.0 * zoom);
+            if from.x < to.x {
+                painter.extend(Shape::dashed_line(
+                    &[from, Pos2::new(to.x, from.y)],
+                    Stroke::new(1.0, Color32::BLACK),
+                    6.0 * zoom,
+                    4.0 * zoom,
+                ));
+            }
+        }
+    }
+
+    for lyric in &layout.lyrics {
+        let text = score.notes[lyric.index]
+            .lyric(lyric.verse)
+            .map_or("", |lyric| &lyric.text);
+        let typing = view
+            .lyric_caret
+            .is_some_and(|(index, verse, _)| index == lyric.index && verse == lyric.verse);
+        draw_lyric(&painter, lyric, if typing { "" } else { text }, zoom);
+    }
+    if let Some((index, verse, text)) = view.lyric_caret {
+        if let Some(center) = layout.lyric_position(index, verse) {
+            let rect = painter.text(
+                center,
+                Align2::CENTER_CENTER,
+                text,
+                FontId::proportional(LYRIC_FONT_SIZE * zoom),
+                SELECTION_COLOR,
+            );
+            let x = if text.is_empty() {
+                center.x
+            } else {
+                rect.right() + 1.0
+            };
+            let half = LYRIC_FONT_SIZE * 0.5 * zoom;
+            painter.line_segment(
+                [Pos2::new(x, center.y - half), Pos2::new(x, center.y + half)],
+                Stroke::new(1.5, SELECTION_COLOR),
+            );
+        }
+    }
+
+    for chord in &layout.chords {
+        if let Some(symbol) = &score.notes[chord.index].chord {
+            draw_chord(&painter, chord, symbol, zoom);
+        }
+    }
+
+    for sign in &layout.navigation {
+        draw_navigation(
+            &painter,
+            sign,
+            view.navigation[sign.mark].navigation,
+            &layout.staves,
+            zoom,
+        );
+    }
 
-        draw_measure_lines(&painter, staff_rect, 6);
-        draw_notes_for_staff(&painter, staff_rect, score, idx, *instrument);
+    if let Some(line) = view
+        .caret
+        .and_then(|(staff, beat)| layout.caret_line(staff, beat))
+    {
+        // Drawn just ahead of the beat so it does not cover the notehead there.
+        let offset = Vec2::new(-7.0 * zoom, 0.0);
+        painter.line_segment(
+            [line[0] + offset, line[1] + offset],
+            Stroke::new(2.0, SELECTION_COLOR),
+        );
+    }
+
+    let insert_point = view.insert_duration.and_then(|duration| {
+        let pos = response.hover_pos()?;
+        layout.insert_point(pos, duration.beats())
+    });
+    if let (Some(point), Some(duration)) = (insert_point, view.insert_duration) {
+        if let Some(staff) = layout.staves.iter().find(|s| s.staff == point.staff) {
+            let ghost = SELECTION_COLOR.gamma_multiply(0.5);
+            draw_ledger_lines(&painter, staff, point.center, point.staff_step, ghost);
+            draw_notehead(
+                &painter,
+                point.center,
+                staff.line_spacing() * 0.4,
+                duration,
+                ghost,
+            );
+        }
+    }
 
-        y += 78.0 * zoom;
-        if y > rect.bottom() - 90.0 * zoom {
-            break;
+    if let Some(mut point) = insert_point.filter(|_| response.clicked()) {
+        if !view.concert_pitch {
+            let instrument = parts[point.staff].instrument;
+            point.pitch = instrument.sounding_pitch(point.pitch);
         }
+        return Some(PageInteraction::Insert(point));
+    }
+    page_interaction(ui, &response, &painter, &layout)
+}
+
+/// The score as the players read it; notes and chord symbols are stored
+/// at sounding pitch.
+fn written_score(score: &Score) -> Score {
+    Score {
+        notes: score
+            .notes
+            .iter()
+            .map(|note| NoteEvent {
+                pitch: note.instrument.written_pitch(note.pitch),
+                chord: note
+                    .chord
+                    .as_ref()
+                    .map(|chord| chord.transposed(|pitch| note.instrument.written_pitch(pitch))),
+                ..note.clone()
+            })
+            .collect(),
     }
 }
 
+fn page_interaction(
+    ui: &egui::Ui,
+    response: &egui::Response,
+    painter: &egui::Painter,
+    layout: &PageLayout,
+) -> Option<PageInteraction> {
+    let modifiers = ui.input(|input| input.modifiers);
+    let origin_id = response.id.with("marquee_origin");
+
+    if response.drag_started() {
+        if let Some(pos) = response.interact_pointer_pos() {
+            ui.data_mut(|data| data.insert_temp(origin_id, pos));
+        }
+    }
+
+    let marquee = ui
+        .data(|data| data.get_temp::<Pos2>(origin_id))
+        .zip(response.interact_pointer_pos())
+        .map(|(origin, pos)| Rect::from_two_pos(origin, pos));
+
+    if response.dragged() {
+        if let Some(area) = marquee {
+            painter.rect(
+                area,
+                0.0,
+                SELECTION_COLOR.gamma_multiply(0.08),
+                Stroke::new(1.0, SELECTION_COLOR),
+                egui::StrokeKind::Inside,
+            );
+        }
+    }
+
+    if response.drag_stopped() {
+        ui.data_mut(|data| data.remove::<Pos2>(origin_id));
+        let area = marquee?;
+        return Some(PageInteraction::Marquee {
+            notes: layout
+                .notes_in(area)
+                .map(|note| HitTarget::Note {
+                    index: note.index,
+                    staff: note.staff,
+                    measure: note.measure,
+                })
+                .collect(),
+            modifiers,
+        });
+    }
+
+    if response.clicked() {
+        let pos = response.interact_pointer_pos()?;
+        return Some(PageInteraction::Click {
+            target: layout.hit_test(pos),
+            modifiers,
+        });
+    }
+
+    None
+}
+
 fn draw_staff(painter: &egui::Painter, rect: Rect) {
     let spacing = rect.height() / 4.0;
     for i in 0..5 {
@@ -79,10 +412,10 @@ fn draw_staff(painter: &egui::Painter, rect: Rect) {
     }
 }
 
-fn draw_measure_lines(painter: &egui::Painter, rect: Rect, count: usize) {
-    let width = rect.width() / count as f32;
-    for i in 0..=count {
-        let x = rect.left() + i as f32 * width;
+/// Barlines down `rect` at the `barlines` offsets from its left edge.
+fn draw_measure_lines(painter: &egui::Painter, rect: Rect, barlines: &[f32]) {
+    for barline in barlines {
+        let x = rect.left() + barline;
         painter.line_segment(
             [Pos2::new(x, rect.top()), Pos2::new(x, rect.bottom())],
             Stroke::new(0.8, Color32::from_gray(120)),
@@ -90,84 +423,479 @@ fn draw_measure_lines(painter: &egui::Painter, rect: Rect, count: usize) {
     }
 }
 
-fn draw_notes_for_staff(
-    painter: &egui::Painter,
-    rect: Rect,
-    score: &Score,
-    staff_index: usize,
-    instrument: Instrument,
-) {
-    if score.notes.is_empty() {
-        return;
+/// A square bracket `offset` left of the staves in `rect`, with hooks
+/// bending right at both ends.
+fn draw_bracket(painter: &egui::Painter, rect: Rect, offset: f32, width: f32) {
+    let x = rect.left() - offset;
+    let overhang = offset.min(6.0 * width);
+    let stroke = Stroke::new(width, Color32::BLACK);
+    painter.line_segment(
+        [
+            Pos2::new(x, rect.top() - overhang / 2.0),
+            Pos2::new(x, rect.bottom() + overhang / 2.0),
+        ],
+        stroke,
+    );
+    for y in [rect.top() - overhang / 2.0, rect.bottom() + overhang / 2.0] {
+        painter.line_segment(
+            [Pos2::new(x, y), Pos2::new(x + overhang, y)],
+            Stroke::new(width * 0.6, Color32::BLACK),
+        );
     }
+}
 
-    let note_count = score.notes.len().min(16);
-    let spacing = (rect.width() - 18.0) / note_count as f32;
-
-    for i in 0..note_count {
-        let note = &score.notes[i];
-
-        if i % 4 == 0 && note.instrument != instrument {
-            continue;
+/// A curly brace just left of the staves of a grand staff.
+fn draw_articulation(painter: &egui::Painter, mark: &ArticulationLayout, spacing: f32) {
+    let center = mark.center;
+    let stroke = Stroke::new(1.4, Color32::BLACK);
+    // Points away from the note: up when the mark is above it.
+    let out = if mark.above { -1.0 } else { 1.0 };
+    let at = |x: f32, y: f32| center + Vec2::new(x * spacing, y * spacing * out);
+    let shape = match mark.articulation {
+        Articulation::Staccato => Shape::circle_filled(center, spacing * 0.15, Color32::BLACK),
+        Articulation::Staccatissimo => Shape::convex_polygon(
+            vec![at(-0.2, 0.3), at(0.2, 0.3), at(0.0, -0.3)],
+            Color32::BLACK,
+            Stroke::NONE,
+        ),
+        Articulation::Tenuto => Shape::line_segment([at(-0.55, 0.0), at(0.55, 0.0)], stroke),
+        Articulation::Accent => {
+            Shape::line(vec![at(-0.6, -0.3), at(0.6, 0.0), at(-0.6, 0.3)], stroke)
+        }
+        Articulation::Marcato => {
+            Shape::line(vec![at(-0.4, -0.4), at(0.0, 0.4), at(0.4, -0.4)], stroke)
         }
+        Articulation::Fermata => {
+            let arc = (0..=12)
+                .map(|step| {
+                    let angle = std::f32::consts::PI * step as f32 / 12.0;
+                    center + Vec2::new(-angle.cos(), -angle.sin()) * spacing
+                })
+                .collect();
+            let dot = center - Vec2::new(0.0, spacing * 0.2);
+            Shape::Vec(vec![
+                Shape::line(arc, stroke),
+                Shape::circle_filled(dot, spacing * 0.15, Color32::BLACK),
+            ])
+        }
+    };
+    painter.add(shape);
+}
 
-        let x = rect.left() + 12.0 + i as f32 * spacing;
-        let y = note_y(rect, note, staff_index);
+/// A wedge opening to `half_width` either side of its middle line at the loud end.
+fn draw_hairpin(painter: &egui::Painter, hairpin: &HairpinLayout, half_width: f32) {
+    let (tip, mouth) = match hairpin.hairpin {
+        Hairpin::Crescendo => (hairpin.from, hairpin.to),
+        Hairpin::Diminuendo => (hairpin.to, hairpin.from),
+    };
+    let stroke = Stroke::new(1.2, Color32::BLACK);
+    for side in [-half_width, half_width] {
+        painter.line_segment([tip, mouth + Vec2::new(0.0, side)], stroke);
+    }
+}
+
+/// A syllable with the hyphen or extender line after it.
+fn draw_lyric(painter: &egui::Painter, lyric: &LyricLayout, text: &str, zoom: f32) {
+    let rect = painter.text(
+        lyric.center,
+        Align2::CENTER_CENTER,
+        text,
+        FontId::proportional(LYRIC_FONT_SIZE * zoom),
+        Color32::BLACK,
+    );
+    let stroke = Stroke::new(1.0 * zoom, Color32::BLACK);
+    let from = rect.right().max(lyric.center.x + lyric.width / 2.0) + 2.0 * zoom;
+    if let Some(to) = lyric.hyphen_to.filter(|to| *to > from) {
+        let middle = (from + to) / 2.0;
+        let half = (4.0 * zoom).min((to - from) / 2.0);
+        painter.line_segment(
+            [
+                Pos2::new(middle - half, lyric.center.y),
+                Pos2::new(middle + half, lyric.center.y),
+            ],
+            stroke,
+        );
+    }
+    if let Some(to) = lyric.extend_to.filter(|to| *to > from) {
+        let y = rect.bottom() - 2.0 * zoom;
+        painter.line_segment([Pos2::new(from, y), Pos2::new(to, y)], stroke);
+    }
+}
 
-        draw_notehead(painter, Pos2::new(x, y), note.duration);
+/// A chord symbol: the root and triad, the extensions and alterations
+/// raised in a smaller size, then the bass.
+fn draw_chord(painter: &egui::Painter, chord: &ChordLayout, symbol: &ChordSymbol, zoom: f32) {
+    let label = symbol.label();
+    let size = CHORD_FONT_SIZE * zoom;
+    let mut x = painter
+        .text(
+            chord.anchor,
+            Align2::LEFT_BOTTOM,
+            &label.base,
+            FontId::proportional(size),
+            Color32::BLACK,
+        )
+        .right();
+    if !label.superscript.is_empty() {
+        x = painter
+            .text(
+                Pos2::new(x + zoom, chord.anchor.y - size * 0.4),
+                Align2::LEFT_BOTTOM,
+                &label.superscript,
+                FontId::proportional(size * 0.65),
+                Color32::BLACK,
+            )
+            .right();
+    }
+    if !label.bass.is_empty() {
+        painter.text(
+            Pos2::new(x + zoom, chord.anchor.y),
+            Align2::LEFT_BOTTOM,
+            &label.bass,
+            FontId::proportional(size),
+            Color32::BLACK,
+        );
+    }
+}
 
-        if needs_stem(note.duration) {
+/// A repeat barline with its dots on every staff, an ending's bracket and
+/// numbers, or a navigation sign or its words.
+fn draw_navigation(
+    painter: &egui::Painter,
+    sign: &NavigationLayout,
+    navigation: Navigation,
+    staves: &[StaffLayout],
+    zoom: f32,
+) {
+    let rect = sign.rect;
+    let stroke = Stroke::new(1.2 * zoom, Color32::BLACK);
+    match navigation {
+        Navigation::RepeatStart | Navigation::RepeatEnd { .. } => {
+            // The thick line is on the outside, the dots face the repeated music.
+            let inward = if navigation == Navigation::RepeatStart {
+                1.0
+            } else {
+                -1.0
+            };
+            let x = rect.left() + inward * 2.0 * zoom;
+            painter.line_segment(
+                [Pos2::new(x, rect.top()), Pos2::new(x, rect.bottom())],
+                Stroke::new(3.5 * zoom, Color32::BLACK),
+            );
+            let thin = x + inward * 5.0 * zoom;
             painter.line_segment(
-                [Pos2::new(x + 5.5, y), Pos2::new(x + 5.5, y - 25.0)],
-                Stroke::new(1.2, Color32::BLACK),
+                [Pos2::new(thin, rect.top()), Pos2::new(thin, rect.bottom())],
+                stroke,
+            );
+            for staff in staves {
+                let spacing = staff.line_spacing();
+                for space in [1.5, 2.5] {
+                    painter.circle_filled(
+                        Pos2::new(
+                            thin + inward * 4.0 * zoom,
+                            staff.rect.top() + space * spacing,
+                        ),
+                        1.8 * zoom,
+                        Color32::BLACK,
+                    );
+                }
+            }
+            if let Navigation::RepeatEnd { times } = navigation {
+                if times > 2 {
+                    painter.text(
+                        Pos2::new(x, rect.top() - 4.0 * zoom),
+                        Align2::RIGHT_BOTTOM,
+                        format!("{times}x"),
+                        FontId::proportional(12.0 * zoom),
+                        Color32::BLACK,
+                    );
+                }
+            }
+        }
+        Navigation::Ending { passes } => {
+            let mut line = vec![rect.left_bottom(), rect.left_top(), rect.right_top()];
+            if sign.closed {
+                line.push(rect.right_bottom());
+            }
+            painter.add(Shape::line(line, stroke));
+            painter.text(
+                rect.left_top() + Vec2::new(4.0 * zoom, 2.0 * zoom),
+                Align2::LEFT_TOP,
+                music::ending_numbers(passes),
+                FontId::proportional(12.0 * zoom),
+                Color32::BLACK,
             );
         }
+        Navigation::Segno => draw_segno(painter, rect.left_bottom(), rect.height() / 2.0, zoom),
+        Navigation::Coda => draw_coda(painter, rect.left_bottom(), rect.height() / 2.0, zoom),
+        words => {
+            let galley = painter.layout_job(egui::text::LayoutJob::single_section(
+                words.words().unwrap_or_default().to_owned(),
+                egui::TextFormat {
+                    font_id: FontId::proportional(14.0 * zoom),
+                    color: Color32::BLACK,
+                    italics: true,
+                    ..Default::default()
+                },
+            ));
+            let corner = rect.right_bottom() - galley.size();
+            painter.galley(corner, galley, Color32::BLACK);
+        }
+    }
+}
+
+/// A segno of height `2 * radius` standing on `base`, from its left edge.
+fn draw_segno(painter: &egui::Painter, base: Pos2, radius: f32, zoom: f32) {
+    let center = base + Vec2::new(radius * 0.6, -radius);
+    let at = |x: f32, y: f32| center + Vec2::new(x * radius, y * radius);
+    let stroke = Stroke::new(1.6 * zoom, Color32::BLACK);
+    painter.add(egui::epaint::CubicBezierShape::from_points_stroke(
+        [at(0.45, -0.6), at(-0.7, -1.2), at(-0.7, -0.1), at(0.0, 0.0)],
+        false,
+        Color32::TRANSPARENT,
+        stroke,
+    ));
+    painter.add(egui::epaint::CubicBezierShape::from_points_stroke(
+        [at(0.0, 0.0), at(0.7, 0.1), at(0.7, 1.2), at(-0.45, 0.6)],
+        false,
+        Color32::TRANSPARENT,
+        stroke,
+    ));
+    painter.line_segment([at(-0.6, 0.9), at(0.6, -0.9)], stroke);
+    for dot in [at(-0.5, -0.2), at(0.5, 0.2)] {
+        painter.circle_filled(dot, 1.6 * zoom, Color32::BLACK);
+    }
+}
+
+/// A coda sign of height `2 * radius` standing on `base`, from its left edge.
+fn draw_coda(painter: &egui::Painter, base: Pos2, radius: f32, zoom: f32) {
+    let center = base + Vec2::new(radius, -radius);
+    let stroke = Stroke::new(1.6 * zoom, Color32::BLACK);
+    painter.add(Shape::ellipse_stroke(
+        center,
+        Vec2::new(radius * 0.55, radius * 0.75),
+        stroke,
+    ));
+    painter.line_segment(
+        [
+            center - Vec2::new(radius, 0.0),
+            center + Vec2::new(radius, 0.0),
+        ],
+        stroke,
+    );
+    painter.line_segment(
+        [
+            center - Vec2::new(0.0, radius),
+            center + Vec2::new(0.0, radius),
+        ],
+        stroke,
+    );
+}
+
+fn draw_brace(painter: &egui::Painter, rect: Rect, zoom: f32) {
+    let x = rect.left() - 6.0 * zoom;
+    let width = 8.0 * zoom;
+    let middle = rect.center().y;
+    let stroke = Stroke::new(2.0 * zoom, Color32::BLACK);
+    // Two halves meeting in a point at the middle.
+    for end in [rect.top(), rect.bottom()] {
+        let quarter = (middle - end) / 4.0;
+        painter.add(egui::epaint::CubicBezierShape::from_points_stroke(
+            [
+                Pos2::new(x, end),
+                Pos2::new(x - width, end + quarter * 0.5),
+                Pos2::new(x + width * 0.3, middle - quarter * 1.5),
+                Pos2::new(x - width, middle),
+            ],
+            false,
+            Color32::TRANSPARENT,
+            stroke,
+        ));
+    }
+}
+
+/// Numerator over denominator, just right of the barline at `x`.
+fn draw_meter(painter: &egui::Painter, staff: &StaffLayout, x: f32, meter: Meter) {
+    let spacing = staff.line_spacing();
+    let x = x + spacing * 0.3;
+    for (number, y) in [
+        (meter.numerator, staff.rect.top() + spacing),
+        (meter.denominator, staff.rect.bottom() - spacing),
+    ] {
+        painter.text(
+            Pos2::new(x, y),
+            Align2::LEFT_CENTER,
+            number.to_string(),
+            FontId::proportional(spacing * 2.0),
+            Color32::BLACK,
+        );
+    }
+}
+
+fn draw_event(
+    painter: &egui::Painter,
+    staff: &StaffLayout,
+    layout: &NoteLayout,
+    note: &NoteEvent,
+    color: Color32,
+) {
+    let spacing = staff.line_spacing();
+    let center = layout.center;
+
+    if note.rest {
+        draw_rest(painter, center, spacing, note.duration, color);
+        return;
+    }
 
-        if note.pitch.class == PitchClass::C && note.pitch.octave >= 5 {
+    draw_ledger_lines(painter, staff, center, layout.staff_step, Color32::BLACK);
+    draw_notehead(painter, center, spacing * 0.4, note.duration, color);
+
+    if note.pitch.alter != 0 {
+        let sign = match note.pitch.alter {
+            1 => "#",
+            2 => "x",
+            -1 => "b",
+            _ => "bb",
+        };
+        painter.text(
+            Pos2::new(center.x - spacing * 0.9, center.y),
+            Align2::RIGHT_CENTER,
+            sign,
+            FontId::proportional(spacing * 1.2),
+            color,
+        );
+    }
+
+    if let Some(tip) = layout.stem_end {
+        // Beamed: the beam replaces the flags.
+        painter.line_segment([Pos2::new(tip.x, center.y), tip], Stroke::new(1.2, color));
+    } else if needs_stem(note.duration) {
+        // Alone, notes on or above the middle line take their stem down on
+        // the left; voices sharing a measure keep their own direction.
+        let (x, direction) = if layout.stem_down() {
+            (center.x - spacing * 0.4, 1.0)
+        } else {
+            (center.x + spacing * 0.4, -1.0)
+        };
+        let tip = Pos2::new(x, center.y + direction * spacing * 3.5);
+        painter.line_segment([Pos2::new(x, center.y), tip], Stroke::new(1.2, color));
+
+        let flags = match note.duration {
+            DurationValue::Eighth => 1,
+            DurationValue::Sixteenth => 2,
+            _ => 0,
+        };
+        for flag in 0..flags {
+            let start = Pos2::new(tip.x, tip.y - direction * flag as f32 * spacing * 0.6);
             painter.line_segment(
-                [Pos2::new(x - 10.0, y), Pos2::new(x + 10.0, y)],
-                Stroke::new(1.0, Color32::BLACK),
+                [
+                    start,
+                    start + Vec2::new(spacing * 0.7, -direction * spacing * 0.9),
+                ],
+                Stroke::new(1.2, color),
             );
         }
     }
+
+    for dot in 0..note.dots {
+        painter.circle_filled(
+            Pos2::new(
+                center.x + spacing * (0.9 + dot as f32 * 0.4),
+                center.y - spacing * 0.25,
+            ),
+            spacing * 0.1,
+            color,
+        );
+    }
 }
 
-fn draw_notehead(painter: &egui::Painter, center: Pos2, duration: DurationValue) {
+fn draw_ledger_lines(
+    painter: &egui::Painter,
+    staff: &StaffLayout,
+    center: Pos2,
+    staff_step: i32,
+    color: Color32,
+) {
+    let spacing = staff.line_spacing();
+    let steps = if staff_step <= -2 {
+        (staff_step..=-2).collect::<Vec<_>>()
+    } else if staff_step >= 10 {
+        (10..=staff_step).collect()
+    } else {
+        Vec::new()
+    };
+
+    for step in steps.into_iter().filter(|step| step % 2 == 0) {
+        let y = staff.rect.bottom() - step as f32 * spacing / 2.0;
+        painter.line_segment(
+            [
+                Pos2::new(center.x - spacing * 0.75, y),
+                Pos2::new(center.x + spacing * 0.75, y),
+            ],
+            Stroke::new(1.0, color),
+        );
+    }
+}
+
+fn draw_notehead(
+    painter: &egui::Painter,
+    center: Pos2,
+    radius: f32,
+    duration: DurationValue,
+    color: Color32,
+) {
     let fill = if duration == DurationValue::Whole || duration == DurationValue::Half {
         Color32::WHITE
     } else {
-        Color32::BLACK
+        color
     };
 
-    painter.circle_filled(center, 5.4, fill);
-    painter.circle_stroke(center, 5.4, Stroke::new(1.2, Color32::BLACK));
+    painter.circle_filled(center, radius, fill);
+    painter.circle_stroke(center, radius, Stroke::new(1.2, color));
 }
 
-fn note_y(rect: Rect, note: &NoteEvent, staff_index: usize) -> f32 {
-    let midi = (note.pitch.octave as i32 + 1) * 12 + note.pitch.class.semitone_offset();
-    let e4_midi = 64;
-    let half_steps = midi - e4_midi;
-    let base = rect.center().y + (staff_index % 3) as f32 * 1.0;
-    base + 14.0 - half_steps as f32 * 2.5
+fn draw_rest(
+    painter: &egui::Painter,
+    center: Pos2,
+    spacing: f32,
+    duration: DurationValue,
+    color: Color32,
+) {
+    match duration {
+        // Whole rests hang below the fourth line, half rests sit on the middle line.
+        DurationValue::Whole | DurationValue::Half => {
+            let top = if duration == DurationValue::Whole {
+                center.y - spacing
+            } else {
+                center.y - spacing * 0.5
+            };
+            painter.rect_filled(
+                Rect::from_min_size(
+                    Pos2::new(center.x - spacing * 0.5, top),
+                    Vec2::new(spacing, spacing * 0.5),
+                ),
+                0.0,
+                color,
+            );
+        }
+        _ => {
+            painter.line_segment(
+                [
+                    Pos2::new(center.x, center.y - spacing * 1.2),
+                    Pos2::new(center.x, center.y + spacing * 1.2),
+                ],
+                Stroke::new(spacing * 0.3, color),
+            );
+        }
+    }
 }
 
 fn needs_stem(duration: DurationValue) -> bool {
     matches!(
         duration,
-        DurationValue::Half | DurationValue::Quarter | DurationValue::Eighth
+        DurationValue::Half
+            | DurationValue::Quarter
+            | DurationValue::Eighth
+            | DurationValue::Sixteenth
     )
 }
-
-fn instrument_short_name(instrument: Instrument) -> &'static str {
-    match instrument {
-        Instrument::Violin => "Vln.",
-        Instrument::Viola => "Vla.",
-        Instrument::Cello => "Vcl.",
-        Instrument::Flute => "Fl.",
-        Instrument::Clarinet => "Cl.",
-        Instrument::Trumpet => "Tpt.",
-        Instrument::Horn => "Hn.",
-        Instrument::Timpani => "Tmp.",
-        Instrument::Piano => "Pno.",
-    }
-}
@@ -0,0 +1,218 @@
+//! Changing and deleting events that are already in the score.
+
+use crate::clipboard;
+use crate::music::{Articulation, DurationValue, NoteEvent, Part, PitchClass, Score};
+use crate::rebar;
+
+/// Properties to overwrite on existing events; `None` leaves one untouched.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct NoteChange {
+    pub pitch: Option<PitchClass>,
+    pub octave: Option<i8>,
+    pub duration: Option<DurationValue>,
+    /// Moves the events to another staff.
+    pub part: Option<Part>,
+}
+
+impl NoteChange {
+    /// Giving a rest a pitch turns it into a note; a new octave alone does not.
+    pub fn apply(&self, note: &NoteEvent) -> NoteEvent {
+        let mut note = note.clone();
+        if let Some(class) = self.pitch {
+            note.pitch.class = class;
+            note.pitch.alter = 0;
+            note.rest = false;
+        }
+        if let Some(octave) = self.octave {
+            note.pitch.octave = octave;
+        }
+        if let Some(duration) = self.duration {
+            note.duration = duration;
+            note.dots = 0;
+        }
+        if let Some(part) = self.part {
+            note.set_part(part);
+        }
+        note
+    }
+}
+
+/// What happens to the time a deleted event occupied.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DeleteMode {
+    /// Leave rests of the same length so the measures stay full.
+    #[default]
+    Rests,
+    /// Close the gap by moving the following events of the staff back.
+    Shift,
+}
+
+impl DeleteMode {
+    pub const ALL: [Self; 2] = [Self::Rests, Self::Shift];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Rests => "Substituir por pausas",
+            Self::Shift => "Deslocar notas seguintes",
+        }
+    }
+}
+
+/// The score with `change` applied to the events at `indices`.
+pub fn change(score: &Score, indices: &[usize], change: NoteChange) -> Vec<NoteEvent> {
+    let mut notes = score.notes.clone();
+    for index in indices {
+        if let Some(note) = notes.get_mut(*index) {
+            *note = change.apply(note);
+        }
+    }
+    rebar::clear_broken_ties(&mut notes);
+    notes
+}
+
+/// Adds `articulation` to the events at `indices`, or takes it off them when
+/// they all have it already. Rests only take a fermata.
+pub fn toggle_articulation(
+    score: &Score,
+    indices: &[usize],
+    articulation: Articulation,
+) -> Vec<NoteEvent> {
+    let mut notes = score.notes.clone();
+    let marked: Vec<usize> = indices
+        .iter()
+        .copied()
+        .filter(|index| {
+            notes
+                .get(*index)
+                .is_some_and(|note| !note.rest || articulation == Articulation::Fermata)
+        })
+        .collect();
+    let remove = marked
+        .iter()
+        .all(|index| notes[*index].articulations.contains(&articulation));
+    for index in marked {
+        let articulations = &mut notes[index].articulations;
+        if remove {
+            articulations.retain(|mark| *mark != articulation);
+        } else if !articulations.contains(&articulation) {
+            articulations.push(articulation);
+        }
+    }
+    notes
+}
+
+/// The score without the events at `indices`.
+pub fn delete(score: &Score, indices: &[usize], mode: DeleteMode) -> Vec<NoteEvent> {
+    match mode {
+        DeleteMode::Rests => clipboard::silence(score, indices),
+        DeleteMode::Shift => {
+            let mut notes: Vec<NoteEvent> = score
+                .notes
+                .iter()
+                .enumerate()
+                .filter(|(index, _)| !indices.contains(index))
+                .map(|(_, note)| note.clone())
+                .collect();
+            rebar::clear_broken_ties(&mut notes);
+            notes
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::music::{Instrument, Pitch};
+
+    fn violin(class: PitchClass, duration: DurationValue) -> NoteEvent {
+        NoteEvent::new(Pitch::new(class, 4), duration, Instrument::VIOLIN)
+    }
+
+    #[test]
+    fn changes_only_the_given_properties() {
+        let score = Score {
+            notes: vec![
+                NoteEvent {
+                    dots: 1,
+                    ..violin(PitchClass::C, DurationValue::Quarter)
+                },
+                NoteEvent::rest(DurationValue::Half, Instrument::VIOLIN),
+            ],
+        };
+
+        let notes = change(
+            &score,
+            &[0, 1],
+            NoteChange {
+                pitch: Some(PitchClass::G),
+                part: Some(Instrument::FLUTE.into()),
+                ..NoteChange::default()
+            },
+        );
+
+        assert_eq!(notes[0].pitch, Pitch::new(PitchClass::G, 4));
+        assert_eq!(notes[0].dots, 1);
+        assert_eq!(notes[0].instrument, Instrument::FLUTE);
+        assert!(!notes[1].rest);
+        assert_eq!(notes[1].duration, DurationValue::Half);
+
+        let octave = NoteChange {
+            octave: Some(6),
+            ..NoteChange::default()
+        };
+        assert!(octave.apply(&score.notes[1]).rest);
+    }
+
+    #[test]
+    fn articulations_toggle_on_notes_and_fermatas_on_rests() {
+        let score = Score {
+            notes: vec![
+                NoteEvent {
+                    articulations: vec![Articulation::Accent],
+                    ..violin(PitchClass::C, DurationValue::Quarter)
+                },
+                violin(PitchClass::D, DurationValue::Quarter),
+                NoteEvent::rest(DurationValue::Half, Instrument::VIOLIN),
+            ],
+        };
+
+        let accented = toggle_articulation(&score, &[0, 1, 2], Articulation::Accent);
+        assert_eq!(accented[1].articulations, vec![Articulation::Accent]);
+        assert!(accented[2].articulations.is_empty());
+
+        let cleared =
+            toggle_articulation(&Score { notes: accented }, &[0, 1], Articulation::Accent);
+        assert!(cleared.iter().all(|note| note.articulations.is_empty()));
+
+        let held = toggle_articulation(&score, &[2], Articulation::Fermata);
+        assert_eq!(held[2].articulations, vec![Articulation::Fermata]);
+    }
+
+    #[test]
+    fn deleting_keeps_bars_full_or_shifts_the_rest_back() {
+        let score = Score {
+            notes: vec![
+                NoteEvent {
+                    tie: true,
+                    ..violin(PitchClass::C, DurationValue::Quarter)
+                },
+                violin(PitchClass::C, DurationValue::Quarter),
+                violin(PitchClass::E, DurationValue::Half),
+            ],
+        };
+
+        let rests = delete(&score, &[1], DeleteMode::Rests);
+        assert_eq!(rests.len(), 3);
+        assert!(rests[1].rest);
+        assert!(!rests[0].tie);
+
+        let shifted = delete(&score, &[1], DeleteMode::Shift);
+        assert_eq!(
+            shifted,
+            vec![
+                violin(PitchClass::C, DurationValue::Quarter),
+                violin(PitchClass::E, DurationValue::Half),
+            ]
+        );
+    }
+}
@@ -0,0 +1,547 @@
+//! Keyboard note entry: a caret on one staff and the keys that write at it.
+//!
+//! | Key                | Action                                         |
+//! |--------------------|------------------------------------------------|
+//! | `A`–`G`            | note of that step, octave nearest the previous |
+//! | `0`                | rest                                           |
+//! | `3`–`7`            | sixteenth, eighth, quarter, half, whole        |
+//! | `.`                | one more dot (wraps back to none)              |
+//! | `←` / `→`          | caret to the previous / next event             |
+//! | `↑` / `↓`          | last note a semitone up / down                 |
+//! | `Ctrl+↑` / `Ctrl+↓`| last note an octave up / down                  |
+//! | `Alt+1`–`Alt+4`    | write in voice 1 to 4                          |
+
+use crate::music::{
+    DurationValue, NoteEvent, Part, Pitch, PitchClass, Score, ScoreSettings, BEAT_EPSILON,
+};
+use crate::rebar;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputKey {
+    Pitch(PitchClass),
+    Rest,
+    Duration(DurationValue),
+    Dot,
+    Left,
+    Right,
+    SemitoneUp,
+    SemitoneDown,
+    OctaveUp,
+    OctaveDown,
+    Voice(u8),
+}
+
+impl InputKey {
+    pub fn from_key(key: egui::Key, modifiers: egui::Modifiers) -> Option<Self> {
+        use egui::Key;
+
+        if modifiers.command {
+            return match key {
+                Key::ArrowUp => Some(Self::OctaveUp),
+                Key::ArrowDown => Some(Self::OctaveDown),
+                _ => None,
+            };
+        }
+        if modifiers.alt {
+            return match key {
+                Key::Num1 => Some(Self::Voice(0)),
+                Key::Num2 => Some(Self::Voice(1)),
+                Key::Num3 => Some(Self::Voice(2)),
+                Key::Num4 => Some(Self::Voice(3)),
+                _ => None,
+            };
+        }
+
+        Some(match key {
+            Key::A => Self::Pitch(PitchClass::A),
+            Key::B => Self::Pitch(PitchClass::B),
+            Key::C => Self::Pitch(PitchClass::C),
+            Key::D => Self::Pitch(PitchClass::D),
+            Key::E => Self::Pitch(PitchClass::E),
+            Key::F => Self::Pitch(PitchClass::F),
+            Key::G => Self::Pitch(PitchClass::G),
+            Key::Num0 => Self::Rest,
+            Key::Num3 => Self::Duration(DurationValue::Sixteenth),
+            Key::Num4 => Self::Duration(DurationValue::Eighth),
+            Key::Num5 => Self::Duration(DurationValue::Quarter),
+            Key::Num6 => Self::Duration(DurationValue::Half),
+            Key::Num7 => Self::Duration(DurationValue::Whole),
+            Key::Period => Self::Dot,
+            Key::ArrowLeft => Self::Left,
+            Key::ArrowRight => Self::Right,
+            Key::ArrowUp => Self::SemitoneUp,
+            Key::ArrowDown => Self::SemitoneDown,
+            _ => return None,
+        })
+    }
+}
+
+/// Where the next note goes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Caret {
+    pub staff: usize,
+    /// Beats from the start of the score.
+    pub beat: f32,
+}
+
+/// A score change requested by a key, for the caller to record in the history.
+#[derive(Debug, Clone, PartialEq)]
+pub enum InputEdit {
+    Replace { index: usize, after: NoteEvent },
+    Rewrite(Vec<NoteEvent>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct NoteInput {
+    pub caret: Option<Caret>,
+    pub duration: DurationValue,
+    pub dots: u8,
+    /// Voice of the staff that new notes go to.
+    pub voice: u8,
+}
+
+impl Default for NoteInput {
+    fn default() -> Self {
+        Self {
+            caret: None,
+            duration: DurationValue::Quarter,
+            dots: 0,
+            voice: 0,
+        }
+    }
+}
+
+impl NoteInput {
+    pub fn is_active(&self) -> bool {
+        self.caret.is_some()
+    }
+
+    pub fn start(&mut self, staff: usize, beat: f32) {
+        self.caret = Some(Caret { staff, beat });
+    }
+
+    pub fn stop(&mut self) {
+        self.caret = None;
+    }
+
+    /// Describes the current input value, e.g. for the status bar.
+    pub fn describe(&self) -> String {
+        let value = match self.dots {
+            0 => self.duration.label().to_owned(),
+            1 => format!("{} pontuada", self.duration.label()),
+            _ => format!("{} duplamente pontuada", self.duration.label()),
+        };
+        match self.voice {
+            0 => value,
+            voice => format!("{value}, voz {}", voice + 1),
+        }
+    }
+
+    pub fn apply(
+        &mut self,
+        key: InputKey,
+        score: &Score,
+        parts: &[Part],
+        settings: &ScoreSettings,
+    ) -> Option<InputEdit> {
+        let caret = self.caret?;
+        let part = *parts.get(caret.staff)?;
+        let stream = stream_with_indices(score, part, self.voice);
+
+        match key {
+            InputKey::Voice(voice) => {
+                self.voice = voice.min(NoteEvent::VOICES - 1);
+                None
+            }
+            InputKey::Duration(duration) => {
+                self.duration = duration;
+                self.dots = 0;
+                None
+            }
+            InputKey::Dot => {
+                self.dots = (self.dots + 1) % (DurationValue::MAX_DOTS + 1);
+                None
+            }
+            InputKey::Left => {
+                let previous = stream
+                    .iter()
+                    .rev()
+                    .find(|(_, start)| *start < caret.beat - BEAT_EPSILON);
+                self.move_caret(previous.map_or(0.0, |(_, start)| *start));
+                None
+            }
+            InputKey::Right => {
+                let next = stream
+                    .iter()
+                    .map(|(index, start)| start + score.notes[*index].beats())
+                    .find(|end| *end > caret.beat + BEAT_EPSILON);
+                if let Some(end) = next {
+                    self.move_caret(end);
+                }
+                None
+            }
+            InputKey::Pitch(class) => {
+                let octave = match previous_pitch(score, &stream, caret.beat) {
+                    Some(previous) => nearest_octave(class, previous),
+                    None => nearest_octave(class, middle_line(part)),
+                };
+                let pitch = Pitch {
+                    class,
+                    alter: settings.key_signature.alteration(class),
+                    octave,
+                };
+                Some(self.write(
+                    score,
+                    part,
+                    NoteEvent::new(pitch, self.duration, part),
+                    settings,
+                ))
+            }
+            InputKey::Rest => {
+                let rest = NoteEvent::rest(self.duration, part);
+                Some(self.write(score, part, rest, settings))
+            }
+            InputKey::SemitoneUp
+            | InputKey::SemitoneDown
+            | InputKey::OctaveUp
+            | InputKey::OctaveDown => {
+                let (index, _) = *stream
+                    .iter()
+                    .rev()
+                    .find(|(_, start)| *start < caret.beat - BEAT_EPSILON)?;
+                let before = &score.notes[index];
+                if before.rest {
+                    return None;
+                }
+                let midi = before.pitch.midi_number();
+                let pitch = match key {
+                    InputKey::SemitoneUp => Pitch::from_midi(midi + 1, false),
+                    InputKey::SemitoneDown => Pitch::from_midi(midi - 1, true),
+                    InputKey::OctaveUp => Pitch {
+                        octave: before.pitch.octave.checked_add(1)?,
+                        ..before.pitch
+                    },
+                    _ => Pitch {
+                        octave: before.pitch.octave.checked_sub(1)?,
+                        ..before.pitch
+                    },
+                };
+                // Playback and layout only know the MIDI notes.
+                if !(0..=127).contains(&pitch.midi_number()) {
+                    return None;
+                }
+                Some(InputEdit::Replace {
+                    index,
+                    after: NoteEvent {
+                        pitch,
+                        ..before.clone()
+                    },
+                })
+            }
+        }
+    }
+
+    /// Writes a note at `beat` of `staff`, as a click on the staff does. The
+    /// key signature is applied to `pitch` and the caret ends up after the note.
+    pub fn insert_at(
+        &mut self,
+        staff: usize,
+        beat: f32,
+        pitch: Pitch,
+        score: &Score,
+        parts: &[Part],
+        settings: &ScoreSettings,
+    ) -> Option<InputEdit> {
+        let part = *parts.get(staff)?;
+        self.start(staff, beat);
+        let pitch = Pitch {
+            alter: settings.key_signature.alteration(pitch.class),
+            ..pitch
+        };
+        let note = NoteEvent::new(pitch, self.duration, part);
+        Some(self.write(score, part, note, settings))
+    }
+
+    fn move_caret(&mut self, beat: f32) {
+        if let Some(caret) = &mut self.caret {
+            caret.beat = beat.max(0.0);
+        }
+    }
+
+    /// Writes `event` at the caret, over whatever was there, and moves past it.
+    fn write(
+        &mut self,
+        score: &Score,
+        part: Part,
+        event: NoteEvent,
+        settings: &ScoreSettings,
+    ) -> InputEdit {
+        let event = NoteEvent {
+            dots: self.dots,
+            voice: self.voice,
+            ..event
+        };
+        let beat = self.caret.map_or(0.0, |caret| caret.beat);
+        let stream = rebar::overwrite(
+            &rebar::voice_stream(&score.notes, part, self.voice),
+            part,
+            beat,
+            std::slice::from_ref(&event),
+            &settings.measures(),
+        );
+        self.move_caret(beat + event.beats());
+        InputEdit::Rewrite(rebar::replace_stream(
+            &score.notes,
+            part,
+            self.voice,
+            stream,
+        ))
+    }
+}
+
+/// Indices of the events of one voice of `part` in `score.notes`, with
+/// their start beats.
+fn stream_with_indices(score: &Score, part: Part, voice: u8) -> Vec<(usize, f32)> {
+    let mut position = 0.0;
+    score
+        .notes
+        .iter()
+        .enumerate()
+        .filter(|(_, note)| note.part() == part && note.voice == voice)
+        .map(|(index, note)| {
+            let start = position;
+            position += note.beats();
+            (index, start)
+        })
+        .collect()
+}
+
+/// The last sounding pitch before `beat`.
+fn previous_pitch(score: &Score, stream: &[(usize, f32)], beat: f32) -> Option<Pitch> {
+    stream
+        .iter()
+        .rev()
+        .filter(|(_, start)| *start < beat - BEAT_EPSILON)
+        .map(|(index, _)| &score.notes[*index])
+        .find(|note| !note.rest)
+        .map(|note| note.pitch)
+}
+
+fn middle_line(part: Part) -> Pitch {
+    let bottom = part.clef().bottom_line();
+    let steps = bottom.diatonic_number() + 4;
+    Pitch::new(
+        PitchClass::ALL[steps.rem_euclid(7) as usize],
+        steps.div_euclid(7) as i8,
+    )
+}
+
+/// Octave that puts `class` closest on the staff to `previous`.
+fn nearest_octave(class: PitchClass, previous: Pitch) -> i8 {
+    (previous.octave - 1..=previous.octave + 1)
+        .min_by_key(|octave| {
+            (Pitch::new(class, *octave).diatonic_number() - previous.diatonic_number()).abs()
+        })
+        .unwrap_or(previous.octave)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::music::{Instrument, KeySignature};
+
+    fn entry(staff: usize) -> NoteInput {
+        let mut input = NoteInput::default();
+        input.start(staff, 0.0);
+        input
+    }
+
+    fn rewrite(edit: Option<InputEdit>) -> Vec<NoteEvent> {
+        match edit {
+            Some(InputEdit::Rewrite(notes)) => notes,
+            other => panic!("expected a rewrite, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn letters_pick_the_nearest_octave_and_advance() {
+        let parts = [Instrument::VIOLIN].map(Part::from);
+        let settings = ScoreSettings::default();
+        let mut input = entry(0);
+        let mut score = Score::default();
+
+        for class in [PitchClass::C, PitchClass::A, PitchClass::D] {
+            score.notes = rewrite(input.apply(InputKey::Pitch(class), &score, &parts, &settings));
+        }
+
+        let octaves: Vec<i8> = score.notes.iter().map(|note| note.pitch.octave).collect();
+        // Treble clef starts near B4: C5, then A4 below, then D5 above.
+        assert_eq!(octaves, vec![5, 4, 5]);
+        assert_eq!(input.caret.map(|caret| caret.beat), Some(3.0));
+    }
+
+    #[test]
+    fn durations_dots_rests_and_key_signature() {
+        let parts = [Instrument::CELLO].map(Part::from);
+        let settings = ScoreSettings {
+            key_signature: KeySignature::Bb,
+            ..ScoreSettings::default()
+        };
+        let mut input = entry(0);
+        let mut score = Score::default();
+
+        input.apply(
+            InputKey::Duration(DurationValue::Half),
+            &score,
+            &parts,
+            &settings,
+        );
+        input.apply(InputKey::Dot, &score, &parts, &settings);
+        score.notes =
+            rewrite(input.apply(InputKey::Pitch(PitchClass::E), &score, &parts, &settings));
+        score.notes = rewrite(input.apply(InputKey::Rest, &score, &parts, &settings));
+
+        assert_eq!(score.notes[0].pitch.alter, -1);
+        assert_eq!(score.notes[0].pitch.octave, 3);
+        assert_eq!(score.notes[0].dots, 1);
+        assert!(score.notes[1].rest);
+        assert_eq!(input.describe(), "Mínima pontuada");
+    }
+
+    #[test]
+    fn each_voice_is_written_on_its_own() {
+        let parts = [Instrument::PIANO].map(Part::from);
+        let settings = ScoreSettings::default();
+        let mut input = entry(0);
+        let mut score = Score::default();
+        for class in [PitchClass::E, PitchClass::F] {
+            score.notes = rewrite(input.apply(InputKey::Pitch(class), &score, &parts, &settings));
+        }
+
+        input.apply(InputKey::Voice(1), &score, &parts, &settings);
+        input.start(0, 0.0);
+        score.notes =
+            rewrite(input.apply(InputKey::Pitch(PitchClass::C), &score, &parts, &settings));
+
+        let voices: Vec<(u8, PitchClass)> = score
+            .notes
+            .iter()
+            .map(|note| (note.voice, note.pitch.class))
+            .collect();
+        assert_eq!(
+            voices,
+            vec![(0, PitchClass::E), (0, PitchClass::F), (1, PitchClass::C)]
+        );
+        assert_eq!(input.describe(), "Semínima, voz 2");
+    }
+
+    #[test]
+    fn arrows_move_the_caret_and_alter_the_last_note() {
+        let parts = [Instrument::FLUTE].map(Part::from);
+        let settings = ScoreSettings::default();
+        let mut input = entry(0);
+        let mut score = Score::default();
+        score.notes =
+            rewrite(input.apply(InputKey::Pitch(PitchClass::E), &score, &parts, &settings));
+
+        let raised = input.apply(InputKey::SemitoneUp, &score, &parts, &settings);
+        assert_eq!(
+            raised,
+            Some(InputEdit::Replace {
+                index: 0,
+                after: NoteEvent::new(
+                    Pitch::new(PitchClass::F, 5),
+                    DurationValue::Quarter,
+                    Instrument::FLUTE
+                ),
+            })
+        );
+        let Some(InputEdit::Replace { after, .. }) =
+            input.apply(InputKey::OctaveDown, &score, &parts, &settings)
+        else {
+            panic!("octave change should replace the note");
+        };
+        assert_eq!(after.pitch.octave, 4);
+
+        // Nothing goes above G9, the highest MIDI note.
+        let mut high = score.clone();
+        high.notes[0].pitch = Pitch::new(PitchClass::E, 9);
+        assert_eq!(
+            input.apply(InputKey::OctaveUp, &high, &parts, &settings),
+            None
+        );
+        high.notes[0].pitch = Pitch::new(PitchClass::G, 9);
+        assert_eq!(
+            input.apply(InputKey::SemitoneUp, &high, &parts, &settings),
+            None
+        );
+
+        input.apply(InputKey::Left, &score, &parts, &settings);
+        assert_eq!(input.caret.map(|caret| caret.beat), Some(0.0));
+        assert_eq!(
+            input.apply(InputKey::SemitoneUp, &score, &parts, &settings),
+            None
+        );
+        input.apply(InputKey::Right, &score, &parts, &settings);
+        assert_eq!(input.caret.map(|caret| caret.beat), Some(1.0));
+    }
+
+    #[test]
+    fn clicking_inserts_at_the_beat_and_pads_with_rests() {
+        let parts = [Instrument::VIOLIN, Instrument::HORN].map(Part::from);
+        let settings = ScoreSettings {
+            key_signature: KeySignature::G,
+            ..ScoreSettings::default()
+        };
+        let mut input = NoteInput::default();
+
+        let notes = rewrite(input.insert_at(
+            1,
+            5.0,
+            Pitch::new(PitchClass::F, 4),
+            &Score::default(),
+            &parts,
+            &settings,
+        ));
+
+        let values: Vec<(bool, DurationValue)> = notes
+            .iter()
+            .map(|note| (note.rest, note.duration))
+            .collect();
+        assert_eq!(
+            values,
+            vec![
+                (true, DurationValue::Whole),
+                (true, DurationValue::Quarter),
+                (false, DurationValue::Quarter),
+            ]
+        );
+        assert_eq!(notes[2].pitch.alter, 1);
+        assert_eq!(notes[2].instrument, Instrument::HORN);
+        assert_eq!(
+            input.caret,
+            Some(Caret {
+                staff: 1,
+                beat: 6.0
+            })
+        );
+    }
+
+    #[test]
+    fn keys_map_to_commands() {
+        let none = egui::Modifiers::NONE;
+        assert_eq!(
+            InputKey::from_key(egui::Key::D, none),
+            Some(InputKey::Pitch(PitchClass::D))
+        );
+        assert_eq!(
+            InputKey::from_key(egui::Key::ArrowUp, egui::Modifiers::COMMAND),
+            Some(InputKey::OctaveUp)
+        );
+        assert_eq!(
+            InputKey::from_key(egui::Key::C, egui::Modifiers::COMMAND),
+            None
+        );
+        assert_eq!(InputKey::from_key(egui::Key::H, none), None);
+    }
+}
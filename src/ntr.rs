@@ -0,0 +1,1331 @@
+//! The `.ntr` score file format.
+//!
+//! `NTR2` files are line based. The first line is the version header; every
+//! other non-blank line is a record: a keyword followed by `name=value` fields.
+//! Values are bare tokens, or double-quoted strings where `\"`, `\\`, `\n`,
+//! `\r` and `\t` are the only escapes. Lines starting with `#` are comments.
+//!
+//! ```text
+//! NTR2
+//! score title="Nova Partitura" composer="Compositor" key=C time=FourFour paper=A4 bpm=110 range=Professional
+//! meter measure=4 numerator=5 denominator=8
+//! tempo beat=16 text="Più mosso" bpm=132
+//! navigation measure=7 sign=RepeatEnd times=2
+//! staff instrument=Violin player=0 staff=0 name="" short=""
+//! note instrument=Violin player=0 staff=0 cross=0 voice=0 step=C alter=0 octave=4 value=Quarter dots=0 tie=false
+//! lyric verse=0 text="Glo" syllabic=Begin
+//! rest instrument=Violin player=0 staff=0 voice=0 value=Half dots=0
+//! ```
+//!
+//! Records:
+//! - `score` (exactly once): `title`, `composer`, `key`, `time`, `paper`, `bpm`;
+//!   optional `range` (`Amateur` or `Professional`; `Professional`), the
+//!   players instrument ranges are checked for.
+//! - `meter`: `measure` (counted from 0), `numerator`, `denominator`; the
+//!   meter from that measure on.
+//! - `tempo` (in score order): `beat` (counted from 0 at the start of the
+//!   score); optional `text` (empty), `bpm` and `change` (`Ritardando` or
+//!   `Accelerando`, running to the next `tempo` record).
+//! - `navigation`: `measure` (counted from 0) and `sign`, one of
+//!   `RepeatStart`, `RepeatEnd` (with optional `times`, 2), `Ending` (with
+//!   `passes`, the pass numbers comma separated), `Segno`, `Coda`, `Fine`,
+//!   `ToCoda`, `DaCapo` and `DalSegno` (with optional `until`: `End`, `Fine`
+//!   or `Coda`, by default `End`).
+//! - `staff` (in display order): `instrument`; optional `player` (0), `staff`
+//!   (0, the staff of a grand staff counted from the top), `name` and `short`
+//!   (empty, meaning the instrument's own names).
+//! - `note`: `instrument`, `step`, `octave`, `value`; optional `player` (0),
+//!   `staff` (0), `cross` (0, staves down to draw the note on; negative is
+//!   up), `voice` (0), `alter` (0), `dots` (0) and `tie` (false).
+//! - `rest`: `instrument`, `value`; optional `player` (0), `staff` (0),
+//!   `voice` (0) and `dots` (0).
+//! - `lyric` (after the `note` it is sung on, one per verse): `verse`
+//!   (counted from 0), `text`; optional `syllabic` (`Single`, `Begin`,
+//!   `Middle` or `End`; `Single`) and `extend` (false).
+//!
+//! Notes and rests may also carry `dynamic` (a marking such as `Mf` or `Sfz`),
+//! `hairpin` (`Crescendo` or `Diminuendo`), `articulations` (names such as
+//! `Staccato,Accent`, comma separated) and `chord` (a chord symbol as typed,
+//! such as `"F#m7b5/C"`), all absent by default.
+//!
+//! `instrument` fields hold an id of the instrument catalogue (see
+//! [`crate::instruments`]), such as `Violin` or `EnglishHorn`. Files without
+//! `staff` records get the default orchestral staves.
+//!
+//! The writer always emits every field except `dynamic`, `hairpin`,
+//! `articulations` and `chord`, which are left out on unmarked events, and a tempo mark's
+//! `bpm` and `change` when it has none. Legacy `NTR1` files are still read and
+//! migrated to the same model.
+
+use std::fmt;
+use std::str::FromStr;
+
+use crate::harmony::ChordSymbol;
+use crate::music::{
+    Articulation, DurationValue, Dynamic, Hairpin, Instrument, KeySignature, Lyric, Meter,
+    MeterChange, Navigation, NavigationMark, NoteEvent, PaperSize, Part, Pitch, PitchClass,
+    RangeLevel, Score, ScoreSettings, Staff, Syllabic, TempoChange, TempoMark, TimeSignature,
+    Until,
+};
+
+const HEADER_V1: &str = "NTR1";
+const HEADER_V2: &str = "NTR2";
+
+/// Values of a `navigation` record's `sign` field.
+#[derive(Debug, Clone, Copy)]
+enum Sign {
+    RepeatStart,
+    RepeatEnd,
+    Ending,
+    Segno,
+    Coda,
+    Fine,
+    ToCoda,
+    DaCapo,
+    DalSegno,
+}
+
+impl Sign {
+    const ALL: [Self; 9] = [
+        Self::RepeatStart,
+        Self::RepeatEnd,
+        Self::Ending,
+        Self::Segno,
+        Self::Coda,
+        Self::Fine,
+        Self::ToCoda,
+        Self::DaCapo,
+        Self::DalSegno,
+    ];
+
+    fn of(navigation: Navigation) -> Self {
+        match navigation {
+            Navigation::RepeatStart => Self::RepeatStart,
+            Navigation::RepeatEnd { .. } => Self::RepeatEnd,
+            Navigation::Ending { .. } => Self::Ending,
+            Navigation::Segno => Self::Segno,
+            Navigation::Coda => Self::Coda,
+            Navigation::Fine => Self::Fine,
+            Navigation::ToCoda => Self::ToCoda,
+            Navigation::DaCapo(_) => Self::DaCapo,
+            Navigation::DalSegno(_) => Self::DalSegno,
+        }
+    }
+}
+
+/// Everything stored in a `.ntr` file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NtrDocument {
+    pub settings: ScoreSettings,
+    pub score: Score,
+    /// Version the file was written in; 1 means it was migrated on load.
+    pub version: u8,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct NtrError {
+    /// 1-based line number.
+    pub line: usize,
+    /// 1-based character column.
+    pub column: usize,
+    pub kind: NtrErrorKind,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum NtrErrorKind {
+    EmptyFile,
+    UnknownVersion(String),
+    UnknownRecord(String),
+    UnknownField {
+        record: String,
+        field: String,
+    },
+    MissingField {
+        record: String,
+        field: &'static str,
+    },
+    DuplicateField(String),
+    InvalidValue {
+        field: String,
+        value: String,
+        expected: &'static str,
+    },
+    MalformedField(String),
+    UnterminatedString,
+    InvalidEscape(char),
+    MissingScoreRecord,
+    DuplicateScoreRecord,
+    LyricWithoutNote,
+}
+
+impl fmt::Display for NtrError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "linha {}, coluna {}: ", self.line, self.column)?;
+        match &self.kind {
+            NtrErrorKind::EmptyFile => write!(f, "arquivo vazio"),
+            NtrErrorKind::UnknownVersion(header) => {
+                write!(f, "versão \"{header}\" desconhecida (esperado {HEADER_V2})")
+            }
+            NtrErrorKind::UnknownRecord(record) => write!(f, "registro \"{record}\" desconhecido"),
+            NtrErrorKind::UnknownField { record, field } => {
+                write!(f, "campo \"{field}\" não existe em \"{record}\"")
+            }
+            NtrErrorKind::MissingField { record, field } => {
+                write!(f, "campo obrigatório \"{field}\" ausente em \"{record}\"")
+            }
+            NtrErrorKind::DuplicateField(field) => write!(f, "campo \"{field}\" repetido"),
+            NtrErrorKind::InvalidValue {
+                field,
+                value,
+                expected,
+            } => write!(
+                f,
+                "valor \"{value}\" inválido para \"{field}\" (esperado {expected})"
+            ),
+            NtrErrorKind::MalformedField(token) => {
+                write!(f, "\"{token}\" não está no formato nome=valor")
+            }
+            NtrErrorKind::UnterminatedString => write!(f, "texto entre aspas sem fechamento"),
+            NtrErrorKind::InvalidEscape(ch) => write!(f, "escape \"\\{ch}\" inválido"),
+            NtrErrorKind::MissingScoreRecord => write!(f, "registro \"score\" ausente"),
+            NtrErrorKind::DuplicateScoreRecord => write!(f, "registro \"score\" repetido"),
+            NtrErrorKind::LyricWithoutNote => write!(f, "letra sem uma nota antes dela"),
+        }
+    }
+}
+
+impl std::error::Error for NtrError {}
+
+pub fn serialize_ntr(settings: &ScoreSettings, score: &Score) -> String {
+    let mut out = String::new();
+    out.push_str(HEADER_V2);
+    out.push('\n');
+    out.push_str(&format!(
+        "score title={} composer={} key={:?} time={:?} paper={:?} bpm={} range={:?}\n",
+        quote(&settings.title),
+        quote(&settings.composer),
+        settings.key_signature,
+        settings.time_signature,
+        settings.paper_size,
+        settings.bpm,
+        settings.range_level
+    ));
+
+    for change in &settings.meter_changes {
+        out.push_str(&format!(
+            "meter measure={} numerator={} denominator={}\n",
+            change.measure, change.meter.numerator, change.meter.denominator
+        ));
+    }
+
+    for mark in &settings.tempo_marks {
+        out.push_str(&format!(
+            "tempo beat={} text={}",
+            mark.beat,
+            quote(&mark.text)
+        ));
+        if let Some(bpm) = mark.bpm {
+            out.push_str(&format!(" bpm={bpm}"));
+        }
+        if let Some(change) = mark.change {
+            out.push_str(&format!(" change={change:?}"));
+        }
+        out.push('\n');
+    }
+
+    for mark in &settings.navigation {
+        let sign = Sign::of(mark.navigation);
+        out.push_str(&format!(
+            "navigation measure={} sign={sign:?}",
+            mark.measure
+        ));
+        match mark.navigation {
+            Navigation::RepeatEnd { times } => out.push_str(&format!(" times={times}")),
+            Navigation::Ending { passes } => {
+                let numbers: Vec<String> = (0..8)
+                    .filter(|pass| passes & (1 << pass) != 0)
+                    .map(|pass| (pass + 1).to_string())
+                    .collect();
+                out.push_str(&format!(" passes={}", numbers.join(",")));
+            }
+            Navigation::DaCapo(until) | Navigation::DalSegno(until) => {
+                out.push_str(&format!(" until={until:?}"));
+            }
+            _ => {}
+        }
+        out.push('\n');
+    }
+
+    for staff in &settings.staves {
+        out.push_str(&format!(
+            "staff instrument={} player={} staff={} name={} short={}\n",
+            staff.part.instrument.id(),
+            staff.part.player,
+            staff.part.sub_staff,
+            quote(&staff.name),
+            quote(&staff.short_name)
+        ));
+    }
+
+    for note in &score.notes {
+        if note.rest {
+            out.push_str(&format!(
+                "rest instrument={} player={} staff={} voice={} value={:?} dots={}",
+                note.instrument.id(),
+                note.player,
+                note.sub_staff,
+                note.voice,
+                note.duration,
+                note.dots
+            ));
+        } else {
+            out.push_str(&format!(
+                "note instrument={} player={} staff={} cross={} voice={} step={:?} alter={} octave={} value={:?} dots={} tie={}",
+                note.instrument.id(),
+                note.player,
+                note.sub_staff,
+                note.cross_staff,
+                note.voice,
+                note.pitch.class,
+                note.pitch.alter,
+                note.pitch.octave,
+                note.duration,
+                note.dots,
+                note.tie
+            ));
+        }
+        if let Some(dynamic) = note.dynamic {
+            out.push_str(&format!(" dynamic={dynamic:?}"));
+        }
+        if let Some(hairpin) = note.hairpin {
+            out.push_str(&format!(" hairpin={hairpin:?}"));
+        }
+        if !note.articulations.is_empty() {
+            let names: Vec<String> = note
+                .articulations
+                .iter()
+                .map(|articulation| format!("{articulation:?}"))
+                .collect();
+            out.push_str(&format!(" articulations={}", names.join(",")));
+        }
+        if let Some(chord) = &note.chord {
+            out.push_str(&format!(" chord={}", quote(&chord.to_string())));
+        }
+        out.push('\n');
+        for lyric in &note.lyrics {
+            out.push_str(&format!(
+                "lyric verse={} text={} syllabic={:?} extend={}\n",
+                lyric.verse,
+                quote(&lyric.text),
+                lyric.syllabic,
+                lyric.extend
+            ));
+        }
+    }
+
+    out
+}
+
+pub fn deserialize_ntr(contents: &str) -> Result<NtrDocument, NtrError> {
+    let mut lines = contents.lines().enumerate();
+    let Some((_, header)) = lines.next() else {
+        return Err(NtrError {
+            line: 1,
+            column: 1,
+            kind: NtrErrorKind::EmptyFile,
+        });
+    };
+
+    match header.trim_start_matches('\u{feff}').trim() {
+        HEADER_V2 => deserialize_v2(lines),
+        HEADER_V1 => deserialize_v1(lines),
+        other => Err(NtrError {
+            line: 1,
+            column: 1,
+            kind: NtrErrorKind::UnknownVersion(other.to_owned()),
+        }),
+    }
+}
+
+fn deserialize_v2<'a>(
+    lines: impl Iterator<Item = (usize, &'a str)>,
+) -> Result<NtrDocument, NtrError> {
+    let mut settings: Option<ScoreSettings> = None;
+    let mut staves = Vec::new();
+    let mut meter_changes = Vec::new();
+    let mut tempo_marks = Vec::new();
+    let mut navigation = Vec::new();
+    let mut notes = Vec::new();
+
+    for (index, line) in lines {
+        let trimmed = line.trim_start();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            continue;
+        }
+
+        let mut record = Record::parse(line, index + 1)?;
+        match record.keyword.as_str() {
+            "score" => {
+                if settings.is_some() {
+                    return Err(record.error(NtrErrorKind::DuplicateScoreRecord));
+                }
+                settings = Some(ScoreSettings {
+                    title: record.required("title")?.value,
+                    composer: record.required("composer")?.value,
+                    key_signature: record.required("key")?.parse_enum(&KeySignature::ALL)?,
+                    time_signature: record.required("time")?.parse_enum(&TimeSignature::ALL)?,
+                    meter_changes: Vec::new(),
+                    paper_size: record.required("paper")?.parse_enum(&PaperSize::ALL)?,
+                    staves: Vec::new(),
+                    bpm: record.required("bpm")?.parse_bpm()?,
+                    tempo_marks: Vec::new(),
+                    navigation: Vec::new(),
+                    range_level: match record.take("range") {
+                        Some(field) => field.parse_enum(&RangeLevel::ALL)?,
+                        None => RangeLevel::default(),
+                    },
+                });
+            }
+            "navigation" => {
+                let measure = record
+                    .required("measure")?
+                    .parse_number::<usize>("número de compasso")?;
+                navigation.push(NavigationMark {
+                    measure,
+                    navigation: record.navigation()?,
+                });
+            }
+            "tempo" => {
+                let beat_field = record.required("beat")?;
+                let beat = beat_field.parse_number::<f32>("posição em tempos")?;
+                if !beat.is_finite() || beat < 0.0 {
+                    return Err(beat_field.invalid("posição em tempos"));
+                }
+                let bpm = match record.take("bpm") {
+                    Some(field) => Some(field.parse_bpm()?),
+                    None => None,
+                };
+                let change = match record.take("change") {
+                    Some(field) => Some(field.parse_enum(&TempoChange::ALL)?),
+                    None => None,
+                };
+                tempo_marks.push(TempoMark {
+                    beat,
+                    text: record
+                        .take("text")
+                        .map(|field| field.value)
+                        .unwrap_or_default(),
+                    bpm,
+                    change,
+                });
+            }
+            "meter" => {
+                let measure = record
+                    .required("measure")?
+                    .parse_number::<usize>("número de compasso")?;
+                let mut count = |name: &'static str| -> Result<u8, NtrError> {
+                    let field = record.required(name)?;
+                    match field.parse_number::<u8>("inteiro positivo")? {
+                        0 => Err(field.invalid("inteiro positivo")),
+                        count => Ok(count),
+                    }
+                };
+                meter_changes.push(MeterChange {
+                    measure,
+                    meter: Meter {
+                        numerator: count("numerator")?,
+                        denominator: count("denominator")?,
+                    },
+                });
+            }
+            "staff" => {
+                let part = record.part()?;
+                let mut text = |name: &str| record.take(name).map(|field| field.value);
+                staves.push(Staff {
+                    part,
+                    name: text("name").unwrap_or_default(),
+                    short_name: text("short").unwrap_or_default(),
+                });
+            }
+            "note" => {
+                let part = record.part()?;
+                let class = record.required("step")?.parse_enum(&PitchClass::ALL)?;
+                let octave = record
+                    .required("octave")?
+                    .parse_number::<i8>("oitava inteira")?;
+                let duration = record.required("value")?.parse_enum(&DurationValue::ALL)?;
+                let alter = record.optional_number("alter", 0_i8, "alteração inteira")?;
+                let cross_staff = record.optional_number("cross", 0_i8, "número de pautas")?;
+                let voice = record.optional_voice()?;
+                let dots = record.optional_dots()?;
+                let tie = match record.take("tie") {
+                    Some(field) => field.parse_bool()?,
+                    None => false,
+                };
+                let (dynamic, hairpin) = record.marks()?;
+                let articulations = record.articulations()?;
+                let chord = record.chord()?;
+                notes.push(NoteEvent {
+                    cross_staff,
+                    voice,
+                    dots,
+                    tie,
+                    dynamic,
+                    hairpin,
+                    articulations,
+                    chord,
+                    ..NoteEvent::new(
+                        Pitch {
+                            class,
+                            alter,
+                            octave,
+                        },
+                        duration,
+                        part,
+                    )
+                });
+            }
+            "lyric" => {
+                let field = record.required("verse")?;
+                let verse = match field.value.parse::<u8>() {
+                    Ok(verse) if verse < Lyric::VERSES => verse,
+                    _ => return Err(field.invalid("estrofe de 0 a 7")),
+                };
+                let text = record.required("text")?.value;
+                let syllabic = match record.take("syllabic") {
+                    Some(field) => field.parse_enum(&Syllabic::ALL)?,
+                    None => Syllabic::Single,
+                };
+                let extend = match record.take("extend") {
+                    Some(field) => field.parse_bool()?,
+                    None => false,
+                };
+                match notes.last_mut() {
+                    Some(note @ NoteEvent { rest: false, .. }) => note.set_lyric(Lyric {
+                        verse,
+                        text,
+                        syllabic,
+                        extend,
+                    }),
+                    _ => return Err(record.error(NtrErrorKind::LyricWithoutNote)),
+                }
+            }
+            "rest" => {
+                let part = record.part()?;
+                let duration = record.required("value")?.parse_enum(&DurationValue::ALL)?;
+                let voice = record.optional_voice()?;
+                let dots = record.optional_dots()?;
+                let (dynamic, hairpin) = record.marks()?;
+                let articulations = record.articulations()?;
+                let chord = record.chord()?;
+                notes.push(NoteEvent {
+                    voice,
+                    dots,
+                    dynamic,
+                    hairpin,
+                    articulations,
+                    chord,
+                    ..NoteEvent::rest(duration, part)
+                });
+            }
+            other => {
+                return Err(record.error(NtrErrorKind::UnknownRecord(other.to_owned())));
+            }
+        }
+        record.finish()?;
+    }
+
+    let Some(mut settings) = settings else {
+        return Err(NtrError {
+            line: 1,
+            column: 1,
+            kind: NtrErrorKind::MissingScoreRecord,
+        });
+    };
+
+    meter_changes.sort_by_key(|change: &MeterChange| change.measure);
+    settings.meter_changes = meter_changes;
+    tempo_marks.sort_by(|a: &TempoMark, b| a.beat.total_cmp(&b.beat));
+    settings.tempo_marks = tempo_marks;
+    navigation.sort_by_key(|mark: &NavigationMark| mark.measure);
+    settings.navigation = navigation;
+    settings.staves = if staves.is_empty() {
+        ScoreSettings::default().staves
+    } else {
+        staves
+    };
+
+    Ok(NtrDocument {
+        settings,
+        score: Score { notes },
+        version: 2,
+    })
+}
+
+/// Reads the original `key=value` header plus `octave,Class,beats,Instrument` note lines.
+fn deserialize_v1<'a>(
+    lines: impl Iterator<Item = (usize, &'a str)>,
+) -> Result<NtrDocument, NtrError> {
+    let mut settings = ScoreSettings::default();
+    let mut notes = Vec::new();
+    let mut in_notes = false;
+
+    for (index, line) in lines {
+        let line_number = index + 1;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        if line == "notes:" {
+            in_notes = true;
+            continue;
+        }
+
+        if !in_notes {
+            let Some((name, value)) = line.split_once('=') else {
+                return Err(NtrError {
+                    line: line_number,
+                    column: 1,
+                    kind: NtrErrorKind::MalformedField(line.to_owned()),
+                });
+            };
+            let field = Field {
+                name: name.to_owned(),
+                value: value.to_owned(),
+                line: line_number,
+                column: name.chars().count() + 2,
+            };
+            match name {
+                "title" => settings.title = field.value,
+                "composer" => settings.composer = field.value,
+                "bpm" => settings.bpm = field.parse_bpm()?,
+                "key" => settings.key_signature = field.parse_enum(&KeySignature::ALL)?,
+                "time" => settings.time_signature = field.parse_enum(&TimeSignature::ALL)?,
+                "paper" => settings.paper_size = field.parse_enum(&PaperSize::ALL)?,
+                _ => {
+                    return Err(NtrError {
+                        line: line_number,
+                        column: 1,
+                        kind: NtrErrorKind::UnknownRecord(name.to_owned()),
+                    });
+                }
+            }
+            continue;
+        }
+
+        let mut fields = Vec::new();
+        let mut column = 1;
+        for (position, value) in line.split(',').enumerate() {
+            let name = ["octave", "step", "beats", "instrument"]
+                .get(position)
+                .copied()
+                .unwrap_or("extra");
+            fields.push(Field {
+                name: name.to_owned(),
+                value: value.to_owned(),
+                line: line_number,
+                column,
+            });
+            column += value.chars().count() + 1;
+        }
+        if fields.len() != 4 {
+            return Err(NtrError {
+                line: line_number,
+                column: 1,
+                kind: NtrErrorKind::MalformedField(line.to_owned()),
+            });
+        }
+
+        let octave = fields[0].parse_number::<i8>("oitava inteira")?;
+        let class = fields[1].parse_enum(&PitchClass::ALL)?;
+        let beats = fields[2].parse_number::<f32>("duração em tempos")?;
+        let (duration, dots) = DurationValue::from_beats(beats)
+            .ok_or_else(|| fields[2].invalid("duração em tempos"))?;
+        let instrument = fields[3].parse_instrument()?;
+        notes.push(NoteEvent {
+            dots,
+            ..NoteEvent::new(Pitch::new(class, octave), duration, instrument)
+        });
+    }
+
+    Ok(NtrDocument {
+        settings,
+        score: Score { notes },
+        version: 1,
+    })
+}
+
+pub(crate) struct Field {
+    name: String,
+    pub(crate) value: String,
+    line: usize,
+    /// Column where the value starts.
+    column: usize,
+}
+
+impl Field {
+    pub(crate) fn invalid(&self, expected: &'static str) -> NtrError {
+        NtrError {
+            line: self.line,
+            column: self.column,
+            kind: NtrErrorKind::InvalidValue {
+                field: self.name.clone(),
+                value: self.value.clone(),
+                expected,
+            },
+        }
+    }
+
+    /// Matches the value against the `Debug` names of `all`.
+    pub(crate) fn parse_enum<T: Copy + fmt::Debug>(&self, all: &[T]) -> Result<T, NtrError> {
+        all.iter()
+            .copied()
+            .find(|candidate| format!("{candidate:?}") == self.value)
+            .ok_or_else(|| self.invalid("um nome de variante conhecido"))
+    }
+
+    /// Matches the value against the ids of the instrument catalogue.
+    fn parse_instrument(&self) -> Result<Instrument, NtrError> {
+        Instrument::from_id(&self.value).ok_or_else(|| self.invalid("um instrumento do catálogo"))
+    }
+
+    pub(crate) fn parse_number<T: FromStr>(&self, expected: &'static str) -> Result<T, NtrError> {
+        self.value.parse::<T>().map_err(|_| self.invalid(expected))
+    }
+
+    fn parse_bpm(&self) -> Result<f32, NtrError> {
+        match self.parse_number::<f32>("número positivo")? {
+            bpm if bpm.is_finite() && bpm > 0.0 => Ok(bpm),
+            _ => Err(self.invalid("número positivo")),
+        }
+    }
+
+    fn parse_bool(&self) -> Result<bool, NtrError> {
+        match self.value.as_str() {
+            "true" => Ok(true),
+            "false" => Ok(false),
+            _ => Err(self.invalid("true ou false")),
+        }
+    }
+}
+
+pub(crate) struct Record {
+    pub(crate) keyword: String,
+    fields: Vec<Field>,
+    line: usize,
+}
+
+impl Record {
+    pub(crate) fn parse(line: &str, line_number: usize) -> Result<Self, NtrError> {
+        let chars: Vec<char> = line.chars().collect();
+        let error = |column: usize, kind: NtrErrorKind| NtrError {
+            line: line_number,
+            column,
+            kind,
+        };
+        let mut index = 0;
+
+        let skip_spaces = |index: &mut usize| {
+            while *index < chars.len() && chars[*index].is_whitespace() {
+                *index += 1;
+            }
+        };
+        let read_bare = |index: &mut usize| {
+            let start = *index;
+            while *index < chars.len() && !chars[*index].is_whitespace() {
+                *index += 1;
+            }
+            chars[start..*index].iter().collect::<String>()
+        };
+
+        skip_spaces(&mut index);
+        let keyword = read_bare(&mut index);
+        let mut fields: Vec<Field> = Vec::new();
+
+        loop {
+            skip_spaces(&mut index);
+            if index >= chars.len() {
+                break;
+            }
+
+            let name_start = index;
+            while index < chars.len() && chars[index] != '=' && !chars[index].is_whitespace() {
+                index += 1;
+            }
+            let name: String = chars[name_start..index].iter().collect();
+            if index >= chars.len() || chars[index] != '=' || name.is_empty() {
+                let token = chars[name_start..index].iter().collect();
+                return Err(error(name_start + 1, NtrErrorKind::MalformedField(token)));
+            }
+            if fields.iter().any(|field| field.name == name) {
+                return Err(error(name_start + 1, NtrErrorKind::DuplicateField(name)));
+            }
+            index += 1;
+
+            let value_start = index;
+            let value = if chars.get(index) == Some(&'"') {
+                index += 1;
+                let mut value = String::new();
+                loop {
+                    match chars.get(index) {
+                        None => {
+                            return Err(error(value_start + 1, NtrErrorKind::UnterminatedString))
+                        }
+                        Some('"') => {
+                            index += 1;
+                            break;
+                        }
+                        Some('\\') => {
+                            let escaped = match chars.get(index + 1) {
+                                Some('"') => '"',
+                                Some('\\') => '\\',
+                                Some('n') => '\n',
+                                Some('r') => '\r',
+                                Some('t') => '\t',
+                                Some(other) => {
+                                    return Err(error(
+                                        index + 1,
+                                        NtrErrorKind::InvalidEscape(*other),
+                                    ))
+                                }
+                                None => {
+                                    return Err(error(
+                                        value_start + 1,
+                                        NtrErrorKind::UnterminatedString,
+                                    ))
+                                }
+                            };
+                            value.push(escaped);
+                            index += 2;
+                        }
+                        Some(ch) => {
+                            value.push(*ch);
+                            index += 1;
+                        }
+                    }
+                }
+                if index < chars.len() && !chars[index].is_whitespace() {
+                    let token = chars[name_start..].iter().collect();
+                    return Err(error(name_start + 1, NtrErrorKind::MalformedField(token)));
+                }
+                value
+            } else {
+                read_bare(&mut index)
+            };
+
+            fields.push(Field {
+                name,
+                value,
+                line: line_number,
+                column: value_start + 1,
+            });
+        }
+
+        Ok(Self {
+            keyword,
+            fields,
+            line: line_number,
+        })
+    }
+
+    pub(crate) fn error(&self, kind: NtrErrorKind) -> NtrError {
+        NtrError {
+            line: self.line,
+            column: 1,
+            kind,
+        }
+    }
+
+    pub(crate) fn take(&mut self, name: &str) -> Option<Field> {
+        let position = self.fields.iter().position(|field| field.name == name)?;
+        Some(self.fields.remove(position))
+    }
+
+    pub(crate) fn required(&mut self, name: &'static str) -> Result<Field, NtrError> {
+        self.take(name).ok_or_else(|| {
+            self.error(NtrErrorKind::MissingField {
+                record: self.keyword.clone(),
+                field: name,
+            })
+        })
+    }
+
+    pub(crate) fn optional_number<T: FromStr>(
+        &mut self,
+        name: &str,
+        default: T,
+        expected: &'static str,
+    ) -> Result<T, NtrError> {
+        match self.take(name) {
+            Some(field) => field.parse_number(expected),
+            None => Ok(default),
+        }
+    }
+
+    /// The `instrument` and optional `player` and `staff` fields.
+    fn part(&mut self) -> Result<Part, NtrError> {
+        let instrument = self.required("instrument")?.parse_instrument()?;
+        let player = self.optional_number("player", 0_u8, "número de músico")?;
+        let sub_staff = match self.take("staff") {
+            Some(field) => match field.value.parse::<u8>() {
+                Ok(staff) if staff < instrument.staff_count() => staff,
+                _ => return Err(field.invalid("pauta do instrumento")),
+            },
+            None => 0,
+        };
+        Ok(Part {
+            instrument,
+            player,
+            sub_staff,
+        })
+    }
+
+    fn optional_voice(&mut self) -> Result<u8, NtrError> {
+        match self.take("voice") {
+            Some(field) => match field.value.parse::<u8>() {
+                Ok(voice) if voice < NoteEvent::VOICES => Ok(voice),
+                _ => Err(field.invalid("0 a 3")),
+            },
+            None => Ok(0),
+        }
+    }
+
+    fn optional_dots(&mut self) -> Result<u8, NtrError> {
+        match self.take("dots") {
+            Some(field) => match field.value.parse::<u8>() {
+                Ok(dots) if dots <= DurationValue::MAX_DOTS => Ok(dots),
+                _ => Err(field.invalid("0, 1 ou 2")),
+            },
+            None => Ok(0),
+        }
+    }
+
+    /// The optional `dynamic` and `hairpin` fields.
+    fn marks(&mut self) -> Result<(Option<Dynamic>, Option<Hairpin>), NtrError> {
+        let dynamic = match self.take("dynamic") {
+            Some(field) => Some(field.parse_enum(&Dynamic::ALL)?),
+            None => None,
+        };
+        let hairpin = match self.take("hairpin") {
+            Some(field) => Some(field.parse_enum(&Hairpin::ALL)?),
+            None => None,
+        };
+        Ok((dynamic, hairpin))
+    }
+
+    /// The optional comma-separated `articulations` field.
+    fn articulations(&mut self) -> Result<Vec<Articulation>, NtrError> {
+        let Some(field) = self.take("articulations") else {
+            return Ok(Vec::new());
+        };
+        let mut column = field.column;
+        let mut articulations = Vec::new();
+        for name in field.value.split(',') {
+            let part = Field {
+                name: field.name.clone(),
+                value: name.to_owned(),
+                line: field.line,
+                column,
+            };
+            articulations.push(part.parse_enum(&Articulation::ALL)?);
+            column += name.chars().count() + 1;
+        }
+        Ok(articulations)
+    }
+
+    /// The optional `chord` field.
+    fn chord(&mut self) -> Result<Option<ChordSymbol>, NtrError> {
+        match self.take("chord") {
+            Some(field) => field
+                .value
+                .parse()
+                .map(Some)
+                .map_err(|_| field.invalid("uma cifra como Cmaj7 ou F#m7b5/C")),
+            None => Ok(None),
+        }
+    }
+
+    /// The `sign` of a `navigation` record and the fields that go with it.
+    fn navigation(&mut self) -> Result<Navigation, NtrError> {
+        let until = |record: &mut Self| match record.take("until") {
+            Some(field) => field.parse_enum(&Until::ALL),
+            None => Ok(Until::End),
+        };
+        Ok(match self.required("sign")?.parse_enum(&Sign::ALL)? {
+            Sign::RepeatStart => Navigation::RepeatStart,
+            Sign::RepeatEnd => {
+                let times = match self.take("times") {
+                    Some(field) => match field.value.parse::<u8>() {
+                        Ok(times @ 2..=9) => times,
+                        _ => return Err(field.invalid("inteiro de 2 a 9")),
+                    },
+                    None => 2,
+                };
+                Navigation::RepeatEnd { times }
+            }
+            Sign::Ending => {
+                let field = self.required("passes")?;
+                let mut passes = 0_u8;
+                for number in field.value.split(',') {
+                    match number.parse::<u8>() {
+                        Ok(pass @ 1..=8) => passes |= 1 << (pass - 1),
+                        _ => return Err(field.invalid("números de 1 a 8 separados por vírgula")),
+                    }
+                }
+                Navigation::Ending { passes }
+            }
+            Sign::Segno => Navigation::Segno,
+            Sign::Coda => Navigation::Coda,
+            Sign::Fine => Navigation::Fine,
+            Sign::ToCoda => Navigation::ToCoda,
+            Sign::DaCapo => Navigation::DaCapo(until(self)?),
+            Sign::DalSegno => Navigation::DalSegno(until(self)?),
+        })
+    }
+
+    /// Rejects any field the record type does not define.
+    pub(crate) fn finish(self) -> Result<(), NtrError> {
+        match self.fields.into_iter().next() {
+            Some(field) => Err(NtrError {
+                line: field.line,
+                column: field.column.saturating_sub(field.name.chars().count() + 1),
+                kind: NtrErrorKind::UnknownField {
+                    record: self.keyword,
+                    field: field.name,
+                },
+            }),
+            None => Ok(()),
+        }
+    }
+}
+
+fn quote(text: &str) -> String {
+    let mut out = String::with_capacity(text.len() + 2);
+    out.push('"');
+    for ch in text.chars() {
+        match ch {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            _ => out.push(ch),
+        }
+    }
+    out.push('"');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_score() -> Score {
+        Score {
+            notes: vec![
+                NoteEvent {
+                    dots: 1,
+                    tie: true,
+                    dynamic: Some(Dynamic::Sfz),
+                    hairpin: Some(Hairpin::Diminuendo),
+                    articulations: vec![Articulation::Staccato, Articulation::Fermata],
+                    lyrics: vec![
+                        Lyric {
+                            verse: 0,
+                            text: "Glo".to_owned(),
+                            syllabic: Syllabic::Begin,
+                            extend: false,
+                        },
+                        Lyric {
+                            verse: 2,
+                            text: "\"Ah\"".to_owned(),
+                            syllabic: Syllabic::Single,
+                            extend: true,
+                        },
+                    ],
+                    ..NoteEvent::new(
+                        Pitch {
+                            alter: -1,
+                            ..Pitch::new(PitchClass::E, 5)
+                        },
+                        DurationValue::Quarter,
+                        Instrument::VIOLIN,
+                    )
+                },
+                NoteEvent {
+                    dynamic: Some(Dynamic::Pp),
+                    chord: Some("F#m7b5/C".parse().unwrap()),
+                    ..NoteEvent::rest(DurationValue::Sixteenth, Instrument::CELLO)
+                },
+                NoteEvent::rest(
+                    DurationValue::Half,
+                    Part {
+                        player: 1,
+                        ..Instrument::VIOLIN.into()
+                    },
+                ),
+                NoteEvent::new(
+                    Pitch::new(PitchClass::G, 2),
+                    DurationValue::Whole,
+                    Instrument::CELLO,
+                ),
+                NoteEvent {
+                    voice: 1,
+                    ..NoteEvent::new(
+                        Pitch::new(PitchClass::D, 2),
+                        DurationValue::Whole,
+                        Instrument::CELLO,
+                    )
+                },
+                NoteEvent {
+                    cross_staff: -1,
+                    ..NoteEvent::new(
+                        Pitch::new(PitchClass::D, 4),
+                        DurationValue::Eighth,
+                        Part::from(Instrument::PIANO).on_staff(1),
+                    )
+                },
+            ],
+        }
+    }
+
+    #[test]
+    fn v2_round_trip_is_lossless() {
+        let settings = ScoreSettings {
+            title: "Linha 1\nLinha 2 \"citação\" \\ fim".to_owned(),
+            composer: "A. Compositor\t(1900)".to_owned(),
+            key_signature: KeySignature::Gb,
+            time_signature: TimeSignature::SixEight,
+            meter_changes: vec![MeterChange {
+                measure: 3,
+                meter: Meter {
+                    numerator: 5,
+                    denominator: 8,
+                },
+            }],
+            paper_size: PaperSize::Letter,
+            staves: vec![
+                Staff::new(Instrument::VIOLIN),
+                Staff {
+                    name: "Violino \"solo\"".to_owned(),
+                    short_name: "Vln. s.".to_owned(),
+                    ..Staff::new(Part {
+                        player: 1,
+                        ..Instrument::VIOLIN.into()
+                    })
+                },
+                Staff::new(Instrument::CELLO),
+                Staff::new(Instrument::PIANO),
+                Staff::new(Part::from(Instrument::PIANO).on_staff(1)),
+            ],
+            bpm: 96.5,
+            tempo_marks: vec![
+                TempoMark {
+                    beat: 8.0,
+                    text: "rit.".to_owned(),
+                    bpm: None,
+                    change: Some(TempoChange::Ritardando),
+                },
+                TempoMark {
+                    beat: 12.5,
+                    text: "a tempo \"primo\"".to_owned(),
+                    bpm: Some(96.5),
+                    change: None,
+                },
+            ],
+            navigation: vec![
+                NavigationMark {
+                    measure: 0,
+                    navigation: Navigation::Segno,
+                },
+                NavigationMark {
+                    measure: 2,
+                    navigation: Navigation::Ending { passes: 0b011 },
+                },
+                NavigationMark {
+                    measure: 2,
+                    navigation: Navigation::RepeatEnd { times: 3 },
+                },
+                NavigationMark {
+                    measure: 3,
+                    navigation: Navigation::DalSegno(Until::Coda),
+                },
+            ],
+            range_level: RangeLevel::Amateur,
+        };
+
+        let text = serialize_ntr(&settings, &sample_score());
+        let document = deserialize_ntr(&text).unwrap();
+
+        assert_eq!(
+            document,
+            NtrDocument {
+                settings,
+                score: sample_score(),
+                version: 2,
+            }
+        );
+    }
+
+    #[test]
+    fn v2_optional_note_fields_default() {
+        let text = "NTR2\n\
+                    # comentário\n\
+                    score title=\"T\" composer=\"C\" key=C time=FourFour paper=A4 bpm=120\n\
+                    \n\
+                    note instrument=Flute step=A octave=4 value=Half\n";
+
+        let document = deserialize_ntr(text).unwrap();
+
+        assert_eq!(
+            document.score.notes,
+            vec![NoteEvent::new(
+                Pitch::new(PitchClass::A, 4),
+                DurationValue::Half,
+                Instrument::FLUTE
+            )]
+        );
+    }
+
+    #[test]
+    fn migrates_ntr1_files() {
+        let text = "NTR1\n\
+                    title=Antiga\n\
+                    composer=Alguém\n\
+                    bpm=90\n\
+                    key=Bb\n\
+                    time=ThreeFour\n\
+                    paper=A3\n\
+                    notes:\n\
+                    4,C,1.5,Violin\n\
+                    3,G,4,Cello\n";
+
+        let document = deserialize_ntr(text).unwrap();
+
+        assert_eq!(document.version, 1);
+        assert_eq!(document.settings.title, "Antiga");
+        assert_eq!(document.settings.key_signature, KeySignature::Bb);
+        assert_eq!(document.settings.paper_size, PaperSize::A3);
+        assert_eq!(document.settings.bpm, 90.0);
+        assert_eq!(document.score.notes[0].duration, DurationValue::Quarter);
+        assert_eq!(document.score.notes[0].dots, 1);
+        assert_eq!(document.score.notes[1].instrument, Instrument::CELLO);
+
+        for bpm in ["0", "-60", "NaN"] {
+            let error =
+                deserialize_ntr(&text.replace("bpm=90", &format!("bpm={bpm}"))).unwrap_err();
+            assert_eq!((error.line, error.column), (4, 5));
+            assert!(matches!(
+                error.kind,
+                NtrErrorKind::InvalidValue { ref field, .. } if field == "bpm"
+            ));
+        }
+    }
+
+    #[test]
+    fn ntr1_reports_bad_note_with_position() {
+        let text = "NTR1\ntitle=x\nnotes:\n4,H,1,Violin\n";
+
+        let error = deserialize_ntr(text).unwrap_err();
+
+        assert_eq!(error.line, 4);
+        assert_eq!(error.column, 3);
+        assert!(matches!(
+            error.kind,
+            NtrErrorKind::InvalidValue { ref field, ref value, .. } if field == "step" && value == "H"
+        ));
+    }
+
+    #[test]
+    fn reports_invalid_value_with_line_and_column() {
+        let text = "NTR2\n\
+                    score title=\"T\" composer=\"C\" key=C time=FourFour paper=A4 bpm=120\n\
+                    note instrument=Violin step=C octave=quatro value=Quarter\n";
+
+        let error = deserialize_ntr(text).unwrap_err();
+
+        assert_eq!(error.line, 3);
+        assert_eq!(error.column, 38);
+        assert_eq!(
+            error.kind,
+            NtrErrorKind::InvalidValue {
+                field: "octave".to_owned(),
+                value: "quatro".to_owned(),
+                expected: "oitava inteira",
+            }
+        );
+        assert_eq!(
+            error.to_string(),
+            "linha 3, coluna 38: valor \"quatro\" inválido para \"octave\" (esperado oitava inteira)"
+        );
+    }
+
+    #[test]
+    fn reports_structural_errors() {
+        let score = "score title=\"T\" composer=\"C\" key=C time=FourFour paper=A4 bpm=120";
+        let cases = [
+            ("", NtrErrorKind::EmptyFile),
+            ("NTR9\n", NtrErrorKind::UnknownVersion("NTR9".to_owned())),
+            ("NTR2\n", NtrErrorKind::MissingScoreRecord),
+            (
+                "NTR2\nscore title=\"sem fim\n",
+                NtrErrorKind::UnterminatedString,
+            ),
+            (
+                "NTR2\nscore title=\"\\q\"\n",
+                NtrErrorKind::InvalidEscape('q'),
+            ),
+            (
+                "NTR2\nscore title=a title=b\n",
+                NtrErrorKind::DuplicateField("title".to_owned()),
+            ),
+            (
+                "NTR2\nscore title\n",
+                NtrErrorKind::MalformedField("title".to_owned()),
+            ),
+            (
+                "NTR2\nchord notes=3\n",
+                NtrErrorKind::UnknownRecord("chord".to_owned()),
+            ),
+            (
+                "NTR2\nscore title=T\n",
+                NtrErrorKind::MissingField {
+                    record: "score".to_owned(),
+                    field: "composer",
+                },
+            ),
+        ];
+
+        for (text, kind) in cases {
+            assert_eq!(deserialize_ntr(text).unwrap_err().kind, kind, "{text:?}");
+        }
+
+        let orphan = format!("NTR2\n{score}\nlyric verse=0 text=\"la\"\n");
+        let error = deserialize_ntr(&orphan).unwrap_err();
+        assert_eq!(
+            (error.line, error.kind),
+            (3, NtrErrorKind::LyricWithoutNote)
+        );
+
+        let duplicated = format!("NTR2\n{score}\n{score}\n");
+        assert_eq!(
+            deserialize_ntr(&duplicated).unwrap_err().kind,
+            NtrErrorKind::DuplicateScoreRecord
+        );
+
+        let unknown_field = format!("NTR2\n{score}\nstaff instrument=Flute color=red\n");
+        let error = deserialize_ntr(&unknown_field).unwrap_err();
+        assert_eq!(
+            (error.line, error.column),
+            (3, 24),
+            "column points at the field name"
+        );
+        assert_eq!(
+            error.kind,
+            NtrErrorKind::UnknownField {
+                record: "staff".to_owned(),
+                field: "color".to_owned(),
+            }
+        );
+    }
+}
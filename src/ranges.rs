@@ -0,0 +1,207 @@
+//! Instrument ranges: which notes the players of a score cannot reach, for
+//! the renderer, for note entry and for the review report.
+
+use std::fmt;
+
+use crate::harmony::Tone;
+use crate::layout;
+use crate::music::{Instrument, NoteEvent, Pitch, RangeLevel, Score, ScoreSettings};
+
+/// A note outside the range of its instrument.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RangeIssue {
+    /// Index into `Score::notes`.
+    pub index: usize,
+    pub staff: usize,
+    /// Counted from 0.
+    pub measure: usize,
+    pub instrument: Instrument,
+    /// Sounding pitch.
+    pub pitch: Pitch,
+    /// The range the note falls outside; see [`beyond`].
+    pub beyond: RangeLevel,
+}
+
+impl fmt::Display for RangeIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (low, high) = self.instrument.range(self.beyond);
+        write!(
+            f,
+            "{}, compasso {}: {} fora da extensão {} ({}–{})",
+            self.instrument.label(),
+            self.measure + 1,
+            pitch_label(self.pitch),
+            match self.beyond {
+                RangeLevel::Amateur => "amadora",
+                RangeLevel::Professional => "profissional",
+            },
+            pitch_label(low),
+            pitch_label(high)
+        )
+    }
+}
+
+/// Sounding pitch with its octave, as in `C♯4`.
+pub fn pitch_label(pitch: Pitch) -> String {
+    format!("{}{}", Tone::of(pitch).label(), pitch.octave)
+}
+
+/// The range a note falls outside when players of `level` cannot reach it:
+/// `Professional` when nobody can, `Amateur` when only professionals can.
+pub fn beyond(note: &NoteEvent, level: RangeLevel) -> Option<RangeLevel> {
+    if note.rest || note.instrument.in_range(note.pitch, level) {
+        None
+    } else if note
+        .instrument
+        .in_range(note.pitch, RangeLevel::Professional)
+    {
+        Some(RangeLevel::Amateur)
+    } else {
+        Some(RangeLevel::Professional)
+    }
+}
+
+/// Every note of the score outside the range of the score's level, in
+/// score order. Notes of parts without a staff are left out.
+pub fn check(score: &Score, settings: &ScoreSettings) -> Vec<RangeIssue> {
+    let locations = layout::locate_events(score, &settings.parts(), &settings.measures());
+    score
+        .notes
+        .iter()
+        .zip(locations)
+        .enumerate()
+        .filter_map(|(index, (note, location))| {
+            let location = location?;
+            Some(RangeIssue {
+                index,
+                staff: location.staff,
+                measure: location.measure,
+                instrument: note.instrument,
+                pitch: note.pitch,
+                beyond: beyond(note, settings.range_level)?,
+            })
+        })
+        .collect()
+}
+
+/// The first note of `after` that an edit from `before` puts outside the
+/// range of `level`. Notes already out of range before the edit, even when
+/// it splits or moves them, do not count again.
+pub fn newly_out_of_range<'a>(
+    before: &[NoteEvent],
+    after: &'a [NoteEvent],
+    level: RangeLevel,
+) -> Option<&'a NoteEvent> {
+    let key = |note: &NoteEvent| (note.part(), note.pitch.midi_number());
+    let mut known: Vec<_> = before
+        .iter()
+        .filter(|note| beyond(note, level).is_some())
+        .map(key)
+        .collect();
+    after
+        .iter()
+        .filter(|note| beyond(note, level).is_some())
+        .find(
+            |note| match known.iter().position(|known| *known == key(note)) {
+                Some(position) => {
+                    known.swap_remove(position);
+                    false
+                }
+                None => true,
+            },
+        )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::music::{DurationValue, PitchClass, Staff};
+
+    fn flute(class: PitchClass, octave: i8) -> NoteEvent {
+        NoteEvent::new(
+            Pitch::new(class, octave),
+            DurationValue::Quarter,
+            Instrument::FLUTE,
+        )
+    }
+
+    #[test]
+    fn amateur_ranges_are_narrower_and_notes_report_the_range_they_leave() {
+        let high_b = flute(PitchClass::B, 6);
+        assert_eq!(beyond(&high_b, RangeLevel::Professional), None);
+        assert_eq!(
+            beyond(&high_b, RangeLevel::Amateur),
+            Some(RangeLevel::Amateur)
+        );
+        let low_c = flute(PitchClass::C, 1);
+        assert_eq!(
+            beyond(&low_c, RangeLevel::Amateur),
+            Some(RangeLevel::Professional)
+        );
+        assert_eq!(
+            beyond(
+                &NoteEvent::rest(DurationValue::Quarter, Instrument::FLUTE),
+                RangeLevel::Amateur
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn the_report_locates_each_note_out_of_range() {
+        let score = Score {
+            notes: vec![
+                flute(PitchClass::C, 5),
+                flute(PitchClass::C, 5),
+                flute(PitchClass::C, 5),
+                flute(PitchClass::C, 5),
+                flute(PitchClass::B, 6),
+                flute(PitchClass::C, 3),
+            ],
+        };
+        let mut settings = ScoreSettings {
+            staves: vec![
+                Staff::new(Instrument::VIOLIN),
+                Staff::new(Instrument::FLUTE),
+            ],
+            ..ScoreSettings::default()
+        };
+
+        let issues = check(&score, &settings);
+        assert_eq!(issues.len(), 1);
+        assert_eq!((issues[0].index, issues[0].staff), (5, 1));
+        assert_eq!(issues[0].measure, 1);
+        assert_eq!(
+            issues[0].to_string(),
+            "Flauta, compasso 2: C3 fora da extensão profissional (C4–C7)"
+        );
+
+        settings.range_level = RangeLevel::Amateur;
+        let levels: Vec<_> = check(&score, &settings)
+            .iter()
+            .map(|issue| (issue.index, issue.beyond))
+            .collect();
+        assert_eq!(
+            levels,
+            [(4, RangeLevel::Amateur), (5, RangeLevel::Professional)]
+        );
+    }
+
+    #[test]
+    fn only_notes_an_edit_adds_out_of_range_count_as_new() {
+        let low = flute(PitchClass::C, 3);
+        let before = vec![flute(PitchClass::C, 5), low.clone()];
+        let mut after = before.clone();
+        after.swap(0, 1);
+        assert_eq!(
+            newly_out_of_range(&before, &after, RangeLevel::Professional),
+            None
+        );
+
+        after.push(low.clone());
+        assert_eq!(
+            newly_out_of_range(&before, &after, RangeLevel::Professional),
+            Some(&low)
+        );
+    }
+}
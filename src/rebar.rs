@@ -0,0 +1,471 @@
+//! Rewrites one staff's event stream while keeping it correctly barred.
+//!
+//! Measures are never stored: they fall out of the running sum of durations in
+//! each part's stream. Edits that cut into that stream therefore split
+//! the events they overlap into tied written values and fill gaps with rests.
+
+use crate::measures::MeasureMap;
+use crate::music::{DurationValue, NoteEvent, Part, BEAT_EPSILON};
+
+/// The events of `part` in every voice, in score order.
+pub fn staff_stream(notes: &[NoteEvent], part: Part) -> Vec<NoteEvent> {
+    notes
+        .iter()
+        .filter(|note| note.part() == part)
+        .cloned()
+        .collect()
+}
+
+/// The events of one voice of `part`, in score order.
+pub fn voice_stream(notes: &[NoteEvent], part: Part, voice: u8) -> Vec<NoteEvent> {
+    notes
+        .iter()
+        .filter(|note| note.part() == part && note.voice == voice)
+        .cloned()
+        .collect()
+}
+
+/// Voices of `part` that hold events, always including the first.
+pub fn voices(notes: &[NoteEvent], part: Part) -> Vec<u8> {
+    (0..NoteEvent::VOICES)
+        .filter(|voice| {
+            *voice == 0
+                || notes
+                    .iter()
+                    .any(|note| note.part() == part && note.voice == *voice)
+        })
+        .collect()
+}
+
+/// Puts `stream` back in place of the events of `voice` of `part`. Other
+/// events keep their order; the stream goes where the voice's first event
+/// used to be, or after the part's last event, or at the end.
+pub fn replace_stream(
+    notes: &[NoteEvent],
+    part: Part,
+    voice: u8,
+    stream: Vec<NoteEvent>,
+) -> Vec<NoteEvent> {
+    let in_voice = |note: &NoteEvent| note.part() == part && note.voice == voice;
+    let at = notes
+        .iter()
+        .position(in_voice)
+        .or_else(|| {
+            notes
+                .iter()
+                .rposition(|note| note.part() == part)
+                .map(|last| last + 1)
+        })
+        .unwrap_or(notes.len());
+    let mut out: Vec<NoteEvent> = notes[..at]
+        .iter()
+        .filter(|note| !in_voice(note))
+        .cloned()
+        .collect();
+    out.extend(stream);
+    out.extend(notes[at..].iter().filter(|note| !in_voice(note)).cloned());
+    out
+}
+
+/// Written values lasting `beats` with `template`'s pitch. Notes are tied
+/// through; the last one keeps the template's own tie and only the first its
+/// dynamic, articulations, lyrics and chord symbol.
+pub fn events_for(template: &NoteEvent, beats: f32) -> Vec<NoteEvent> {
+    let values = DurationValue::decompose(beats);
+    let count = values.len();
+    values
+        .into_iter()
+        .enumerate()
+        .map(|(position, (duration, dots))| NoteEvent {
+            duration,
+            dots,
+            tie: !template.rest && (position + 1 < count || template.tie),
+            dynamic: template.dynamic.filter(|_| position == 0),
+            articulations: if position == 0 {
+                template.articulations.clone()
+            } else {
+                Vec::new()
+            },
+            lyrics: if position == 0 {
+                template.lyrics.clone()
+            } else {
+                Vec::new()
+            },
+            chord: template.chord.clone().filter(|_| position == 0),
+            ..template.clone()
+        })
+        .collect()
+}
+
+/// Rests in `voice` covering `beats` from `from_beat`, broken at barlines.
+pub fn rests(
+    part: Part,
+    voice: u8,
+    from_beat: f32,
+    beats: f32,
+    measures: &MeasureMap,
+) -> Vec<NoteEvent> {
+    let template = NoteEvent {
+        voice,
+        ..NoteEvent::rest(DurationValue::Quarter, part)
+    };
+    barred(&template, from_beat, beats, measures)
+}
+
+/// Written values lasting `beats` from `from_beat` with `template`'s pitch,
+/// broken at barlines and tied across them.
+fn barred(
+    template: &NoteEvent,
+    from_beat: f32,
+    beats: f32,
+    measures: &MeasureMap,
+) -> Vec<NoteEvent> {
+    let mut out = Vec::new();
+    let mut position = from_beat;
+    let end = from_beat + beats;
+
+    while end - position > BEAT_EPSILON {
+        let (measure, offset) = measures.locate(position);
+        let chunk = (measures.length(measure) - offset).min(end - position);
+        let last = end - position - chunk <= BEAT_EPSILON;
+        out.extend(events_for(
+            &NoteEvent {
+                tie: !template.rest && (!last || template.tie),
+                dynamic: template.dynamic.filter(|_| out.is_empty()),
+                articulations: if out.is_empty() {
+                    template.articulations.clone()
+                } else {
+                    Vec::new()
+                },
+                lyrics: if out.is_empty() {
+                    template.lyrics.clone()
+                } else {
+                    Vec::new()
+                },
+                chord: template.chord.clone().filter(|_| out.is_empty()),
+                ..template.clone()
+            },
+            chunk,
+        ));
+        position += chunk;
+    }
+
+    out
+}
+
+/// Rewrites the events of `stream` that touch `[from, to)` so they break
+/// exactly at the barlines of `measures`. Tied notes of one pitch are merged
+/// first, so ties across a barline that is gone become a single value.
+pub fn reflow(stream: &[NoteEvent], measures: &MeasureMap, from: f32, to: f32) -> Vec<NoteEvent> {
+    let mut out = Vec::new();
+    let mut position = 0.0_f32;
+    let mut index = 0;
+
+    while index < stream.len() {
+        // A note and the notes tied on from it, or a single rest.
+        let mut end = index + 1;
+        while !stream[end - 1].rest
+            && stream[end - 1].tie
+            && stream
+                .get(end)
+                .is_some_and(|next| !next.rest && next.pitch == stream[index].pitch)
+        {
+            end += 1;
+        }
+        let chain = &stream[index..end];
+        let beats: f32 = chain.iter().map(NoteEvent::beats).sum();
+
+        if position + beats > from + BEAT_EPSILON && position < to - BEAT_EPSILON {
+            let template = NoteEvent {
+                dynamic: chain[0].dynamic,
+                articulations: chain[0].articulations.clone(),
+                lyrics: chain[0].lyrics.clone(),
+                chord: chain[0].chord.clone(),
+                ..chain[chain.len() - 1].clone()
+            };
+            out.extend(barred(&template, position, beats, measures));
+        } else {
+            out.extend(chain.iter().cloned());
+        }
+        position += beats;
+        index = end;
+    }
+
+    out
+}
+
+/// Replaces whatever `stream` holds in `[start, start + length of insert)`
+/// with `insert`. Events overlapping either edge are cut down, and the stream
+/// is padded with rests when it ends before `start`.
+pub fn overwrite(
+    stream: &[NoteEvent],
+    part: Part,
+    start: f32,
+    insert: &[NoteEvent],
+    measures: &MeasureMap,
+) -> Vec<NoteEvent> {
+    let end = start + insert.iter().map(NoteEvent::beats).sum::<f32>();
+    splice(stream, part, start, end, insert, measures)
+}
+
+/// Replaces whatever `stream` holds in `[start, end)` with `insert`, which
+/// may be longer or shorter, moving the events after `end` along. Events
+/// overlapping either edge are cut down and ties into the replaced span are
+/// dropped; a stream ending before `start` is padded with rests unless there
+/// is nothing to insert.
+pub fn splice(
+    stream: &[NoteEvent],
+    part: Part,
+    start: f32,
+    end: f32,
+    insert: &[NoteEvent],
+    measures: &MeasureMap,
+) -> Vec<NoteEvent> {
+    let mut head = Vec::new();
+    let mut tail = Vec::new();
+    let mut position = 0.0_f32;
+
+    for event in stream {
+        let event_start = position;
+        let event_end = position + event.beats();
+        position = event_end;
+
+        if event_end <= start + BEAT_EPSILON {
+            head.push(event.clone());
+            continue;
+        }
+        if event_start >= end - BEAT_EPSILON {
+            tail.push(event.clone());
+            continue;
+        }
+        if event_start < start - BEAT_EPSILON {
+            let cut = NoteEvent {
+                tie: false,
+                ..event.clone()
+            };
+            head.extend(events_for(&cut, start - event_start));
+        }
+        if event_end > end + BEAT_EPSILON {
+            let remainder = NoteEvent {
+                dynamic: None,
+                articulations: Vec::new(),
+                lyrics: Vec::new(),
+                chord: None,
+                ..event.clone()
+            };
+            tail.extend(events_for(&remainder, event_end - end));
+        }
+    }
+
+    if let Some(first) = insert.first().filter(|_| position < start - BEAT_EPSILON) {
+        head.extend(rests(
+            part,
+            first.voice,
+            position,
+            start - position,
+            measures,
+        ));
+    }
+    // A tie into the replaced span has nothing left to hold on to.
+    if end > start + BEAT_EPSILON {
+        if let Some(last) = head.last_mut() {
+            last.tie = false;
+        }
+    }
+
+    let mut out = head;
+    out.extend(insert.iter().cloned());
+    out.extend(tail);
+    clear_dangling_ties(&mut out);
+    out
+}
+
+/// Drops ties that lead into a rest or off the end of the stream.
+pub fn clear_dangling_ties(stream: &mut [NoteEvent]) {
+    for index in 0..stream.len() {
+        let leads_to_note = stream.get(index + 1).is_some_and(|next| !next.rest);
+        if !leads_to_note {
+            stream[index].tie = false;
+        }
+    }
+}
+
+/// Drops ties whose next event of the same part and voice is not a note of
+/// the same pitch, over a whole score.
+pub fn clear_broken_ties(notes: &mut [NoteEvent]) {
+    for index in 0..notes.len() {
+        if notes[index].tie {
+            let (part, voice) = (notes[index].part(), notes[index].voice);
+            let next = notes[index + 1..]
+                .iter()
+                .find(|note| note.part() == part && note.voice == voice);
+            let pitch = notes[index].pitch;
+            notes[index].tie = next.is_some_and(|note| !note.rest && note.pitch == pitch);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::music::{Instrument, Meter, MeterChange, Pitch, PitchClass, TimeSignature};
+
+    fn note(class: PitchClass, duration: DurationValue) -> NoteEvent {
+        NoteEvent::new(Pitch::new(class, 4), duration, Instrument::VIOLIN)
+    }
+
+    fn four_four() -> MeasureMap {
+        MeasureMap::new(TimeSignature::FourFour, &[])
+    }
+
+    fn values(stream: &[NoteEvent]) -> Vec<(bool, DurationValue, u8, bool)> {
+        stream
+            .iter()
+            .map(|event| (event.rest, event.duration, event.dots, event.tie))
+            .collect()
+    }
+
+    #[test]
+    fn overwrite_splits_events_at_both_edges() {
+        let stream = vec![note(PitchClass::C, DurationValue::Whole)];
+        let insert = vec![note(PitchClass::G, DurationValue::Quarter)];
+
+        let out = overwrite(
+            &stream,
+            Instrument::VIOLIN.into(),
+            1.0,
+            &insert,
+            &four_four(),
+        );
+
+        assert_eq!(
+            values(&out),
+            vec![
+                (false, DurationValue::Quarter, 0, false),
+                (false, DurationValue::Quarter, 0, false),
+                (false, DurationValue::Half, 0, false),
+            ]
+        );
+        assert_eq!(out[1].pitch.class, PitchClass::G);
+        assert_eq!(out[2].pitch.class, PitchClass::C);
+    }
+
+    #[test]
+    fn overwrite_past_the_end_pads_with_barred_rests() {
+        let stream = vec![note(PitchClass::C, DurationValue::Quarter)];
+        let insert = vec![note(PitchClass::E, DurationValue::Half)];
+
+        let out = overwrite(
+            &stream,
+            Instrument::VIOLIN.into(),
+            6.0,
+            &insert,
+            &four_four(),
+        );
+
+        assert_eq!(
+            values(&out),
+            vec![
+                (false, DurationValue::Quarter, 0, false),
+                (true, DurationValue::Half, 1, false),
+                (true, DurationValue::Half, 0, false),
+                (false, DurationValue::Half, 0, false),
+            ]
+        );
+    }
+
+    #[test]
+    fn reflow_follows_moved_barlines() {
+        let tied_half = NoteEvent {
+            tie: true,
+            ..note(PitchClass::C, DurationValue::Half)
+        };
+        let stream = vec![
+            note(PitchClass::E, DurationValue::Half),
+            tied_half.clone(),
+            note(PitchClass::C, DurationValue::Half),
+            note(PitchClass::G, DurationValue::Half),
+        ];
+        let joined = MeasureMap::new(
+            TimeSignature::FourFour,
+            &[MeterChange {
+                measure: 0,
+                meter: Meter {
+                    numerator: 8,
+                    denominator: 4,
+                },
+            }],
+        );
+
+        let out = reflow(&stream, &joined, 0.0, 8.0);
+        assert_eq!(
+            values(&out),
+            vec![
+                (false, DurationValue::Half, 0, false),
+                (false, DurationValue::Whole, 0, false),
+                (false, DurationValue::Half, 0, false),
+            ]
+        );
+
+        let back = reflow(&out, &four_four(), 0.0, 8.0);
+        assert_eq!(back, stream);
+    }
+
+    #[test]
+    fn replaced_stream_keeps_other_instruments_in_order() {
+        let flute = NoteEvent::new(
+            Pitch::new(PitchClass::A, 5),
+            DurationValue::Quarter,
+            Instrument::FLUTE,
+        );
+        let lower = NoteEvent {
+            voice: 1,
+            ..note(PitchClass::G, DurationValue::Whole)
+        };
+        let notes = vec![
+            flute.clone(),
+            note(PitchClass::C, DurationValue::Half),
+            lower.clone(),
+            flute.clone(),
+            note(PitchClass::D, DurationValue::Half),
+        ];
+
+        let out = replace_stream(
+            &notes,
+            Instrument::VIOLIN.into(),
+            0,
+            vec![note(PitchClass::E, DurationValue::Whole)],
+        );
+
+        assert_eq!(
+            out,
+            vec![
+                flute.clone(),
+                note(PitchClass::E, DurationValue::Whole),
+                lower.clone(),
+                flute
+            ]
+        );
+        assert_eq!(voices(&out, Instrument::VIOLIN.into()), [0, 1]);
+        assert_eq!(voice_stream(&out, Instrument::VIOLIN.into(), 1), [lower]);
+    }
+
+    #[test]
+    fn ties_into_rests_are_cleared() {
+        let mut stream = vec![
+            NoteEvent {
+                tie: true,
+                ..note(PitchClass::C, DurationValue::Quarter)
+            },
+            NoteEvent::rest(DurationValue::Quarter, Instrument::VIOLIN),
+            NoteEvent {
+                tie: true,
+                ..note(PitchClass::C, DurationValue::Quarter)
+            },
+        ];
+
+        clear_dangling_ties(&mut stream);
+
+        assert!(stream.iter().all(|event| !event.tie));
+    }
+}
@@ -0,0 +1,315 @@
+use std::collections::BTreeSet;
+
+use crate::layout::{self, EventLocation};
+use crate::measures::MeasureMap;
+use crate::music::{Part, Score};
+
+/// A rectangular block of whole measures over adjacent staves, bounds inclusive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MeasureRange {
+    pub first_staff: usize,
+    pub last_staff: usize,
+    pub first_measure: usize,
+    pub last_measure: usize,
+}
+
+impl MeasureRange {
+    /// The smallest range covering both `(staff, measure)` cells, in any order.
+    pub fn spanning(a: (usize, usize), b: (usize, usize)) -> Self {
+        Self {
+            first_staff: a.0.min(b.0),
+            last_staff: a.0.max(b.0),
+            first_measure: a.1.min(b.1),
+            last_measure: a.1.max(b.1),
+        }
+    }
+
+    pub fn contains(&self, staff: usize, measure: usize) -> bool {
+        (self.first_staff..=self.last_staff).contains(&staff)
+            && (self.first_measure..=self.last_measure).contains(&measure)
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum SelectionKind {
+    #[default]
+    Empty,
+    /// Individually picked events, as indices into `Score::notes`.
+    Notes(BTreeSet<usize>),
+    Range(MeasureRange),
+}
+
+/// What the user has selected in the score view. Commands that act on "the
+/// selection" resolve it with [`Selection::event_indices`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Selection {
+    kind: SelectionKind,
+    /// Staff and measure a shift-click extends from.
+    anchor: Option<(usize, usize)>,
+}
+
+impl Selection {
+    pub fn is_empty(&self) -> bool {
+        match &self.kind {
+            SelectionKind::Empty => true,
+            SelectionKind::Notes(notes) => notes.is_empty(),
+            SelectionKind::Range(_) => false,
+        }
+    }
+
+    pub fn clear(&mut self) {
+        *self = Self::default();
+    }
+
+    pub fn select_note(&mut self, index: usize, staff: usize, measure: usize) {
+        self.kind = SelectionKind::Notes(BTreeSet::from([index]));
+        self.anchor = Some((staff, measure));
+    }
+
+    /// Adds the note to a note selection, or removes it if already there.
+    pub fn toggle_note(&mut self, index: usize, staff: usize, measure: usize) {
+        match &mut self.kind {
+            SelectionKind::Notes(notes) => {
+                if !notes.remove(&index) {
+                    notes.insert(index);
+                }
+                self.anchor = Some((staff, measure));
+            }
+            _ => self.select_note(index, staff, measure),
+        }
+    }
+
+    /// Replaces the selection with the given notes, or adds them when `additive`.
+    pub fn select_notes(&mut self, indices: impl IntoIterator<Item = usize>, additive: bool) {
+        let mut notes = match (&self.kind, additive) {
+            (SelectionKind::Notes(current), true) => current.clone(),
+            _ => BTreeSet::new(),
+        };
+        notes.extend(indices);
+        self.kind = if notes.is_empty() {
+            SelectionKind::Empty
+        } else {
+            SelectionKind::Notes(notes)
+        };
+    }
+
+    /// The measure block, when the selection is one.
+    pub fn range(&self) -> Option<MeasureRange> {
+        match self.kind {
+            SelectionKind::Range(range) => Some(range),
+            _ => None,
+        }
+    }
+
+    pub fn select_range(&mut self, range: MeasureRange) {
+        self.kind = SelectionKind::Range(range);
+        self.anchor = Some((range.first_staff, range.first_measure));
+    }
+
+    pub fn select_measure(&mut self, staff: usize, measure: usize) {
+        self.kind =
+            SelectionKind::Range(MeasureRange::spanning((staff, measure), (staff, measure)));
+        self.anchor = Some((staff, measure));
+    }
+
+    /// Selects every measure of one staff.
+    pub fn select_staff(&mut self, staff: usize, measure_count: usize) {
+        self.kind = SelectionKind::Range(MeasureRange::spanning(
+            (staff, 0),
+            (staff, measure_count.saturating_sub(1)),
+        ));
+        self.anchor = Some((staff, 0));
+    }
+
+    pub fn select_all(&mut self, staff_count: usize, measure_count: usize) {
+        if staff_count == 0 {
+            self.clear();
+            return;
+        }
+        self.kind = SelectionKind::Range(MeasureRange::spanning(
+            (0, 0),
+            (staff_count - 1, measure_count.saturating_sub(1)),
+        ));
+        self.anchor = Some((0, 0));
+    }
+
+    /// Shift-click: grows a range from the anchor to the given cell.
+    pub fn extend_to(&mut self, staff: usize, measure: usize) {
+        match self.anchor {
+            Some(anchor) => {
+                self.kind = SelectionKind::Range(MeasureRange::spanning(anchor, (staff, measure)));
+            }
+            None => self.select_measure(staff, measure),
+        }
+    }
+
+    pub fn contains_note(&self, index: usize, staff: usize, measure: usize) -> bool {
+        match &self.kind {
+            SelectionKind::Empty => false,
+            SelectionKind::Notes(notes) => notes.contains(&index),
+            SelectionKind::Range(range) => range.contains(staff, measure),
+        }
+    }
+
+    pub fn contains_measure(&self, staff: usize, measure: usize) -> bool {
+        matches!(&self.kind, SelectionKind::Range(range) if range.contains(staff, measure))
+    }
+
+    /// Selected events in score order.
+    pub fn event_indices(
+        &self,
+        score: &Score,
+        parts: &[Part],
+        measures: &MeasureMap,
+    ) -> Vec<usize> {
+        match &self.kind {
+            SelectionKind::Empty => Vec::new(),
+            SelectionKind::Notes(notes) => notes
+                .iter()
+                .copied()
+                .filter(|index| *index < score.notes.len())
+                .collect(),
+            SelectionKind::Range(range) => layout::locate_events(score, parts, measures)
+                .into_iter()
+                .enumerate()
+                .filter_map(|(index, location)| match location {
+                    Some(EventLocation { staff, measure, .. })
+                        if range.contains(staff, measure) =>
+                    {
+                        Some(index)
+                    }
+                    _ => None,
+                })
+                .collect(),
+        }
+    }
+
+    /// Drops picked notes that no longer exist after an edit.
+    pub fn retain_valid(&mut self, note_count: usize) {
+        if let SelectionKind::Notes(notes) = &mut self.kind {
+            notes.retain(|index| *index < note_count);
+            if notes.is_empty() {
+                self.kind = SelectionKind::Empty;
+            }
+        }
+    }
+
+    /// Short text for the status bar.
+    pub fn describe(&self) -> String {
+        match &self.kind {
+            SelectionKind::Empty => "Nenhuma seleção".to_owned(),
+            SelectionKind::Notes(notes) if notes.len() == 1 => "1 nota selecionada".to_owned(),
+            SelectionKind::Notes(notes) => format!("{} notas selecionadas", notes.len()),
+            SelectionKind::Range(range) => {
+                let staves = range.last_staff - range.first_staff + 1;
+                let measures = if range.first_measure == range.last_measure {
+                    format!("Compasso {}", range.first_measure + 1)
+                } else {
+                    format!(
+                        "Compassos {}–{}",
+                        range.first_measure + 1,
+                        range.last_measure + 1
+                    )
+                };
+                if staves == 1 {
+                    format!("{measures}, 1 pauta")
+                } else {
+                    format!("{measures}, {staves} pautas")
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::music::{DurationValue, Instrument, NoteEvent, Pitch, PitchClass, TimeSignature};
+
+    fn score() -> Score {
+        let half = |instrument| {
+            NoteEvent::new(
+                Pitch::new(PitchClass::C, 5),
+                DurationValue::Half,
+                instrument,
+            )
+        };
+        Score {
+            notes: vec![
+                half(Instrument::FLUTE),
+                half(Instrument::FLUTE),
+                half(Instrument::FLUTE),
+                half(Instrument::CLARINET),
+                half(Instrument::CLARINET),
+                half(Instrument::CLARINET),
+            ],
+        }
+    }
+
+    #[test]
+    fn shift_click_extends_a_range_from_the_anchor() {
+        let mut selection = Selection::default();
+        selection.select_note(2, 0, 1);
+        selection.extend_to(1, 0);
+
+        assert_eq!(
+            selection.kind,
+            SelectionKind::Range(MeasureRange {
+                first_staff: 0,
+                last_staff: 1,
+                first_measure: 0,
+                last_measure: 1,
+            })
+        );
+        assert!(selection.contains_measure(1, 1));
+        assert!(!selection.contains_measure(2, 1));
+        assert_eq!(selection.describe(), "Compassos 1–2, 2 pautas");
+    }
+
+    #[test]
+    fn range_resolves_to_events_in_score_order() {
+        let parts = [Instrument::FLUTE, Instrument::CLARINET].map(Part::from);
+        let mut selection = Selection::default();
+        selection.select_measure(1, 1);
+        selection.extend_to(0, 1);
+
+        assert_eq!(
+            selection.event_indices(
+                &score(),
+                &parts,
+                &MeasureMap::new(TimeSignature::FourFour, &[])
+            ),
+            vec![2, 5]
+        );
+
+        selection.select_staff(0, 2);
+        assert_eq!(
+            selection.event_indices(
+                &score(),
+                &parts,
+                &MeasureMap::new(TimeSignature::FourFour, &[])
+            ),
+            vec![0, 1, 2]
+        );
+    }
+
+    #[test]
+    fn toggling_and_marquee_build_note_sets() {
+        let mut selection = Selection::default();
+        selection.toggle_note(4, 1, 0);
+        selection.toggle_note(1, 0, 0);
+        selection.toggle_note(4, 1, 0);
+        assert_eq!(selection.kind, SelectionKind::Notes(BTreeSet::from([1])));
+
+        selection.select_notes([3, 0], true);
+        assert_eq!(selection.describe(), "3 notas selecionadas");
+
+        selection.retain_valid(2);
+        assert_eq!(selection.kind, SelectionKind::Notes(BTreeSet::from([0, 1])));
+        selection.retain_valid(0);
+        assert!(selection.is_empty());
+
+        selection.select_notes([], false);
+        assert_eq!(selection.kind, SelectionKind::Empty);
+    }
+}
@@ -0,0 +1,342 @@
+//! Editing the list of staves: adding players, removing staves and sorting
+//! them into a conventional score order.
+
+use std::ops::Range;
+
+use crate::music::{Clef, Family, Instrument, NoteEvent, Part, Pitch, PitchClass, Staff};
+
+/// Conventional top-to-bottom orders for the staves of a score.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScoreOrder {
+    /// Woodwinds, brass, percussion, harp and keyboards, voices, strings.
+    Orchestral,
+    /// Woodwinds, brass, low strings, percussion and keyboards; upper strings last.
+    Band,
+    /// The orchestra, then the voices and, at the bottom, the keyboards that
+    /// accompany them.
+    Choir,
+}
+
+impl ScoreOrder {
+    pub const ALL: [Self; 3] = [Self::Orchestral, Self::Band, Self::Choir];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Orchestral => "Orquestra",
+            Self::Band => "Banda",
+            Self::Choir => "Coro",
+        }
+    }
+
+    /// Sections top to bottom: families, with the strings of the band
+    /// split into the low strings (`Some(true)`, read in the bass clef) and
+    /// the upper ones.
+    fn sections(self) -> &'static [(Family, Option<bool>)] {
+        use Family::*;
+        match self {
+            Self::Orchestral => &[
+                (Woodwinds, None),
+                (Brass, None),
+                (Percussion, None),
+                (Plucked, None),
+                (Keyboards, None),
+                (Voices, None),
+                (Strings, None),
+            ],
+            Self::Band => &[
+                (Woodwinds, None),
+                (Brass, None),
+                (Strings, Some(true)),
+                (Percussion, None),
+                (Plucked, None),
+                (Keyboards, None),
+                (Voices, None),
+                (Strings, Some(false)),
+            ],
+            Self::Choir => &[
+                (Woodwinds, None),
+                (Brass, None),
+                (Percussion, None),
+                (Plucked, None),
+                (Strings, None),
+                (Voices, None),
+                (Keyboards, None),
+            ],
+        }
+    }
+
+    /// Where `instrument` goes: its section, then its place in the
+    /// instrument catalogue.
+    fn rank(self, instrument: Instrument) -> (usize, usize) {
+        let low = instrument.clef() == Clef::Bass;
+        let section = self
+            .sections()
+            .iter()
+            .position(|(family, bass)| {
+                *family == instrument.family() && bass.is_none_or(|bass| bass == low)
+            })
+            .unwrap_or(usize::MAX);
+        let place = Instrument::all()
+            .position(|known| known == instrument)
+            .unwrap_or(usize::MAX);
+        (section, place)
+    }
+
+    /// Sorts the staves into this order. Players of one instrument stay
+    /// together, first player on top, each with its grand staff intact.
+    pub fn sort(self, staves: &mut [Staff]) {
+        staves.sort_by_key(|staff| {
+            (
+                self.rank(staff.part.instrument),
+                staff.part.player,
+                staff.part.sub_staff,
+            )
+        });
+    }
+}
+
+/// Adds the staves for the next player of `instrument`, right below its other
+/// staves or at the bottom when it has none. Keyboards and harp get a grand
+/// staff. Returns the index of the new top staff.
+pub fn add_staff(staves: &mut Vec<Staff>, instrument: Instrument) -> usize {
+    let player = staves
+        .iter()
+        .filter(|staff| staff.part.instrument == instrument)
+        .map(|staff| staff.part.player + 1)
+        .max()
+        .unwrap_or(0);
+    let index = staves
+        .iter()
+        .rposition(|staff| staff.part.instrument == instrument)
+        .map_or(staves.len(), |last| last + 1);
+    let part = Part {
+        player,
+        ..instrument.into()
+    };
+    staves.splice(
+        index..index,
+        (0..instrument.staff_count()).map(|sub_staff| Staff::new(part.on_staff(sub_staff))),
+    );
+    index
+}
+
+/// The staves braced together with the one at `index`: all of its player's.
+pub fn grand_staff(staves: &[Staff], index: usize) -> Range<usize> {
+    let Some(staff) = staves.get(index) else {
+        return index..index;
+    };
+    let same_player = |other: &Staff| other.part.on_staff(0) == staff.part.on_staff(0);
+    let first = staves[..index]
+        .iter()
+        .rposition(|other| !same_player(other))
+        .map_or(0, |before| before + 1);
+    let end = staves[index..]
+        .iter()
+        .position(|other| !same_player(other))
+        .map_or(staves.len(), |after| index + after);
+    first..end
+}
+
+/// Adds the next lower staff to the grand staff at `index`, such as the
+/// pedal staff of an organ. Returns its index, or `None` when it is complete.
+pub fn add_lower_staff(staves: &mut Vec<Staff>, index: usize) -> Option<usize> {
+    let group = grand_staff(staves, index);
+    let top = staves.get(group.start)?.part;
+    let sub_staff = u8::try_from(group.len()).ok()?;
+    if sub_staff >= top.instrument.staff_count() {
+        return None;
+    }
+    staves.insert(group.end, Staff::new(top.on_staff(sub_staff)));
+    Some(group.end)
+}
+
+/// Moves the grand staff at `index` past its neighbour above or below.
+pub fn move_grand_staff(staves: &mut [Staff], index: usize, down: bool) {
+    let group = grand_staff(staves, index);
+    if down {
+        let next = grand_staff(staves, group.end);
+        staves[group.start..next.end].rotate_left(group.len());
+    } else if group.start > 0 {
+        let previous = grand_staff(staves, group.start - 1);
+        staves[previous.start..group.end].rotate_right(group.len());
+    }
+}
+
+/// The events left once the staff of `part` is removed. Notes drawn across
+/// onto that staff go back to their own.
+pub fn notes_without(notes: &[NoteEvent], part: Part) -> Vec<NoteEvent> {
+    notes
+        .iter()
+        .filter(|note| note.part() != part)
+        .map(|note| NoteEvent {
+            cross_staff: if note.shown_part() == part {
+                0
+            } else {
+                note.cross_staff
+            },
+            ..note.clone()
+        })
+        .collect()
+}
+
+/// Draws the given notes one staff further up (`step` -1) or down (1) in
+/// their grand staff, keeping them in their own staff's rhythm. Notes with no
+/// staff there stay put.
+pub fn cross_staff(
+    notes: &[NoteEvent],
+    indices: &[usize],
+    parts: &[Part],
+    step: i8,
+) -> Vec<NoteEvent> {
+    let mut notes = notes.to_vec();
+    for index in indices {
+        let Some(note) = notes.get_mut(*index) else {
+            continue;
+        };
+        let crossed = NoteEvent {
+            cross_staff: note.cross_staff + step,
+            ..note.clone()
+        };
+        if !note.rest
+            && parts.contains(&crossed.shown_part())
+            && crossed.shown_part() != note.shown_part()
+        {
+            *note = crossed;
+        }
+    }
+    notes
+}
+
+/// Puts the given notes of a grand staff on the hand their pitch suits:
+/// middle C and up on the top staff, lower notes on the one below it. Notes
+/// of a pedal staff and of single staves are left alone.
+pub fn assign_staves(notes: &[NoteEvent], indices: &[usize], parts: &[Part]) -> Vec<NoteEvent> {
+    let middle_c = Pitch::new(PitchClass::C, 4).midi_number();
+    let mut notes = notes.to_vec();
+    for index in indices {
+        let Some(note) = notes.get_mut(*index) else {
+            continue;
+        };
+        let hand: u8 = if note.pitch.midi_number() >= middle_c {
+            0
+        } else {
+            1
+        };
+        let grand = [0, 1]
+            .iter()
+            .all(|staff| parts.contains(&note.part().on_staff(*staff)));
+        if !note.rest && note.sub_staff <= 1 && grand {
+            note.cross_staff = hand as i8 - note.sub_staff as i8;
+        }
+    }
+    notes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::music::{Clef, DurationValue};
+
+    #[test]
+    fn added_players_sit_below_their_section() {
+        let mut staves: Vec<Staff> = [Instrument::VIOLIN, Instrument::CELLO]
+            .into_iter()
+            .map(Staff::new)
+            .collect();
+
+        assert_eq!(add_staff(&mut staves, Instrument::VIOLIN), 1);
+        assert_eq!(add_staff(&mut staves, Instrument::FLUTE), 3);
+
+        assert_eq!(staves[1].part.player, 1);
+        let labels: Vec<String> = staves.iter().map(|staff| staff.labels(&staves).0).collect();
+        assert_eq!(labels, ["Violino I", "Violino II", "Violoncelo", "Flauta"]);
+    }
+
+    #[test]
+    fn grand_staves_are_added_moved_and_completed_together() {
+        let mut staves = vec![Staff::new(Instrument::FLUTE)];
+
+        assert_eq!(
+            add_staff(&mut staves, Instrument::from_id("Organ").unwrap()),
+            1
+        );
+        assert_eq!(grand_staff(&staves, 2), 1..4);
+        assert_eq!(staves[3].labels(&staves).0, "Órgão");
+        assert_eq!(staves[3].part.clef(), Clef::Bass);
+
+        move_grand_staff(&mut staves, 2, false);
+        let order: Vec<(Instrument, u8)> = staves
+            .iter()
+            .map(|staff| (staff.part.instrument, staff.part.sub_staff))
+            .collect();
+        assert_eq!(
+            order,
+            [
+                (Instrument::from_id("Organ").unwrap(), 0),
+                (Instrument::from_id("Organ").unwrap(), 1),
+                (Instrument::from_id("Organ").unwrap(), 2),
+                (Instrument::FLUTE, 0),
+            ]
+        );
+
+        // Without its pedal staff the organ can get it back, but no fourth.
+        staves.remove(2);
+        assert_eq!(add_lower_staff(&mut staves, 0), Some(2));
+        assert_eq!(add_lower_staff(&mut staves, 0), None);
+    }
+
+    #[test]
+    fn notes_cross_to_the_other_hand() {
+        let piano = Part::from(Instrument::PIANO);
+        let parts = [piano, piano.on_staff(1)];
+        let note = |class, octave, part: Part| {
+            NoteEvent::new(Pitch::new(class, octave), DurationValue::Eighth, part)
+        };
+        let notes = vec![
+            note(PitchClass::E, 4, piano),
+            note(PitchClass::A, 3, piano),
+            note(PitchClass::D, 4, piano.on_staff(1)),
+            note(PitchClass::G, 3, Instrument::VIOLIN.into()),
+        ];
+
+        let crossed = cross_staff(&notes, &[0, 2], &parts, 1);
+        assert_eq!(crossed[0].shown_part(), piano.on_staff(1));
+        // There is no third staff to cross down to.
+        assert_eq!(crossed[2].cross_staff, 0);
+
+        let assigned = assign_staves(&notes, &[0, 1, 2, 3], &parts);
+        let crosses: Vec<i8> = assigned.iter().map(|note| note.cross_staff).collect();
+        assert_eq!(crosses, [0, 1, -1, 0]);
+    }
+
+    #[test]
+    fn presets_sort_sections_and_keep_players_in_order() {
+        let mut staves = vec![
+            Staff::new(Instrument::PIANO),
+            Staff::new(Part {
+                player: 1,
+                ..Instrument::VIOLIN.into()
+            }),
+            Staff::new(Instrument::VIOLIN),
+            Staff::new(Instrument::FLUTE),
+        ];
+
+        ScoreOrder::Orchestral.sort(&mut staves);
+        let order: Vec<(Instrument, u8)> = staves
+            .iter()
+            .map(|staff| (staff.part.instrument, staff.part.player))
+            .collect();
+        assert_eq!(
+            order,
+            [
+                (Instrument::FLUTE, 0),
+                (Instrument::PIANO, 0),
+                (Instrument::VIOLIN, 0),
+                (Instrument::VIOLIN, 1),
+            ]
+        );
+
+        ScoreOrder::Choir.sort(&mut staves);
+        assert_eq!(staves[3].part.instrument, Instrument::PIANO);
+    }
+}
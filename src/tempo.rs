@@ -0,0 +1,322 @@
+//! Tempo over the course of a score: the opening tempo, tempo marks and
+//! gradual changes, and the fermatas that hold the music, turned into
+//! playing time.
+
+use crate::music::{Articulation, NoteEvent, ScoreSettings, TempoChange};
+
+/// Slowest tempo the map will play, so a long ritardando never stalls.
+const MIN_BPM: f32 = 10.0;
+
+/// How many times its written length a fermata holds the music.
+pub const FERMATA_HOLD: f32 = 2.0;
+
+/// Beats between the tempo steps a gradual change is written as in MIDI.
+const RAMP_STEP: f32 = 0.25;
+
+impl TempoChange {
+    /// Tempo reached at the end of a change with no metronome mark after it,
+    /// as a share of the tempo it starts from.
+    fn default_ratio(self) -> f32 {
+        match self {
+            Self::Ritardando => 0.75,
+            Self::Accelerando => 4.0 / 3.0,
+        }
+    }
+}
+
+/// A stretch of the score whose tempo is steady or moves evenly from `from`
+/// to `to` beats per minute.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Segment {
+    start: f32,
+    end: f32,
+    from: f32,
+    to: f32,
+}
+
+impl Segment {
+    fn is_ramp(&self) -> bool {
+        (self.to - self.from).abs() > 1e-3
+    }
+
+    fn bpm_at(&self, beat: f32) -> f32 {
+        if !self.is_ramp() {
+            return self.from;
+        }
+        let share = ((beat - self.start) / (self.end - self.start)).clamp(0.0, 1.0);
+        self.from + (self.to - self.from) * share
+    }
+
+    /// Seconds from the start of the segment to `beat`, which is clamped to
+    /// the segment.
+    fn seconds(&self, beat: f32) -> f32 {
+        let beats = (beat.min(self.end) - self.start).max(0.0);
+        if !self.is_ramp() {
+            return beats * 60.0 / self.from;
+        }
+        // The time per beat is 60 / bpm with bpm linear in the beat, which
+        // integrates to a logarithm.
+        let slope = (self.to - self.from) / (self.end - self.start);
+        60.0 / slope * (self.bpm_at(self.start + beats) / self.from).ln()
+    }
+}
+
+/// The tempo at every beat of a score.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TempoMap {
+    /// Back to back from beat 0; the last one runs on forever.
+    segments: Vec<Segment>,
+}
+
+impl TempoMap {
+    /// Builds the map of a score `length` beats long. A gradual change runs to
+    /// the next mark, or to the end of the score, and reaches the next mark's
+    /// tempo, or a set share of its own without one.
+    pub fn new(settings: &ScoreSettings, length: f32) -> Self {
+        let marks = &settings.tempo_marks;
+        let mut segments = Vec::new();
+        let mut start = 0.0;
+        let mut bpm = settings.bpm.max(MIN_BPM);
+
+        for (index, mark) in marks.iter().enumerate() {
+            let beat = mark.beat.max(start);
+            if beat > start {
+                segments.push(Segment {
+                    start,
+                    end: beat,
+                    from: bpm,
+                    to: bpm,
+                });
+                start = beat;
+            }
+            if let Some(marked) = mark.bpm {
+                bpm = marked.max(MIN_BPM);
+            }
+            let Some(change) = mark.change else {
+                continue;
+            };
+            let next = marks.get(index + 1);
+            let end = next.map_or(length, |next| next.beat);
+            let target = next
+                .and_then(|next| next.bpm)
+                .unwrap_or(bpm * change.default_ratio())
+                .max(MIN_BPM);
+            if end > start {
+                segments.push(Segment {
+                    start,
+                    end,
+                    from: bpm,
+                    to: target,
+                });
+                start = end;
+            }
+            bpm = target;
+        }
+
+        segments.push(Segment {
+            start,
+            end: f32::INFINITY,
+            from: bpm,
+            to: bpm,
+        });
+        Self { segments }
+    }
+
+    fn segment(&self, beat: f32) -> &Segment {
+        self.segments
+            .iter()
+            .rev()
+            .find(|segment| segment.start <= beat)
+            .unwrap_or(&self.segments[0])
+    }
+
+    pub fn bpm_at(&self, beat: f32) -> f32 {
+        self.segment(beat).bpm_at(beat)
+    }
+
+    /// Seconds from the start of the score to `beat`.
+    pub fn seconds(&self, beat: f32) -> f32 {
+        self.segments
+            .iter()
+            .take_while(|segment| segment.start < beat)
+            .map(|segment| segment.seconds(beat))
+            .sum()
+    }
+
+    /// Beats where the tempo is worth restating: each segment's start and
+    /// every [`RAMP_STEP`] inside a gradual change.
+    fn breakpoints(&self) -> Vec<f32> {
+        let mut beats = Vec::new();
+        for segment in &self.segments {
+            beats.push(segment.start);
+            if segment.is_ramp() {
+                let mut beat = segment.start + RAMP_STEP;
+                while beat < segment.end - 1e-3 {
+                    beats.push(beat);
+                    beat += RAMP_STEP;
+                }
+            }
+        }
+        beats
+    }
+}
+
+/// Playing time of the score: beats through the tempo map, stretched
+/// wherever a fermata in any voice holds everything that sounds with it.
+pub struct Timeline {
+    tempo: TempoMap,
+    /// Held spans in beats, sorted and not overlapping.
+    holds: Vec<(f32, f32)>,
+    /// Multiplier on every tempo of the map.
+    speed: f32,
+}
+
+impl Timeline {
+    /// Times the given voice streams, played at `speed` times their tempo.
+    pub fn new(settings: &ScoreSettings, streams: &[Vec<NoteEvent>], speed: f32) -> Self {
+        let mut length = 0.0_f32;
+        let mut spans: Vec<(f32, f32)> = Vec::new();
+        for stream in streams {
+            let mut position = 0.0;
+            // A fermata on a tied note holds the whole chain.
+            let mut held_tie = false;
+            for note in stream {
+                let end = position + note.beats();
+                let held = held_tie || note.articulations.contains(&Articulation::Fermata);
+                if held {
+                    spans.push((position, end));
+                }
+                held_tie = held && note.tie && !note.rest;
+                position = end;
+            }
+            length = length.max(position);
+        }
+
+        spans.sort_by(|a, b| a.0.total_cmp(&b.0));
+        let mut holds: Vec<(f32, f32)> = Vec::new();
+        for (start, end) in spans {
+            match holds.last_mut() {
+                Some(last) if start <= last.1 + 1e-3 => last.1 = last.1.max(end),
+                _ => holds.push((start, end)),
+            }
+        }
+
+        Self {
+            tempo: TempoMap::new(settings, length),
+            holds,
+            speed: speed.max(0.1),
+        }
+    }
+
+    /// Seconds from the start of the performance to `beat`.
+    pub fn seconds(&self, beat: f32) -> f32 {
+        let held: f32 = self
+            .holds
+            .iter()
+            .filter(|(start, _)| *start < beat)
+            .map(|(start, end)| self.tempo.seconds(beat.min(*end)) - self.tempo.seconds(*start))
+            .sum();
+        (self.tempo.seconds(beat) + held * (FERMATA_HOLD - 1.0)) / self.speed
+    }
+
+    /// The performance as steady tempos: the beat each one starts on and the
+    /// beats per minute that keep every beat where [`Self::seconds`] puts it.
+    /// Gradual changes come out as small steps.
+    pub fn tempo_steps(&self) -> Vec<(f32, f32)> {
+        let mut beats = self.tempo.breakpoints();
+        for (start, end) in &self.holds {
+            beats.push(*start);
+            beats.push(*end);
+        }
+        beats.sort_by(f32::total_cmp);
+        beats.dedup_by(|a, b| (*a - *b).abs() < 1e-3);
+
+        let mut steps: Vec<(f32, f32)> = Vec::new();
+        for (index, start) in beats.iter().enumerate() {
+            let end = beats.get(index + 1).copied().unwrap_or(start + 1.0);
+            let bpm = 60.0 * (end - start) / (self.seconds(end) - self.seconds(*start));
+            match steps.last() {
+                Some((_, last)) if (last - bpm).abs() < 1e-2 => {}
+                _ => steps.push((*start, bpm)),
+            }
+        }
+        steps
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::music::{DurationValue, Instrument, Pitch, PitchClass, TempoMark};
+
+    fn close(a: f32, b: f32) -> bool {
+        (a - b).abs() < 1e-3
+    }
+
+    fn mark(beat: f32, bpm: Option<f32>, change: Option<TempoChange>) -> TempoMark {
+        TempoMark {
+            beat,
+            text: String::new(),
+            bpm,
+            change,
+        }
+    }
+
+    #[test]
+    fn marks_set_the_tempo_and_changes_ramp_to_the_next_one() {
+        let settings = ScoreSettings {
+            bpm: 60.0,
+            tempo_marks: vec![
+                mark(4.0, Some(120.0), None),
+                mark(8.0, None, Some(TempoChange::Ritardando)),
+                mark(12.0, Some(60.0), None),
+            ],
+            ..ScoreSettings::default()
+        };
+        let map = TempoMap::new(&settings, 16.0);
+
+        assert!(close(map.seconds(4.0), 4.0));
+        assert!(close(map.seconds(8.0), 6.0));
+        assert!(close(map.bpm_at(10.0), 90.0));
+        // Four beats slowing evenly from 120 to 60.
+        let ramp = 4.0 * 60.0 / -60.0 * 0.5_f32.ln();
+        assert!(close(map.seconds(12.0), 6.0 + ramp));
+        assert!(close(map.seconds(16.0), 10.0 + ramp));
+    }
+
+    #[test]
+    fn change_without_a_following_mark_runs_to_the_end() {
+        let settings = ScoreSettings {
+            bpm: 90.0,
+            tempo_marks: vec![mark(2.0, None, Some(TempoChange::Accelerando))],
+            ..ScoreSettings::default()
+        };
+        let map = TempoMap::new(&settings, 6.0);
+
+        assert!(close(map.bpm_at(1.0), 90.0));
+        assert!(close(map.bpm_at(6.0), 120.0));
+        assert!(close(map.bpm_at(20.0), 120.0));
+    }
+
+    #[test]
+    fn fermata_holds_every_voice_at_the_tempo_of_its_beat() {
+        let quarter = NoteEvent::new(
+            Pitch::new(PitchClass::A, 4),
+            DurationValue::Quarter,
+            Instrument::FLUTE,
+        );
+        let mut held = quarter.clone();
+        held.articulations.push(Articulation::Fermata);
+        let settings = ScoreSettings {
+            bpm: 60.0,
+            ..ScoreSettings::default()
+        };
+        let timeline = Timeline::new(&settings, &[vec![quarter, held]], 2.0);
+
+        assert!(close(timeline.seconds(1.0), 0.5));
+        assert!(close(timeline.seconds(2.0), 1.5));
+        let steps = timeline.tempo_steps();
+        assert_eq!(steps.len(), 3);
+        assert!(close(steps[0].1, 120.0) && close(steps[1].1, 60.0) && close(steps[2].1, 120.0));
+    }
+}
@@ -0,0 +1,424 @@
+//! Transposing notes by interval, by key and by octave.
+
+use crate::music::{KeySignature, NoteEvent, Pitch, RangeLevel, Score};
+use crate::rebar;
+
+/// A distance between two pitches: `steps` counts staff positions and
+/// `semitones` the sounding distance, so a minor third (2, 3) and an
+/// augmented second (1, 3) stay apart.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Interval {
+    pub steps: i32,
+    pub semitones: i32,
+}
+
+impl Interval {
+    /// Ascending intervals offered in the transpose dialog.
+    pub const NAMED: [(&'static str, Self); 14] = [
+        ("Uníssono aumentado", Self::new(0, 1)),
+        ("2ª menor", Self::new(1, 1)),
+        ("2ª maior", Self::new(1, 2)),
+        ("3ª menor", Self::new(2, 3)),
+        ("3ª maior", Self::new(2, 4)),
+        ("4ª justa", Self::new(3, 5)),
+        ("4ª aumentada", Self::new(3, 6)),
+        ("5ª diminuta", Self::new(4, 6)),
+        ("5ª justa", Self::new(4, 7)),
+        ("6ª menor", Self::new(5, 8)),
+        ("6ª maior", Self::new(5, 9)),
+        ("7ª menor", Self::new(6, 10)),
+        ("7ª maior", Self::new(6, 11)),
+        ("8ª justa", Self::new(7, 12)),
+    ];
+
+    pub const fn new(steps: i32, semitones: i32) -> Self {
+        Self { steps, semitones }
+    }
+
+    pub fn octaves(count: i32) -> Self {
+        Self::new(7 * count, 12 * count)
+    }
+
+    pub fn down(self) -> Self {
+        Self::new(-self.steps, -self.semitones)
+    }
+
+    /// The interval from the tonic of `from` up to the nearest tonic of `to`.
+    pub fn between_keys(from: KeySignature, to: KeySignature) -> Self {
+        let (from, to) = (from.tonic(), to.tonic());
+        Self::new(
+            (to.diatonic_number() - from.diatonic_number()).rem_euclid(7),
+            (to.midi_number() - from.midi_number()).rem_euclid(12),
+        )
+    }
+
+    /// Moves `pitch` by this interval keeping the spelling the interval
+    /// implies: E up a minor third is G, B up an augmented fourth is E#.
+    /// Results that would need more than a double sharp or flat are respelled.
+    pub fn apply(self, pitch: Pitch) -> Pitch {
+        let midi = pitch.midi_number() + self.semitones;
+        let target = Pitch::from_diatonic_number(pitch.diatonic_number() + self.steps);
+        spelled(target, midi - target.midi_number(), midi)
+    }
+}
+
+/// Whether accidentals follow the interval exactly or stay within the key.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TransposeMode {
+    /// Every note moves by the same number of semitones.
+    #[default]
+    Chromatic,
+    /// Notes move by scale steps of the key signature, so a third may be
+    /// major or minor depending on where it starts.
+    Diatonic,
+}
+
+impl TransposeMode {
+    pub const ALL: [Self; 2] = [Self::Chromatic, Self::Diatonic];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Chromatic => "Cromática",
+            Self::Diatonic => "Diatônica",
+        }
+    }
+}
+
+/// Moves `pitch` by `interval`; in diatonic mode only its steps count and
+/// the note keeps its distance from the key signature's alteration.
+pub fn transpose_pitch(
+    pitch: Pitch,
+    interval: Interval,
+    mode: TransposeMode,
+    key: KeySignature,
+) -> Pitch {
+    match mode {
+        TransposeMode::Chromatic => interval.apply(pitch),
+        TransposeMode::Diatonic => {
+            let target = Pitch::from_diatonic_number(pitch.diatonic_number() + interval.steps);
+            let accidental = pitch.alter - key.alteration(pitch.class);
+            let alter = (key.alteration(target.class) + accidental) as i32;
+            spelled(target, alter, target.midi_number() + alter)
+        }
+    }
+}
+
+fn spelled(natural: Pitch, alter: i32, midi: i32) -> Pitch {
+    if (-2..=2).contains(&alter) {
+        Pitch {
+            alter: alter as i8,
+            ..natural
+        }
+    } else {
+        Pitch::from_midi(midi, alter < 0)
+    }
+}
+
+/// What the transpose dialog moves the notes by.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TransposeBy {
+    #[default]
+    Interval,
+    /// To another key; the interval is the one between the tonics.
+    Key,
+    Octave,
+}
+
+impl TransposeBy {
+    pub const ALL: [Self; 3] = [Self::Interval, Self::Key, Self::Octave];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Interval => "Intervalo",
+            Self::Key => "Tonalidade",
+            Self::Octave => "Oitava",
+        }
+    }
+}
+
+/// Which notes a transposition touches.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TransposeScope {
+    #[default]
+    Selection,
+    Staff,
+    Score,
+}
+
+impl TransposeScope {
+    pub const ALL: [Self; 3] = [Self::Selection, Self::Staff, Self::Score];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Selection => "Seleção",
+            Self::Staff => "Pauta",
+            Self::Score => "Partitura inteira",
+        }
+    }
+}
+
+/// Choices made in the transpose dialog.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TransposeOptions {
+    pub by: TransposeBy,
+    /// Index into [`Interval::NAMED`].
+    pub interval: usize,
+    pub upward: bool,
+    pub mode: TransposeMode,
+    /// Target key when transposing by key.
+    pub key: KeySignature,
+    pub octaves: i32,
+    pub scope: TransposeScope,
+}
+
+impl Default for TransposeOptions {
+    fn default() -> Self {
+        Self {
+            by: TransposeBy::default(),
+            interval: 2,
+            upward: true,
+            mode: TransposeMode::default(),
+            key: KeySignature::C,
+            octaves: 1,
+            scope: TransposeScope::default(),
+        }
+    }
+}
+
+impl TransposeOptions {
+    /// The interval to move by when the score is currently in `key`.
+    pub fn interval(&self, key: KeySignature) -> Interval {
+        let up = match self.by {
+            TransposeBy::Interval => Interval::NAMED[self.interval].1,
+            TransposeBy::Key => Interval::between_keys(key, self.key),
+            TransposeBy::Octave => Interval::octaves(self.octaves),
+        };
+        match self.by {
+            _ if self.upward => up,
+            // Down to the same tonic an octave lower, not a mirrored interval.
+            TransposeBy::Key if up != Interval::default() => {
+                Interval::new(up.steps - 7, up.semitones - 12)
+            }
+            _ => up.down(),
+        }
+    }
+
+    /// Key and octave transpositions are always chromatic.
+    pub fn mode(&self) -> TransposeMode {
+        match self.by {
+            TransposeBy::Interval => self.mode,
+            TransposeBy::Key | TransposeBy::Octave => TransposeMode::Chromatic,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Transposed {
+    pub notes: Vec<NoteEvent>,
+    /// Transposed notes their instrument cannot play.
+    pub out_of_range: Vec<usize>,
+}
+
+/// The score with the notes at `indices` transposed, chord symbols
+/// included; rests are left alone. Notes are checked against the ranges
+/// of `level`.
+pub fn transpose(
+    score: &Score,
+    indices: &[usize],
+    interval: Interval,
+    mode: TransposeMode,
+    key: KeySignature,
+    level: RangeLevel,
+) -> Transposed {
+    let mut notes = score.notes.clone();
+    let mut out_of_range = Vec::new();
+    for &index in indices {
+        let Some(note) = notes.get_mut(index) else {
+            continue;
+        };
+        if let Some(chord) = &note.chord {
+            note.chord =
+                Some(chord.transposed(|pitch| transpose_pitch(pitch, interval, mode, key)));
+        }
+        if note.rest {
+            continue;
+        }
+        note.pitch = transpose_pitch(note.pitch, interval, mode, key);
+        if !note.instrument.in_range(note.pitch, level) {
+            out_of_range.push(index);
+        }
+    }
+    // Diatonic steps can split a tie between notes that were the same pitch.
+    rebar::clear_broken_ties(&mut notes);
+    Transposed {
+        notes,
+        out_of_range,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::music::{DurationValue, Instrument, PitchClass};
+
+    fn pitch(class: PitchClass, alter: i8, octave: i8) -> Pitch {
+        Pitch {
+            class,
+            alter,
+            octave,
+        }
+    }
+
+    #[test]
+    fn intervals_keep_their_spelling() {
+        let minor_third = Interval::new(2, 3);
+        assert_eq!(
+            minor_third.apply(pitch(PitchClass::E, 0, 4)),
+            pitch(PitchClass::G, 0, 4)
+        );
+        assert_eq!(
+            minor_third.apply(pitch(PitchClass::B, -1, 4)),
+            pitch(PitchClass::D, -1, 5)
+        );
+        assert_eq!(
+            Interval::new(3, 6).apply(pitch(PitchClass::B, 0, 3)),
+            pitch(PitchClass::E, 1, 4)
+        );
+        assert_eq!(
+            Interval::new(1, 2).down().apply(pitch(PitchClass::C, 0, 4)),
+            pitch(PitchClass::B, -1, 3)
+        );
+        assert_eq!(
+            Interval::octaves(-1).apply(pitch(PitchClass::F, 1, 5)),
+            pitch(PitchClass::F, 1, 4)
+        );
+        // B double-sharp up an augmented second would need a triple sharp.
+        assert_eq!(
+            Interval::new(1, 3).apply(pitch(PitchClass::B, 2, 4)),
+            Pitch::from_midi(76, false)
+        );
+    }
+
+    #[test]
+    fn diatonic_mode_follows_the_key() {
+        let third = Interval::new(2, 4);
+        let key = KeySignature::G;
+        assert_eq!(
+            transpose_pitch(
+                pitch(PitchClass::D, 0, 4),
+                third,
+                TransposeMode::Diatonic,
+                key
+            ),
+            pitch(PitchClass::F, 1, 4)
+        );
+        assert_eq!(
+            transpose_pitch(
+                pitch(PitchClass::E, 0, 4),
+                third,
+                TransposeMode::Diatonic,
+                key
+            ),
+            pitch(PitchClass::G, 0, 4)
+        );
+        // A chromatic F natural in G major stays a semitone below the key.
+        assert_eq!(
+            transpose_pitch(
+                pitch(PitchClass::F, 0, 4),
+                third,
+                TransposeMode::Diatonic,
+                key
+            ),
+            pitch(PitchClass::A, -1, 4)
+        );
+    }
+
+    #[test]
+    fn keys_are_a_spelled_interval_apart() {
+        assert_eq!(
+            Interval::between_keys(KeySignature::C, KeySignature::Eb),
+            Interval::new(2, 3)
+        );
+        assert_eq!(
+            Interval::between_keys(KeySignature::A, KeySignature::F),
+            Interval::new(5, 8)
+        );
+        let up = Interval::between_keys(KeySignature::D, KeySignature::Bb);
+        assert_eq!(up.apply(KeySignature::D.tonic()).class, PitchClass::B);
+
+        let down = TransposeOptions {
+            by: TransposeBy::Key,
+            upward: false,
+            key: KeySignature::Eb,
+            ..TransposeOptions::default()
+        };
+        assert_eq!(down.interval(KeySignature::C), Interval::new(-5, -9));
+    }
+
+    #[test]
+    fn transposing_skips_rests_and_reports_the_range() {
+        let score = Score {
+            notes: vec![
+                NoteEvent::new(
+                    pitch(PitchClass::G, 0, 3),
+                    DurationValue::Quarter,
+                    Instrument::VIOLIN,
+                ),
+                NoteEvent::rest(DurationValue::Quarter, Instrument::VIOLIN),
+                NoteEvent::new(
+                    pitch(PitchClass::C, 0, 5),
+                    DurationValue::Quarter,
+                    Instrument::VIOLIN,
+                ),
+            ],
+        };
+
+        let down = transpose(
+            &score,
+            &[0, 1, 2],
+            Interval::new(1, 2).down(),
+            TransposeMode::Chromatic,
+            KeySignature::C,
+            RangeLevel::Professional,
+        );
+
+        assert_eq!(down.notes[0].pitch, pitch(PitchClass::F, 0, 3));
+        assert!(down.notes[1].rest);
+        assert_eq!(down.notes[2].pitch, pitch(PitchClass::B, -1, 4));
+        assert_eq!(down.out_of_range, vec![0]);
+
+        // C7 is beyond amateur violinists only.
+        let up = |level| {
+            transpose(
+                &score,
+                &[2],
+                Interval::octaves(2),
+                TransposeMode::Chromatic,
+                KeySignature::C,
+                level,
+            )
+            .out_of_range
+        };
+        assert_eq!(up(RangeLevel::Professional), Vec::<usize>::new());
+        assert_eq!(up(RangeLevel::Amateur), vec![2]);
+    }
+
+    #[test]
+    fn chord_symbols_move_with_the_music() {
+        let mut score = Score {
+            notes: vec![NoteEvent::rest(DurationValue::Whole, Instrument::PIANO)],
+        };
+        score.notes[0].chord = Some("F#m7b5/C".parse().unwrap());
+
+        let up = transpose(
+            &score,
+            &[0],
+            Interval::new(2, 3),
+            TransposeMode::Chromatic,
+            KeySignature::C,
+            RangeLevel::Professional,
+        );
+        let chord = up.notes[0].chord.as_ref().unwrap();
+        assert_eq!(chord.to_string(), "Am7b5/Eb");
+    }
+}